@@ -25,6 +25,13 @@ pub enum NetworkEvent {
     PeerDisconnected {
         peer_index: u64,
     },
+    // peer dropped too many messages over the rate limits before they were queued
+    PeerRateLimitExceeded {
+        peer_index: u64,
+    },
+    DisconnectFromPeer {
+        peer_index: u64,
+    },
     BlockFetchRequest {
         block_hash: SaitoHash,
         peer_index: u64,
@@ -35,4 +42,8 @@ pub enum NetworkEvent {
         peer_index: u64,
         buffer: Vec<u8>,
     },
+    BlockFetchFailed {
+        block_hash: SaitoHash,
+        peer_index: u64,
+    },
 }
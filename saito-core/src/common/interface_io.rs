@@ -83,7 +83,7 @@ pub trait InterfaceIO {
     /// ```
     ///
     /// ```
-    async fn write_value(&mut self, key: String, value: Vec<u8>) -> Result<(), Error>;
+    async fn write_value(&self, key: String, value: Vec<u8>) -> Result<(), Error>;
     /// Reads a value with the given key from a persistent storage
     ///
     /// # Arguments
@@ -106,4 +106,6 @@ pub trait InterfaceIO {
     async fn remove_value(&self, key: String) -> Result<(), Error>;
     /// Retrieve the prefix for all the keys for blocks
     fn get_block_dir(&self) -> String;
+    /// Retrieve the prefix for the keys of the other files (wallets, issuance, address book)
+    fn get_data_dir(&self) -> String;
 }
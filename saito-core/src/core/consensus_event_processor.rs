@@ -2,19 +2,25 @@ use std::ops::DerefMut;
 use std::sync::Arc;
 use std::time::Duration;
 
+use ahash::AHashMap;
 use async_trait::async_trait;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::RwLock;
 
 use crate::common::command::NetworkEvent;
+use crate::common::defs::SaitoHash;
 use crate::common::keep_time::KeepTime;
 use crate::common::process_event::ProcessEvent;
 use crate::core::data::block::Block;
-use crate::core::data::blockchain::Blockchain;
+use crate::core::data::block_reconstruction::{BlockReconstruction, COMPACT_BLOCK_TIMEOUT};
+use crate::core::data::blockchain::{AddBlockResult, Blockchain};
+use crate::core::data::configuration::Configuration;
 use crate::core::data::golden_ticket::GoldenTicket;
 use crate::core::data::mempool::Mempool;
+use crate::core::data::msg::compact_block::{CompactBlock, CompactBlockTransactions};
 use crate::core::data::network::Network;
+use crate::core::data::peer::PeerMisbehaviour;
 
 use crate::core::data::storage::Storage;
 use crate::core::data::transaction::Transaction;
@@ -24,8 +30,29 @@ use crate::core::routing_event_processor::RoutingEvent;
 
 #[derive(Debug)]
 pub enum ConsensusEvent {
-    NewGoldenTicket { golden_ticket: GoldenTicket },
-    BlockFetched { peer_index: u64, buffer: Vec<u8> },
+    NewGoldenTicket {
+        golden_ticket: GoldenTicket,
+    },
+    BlockFetched {
+        peer_index: u64,
+        buffer: Vec<u8>,
+    },
+    NewBlock {
+        peer_index: u64,
+        block: Block,
+    },
+    NewTransaction {
+        peer_index: u64,
+        transaction: Transaction,
+    },
+    NewCompactBlock {
+        peer_index: u64,
+        compact_block: CompactBlock,
+    },
+    CompactBlockTransactionsReceived {
+        peer_index: u64,
+        response: CompactBlockTransactions,
+    },
 }
 
 /// Manages blockchain and the mempool
@@ -41,16 +68,83 @@ pub struct ConsensusEventProcessor {
     pub time_keeper: Box<dyn KeepTime + Send + Sync>,
     pub network: Network,
     pub storage: Storage,
+    // compact blocks waiting for their missing transactions from the peers
+    pub block_reconstructions: AHashMap<SaitoHash, BlockReconstruction>,
+    pub configs: Arc<RwLock<Configuration>>,
 }
 
 impl ConsensusEventProcessor {
-    /// Test method to generate test transactions
+    /// Adds a transaction received from a peer to the mempool and propagates it if it validates. Only the accepted
+    /// transactions are remembered as seen, so a transaction rejected for a temporary reason (eg: an input from a block
+    /// we don't have yet) is validated again when it's relayed again
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_index`: peer which sent the transaction
+    /// * `transaction`:
+    ///
+    /// returns: bool : true if the transaction is added to the mempool
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn process_new_transaction(
+        &mut self,
+        peer_index: u64,
+        mut transaction: Transaction,
+    ) -> bool {
+        let publickey;
+        {
+            trace!("waiting for the wallet read lock");
+            let wallet = self.wallet.read().await;
+            trace!("acquired the wallet read lock");
+            publickey = wallet.get_publickey();
+        }
+        transaction.generate_metadata(publickey);
+        let transaction_hash = transaction.get_hash_for_signature().unwrap();
+        debug!(
+            "received new transaction : {:?}",
+            hex::encode(transaction_hash)
+        );
+        if self.network.is_transaction_seen(&transaction_hash) {
+            trace!(
+                "transaction : {:?} already seen",
+                hex::encode(transaction_hash)
+            );
+            return false;
+        }
+
+        let accepted;
+        {
+            trace!("waiting for the mempool write lock");
+            let mut mempool = self.mempool.write().await;
+            trace!("acquired the mempool write lock");
+            trace!("waiting for the blockchain read lock");
+            let blockchain = self.blockchain.read().await;
+            trace!("acquired the blockchain read lock");
+            accepted = mempool
+                .add_transaction_if_validates(transaction.clone(), &blockchain)
+                .await;
+        }
+        if accepted {
+            self.network.mark_transaction_as_seen(transaction_hash);
+            self.network
+                .propagate_transaction(&transaction, Some(peer_index), self.wallet.clone())
+                .await;
+        }
+        accepted
+    }
+
+    /// Test method to generate test transactions. transactions accepted to the mempool are propagated to the peers
     ///
     /// # Arguments
     ///
     /// * `mempool`:
     /// * `wallet`:
     /// * `blockchain`:
+    /// * `network`:
     ///
     /// returns: ()
     ///
@@ -63,6 +157,7 @@ impl ConsensusEventProcessor {
         mempool: Arc<RwLock<Mempool>>,
         wallet: Arc<RwLock<Wallet>>,
         blockchain: Arc<RwLock<Blockchain>>,
+        network: &mut Network,
     ) {
         trace!("generating mock transactions");
 
@@ -75,6 +170,7 @@ impl ConsensusEventProcessor {
         let publickey;
         let privatekey;
         let latest_block_id;
+        let mut accepted_transactions = vec![];
 
         {
             trace!("waiting for the wallet read lock");
@@ -84,57 +180,240 @@ impl ConsensusEventProcessor {
             privatekey = wallet.get_privatekey();
         }
 
-        trace!("waiting for the mempool write lock");
-        let mut mempool = mempool_lock_clone.write().await;
-        trace!("acquired the mempool write lock");
-        trace!("waiting for the blockchain read lock");
-        let blockchain = blockchain_lock_clone.read().await;
-        trace!("acquired the blockchain read lock");
+        {
+            trace!("waiting for the mempool write lock");
+            let mut mempool = mempool_lock_clone.write().await;
+            trace!("acquired the mempool write lock");
+            trace!("waiting for the blockchain read lock");
+            let blockchain = blockchain_lock_clone.read().await;
+            trace!("acquired the blockchain read lock");
 
-        latest_block_id = blockchain.get_latest_block_id();
+            latest_block_id = blockchain.get_latest_block_id();
 
-        {
-            if latest_block_id == 0 {
-                let mut vip_transaction = Transaction::generate_vip_transaction(
+            {
+                if latest_block_id == 0 {
+                    let mut vip_transaction = Transaction::generate_vip_transaction(
+                        wallet_lock_clone.clone(),
+                        publickey,
+                        50_000_000,
+                        20,
+                    )
+                    .await;
+                    vip_transaction.sign(privatekey);
+
+                    mempool.add_transaction(vip_transaction).await;
+                }
+            }
+
+            for _i in 0..txs_to_generate {
+                let mut transaction = Transaction::generate_transaction(
                     wallet_lock_clone.clone(),
                     publickey,
-                    50_000_000,
-                    20,
+                    5000,
+                    5000,
                 )
                 .await;
-                vip_transaction.sign(privatekey);
+                transaction.set_message(
+                    (0..bytes_per_tx)
+                        .into_iter()
+                        .map(|_| rand::random::<u8>())
+                        .collect(),
+                );
+                transaction.sign(privatekey);
+                // before validation!
+                transaction.generate_metadata(publickey);
 
-                mempool.add_transaction(vip_transaction).await;
+                transaction
+                    .add_hop_to_path(wallet_lock_clone.clone(), publickey)
+                    .await;
+                transaction
+                    .add_hop_to_path(wallet_lock_clone.clone(), publickey)
+                    .await;
+                {
+                    if mempool
+                        .add_transaction_if_validates(transaction.clone(), &blockchain)
+                        .await
+                    {
+                        accepted_transactions.push(transaction);
+                    }
+                }
             }
         }
+        trace!("generated transaction count: {:?}", txs_to_generate);
 
-        for _i in 0..txs_to_generate {
-            let mut transaction =
-                Transaction::generate_transaction(wallet_lock_clone.clone(), publickey, 5000, 5000)
-                    .await;
-            transaction.set_message(
-                (0..bytes_per_tx)
-                    .into_iter()
-                    .map(|_| rand::random::<u8>())
-                    .collect(),
-            );
-            transaction.sign(privatekey);
-            // before validation!
-            transaction.generate_metadata(publickey);
-
-            transaction
-                .add_hop_to_path(wallet_lock_clone.clone(), publickey)
+        // locks are released before propagating since adding the hops needs the wallet
+        for transaction in accepted_transactions {
+            network.mark_transaction_as_seen(transaction.get_hash_for_signature().unwrap());
+            network
+                .propagate_transaction(&transaction, None, wallet.clone())
                 .await;
-            transaction
-                .add_hop_to_path(wallet_lock_clone.clone(), publickey)
+        }
+    }
+    async fn is_lite_node(&self) -> bool {
+        trace!("waiting for the configs read lock");
+        let configs = self.configs.read().await;
+        trace!("acquired the configs read lock");
+        configs.is_lite_node()
+    }
+    /// Adds a block received from a peer to the blockchain. Peer is reported to the router if the block is invalid
+    async fn add_block_from_peer(&mut self, peer_index: u64, block: Block) {
+        let result;
+        {
+            trace!("waiting for the blockchain write lock");
+            let mut blockchain = self.blockchain.write().await;
+            trace!("acquired the blockchain write lock");
+            result = blockchain
+                .add_block(
+                    block,
+                    &mut self.network,
+                    &mut self.storage,
+                    self.sender_to_miner.clone(),
+                )
                 .await;
-            {
-                mempool
-                    .add_transaction_if_validates(transaction, &blockchain)
-                    .await;
-            }
         }
-        trace!("generated transaction count: {:?}", txs_to_generate);
+        if result == AddBlockResult::FailedValidation {
+            self.sender_to_router
+                .send(RoutingEvent::PeerMisbehaviour {
+                    peer_index,
+                    misbehaviour: PeerMisbehaviour::InvalidBlock,
+                })
+                .await
+                .unwrap();
+        }
+    }
+    /// Rebuilds the block from the mempool transactions and requests the missing transactions from the peer
+    async fn process_compact_block(&mut self, peer_index: u64, compact_block: CompactBlock) {
+        let block_hash = compact_block.header.get_hash();
+        if self.block_reconstructions.contains_key(&block_hash) {
+            trace!(
+                "compact block : {:?} is already being reconstructed",
+                hex::encode(block_hash)
+            );
+            return;
+        }
+        let reconstruction;
+        {
+            trace!("waiting for the mempool read lock");
+            let mempool = self.mempool.read().await;
+            trace!("acquired the mempool read lock");
+            reconstruction = BlockReconstruction::new(
+                compact_block,
+                &mempool.transactions,
+                peer_index,
+                self.time_keeper.get_timestamp(),
+            );
+        }
+        let missing_indices = reconstruction.get_missing_indices();
+        debug!(
+            "received compact block : {:?} from peer : {:?} with {:?} missing transactions",
+            hex::encode(block_hash),
+            peer_index,
+            missing_indices.len()
+        );
+        if missing_indices.is_empty() {
+            self.add_reconstructed_block(reconstruction).await;
+            return;
+        }
+        self.block_reconstructions
+            .insert(block_hash, reconstruction);
+        let result = self
+            .network
+            .request_compact_block_transactions(peer_index, block_hash, missing_indices)
+            .await;
+        if result.is_err() {
+            warn!(
+                "failed requesting transactions of compact block : {:?} from peer : {:?}",
+                hex::encode(block_hash),
+                peer_index
+            );
+            self.block_reconstructions.remove(&block_hash);
+        }
+    }
+    async fn process_compact_block_transactions(
+        &mut self,
+        peer_index: u64,
+        response: CompactBlockTransactions,
+    ) {
+        let block_hash = response.block_hash;
+        let reconstruction = self.block_reconstructions.get_mut(&block_hash);
+        if reconstruction.is_none() || reconstruction.as_ref().unwrap().peer_index != peer_index {
+            debug!(
+                "unexpected transactions for compact block : {:?} from peer : {:?}",
+                hex::encode(block_hash),
+                peer_index
+            );
+            return;
+        }
+        let reconstruction = reconstruction.unwrap();
+        if !reconstruction.add_missing_transactions(response.transactions) {
+            warn!(
+                "peer : {:?} sent wrong number of transactions for compact block : {:?}",
+                peer_index,
+                hex::encode(block_hash)
+            );
+            self.block_reconstructions.remove(&block_hash);
+            self.sender_to_router
+                .send(RoutingEvent::PeerMisbehaviour {
+                    peer_index,
+                    misbehaviour: PeerMisbehaviour::InvalidMessage,
+                })
+                .await
+                .unwrap();
+            return;
+        }
+        let reconstruction = self.block_reconstructions.remove(&block_hash).unwrap();
+        self.add_reconstructed_block(reconstruction).await;
+    }
+    /// Adds the reconstructed block to the blockchain. If the transactions don't match the merkle root (because of a
+    /// short id collision in the mempool), the full block is fetched instead
+    async fn add_reconstructed_block(&mut self, reconstruction: BlockReconstruction) {
+        let peer_index = reconstruction.peer_index;
+        let block_hash = reconstruction.get_block_hash();
+        let block = reconstruction.get_block();
+        if block.is_none() {
+            warn!(
+                "reconstructed block : {:?} doesn't match the merkle root. fetching the full block",
+                hex::encode(block_hash)
+            );
+            self.fetch_full_block(block_hash, peer_index).await;
+            return;
+        }
+        debug!(
+            "compact block : {:?} reconstructed",
+            hex::encode(block_hash)
+        );
+        self.add_block_from_peer(peer_index, block.unwrap()).await;
+    }
+    async fn fetch_full_block(&mut self, block_hash: SaitoHash, peer_index: u64) {
+        let result = self.network.fetch_block(block_hash, peer_index).await;
+        if result.is_err() {
+            warn!(
+                "failed fetching block : {:?} from peer : {:?}",
+                hex::encode(block_hash),
+                peer_index
+            );
+        }
+    }
+    /// Fetches the full blocks of the compact blocks which didn't receive their missing transactions in time
+    async fn check_block_reconstructions(&mut self, current_time: u64) -> bool {
+        let expired: Vec<(SaitoHash, u64)> = self
+            .block_reconstructions
+            .iter()
+            .filter(|(_, reconstruction)| {
+                current_time > reconstruction.start_time + COMPACT_BLOCK_TIMEOUT
+            })
+            .map(|(block_hash, reconstruction)| (*block_hash, reconstruction.peer_index))
+            .collect();
+        for (block_hash, peer_index) in expired.iter() {
+            debug!(
+                "compact block : {:?} from peer : {:?} timed out. fetching the full block",
+                hex::encode(block_hash),
+                peer_index
+            );
+            self.block_reconstructions.remove(block_hash);
+            self.fetch_full_block(*block_hash, *peer_index).await;
+        }
+        !expired.is_empty()
     }
 }
 
@@ -163,6 +442,7 @@ impl ProcessEvent<ConsensusEvent> for ConsensusEventProcessor {
                     self.mempool.clone(),
                     self.wallet.clone(),
                     self.blockchain.clone(),
+                    &mut self.network,
                 )
                 .await;
 
@@ -176,12 +456,15 @@ impl ProcessEvent<ConsensusEvent> for ConsensusEventProcessor {
         self.block_producing_timer = self.block_producing_timer + duration_value;
         // TODO : make timers configurable
         if self.block_producing_timer >= 1_000_000 {
-            trace!("waiting for the mempool read lock");
-            let mempool = self.mempool.read().await;
-            trace!("acquired the mempool read lock");
-            can_bundle = mempool
-                .can_bundle_block(self.blockchain.clone(), timestamp)
-                .await;
+            // lite nodes don't keep the full blocks, so they don't produce them either
+            if !self.is_lite_node().await {
+                trace!("waiting for the mempool read lock");
+                let mempool = self.mempool.read().await;
+                trace!("acquired the mempool read lock");
+                can_bundle = mempool
+                    .can_bundle_block(self.blockchain.clone(), timestamp)
+                    .await;
+            }
             self.block_producing_timer = 0;
             work_done = true;
         }
@@ -221,6 +504,10 @@ impl ProcessEvent<ConsensusEvent> for ConsensusEventProcessor {
             work_done = true;
         }
 
+        if self.check_block_reconstructions(timestamp).await {
+            work_done = true;
+        }
+
         if work_done {
             return Some(());
         }
@@ -239,19 +526,46 @@ impl ProcessEvent<ConsensusEvent> for ConsensusEventProcessor {
                 trace!("acquired the mempool write lock");
                 mempool.add_golden_ticket(golden_ticket).await;
             }
-            ConsensusEvent::BlockFetched {
-                peer_index: _,
-                buffer,
-            } => {
-                let mut blockchain = self.blockchain.write().await;
+            ConsensusEvent::BlockFetched { peer_index, buffer } => {
                 let block = Block::deserialize_for_net(&buffer);
-                blockchain
-                    .add_block(
-                        block,
-                        &mut self.network,
-                        &mut self.storage,
-                        self.sender_to_miner.clone(),
-                    )
+                if block.is_err() {
+                    warn!(
+                        "failed decoding block fetched from peer : {:?} : {:?}",
+                        peer_index,
+                        block.err().unwrap()
+                    );
+                    self.sender_to_router
+                        .send(RoutingEvent::PeerMisbehaviour {
+                            peer_index,
+                            misbehaviour: PeerMisbehaviour::InvalidBlock,
+                        })
+                        .await
+                        .unwrap();
+                    return None;
+                }
+                self.add_block_from_peer(peer_index, block.unwrap()).await;
+            }
+            ConsensusEvent::NewBlock { peer_index, block } => {
+                debug!("received new block : {:?}", hex::encode(block.get_hash()));
+                self.add_block_from_peer(peer_index, block).await;
+            }
+            ConsensusEvent::NewTransaction {
+                peer_index,
+                transaction,
+            } => {
+                self.process_new_transaction(peer_index, transaction).await;
+            }
+            ConsensusEvent::NewCompactBlock {
+                peer_index,
+                compact_block,
+            } => {
+                self.process_compact_block(peer_index, compact_block).await;
+            }
+            ConsensusEvent::CompactBlockTransactionsReceived {
+                peer_index,
+                response,
+            } => {
+                self.process_compact_block_transactions(peer_index, response)
                     .await;
             }
         }
@@ -260,6 +574,11 @@ impl ProcessEvent<ConsensusEvent> for ConsensusEventProcessor {
 
     async fn on_init(&mut self) {
         debug!("on_init");
+        if self.is_lite_node().await {
+            // lite chain is loaded by the routing event processor
+            debug!("lite node doesn't keep full blocks. skipping loading the blocks from disk");
+            return;
+        }
         self.storage
             .load_blocks_from_disk(
                 self.blockchain.clone(),
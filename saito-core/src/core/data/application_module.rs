@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+
+use async_trait::async_trait;
+use log::{debug, warn};
+use tokio::sync::mpsc::Sender;
+
+use crate::common::defs::SaitoPublicKey;
+use crate::core::data::msg::application_message::{ApplicationMessage, MAX_APPLICATION_ID_LENGTH};
+use crate::core::routing_event_processor::RoutingEvent;
+
+/// Module exchanging application messages with the same module in the other nodes. Messages are not written to the
+/// chain, so this is used for things like chat and game moves which only the connected peers need to see.
+#[async_trait]
+pub trait ApplicationModule {
+    /// Identifier of the messages handled by this module. should be unique among the registered modules
+    fn get_application_id(&self) -> String;
+    /// Called when the module is registered, with the sender to be used for sending messages to the peers
+    fn on_register(&mut self, sender: ApplicationMessageSender);
+    /// Handles a message received from a peer
+    ///
+    /// # Arguments
+    ///
+    /// * `sender_public_key`: public key of the peer which sent the message
+    /// * `data`: message content
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    async fn on_message(&mut self, sender_public_key: SaitoPublicKey, data: Vec<u8>);
+}
+
+/// Sends the messages of a module to the peers through the routing event processor
+#[derive(Debug, Clone)]
+pub struct ApplicationMessageSender {
+    application_id: String,
+    sender_to_router: Sender<RoutingEvent>,
+}
+
+impl ApplicationMessageSender {
+    pub fn new(application_id: String, sender_to_router: Sender<RoutingEvent>) -> Self {
+        ApplicationMessageSender {
+            application_id,
+            sender_to_router,
+        }
+    }
+    /// Sends the message to the connected peer with the given public key
+    pub async fn send_to_peer(
+        &self,
+        public_key: SaitoPublicKey,
+        data: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.send(Some(public_key), data).await
+    }
+    /// Sends the message to all the connected peers
+    pub async fn broadcast(&self, data: Vec<u8>) -> Result<(), Error> {
+        self.send(None, data).await
+    }
+    async fn send(&self, recipient: Option<SaitoPublicKey>, data: Vec<u8>) -> Result<(), Error> {
+        self.sender_to_router
+            .send(RoutingEvent::SendApplicationMessage {
+                recipient,
+                message: ApplicationMessage {
+                    application_id: self.application_id.clone(),
+                    data,
+                },
+            })
+            .await
+            .map_err(|_| Error::from(ErrorKind::BrokenPipe))
+    }
+}
+
+/// Registered application modules by their identifiers
+#[derive(Default)]
+pub struct ApplicationModules {
+    modules: HashMap<String, Box<dyn ApplicationModule + Send + Sync>>,
+}
+
+impl ApplicationModules {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    /// Registers the module to receive the messages with its application id
+    ///
+    /// # Arguments
+    ///
+    /// * `module`:
+    /// * `sender_to_router`: sender of the routing event processor, used by the module to send messages
+    ///
+    /// returns: Result<(), Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn register(
+        &mut self,
+        mut module: Box<dyn ApplicationModule + Send + Sync>,
+        sender_to_router: Sender<RoutingEvent>,
+    ) -> Result<(), Error> {
+        let application_id = module.get_application_id();
+        if application_id.is_empty() || application_id.len() > MAX_APPLICATION_ID_LENGTH {
+            warn!("invalid application id : {:?}", application_id);
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+        if self.modules.contains_key(&application_id) {
+            warn!("application : {:?} is already registered", application_id);
+            return Err(Error::from(ErrorKind::AlreadyExists));
+        }
+        debug!("registering application module : {:?}", application_id);
+        module.on_register(ApplicationMessageSender::new(
+            application_id.clone(),
+            sender_to_router,
+        ));
+        self.modules.insert(application_id, module);
+        Ok(())
+    }
+    /// Passes the message to the module registered for its application id. returns false if there is no such module
+    pub async fn handle_message(
+        &mut self,
+        sender_public_key: SaitoPublicKey,
+        message: ApplicationMessage,
+    ) -> bool {
+        let module = self.modules.get_mut(&message.application_id);
+        if module.is_none() {
+            debug!(
+                "no module registered for application : {:?}",
+                message.application_id
+            );
+            return false;
+        }
+        module
+            .unwrap()
+            .on_message(sender_public_key, message.data)
+            .await;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use tokio::sync::Mutex;
+
+    use crate::common::defs::SaitoPublicKey;
+    use crate::core::data::application_module::{
+        ApplicationMessageSender, ApplicationModule, ApplicationModules,
+    };
+    use crate::core::data::msg::application_message::ApplicationMessage;
+    use crate::core::routing_event_processor::RoutingEvent;
+
+    type ReceivedMessages = Arc<Mutex<Vec<(SaitoPublicKey, Vec<u8>)>>>;
+
+    struct EchoModule {
+        sender: Option<ApplicationMessageSender>,
+        received: ReceivedMessages,
+    }
+
+    #[async_trait]
+    impl ApplicationModule for EchoModule {
+        fn get_application_id(&self) -> String {
+            "echo".to_string()
+        }
+        fn on_register(&mut self, sender: ApplicationMessageSender) {
+            self.sender = Some(sender);
+        }
+        async fn on_message(&mut self, sender_public_key: SaitoPublicKey, data: Vec<u8>) {
+            self.received
+                .lock()
+                .await
+                .push((sender_public_key, data.clone()));
+            self.sender
+                .as_ref()
+                .unwrap()
+                .send_to_peer(sender_public_key, data)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_application_modules() {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(10);
+        let received = Arc::new(Mutex::new(vec![]));
+        let mut modules = ApplicationModules::new();
+        modules
+            .register(
+                Box::new(EchoModule {
+                    sender: None,
+                    received: received.clone(),
+                }),
+                sender.clone(),
+            )
+            .unwrap();
+        assert!(modules
+            .register(
+                Box::new(EchoModule {
+                    sender: None,
+                    received: received.clone(),
+                }),
+                sender.clone(),
+            )
+            .is_err());
+
+        assert!(
+            modules
+                .handle_message(
+                    [1; 33],
+                    ApplicationMessage {
+                        application_id: "echo".to_string(),
+                        data: vec![1, 2],
+                    },
+                )
+                .await
+        );
+        assert!(
+            !modules
+                .handle_message(
+                    [1; 33],
+                    ApplicationMessage {
+                        application_id: "other".to_string(),
+                        data: vec![3],
+                    },
+                )
+                .await
+        );
+        assert_eq!(*received.lock().await, vec![([1; 33], vec![1, 2])]);
+
+        match receiver.recv().await.unwrap() {
+            RoutingEvent::SendApplicationMessage { recipient, message } => {
+                assert_eq!(recipient, Some([1; 33]));
+                assert_eq!(message.application_id, "echo");
+                assert_eq!(message.data, vec![1, 2]);
+            }
+            _ => panic!("unexpected routing event"),
+        }
+
+        ApplicationMessageSender::new("echo".to_string(), sender)
+            .broadcast(vec![4])
+            .await
+            .unwrap();
+        match receiver.recv().await.unwrap() {
+            RoutingEvent::SendApplicationMessage { recipient, message } => {
+                assert!(recipient.is_none());
+                assert_eq!(message.data, vec![4]);
+            }
+            _ => panic!("unexpected routing event"),
+        }
+    }
+}
@@ -15,15 +15,17 @@ use crate::core::data::blockchain::{Blockchain, GENESIS_PERIOD, MAX_STAKER_RECUR
 use crate::core::data::burnfee::BurnFee;
 use crate::core::data::crypto::{hash, sign, verify};
 use crate::core::data::golden_ticket::GoldenTicket;
-use crate::core::data::hop::HOP_SIZE;
-use crate::core::data::merkle::MerkleTreeLayer;
-use crate::core::data::slip::{Slip, SlipType, SLIP_SIZE};
+use crate::core::data::merkle::{generate_merkle_root, MerkleTree};
+use crate::core::data::serialize::{check_length, check_min_length, DeserializationError};
+use crate::core::data::slip::{Slip, SlipType};
 use crate::core::data::staking::Staking;
 use crate::core::data::storage::Storage;
 use crate::core::data::transaction::{Transaction, TransactionType, TRANSACTION_SIZE};
 use crate::core::data::wallet::Wallet;
 
 pub const BLOCK_HEADER_SIZE: usize = 213;
+// maximum size of a serialized block. larger buffers are rejected without reading them
+pub const MAX_BLOCK_SIZE: usize = 64 * 1024 * 1024;
 
 //
 // object used when generating and validation transactions, containing the
@@ -472,10 +474,16 @@ impl Block {
         // load the block if it exists on disk.
         //
         if block_type == BlockType::Full {
-            let mut new_block = storage
-                .load_block_from_disk(storage.generate_block_filename(&self))
-                .await
-                .unwrap();
+            let result = storage.load_block_from_disk(&self.hash).await;
+            if result.is_err() {
+                error!(
+                    "failed loading block : {:?} from disk : {:?}",
+                    hex::encode(self.hash),
+                    result.err().unwrap()
+                );
+                return false;
+            }
+            let mut new_block = result.unwrap();
             let hash_for_signature = hash(&new_block.serialize_for_signature());
             new_block.set_pre_hash(hash_for_signature);
             let hash_for_hash = hash(&new_block.serialize_for_hash());
@@ -636,8 +644,14 @@ impl Block {
     /// [burnfee - 8 bytes - u64]
     /// [difficulty - 8 bytes - u64]
     /// [transaction][transaction][transaction]...
-    pub fn deserialize_for_net(bytes: &Vec<u8>) -> Block {
-        // TODO : return Option<Block> to support invalid buffers
+    pub fn deserialize_for_net(bytes: &Vec<u8>) -> Result<Block, DeserializationError> {
+        if bytes.len() > MAX_BLOCK_SIZE {
+            return Err(DeserializationError::SizeLimitExceeded {
+                size: bytes.len() as u64,
+                limit: MAX_BLOCK_SIZE,
+            });
+        }
+        check_min_length(bytes, BLOCK_HEADER_SIZE)?;
         let transactions_len: u32 = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
         let id: u64 = u64::from_be_bytes(bytes[4..12].try_into().unwrap());
         let timestamp: u64 = u64::from_be_bytes(bytes[12..20].try_into().unwrap());
@@ -651,40 +665,27 @@ impl Block {
 
         let burnfee: u64 = u64::from_be_bytes(bytes[197..205].try_into().unwrap());
         let difficulty: u64 = u64::from_be_bytes(bytes[205..213].try_into().unwrap());
+
+        // checked before allocating anything for the transactions, since the count comes from the buffer
+        let min_size = BLOCK_HEADER_SIZE as u64 + transactions_len as u64 * TRANSACTION_SIZE as u64;
+        if min_size > bytes.len() as u64 {
+            return Err(DeserializationError::BufferTooShort {
+                expected: min_size as usize,
+                actual: bytes.len(),
+            });
+        }
         let mut transactions = vec![];
         let mut start_of_transaction_data = BLOCK_HEADER_SIZE;
         for _n in 0..transactions_len {
-            let inputs_len: u32 = u32::from_be_bytes(
-                bytes[start_of_transaction_data..start_of_transaction_data + 4]
-                    .try_into()
-                    .unwrap(),
-            );
-            let outputs_len: u32 = u32::from_be_bytes(
-                bytes[start_of_transaction_data + 4..start_of_transaction_data + 8]
-                    .try_into()
-                    .unwrap(),
-            );
-            let message_len: usize = u32::from_be_bytes(
-                bytes[start_of_transaction_data + 8..start_of_transaction_data + 12]
-                    .try_into()
-                    .unwrap(),
-            ) as usize;
-            let path_len: usize = u32::from_be_bytes(
-                bytes[start_of_transaction_data + 12..start_of_transaction_data + 16]
-                    .try_into()
-                    .unwrap(),
-            ) as usize;
             let end_of_transaction_data = start_of_transaction_data
-                + TRANSACTION_SIZE
-                + ((inputs_len + outputs_len) as usize * SLIP_SIZE)
-                + message_len
-                + path_len as usize * HOP_SIZE;
+                + Transaction::get_serialized_size(&bytes[start_of_transaction_data..])?;
             let transaction = Transaction::deserialize_from_net(
                 bytes[start_of_transaction_data..end_of_transaction_data].to_vec(),
-            );
+            )?;
             transactions.push(transaction);
             start_of_transaction_data = end_of_transaction_data;
         }
+        check_length(bytes, start_of_transaction_data)?;
 
         let mut block = Block::new();
         block.set_id(id);
@@ -702,84 +703,46 @@ impl Block {
             block.set_block_type(BlockType::Header);
         }
         block.generate_hashes();
-        block
+        Ok(block)
+    }
+    /// Returns a copy of the block without the transactions. Used to share the headers without cloning the whole block
+    pub fn get_header(&self) -> Block {
+        let mut header = Block::new();
+        header.set_id(self.id);
+        header.set_timestamp(self.timestamp);
+        header.set_previous_block_hash(self.previous_block_hash);
+        header.set_creator(self.creator);
+        header.set_merkle_root(self.merkle_root);
+        header.set_signature(self.signature);
+        header.set_treasury(self.treasury);
+        header.set_staking_treasury(self.staking_treasury);
+        header.set_burnfee(self.burnfee);
+        header.set_difficulty(self.difficulty);
+        header.set_block_type(BlockType::Header);
+        header.set_pre_hash(self.pre_hash);
+        header.set_hash(self.hash);
+        header
     }
 
-    //
-    // TODO - this logic should probably be in the merkle-root class
-    //
     pub fn generate_merkle_root(&self) -> SaitoHash {
-        if self.transactions.is_empty() {
-            return [0; 32];
-        }
-
         let tx_sig_hashes: Vec<SaitoHash> = self
             .transactions
             .iter()
             .map(|tx| tx.get_hash_for_signature().unwrap())
             .collect();
 
-        let mut mrv: Vec<MerkleTreeLayer> = vec![];
-
-        //
-        // or let's try another approach
-        //
-        let tsh_len = tx_sig_hashes.len();
-        let mut leaf_depth = 0;
-
-        for i in 0..tsh_len {
-            if (i + 1) < tsh_len {
-                mrv.push(MerkleTreeLayer::new(
-                    tx_sig_hashes[i],
-                    tx_sig_hashes[i + 1],
-                    leaf_depth,
-                ));
-            } else {
-                mrv.push(MerkleTreeLayer::new(tx_sig_hashes[i], [0; 32], leaf_depth));
-            }
-        }
-
-        let mut start_point = 0;
-        let mut stop_point = mrv.len();
-        let mut keep_looping = true;
-
-        while keep_looping {
-            // processing new layer
-            leaf_depth += 1;
-
-            // hash the parent in parallel
-            mrv[start_point..stop_point]
-                .par_iter_mut()
-                .all(|leaf| leaf.hash());
-
-            let start_point_old = start_point;
-            start_point = mrv.len();
-
-            for i in (start_point_old..stop_point).step_by(2) {
-                if (i + 1) < stop_point {
-                    mrv.push(MerkleTreeLayer::new(
-                        mrv[i].get_hash(),
-                        mrv[i + 1].get_hash(),
-                        leaf_depth,
-                    ));
-                } else {
-                    mrv.push(MerkleTreeLayer::new(mrv[i].get_hash(), [0; 32], leaf_depth));
-                }
-            }
+        generate_merkle_root(&tx_sig_hashes)
+    }
 
-            stop_point = mrv.len();
-            if stop_point > 0 {
-                keep_looping = start_point < stop_point - 1;
-            } else {
-                keep_looping = false;
-            }
-        }
+    /// Generates the merkle tree of the transactions, to create inclusion proofs for them
+    pub fn generate_merkle_tree(&self) -> MerkleTree {
+        let tx_sig_hashes: Vec<SaitoHash> = self
+            .transactions
+            .iter()
+            .map(|tx| tx.get_hash_for_signature().unwrap())
+            .collect();
 
-        //
-        // hash the final leaf
-        //
-        mrv[start_point].hash();
-        mrv[start_point].get_hash()
+        MerkleTree::generate(&tx_sig_hashes)
     }
 
     //
@@ -1170,8 +1133,17 @@ impl Block {
         //
         if winning_tx.get_transaction_type() == TransactionType::ATR {
             let tmptx = winning_tx.get_message().to_vec();
-            winning_tx_placeholder = Transaction::deserialize_from_net(tmptx);
-            winning_tx = &winning_tx_placeholder;
+            let result = Transaction::deserialize_from_net(tmptx);
+            if let Ok(transaction) = result {
+                winning_tx_placeholder = transaction;
+                winning_tx = &winning_tx_placeholder;
+            } else {
+                // ATR transaction itself is used as the winner if the inner transaction can't be read
+                error!(
+                    "failed decoding the inner transaction of ATR transaction : {:?}",
+                    result.err().unwrap()
+                );
+            }
         }
 
         //
@@ -1326,6 +1298,79 @@ impl Block {
         true
     }
 
+    /// Validates the parts of the block which can be checked without the transactions. Used in headers-first
+    /// synchronisation to check the header chain before downloading the block bodies.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous_block`: header of the previous block in the chain. if None, the header must have the burn fee and
+    ///   the difficulty of a block created without a previous block
+    ///
+    /// returns: bool
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn validate_header(&self, previous_block: Option<&Block>) -> bool {
+        if let Some(previous_block) = previous_block {
+            if self.get_previous_block_hash() != previous_block.get_hash()
+                || self.get_id() != previous_block.get_id() + 1
+            {
+                error!(
+                    "header : {:?} does not link to the previous block : {:?}",
+                    hex::encode(self.get_hash()),
+                    hex::encode(previous_block.get_hash())
+                );
+                return false;
+            }
+        }
+        if !verify(
+            &self.get_pre_hash(),
+            self.get_signature(),
+            self.get_creator(),
+        ) {
+            error!(
+                "header : {:?} is not signed by the creator",
+                hex::encode(self.get_hash())
+            );
+            return false;
+        }
+        // blocks without a previous block are generated using zero as the previous burn fee, timestamp and difficulty
+        let previous_burnfee = previous_block.map_or(0, |block| block.get_burnfee());
+        let previous_timestamp = previous_block.map_or(0, |block| block.get_timestamp());
+        let previous_difficulty = previous_block.map_or(0, |block| block.get_difficulty());
+        let burnfee = BurnFee::return_burnfee_for_block_produced_at_current_timestamp_in_nolan(
+            previous_burnfee,
+            self.get_timestamp(),
+            previous_timestamp,
+        );
+        if burnfee != self.get_burnfee() {
+            error!(
+                "header : {:?} burn fee does not validate, expected : {:?} found : {:?}",
+                hex::encode(self.get_hash()),
+                burnfee,
+                self.get_burnfee()
+            );
+            return false;
+        }
+        // expected difficulty depends on the golden tickets in the block bodies, so the header can only be checked
+        // for moving at most one step from the previous difficulty. without a previous block it's not adjusted
+        if (previous_block.is_some() && self.get_difficulty().abs_diff(previous_difficulty) > 1)
+            || (previous_block.is_none() && self.get_difficulty() != previous_difficulty)
+        {
+            error!(
+                "header : {:?} difficulty : {:?} does not follow the previous difficulty : {:?}",
+                hex::encode(self.get_hash()),
+                self.get_difficulty(),
+                previous_difficulty
+            );
+            return false;
+        }
+        true
+    }
+
     pub async fn validate(
         &self,
         blockchain: &Blockchain,
@@ -1862,11 +1907,12 @@ mod tests {
 
     use ahash::AHashMap;
 
-    use crate::core::data::block::{Block, BlockType};
+    use crate::core::data::block::{Block, BlockType, BLOCK_HEADER_SIZE, MAX_BLOCK_SIZE};
     use crate::core::data::crypto::verify;
+    use crate::core::data::serialize::DeserializationError;
     use crate::core::data::slip::Slip;
     use crate::core::data::staking::Staking;
-    use crate::core::data::transaction::{Transaction, TransactionType};
+    use crate::core::data::transaction::{Transaction, TransactionType, TRANSACTION_SIZE};
     use crate::core::data::wallet::Wallet;
 
     #[test]
@@ -2001,10 +2047,11 @@ mod tests {
         block.set_transactions(&mut vec![mock_tx, mock_tx2]);
 
         let serialized_block = block.serialize_for_net(BlockType::Full);
-        let deserialized_block = Block::deserialize_for_net(&serialized_block);
+        let deserialized_block = Block::deserialize_for_net(&serialized_block).unwrap();
 
         let serialized_block_header = block.serialize_for_net(BlockType::Header);
-        let deserialized_block_header = Block::deserialize_for_net(&serialized_block_header);
+        let deserialized_block_header =
+            Block::deserialize_for_net(&serialized_block_header).unwrap();
 
         assert_eq!(
             block.serialize_for_net(BlockType::Full),
@@ -2037,6 +2084,60 @@ mod tests {
         assert_eq!(deserialized_block_header.get_difficulty(), 3);
     }
 
+    #[test]
+    fn block_deserialize_invalid_buffer_test() {
+        let mut transaction = Transaction::new();
+        transaction.set_message(vec![1, 2, 3]);
+        let mut block = Block::new();
+        block.set_id(1);
+        block.set_transactions(&mut vec![transaction]);
+        let buffer = block.serialize_for_net(BlockType::Full);
+
+        // truncated in the header and in the transactions
+        assert!(matches!(
+            Block::deserialize_for_net(&buffer[0..100].to_vec()),
+            Err(DeserializationError::BufferTooShort { .. })
+        ));
+        assert!(matches!(
+            Block::deserialize_for_net(&buffer[0..buffer.len() - 1].to_vec()),
+            Err(DeserializationError::BufferTooShort { .. })
+        ));
+
+        let mut extended = buffer.clone();
+        extended.push(0);
+        assert!(matches!(
+            Block::deserialize_for_net(&extended),
+            Err(DeserializationError::TrailingBytes { .. })
+        ));
+
+        // transaction count larger than the buffer can hold
+        let mut modified = buffer.clone();
+        modified[0..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            Block::deserialize_for_net(&modified),
+            Err(DeserializationError::BufferTooShort { .. })
+        ));
+
+        // message length larger than the buffer
+        let mut modified = buffer.clone();
+        modified[BLOCK_HEADER_SIZE + 8..BLOCK_HEADER_SIZE + 12]
+            .copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(Block::deserialize_for_net(&modified).is_err());
+
+        // invalid transaction type
+        let mut modified = buffer;
+        modified[BLOCK_HEADER_SIZE + TRANSACTION_SIZE - 1] = 200;
+        assert_eq!(
+            Block::deserialize_for_net(&modified).err(),
+            Some(DeserializationError::InvalidTransactionType(200))
+        );
+
+        assert!(matches!(
+            Block::deserialize_for_net(&vec![0; MAX_BLOCK_SIZE + 1]),
+            Err(DeserializationError::SizeLimitExceeded { .. })
+        ));
+    }
+
     #[test]
     fn block_sign_and_verify_test() {
         let wallet = Wallet::new();
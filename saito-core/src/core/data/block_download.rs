@@ -0,0 +1,380 @@
+use std::collections::VecDeque;
+
+use ahash::AHashMap;
+use log::{debug, warn};
+
+use crate::common::defs::SaitoHash;
+
+// maximum number of blocks fetched from a single peer at the same time
+pub const MAX_BLOCK_FETCHES_PER_PEER: usize = 4;
+// block is requested from another peer if not received within this many milliseconds
+pub const BLOCK_FETCH_TIMEOUT: u64 = 20_000;
+// block download is abandoned after this many failed attempts
+pub const MAX_BLOCK_FETCH_ATTEMPTS: u32 = 5;
+// peers with this many timeouts (without successful fetches in between) are only used if there's no other choice
+pub const SLOW_PEER_TIMEOUT_LIMIT: u32 = 3;
+
+/// Block which is waiting to be fetched or being fetched
+#[derive(Debug, Clone)]
+struct BlockDownload {
+    // peers which announced the block, in the announcement order
+    peers: Vec<u64>,
+    // peers which failed or timed out fetching this block
+    failed_peers: Vec<u64>,
+    // peer the block is currently requested from, with the request time
+    current_request: Option<(u64, u64)>,
+    attempts: u32,
+}
+
+/// Fetch statistics of a peer, used to prefer the faster peers
+#[derive(Debug, Clone, Default)]
+pub struct PeerDownloadStats {
+    pub in_flight: usize,
+    pub fetched: u64,
+    // timeouts since the last successful fetch
+    pub timeouts: u32,
+    // moving average of the fetch times in milliseconds
+    pub average_fetch_time: u64,
+}
+
+impl PeerDownloadStats {
+    pub fn is_slow(&self) -> bool {
+        self.timeouts >= SLOW_PEER_TIMEOUT_LIMIT
+    }
+}
+
+/// Keeps track of the block fetches. Blocks are requested in the announcement order from the peers which announced
+/// them, with a limited number of requests in flight for each peer. Failed and timed out requests are retried from
+/// the other peers.
+///
+/// Scheduler only decides which blocks to fetch from which peers. Sending the requests is done by the network.
+#[derive(Debug, Clone, Default)]
+pub struct BlockDownloadScheduler {
+    downloads: AHashMap<SaitoHash, BlockDownload>,
+    // blocks in the order they were first announced
+    queue: VecDeque<SaitoHash>,
+    peer_stats: AHashMap<u64, PeerDownloadStats>,
+    // blocks lost from the storage, which are not announced by any peer. fetched from the peers as they connect
+    lost_blocks: Vec<SaitoHash>,
+}
+
+impl BlockDownloadScheduler {
+    pub fn new() -> BlockDownloadScheduler {
+        BlockDownloadScheduler {
+            downloads: Default::default(),
+            queue: Default::default(),
+            peer_stats: Default::default(),
+            lost_blocks: vec![],
+        }
+    }
+    /// Adds the peer as a source for the block. returns true if the block wasn't being downloaded already
+    pub fn add_block_announcement(&mut self, block_hash: SaitoHash, peer_index: u64) -> bool {
+        if let Some(download) = self.downloads.get_mut(&block_hash) {
+            if !download.peers.contains(&peer_index) {
+                download.peers.push(peer_index);
+            }
+            return false;
+        }
+        self.downloads.insert(
+            block_hash,
+            BlockDownload {
+                peers: vec![peer_index],
+                failed_peers: vec![],
+                current_request: None,
+                attempts: 0,
+            },
+        );
+        self.queue.push_back(block_hash);
+        true
+    }
+    /// Adds a block to be fetched from all the peers given with add_peer_for_lost_blocks, until it's fetched
+    pub fn add_lost_block(&mut self, block_hash: SaitoHash) {
+        if !self.lost_blocks.contains(&block_hash) {
+            self.lost_blocks.push(block_hash);
+        }
+    }
+    /// Adds the peer as a source for the lost blocks
+    pub fn add_peer_for_lost_blocks(&mut self, peer_index: u64) {
+        for block_hash in self.lost_blocks.clone() {
+            self.add_block_announcement(block_hash, peer_index);
+        }
+    }
+    pub fn is_downloading(&self, block_hash: &SaitoHash) -> bool {
+        self.downloads.contains_key(block_hash)
+    }
+    pub fn is_requested_from(&self, block_hash: &SaitoHash, peer_index: u64) -> bool {
+        let request = self
+            .downloads
+            .get(block_hash)
+            .and_then(|download| download.current_request);
+        matches!(request, Some((requested_peer, _)) if requested_peer == peer_index)
+    }
+    pub fn get_peer_stats(&self, peer_index: u64) -> Option<&PeerDownloadStats> {
+        self.peer_stats.get(&peer_index)
+    }
+    /// Assigns the waiting blocks to the available peers
+    ///
+    /// # Arguments
+    ///
+    /// * `current_time`: timestamp in milliseconds
+    ///
+    /// returns: Vec<(SaitoHash, u64)> : block hashes with the peers to request them from
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn schedule(&mut self, current_time: u64) -> Vec<(SaitoHash, u64)> {
+        let mut requests = vec![];
+        for block_hash in self.queue.iter() {
+            let download = self.downloads.get_mut(block_hash).unwrap();
+            if download.current_request.is_some() {
+                continue;
+            }
+            // prefer the peers which are not slow, then the ones with less requests in flight and faster fetches
+            let peer_index = download
+                .peers
+                .iter()
+                .filter(|peer_index| !download.failed_peers.contains(peer_index))
+                .filter(|peer_index| {
+                    self.peer_stats
+                        .get(peer_index)
+                        .map_or(0, |stats| stats.in_flight)
+                        < MAX_BLOCK_FETCHES_PER_PEER
+                })
+                .min_by_key(|peer_index| {
+                    self.peer_stats
+                        .get(peer_index)
+                        .map_or((false, 0, 0), |stats| {
+                            (stats.is_slow(), stats.in_flight, stats.average_fetch_time)
+                        })
+                })
+                .cloned();
+            let peer_index = match peer_index {
+                Some(peer_index) => peer_index,
+                None => continue,
+            };
+            download.current_request = Some((peer_index, current_time));
+            download.attempts += 1;
+            self.peer_stats.entry(peer_index).or_default().in_flight += 1;
+            requests.push((*block_hash, peer_index));
+        }
+        requests
+    }
+    /// Completes the download of the block and updates the stats of the peer which sent it
+    pub fn on_block_fetched(&mut self, block_hash: &SaitoHash, peer_index: u64, current_time: u64) {
+        self.lost_blocks.retain(|hash| hash != block_hash);
+        let download = match self.downloads.remove(block_hash) {
+            Some(download) => download,
+            None => return,
+        };
+        self.queue.retain(|hash| hash != block_hash);
+        if let Some((requested_peer, request_time)) = download.current_request {
+            self.release_request(requested_peer);
+            if requested_peer == peer_index {
+                let stats = self.peer_stats.entry(peer_index).or_default();
+                let fetch_time = current_time.saturating_sub(request_time);
+                stats.average_fetch_time = if stats.fetched == 0 {
+                    fetch_time
+                } else {
+                    (stats.average_fetch_time * 3 + fetch_time) / 4
+                };
+                stats.fetched += 1;
+                stats.timeouts = 0;
+            }
+        }
+    }
+    /// Marks the peer as failed for the block, so it's requested from another peer.
+    /// returns false if the download is abandoned since there are no more peers to try
+    pub fn on_block_fetch_failed(&mut self, block_hash: &SaitoHash, peer_index: u64) -> bool {
+        let download = match self.downloads.get_mut(block_hash) {
+            Some(download) => download,
+            None => return false,
+        };
+        if !download.failed_peers.contains(&peer_index) {
+            download.failed_peers.push(peer_index);
+        }
+        if matches!(download.current_request, Some((requested_peer, _)) if requested_peer == peer_index)
+        {
+            download.current_request = None;
+            self.release_request(peer_index);
+        }
+        self.remove_if_exhausted(block_hash)
+    }
+    /// Requests which are not completed in time are marked as failed, to be retried from the other peers.
+    /// returns the hashes of the blocks which are abandoned
+    pub fn check_timeouts(&mut self, current_time: u64) -> Vec<SaitoHash> {
+        let timed_out: Vec<(SaitoHash, u64)> = self
+            .downloads
+            .iter()
+            .filter_map(|(block_hash, download)| {
+                download
+                    .current_request
+                    .filter(|(_, request_time)| current_time > request_time + BLOCK_FETCH_TIMEOUT)
+                    .map(|(peer_index, _)| (*block_hash, peer_index))
+            })
+            .collect();
+        let mut abandoned = vec![];
+        for (block_hash, peer_index) in timed_out {
+            debug!(
+                "fetching block : {:?} from peer : {:?} timed out",
+                hex::encode(block_hash),
+                peer_index
+            );
+            let stats = self.peer_stats.entry(peer_index).or_default();
+            stats.timeouts += 1;
+            if stats.is_slow() {
+                debug!("peer : {:?} is slow to send blocks", peer_index);
+            }
+            if !self.on_block_fetch_failed(&block_hash, peer_index) {
+                abandoned.push(block_hash);
+            }
+        }
+        abandoned
+    }
+    /// Removes the peer from the sources of all the blocks. Blocks requested from the peer are retried elsewhere
+    pub fn remove_peer(&mut self, peer_index: u64) {
+        self.peer_stats.remove(&peer_index);
+        let block_hashes: Vec<SaitoHash> = self.downloads.keys().cloned().collect();
+        for block_hash in block_hashes {
+            let download = self.downloads.get_mut(&block_hash).unwrap();
+            download.peers.retain(|index| *index != peer_index);
+            download.failed_peers.retain(|index| *index != peer_index);
+            if matches!(download.current_request, Some((requested_peer, _)) if requested_peer == peer_index)
+            {
+                download.current_request = None;
+            }
+            self.remove_if_exhausted(&block_hash);
+        }
+    }
+    fn release_request(&mut self, peer_index: u64) {
+        if let Some(stats) = self.peer_stats.get_mut(&peer_index) {
+            stats.in_flight = stats.in_flight.saturating_sub(1);
+        }
+    }
+    /// returns false if the download is removed
+    fn remove_if_exhausted(&mut self, block_hash: &SaitoHash) -> bool {
+        let download = self.downloads.get(block_hash).unwrap();
+        if download.current_request.is_some() {
+            return true;
+        }
+        let has_peers = download
+            .peers
+            .iter()
+            .any(|peer_index| !download.failed_peers.contains(peer_index));
+        if has_peers && download.attempts < MAX_BLOCK_FETCH_ATTEMPTS {
+            return true;
+        }
+        warn!(
+            "abandoning download of block : {:?} after {:?} attempts",
+            hex::encode(block_hash),
+            download.attempts
+        );
+        self.downloads.remove(block_hash);
+        self.queue.retain(|hash| hash != block_hash);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::block_download::{
+        BlockDownloadScheduler, BLOCK_FETCH_TIMEOUT, MAX_BLOCK_FETCHES_PER_PEER,
+        SLOW_PEER_TIMEOUT_LIMIT,
+    };
+
+    #[test]
+    fn test_schedule_limits_requests_per_peer() {
+        let mut scheduler = BlockDownloadScheduler::new();
+        for i in 0..(MAX_BLOCK_FETCHES_PER_PEER + 2) {
+            assert!(scheduler.add_block_announcement([i as u8; 32], 1));
+        }
+        let last_block = [(MAX_BLOCK_FETCHES_PER_PEER + 1) as u8; 32];
+        assert!(!scheduler.add_block_announcement(last_block, 2));
+
+        let requests = scheduler.schedule(0);
+        assert_eq!(requests.len(), MAX_BLOCK_FETCHES_PER_PEER + 1);
+        assert!(requests[0..MAX_BLOCK_FETCHES_PER_PEER]
+            .iter()
+            .all(|(_, peer_index)| *peer_index == 1));
+        // last block is also announced by the second peer, so it doesn't wait for the first peer
+        assert_eq!(requests[MAX_BLOCK_FETCHES_PER_PEER], (last_block, 2));
+        assert!(scheduler.schedule(0).is_empty());
+
+        // completing a request frees a slot for the waiting block
+        scheduler.on_block_fetched(&[1; 32], 1, 100);
+        assert!(!scheduler.is_downloading(&[1; 32]));
+        let requests = scheduler.schedule(100);
+        assert_eq!(requests, vec![([MAX_BLOCK_FETCHES_PER_PEER as u8; 32], 1)]);
+        assert_eq!(scheduler.get_peer_stats(1).unwrap().average_fetch_time, 100);
+    }
+
+    #[test]
+    fn test_retry_from_other_peer() {
+        let mut scheduler = BlockDownloadScheduler::new();
+        scheduler.add_block_announcement([1; 32], 1);
+        scheduler.add_block_announcement([1; 32], 2);
+        assert_eq!(scheduler.schedule(0), vec![([1; 32], 1)]);
+        assert!(scheduler.is_requested_from(&[1; 32], 1));
+        assert!(!scheduler.is_requested_from(&[1; 32], 2));
+
+        // timed out request is retried from the other peer
+        assert!(scheduler.check_timeouts(BLOCK_FETCH_TIMEOUT).is_empty());
+        assert!(scheduler.check_timeouts(BLOCK_FETCH_TIMEOUT + 1).is_empty());
+        assert_eq!(scheduler.get_peer_stats(1).unwrap().timeouts, 1);
+        assert!(!scheduler.is_requested_from(&[1; 32], 1));
+        assert_eq!(
+            scheduler.schedule(BLOCK_FETCH_TIMEOUT + 1),
+            vec![([1; 32], 2)]
+        );
+        assert!(scheduler.is_requested_from(&[1; 32], 2));
+
+        // no more peers to try after the second one fails
+        assert!(!scheduler.on_block_fetch_failed(&[1; 32], 2));
+        assert!(!scheduler.is_downloading(&[1; 32]));
+    }
+
+    #[test]
+    fn test_slow_peers_are_avoided() {
+        let mut scheduler = BlockDownloadScheduler::new();
+        let mut current_time = 0;
+        for i in 0..SLOW_PEER_TIMEOUT_LIMIT {
+            scheduler.add_block_announcement([i as u8; 32], 1);
+            scheduler.add_block_announcement([i as u8; 32], 2);
+            assert_eq!(scheduler.schedule(current_time), vec![([i as u8; 32], 1)]);
+            current_time += BLOCK_FETCH_TIMEOUT + 1;
+            scheduler.check_timeouts(current_time);
+            scheduler.on_block_fetched(&[i as u8; 32], 2, current_time);
+        }
+        assert!(scheduler.get_peer_stats(1).unwrap().is_slow());
+
+        scheduler.add_block_announcement([10; 32], 1);
+        scheduler.add_block_announcement([10; 32], 2);
+        assert_eq!(scheduler.schedule(current_time), vec![([10; 32], 2)]);
+
+        // disconnected peer's requests are moved to the remaining peers
+        scheduler.remove_peer(2);
+        assert_eq!(scheduler.schedule(current_time), vec![([10; 32], 1)]);
+        scheduler.remove_peer(1);
+        assert!(!scheduler.is_downloading(&[10; 32]));
+    }
+
+    #[test]
+    fn test_lost_blocks_are_fetched_from_new_peers() {
+        let mut scheduler = BlockDownloadScheduler::new();
+        scheduler.add_lost_block([1; 32]);
+        assert!(scheduler.schedule(0).is_empty());
+
+        scheduler.add_peer_for_lost_blocks(1);
+        assert_eq!(scheduler.schedule(0), vec![([1; 32], 1)]);
+        // block is fetched again from the next peer if the first one can't send it
+        assert!(!scheduler.on_block_fetch_failed(&[1; 32], 1));
+        scheduler.add_peer_for_lost_blocks(2);
+        assert_eq!(scheduler.schedule(0), vec![([1; 32], 2)]);
+
+        scheduler.on_block_fetched(&[1; 32], 2, 100);
+        scheduler.add_peer_for_lost_blocks(3);
+        assert!(!scheduler.is_downloading(&[1; 32]));
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::{Error, ErrorKind};
+
+use crate::common::defs::SaitoHash;
+use crate::core::data::serialize::{check_length, Serialize};
+
+pub const BLOCK_INDEX_ENTRY_SIZE: usize = 81;
+
+/// Persisted details of a stored block, used to rebuild the blockchain indices at the start without replaying the
+/// blocks. timestamp and hash give the file name of the block in the file layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockIndexEntry {
+    pub block_hash: SaitoHash,
+    pub block_id: u64,
+    pub previous_block_hash: SaitoHash,
+    pub timestamp: u64,
+    pub in_longest_chain: bool,
+}
+
+/// [block hash - 32 bytes]
+/// [block id - 8 bytes - u64]
+/// [previous block hash - 32 bytes]
+/// [timestamp - 8 bytes - u64]
+/// [in longest chain - 1 byte]
+impl Serialize<Self> for BlockIndexEntry {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.block_hash.to_vec();
+        buffer.extend(self.block_id.to_be_bytes());
+        buffer.extend(self.previous_block_hash);
+        buffer.extend(self.timestamp.to_be_bytes());
+        buffer.push(self.in_longest_chain as u8);
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        check_length(buffer, BLOCK_INDEX_ENTRY_SIZE)?;
+        if buffer[80] > 1 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        Ok(BlockIndexEntry {
+            block_hash: buffer[0..32].try_into().unwrap(),
+            block_id: u64::from_be_bytes(buffer[32..40].try_into().unwrap()),
+            previous_block_hash: buffer[40..72].try_into().unwrap(),
+            timestamp: u64::from_be_bytes(buffer[72..80].try_into().unwrap()),
+            in_longest_chain: buffer[80] == 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::block_index::{BlockIndexEntry, BLOCK_INDEX_ENTRY_SIZE};
+    use crate::core::data::serialize::Serialize;
+
+    #[test]
+    fn test_block_index_entry_serialize() {
+        let entry = BlockIndexEntry {
+            block_hash: [1; 32],
+            block_id: 10,
+            previous_block_hash: [2; 32],
+            timestamp: 1000,
+            in_longest_chain: true,
+        };
+        let buffer = entry.serialize();
+        assert_eq!(buffer.len(), BLOCK_INDEX_ENTRY_SIZE);
+        assert_eq!(BlockIndexEntry::deserialize(&buffer).unwrap(), entry);
+
+        assert!(BlockIndexEntry::deserialize(&buffer[0..80].to_vec()).is_err());
+        let mut buffer = buffer;
+        buffer[80] = 2;
+        assert!(BlockIndexEntry::deserialize(&buffer).is_err());
+    }
+}
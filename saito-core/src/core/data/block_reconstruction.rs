@@ -0,0 +1,195 @@
+use ahash::AHashMap;
+
+use crate::common::defs::SaitoHash;
+use crate::core::data::block::{Block, BlockType};
+use crate::core::data::msg::compact_block::{get_short_transaction_id, CompactBlock};
+use crate::core::data::transaction::Transaction;
+
+// full block is fetched if the missing transactions of a compact block are not received within this many milliseconds
+pub const COMPACT_BLOCK_TIMEOUT: u64 = 10_000;
+
+/// Block being rebuilt from a compact block. Transactions are taken from the mempool using the short ids, and the
+/// ones which are not found (or match more than one mempool transaction) are requested from the sending peer.
+pub struct BlockReconstruction {
+    pub peer_index: u64,
+    header: Block,
+    // transactions in the block order. None until found in the mempool or received from the peer
+    transactions: Vec<Option<Transaction>>,
+    // timestamp of the compact block in milliseconds
+    pub start_time: u64,
+}
+
+impl BlockReconstruction {
+    pub fn new(
+        compact_block: CompactBlock,
+        mempool_transactions: &[Transaction],
+        peer_index: u64,
+        current_time: u64,
+    ) -> BlockReconstruction {
+        let block_hash = compact_block.header.get_hash();
+        // short ids matching multiple transactions are marked as None, so they are requested from the peer
+        let mut candidates: AHashMap<u64, Option<&Transaction>> = Default::default();
+        for transaction in mempool_transactions.iter() {
+            if transaction.get_hash_for_signature().is_none() {
+                continue;
+            }
+            let short_id = get_short_transaction_id(
+                &block_hash,
+                &transaction.get_hash_for_signature().unwrap(),
+            );
+            if candidates.contains_key(&short_id) {
+                candidates.insert(short_id, None);
+            } else {
+                candidates.insert(short_id, Some(transaction));
+            }
+        }
+        let transactions = compact_block
+            .short_ids
+            .iter()
+            .zip(compact_block.paths)
+            .map(|(short_id, path)| {
+                candidates
+                    .get(short_id)
+                    .cloned()
+                    .flatten()
+                    .map(|transaction| {
+                        let mut transaction = transaction.clone();
+                        transaction.set_path(path);
+                        transaction
+                    })
+            })
+            .collect();
+        BlockReconstruction {
+            peer_index,
+            header: compact_block.header,
+            transactions,
+            start_time: current_time,
+        }
+    }
+    pub fn get_block_hash(&self) -> SaitoHash {
+        self.header.get_hash()
+    }
+    /// Positions of the transactions which are not yet found
+    pub fn get_missing_indices(&self) -> Vec<u32> {
+        self.transactions
+            .iter()
+            .enumerate()
+            .filter(|(_, transaction)| transaction.is_none())
+            .map(|(index, _)| index as u32)
+            .collect()
+    }
+    /// Fills the missing transactions with the ones received from the peer. returns false if the count doesn't match
+    pub fn add_missing_transactions(&mut self, transactions: Vec<Transaction>) -> bool {
+        let missing_indices = self.get_missing_indices();
+        if missing_indices.len() != transactions.len() {
+            return false;
+        }
+        for (index, mut transaction) in missing_indices.into_iter().zip(transactions) {
+            transaction.generate_metadata_hashes();
+            self.transactions[index as usize] = Some(transaction);
+        }
+        true
+    }
+    pub fn is_complete(&self) -> bool {
+        self.transactions
+            .iter()
+            .all(|transaction| transaction.is_some())
+    }
+    /// Builds the full block once all the transactions are found.
+    /// returns None if incomplete or if the transactions don't match the merkle root of the header
+    pub fn get_block(self) -> Option<Block> {
+        if !self.is_complete() {
+            return None;
+        }
+        let mut block = self.header;
+        let mut transactions: Vec<Transaction> = self
+            .transactions
+            .into_iter()
+            .map(|transaction| transaction.unwrap())
+            .collect();
+        block.set_transactions(&mut transactions);
+        block.set_block_type(BlockType::Full);
+        if block.generate_merkle_root() != block.get_merkle_root() {
+            return None;
+        }
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::block::{Block, BlockType};
+    use crate::core::data::block_reconstruction::BlockReconstruction;
+    use crate::core::data::hop::Hop;
+    use crate::core::data::msg::compact_block::CompactBlock;
+    use crate::core::data::transaction::Transaction;
+
+    fn create_block(transaction_count: u64) -> Block {
+        let mut transactions = vec![];
+        for i in 0..transaction_count {
+            let mut transaction = Transaction::new();
+            transaction.set_timestamp(1000 + i);
+            transaction.set_message(vec![i as u8]);
+            transaction.generate_metadata_hashes();
+            transactions.push(transaction);
+        }
+        let mut block = Block::new();
+        block.set_id(10);
+        block.set_timestamp(1000);
+        block.set_transactions(&mut transactions);
+        block.set_merkle_root(block.generate_merkle_root());
+        block.generate_hashes();
+        block
+    }
+
+    #[test]
+    fn test_block_reconstruction() {
+        let mut block = create_block(4);
+        let mut hop = Hop::new();
+        hop.set_from([5; 33]);
+        let mut transactions = block.get_transactions().clone();
+        transactions[1].set_path(vec![hop]);
+        block.set_transactions(&mut transactions);
+
+        // mempool has a different copy of the second transaction without the path
+        let mempool_transactions = vec![
+            create_block(4).get_transactions()[1].clone(),
+            block.get_transactions()[3].clone(),
+        ];
+        let mut reconstruction =
+            BlockReconstruction::new(CompactBlock::new(&block), &mempool_transactions, 1, 100);
+        assert_eq!(reconstruction.get_block_hash(), block.get_hash());
+        assert_eq!(reconstruction.get_missing_indices(), vec![0, 2]);
+        assert!(!reconstruction.is_complete());
+
+        assert!(!reconstruction.add_missing_transactions(vec![block.get_transactions()[0].clone()]));
+        assert!(reconstruction.add_missing_transactions(vec![
+            block.get_transactions()[0].clone(),
+            block.get_transactions()[2].clone(),
+        ]));
+        assert!(reconstruction.is_complete());
+
+        let result = reconstruction.get_block().unwrap();
+        assert_eq!(result.get_hash(), block.get_hash());
+        assert_eq!(result.get_block_type(), BlockType::Full);
+        assert_eq!(result.get_transactions().len(), 4);
+        assert_eq!(
+            result.get_transactions()[1].get_path()[0].get_from(),
+            [5; 33]
+        );
+    }
+
+    #[test]
+    fn test_block_reconstruction_merkle_mismatch() {
+        let block = create_block(2);
+        let mut reconstruction = BlockReconstruction::new(CompactBlock::new(&block), &[], 1, 100);
+        assert_eq!(reconstruction.get_missing_indices(), vec![0, 1]);
+
+        let other_block = create_block(3);
+        assert!(reconstruction.add_missing_transactions(vec![
+            other_block.get_transactions()[2].clone(),
+            block.get_transactions()[1].clone(),
+        ]));
+        assert!(reconstruction.get_block().is_none());
+    }
+}
@@ -7,13 +7,15 @@ use log::{debug, error, info, trace, warn};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::RwLock;
 
-use crate::common::defs::{SaitoHash, UtxoSet};
+use crate::common::defs::{SaitoHash, SaitoUTXOSetKey, UtxoSet};
 use crate::core::data::block::{Block, BlockType};
+use crate::core::data::block_index::BlockIndexEntry;
 use crate::core::data::blockring::BlockRing;
 use crate::core::data::network::Network;
 use crate::core::data::staking::Staking;
 use crate::core::data::storage::Storage;
 use crate::core::data::transaction::TransactionType;
+use crate::core::data::utxo_snapshot::{PurgedUtxo, UtxoSnapshot, UtxoSnapshotEntry};
 use crate::core::data::wallet::Wallet;
 use crate::core::mining_event_processor::MiningEvent;
 
@@ -41,6 +43,17 @@ pub fn bit_unpack(packed: u64) -> (u32, u32) {
     (top, bottom)
 }
 
+/// Outcome of adding a block to the blockchain
+#[derive(Debug, PartialEq)]
+pub enum AddBlockResult {
+    BlockAddedToLongestChain,
+    // block is stored but not on the longest chain
+    BlockAddedToFork,
+    BlockAlreadyExists,
+    // block is on the longest chain but the chain failed to validate
+    FailedValidation,
+}
+
 #[derive(Debug)]
 pub struct Blockchain {
     pub staking: Staking,
@@ -89,7 +102,7 @@ impl Blockchain {
         network: &Network,
         storage: &mut Storage,
         sender_to_miner: Sender<MiningEvent>,
-    ) {
+    ) -> AddBlockResult {
         debug!("adding block to blockchain");
 
         // get missing block
@@ -124,11 +137,12 @@ impl Blockchain {
                                 )
                                 .await;
                             if result.is_err() {
+                                // block is still added as an orphan. previous block can be fetched when
+                                // another peer announces it
                                 warn!(
                                     "couldn't fetch block : {:?}",
                                     hex::encode(block.get_previous_block_hash())
                                 );
-                                todo!()
                             }
                         }
                     }
@@ -160,7 +174,7 @@ impl Blockchain {
                 "ERROR: block exists in blockchain {:?}",
                 &hex::encode(&block.get_hash())
             );
-            return;
+            return AddBlockResult::BlockAlreadyExists;
         }
 
         //
@@ -215,7 +229,7 @@ impl Blockchain {
                 "BLOCK IS ALREADY IN THE BLOCKCHAIN, WHY ARE WE ADDING IT????? {:?}",
                 block.get_hash()
             );
-            return;
+            return AddBlockResult::BlockAlreadyExists;
         }
 
         //
@@ -305,6 +319,9 @@ impl Blockchain {
         // at this point we should have a shared ancestor or not
         // find out whether this new block is claiming to require chain-validation
         let am_i_the_longest_chain = self.is_new_chain_the_longest_chain(&new_chain, &old_chain);
+        // longest chain status of these blocks changes if the new chain validates
+        let reorganized_blocks: Vec<SaitoHash> =
+            new_chain.iter().chain(old_chain.iter()).cloned().collect();
 
         //
         // validate
@@ -335,6 +352,9 @@ impl Blockchain {
                         .set_in_longest_chain(true);
                 }
 
+                self.write_block_index(block_hash, &reorganized_blocks, storage)
+                    .await;
+
                 // TODO : send with the right channel
                 // global_sender
                 //     .send(GlobalEvent::BlockchainAddBlockSuccess { hash: block_hash })
@@ -351,6 +371,7 @@ impl Blockchain {
                     .unwrap();
 
                 debug!("event sent to miner");
+                AddBlockResult::BlockAddedToLongestChain
                 // global_sender
                 //     .send(GlobalEvent::BlockchainNewLongestChainBlock {
                 //         hash: block_hash,
@@ -364,6 +385,7 @@ impl Blockchain {
                 // global_sender
                 //     .send(GlobalEvent::BlockchainAddBlockFailure { hash: block_hash })
                 //     .expect("error: BlockchainAddBlockFailure message failed to send");
+                AddBlockResult::FailedValidation
             }
         } else {
             self.add_block_failure().await;
@@ -372,6 +394,7 @@ impl Blockchain {
             // global_sender
             //     .send(GlobalEvent::BlockchainAddBlockFailure { hash: block_hash })
             //     .expect("error: BlockchainAddBlockFailure message failed to send");
+            AddBlockResult::BlockAddedToFork
         }
     }
     // pub async fn add_block_to_blockchain(blockchain_lock: Arc<RwLock<Blockchain>>, block: Block) {
@@ -395,13 +418,10 @@ impl Blockchain {
         self.print();
 
         //
-        // save to disk
+        // block is saved to disk with its block index entry by write_block_index
         //
         {
             let block = self.get_mut_block(&block_hash).await;
-            if block.get_block_type() != BlockType::Header {
-                storage.write_block_to_disk(block).await;
-            }
             network.propagate_block(block).await;
         }
 
@@ -470,6 +490,154 @@ impl Blockchain {
         // todo!()
     }
 
+    /// Writes the block index entries of the blocks whose longest chain status was changed by adding a block. the added
+    /// block and the blocks wound in from a fork are stored in the same batch as the entries, so a block is never
+    /// stored without its index entry
+    ///
+    /// # Arguments
+    ///
+    /// * `block_hash`: hash of the added block
+    /// * `reorganized_blocks`: hashes of the wound and the unwound blocks
+    /// * `storage`:
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    async fn write_block_index(
+        &self,
+        block_hash: SaitoHash,
+        reorganized_blocks: &[SaitoHash],
+        storage: &mut Storage,
+    ) {
+        let mut blocks = vec![];
+        let mut entries = vec![];
+        for hash in reorganized_blocks {
+            // block might be already deleted with the genesis period update
+            let block = self.blocks.get(hash);
+            if block.is_none() {
+                continue;
+            }
+            let block = block.unwrap();
+            if block.get_block_type() == BlockType::Header {
+                continue;
+            }
+            let in_longest_chain = self
+                .blockring
+                .get_longest_chain_block_hash_by_block_id(block.get_id())
+                == *hash;
+            if *hash == block_hash
+                || (in_longest_chain && block.get_block_type() == BlockType::Full)
+            {
+                blocks.push(block);
+            }
+            entries.push(BlockIndexEntry {
+                block_hash: *hash,
+                block_id: block.get_id(),
+                previous_block_hash: block.get_previous_block_hash(),
+                timestamp: block.get_timestamp(),
+                in_longest_chain,
+            });
+        }
+        storage.write_blocks_with_index(&blocks, entries).await;
+    }
+
+    /// Adds the blocks loaded with the persisted block index without validating them again. longest chain blocks are
+    /// wound into the utxoset, staking tables and the wallet as they were when the blocks were added. if a utxo snapshot
+    /// is given, utxoset and the staking tables are restored from it and only the blocks after it are wound into them.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks`: blocks ordered by the id, with the longest chain flag set from the block index
+    /// * `snapshot`: snapshot taken at one of the given longest chain blocks
+    /// * `storage`:
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn add_blocks_from_index(
+        &mut self,
+        blocks: Vec<Block>,
+        snapshot: Option<UtxoSnapshot>,
+        storage: &Storage,
+    ) {
+        debug!("adding {:?} blocks from the block index", blocks.len());
+        let mut snapshot_block_id = 0;
+        // utxoset keys of the deleted blocks by the block id
+        let mut purged_utxos: AHashMap<u64, Vec<SaitoUTXOSetKey>> = AHashMap::new();
+        if let Some(snapshot) = snapshot {
+            debug!("restoring utxo snapshot at block : {:?}", snapshot.block_id);
+            snapshot_block_id = snapshot.block_id;
+            self.utxoset = AHashMap::with_capacity(snapshot.utxos.len());
+            for entry in snapshot.utxos {
+                self.utxoset.insert(entry.utxoset_key, entry.spendable);
+            }
+            for purged_utxo in snapshot.purged_utxos {
+                purged_utxos
+                    .entry(purged_utxo.block_id)
+                    .or_default()
+                    .push(purged_utxo.utxoset_key);
+            }
+            self.staking = snapshot.staking;
+        }
+        for block in blocks {
+            let block_hash = block.get_hash();
+            if self.blocks.contains_key(&block_hash) {
+                warn!(
+                    "block : {:?} is already in the blockchain",
+                    hex::encode(block_hash)
+                );
+                continue;
+            }
+            self.blockring.add_block(&block);
+
+            if block.is_in_longest_chain() {
+                self.blockring
+                    .on_chain_reorganization(block.get_id(), block_hash, true);
+                {
+                    trace!("waiting for the wallet write lock");
+                    let mut wallet = self.wallet_lock.write().await;
+                    trace!("acquired the wallet write lock");
+                    wallet.on_chain_reorganization(&block, true);
+                }
+            }
+            if block.is_in_longest_chain() && block.get_id() > snapshot_block_id {
+                block.on_chain_reorganization(&mut self.utxoset, true);
+                let (res_spend, res_unspend, res_delete) =
+                    self.staking.on_chain_reorganization(&block, true);
+                for slip in res_spend.iter() {
+                    slip.on_chain_reorganization(&mut self.utxoset, true, true);
+                }
+                for slip in res_unspend.iter() {
+                    slip.on_chain_reorganization(&mut self.utxoset, true, false);
+                }
+                for slip in res_delete.iter() {
+                    slip.delete(&mut self.utxoset);
+                }
+                // blocks purged when this block was added are already deleted from the disk
+                if block.get_id() > 2 * GENESIS_PERIOD {
+                    let purged = purged_utxos.remove(&(block.get_id() - 2 * GENESIS_PERIOD));
+                    for utxoset_key in purged.unwrap_or_default() {
+                        self.utxoset.remove(&utxoset_key);
+                    }
+                }
+            }
+            self.blocks.insert(block_hash, block);
+        }
+
+        self.update_genesis_period(storage).await;
+        let fork_id = self.generate_fork_id(self.get_latest_block_id());
+        self.set_fork_id(fork_id);
+        self.downgrade_blockchain_data().await;
+    }
+
     pub fn generate_fork_id(&self, block_id: u64) -> SaitoHash {
         let mut fork_id = [0; 32];
         let mut current_block_id = block_id;
@@ -1153,9 +1321,10 @@ impl Blockchain {
         storage: &Storage,
     ) {
         //
-        // skip out if earlier than we need to be vis-a-vis last_block_id
+        // skip out if earlier than we need to be vis-a-vis last_block_id. blockring is already
+        // updated with this block, so the latest block id is equal to the block id when winding
         //
-        if self.get_latest_block_id() >= block_id {
+        if self.get_latest_block_id() > block_id {
             return;
         }
 
@@ -1200,6 +1369,13 @@ impl Blockchain {
             // handle purges.
             //
             self.delete_blocks(purge_bid, storage).await;
+
+            if latest_block_id.is_multiple_of(GENESIS_PERIOD) {
+                // blocks up to this one are not wound again when restarting
+                storage
+                    .write_utxo_snapshot(&self.create_utxo_snapshot())
+                    .await;
+            }
         }
 
         //TODO: we already had in update_genesis_period() in self method - maybe no need to call here?
@@ -1245,7 +1421,6 @@ impl Blockchain {
         //
         {
             let pblock = self.blocks.get(&delete_block_hash).unwrap();
-            let pblock_filename = storage.generate_block_filename(pblock);
 
             //
             // remove slips from wallet
@@ -1264,7 +1439,7 @@ impl Blockchain {
             //
             // deletes block from disk
             //
-            storage.delete_block_from_disk(pblock_filename).await;
+            storage.delete_block_from_disk(pblock).await;
         }
 
         //
@@ -1281,6 +1456,40 @@ impl Blockchain {
         }
     }
 
+    /// Takes the utxoset and the staking tables as they are after adding the latest block, along with the slips of the
+    /// current blocks, so they can be purged with those blocks after restoring the snapshot
+    pub fn create_utxo_snapshot(&self) -> UtxoSnapshot {
+        let mut purged_utxos = vec![];
+        for block in self.blocks.values() {
+            for transaction in block.get_transactions() {
+                for slip in transaction
+                    .get_inputs()
+                    .iter()
+                    .chain(transaction.get_outputs().iter())
+                {
+                    purged_utxos.push(PurgedUtxo {
+                        block_id: block.get_id(),
+                        utxoset_key: slip.get_utxoset_key(),
+                    });
+                }
+            }
+        }
+        UtxoSnapshot {
+            block_id: self.get_latest_block_id(),
+            block_hash: self.get_latest_block_hash(),
+            utxos: self
+                .utxoset
+                .iter()
+                .map(|(utxoset_key, spendable)| UtxoSnapshotEntry {
+                    utxoset_key: *utxoset_key,
+                    spendable: *spendable,
+                })
+                .collect(),
+            purged_utxos,
+            staking: self.staking.clone(),
+        }
+    }
+
     pub async fn downgrade_blockchain_data(&mut self) {
         //
         // downgrade blocks still on the chain
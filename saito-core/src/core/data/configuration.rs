@@ -1,5 +1,20 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+
+use log::error;
 use serde::Deserialize;
 
+use crate::core::data::msg::handshake::{
+    SERVICE_FULL_NODE, SERVICE_LITE_NODE, SERVICE_SERVES_HTTP_BLOCKS, SERVICE_SERVES_SOCKET_BLOCKS,
+};
+use crate::core::data::msg::message::MessageType;
+
+// sync type of the peers from which only the headers and the transactions related to our keys are synced
+pub const SYNC_TYPE_LITE: &str = "lite";
+// storage backends keeping the blocks in separate files, or in the embedded key-value database
+pub const STORAGE_BACKEND_FILE: &str = "file";
+pub const STORAGE_BACKEND_KV: &str = "kv";
+
 #[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct PeerConfig {
     pub host: String,
@@ -19,14 +34,137 @@ pub struct Endpoint {
 pub struct Server {
     pub host: String,
     pub port: u16,
+    // connections are served with TLS (wss/https) when this is "https"
     pub protocol: String,
     pub endpoint: Endpoint,
+    // certificate chain in PEM format. required when the protocol is "https"
+    pub tls_certificate: Option<String>,
+    // PKCS#8 private key of the certificate in PEM format. required when the protocol is "https"
+    pub tls_private_key: Option<String>,
+}
+
+/// Token bucket limit. rate is the number of tokens added per second and burst is the size of the bucket
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    pub rate: u64,
+    pub burst: u64,
+}
+
+/// Limits for the messages received from a single peer
+#[derive(Deserialize, Debug, Clone)]
+pub struct PeerRateLimits {
+    // messages of any type
+    pub messages: RateLimit,
+    // bytes of all the messages
+    pub bytes: RateLimit,
+    // limits for the specific message types, keyed by the message type value
+    #[serde(default)]
+    pub message_types: HashMap<u8, RateLimit>,
+    // peers are disconnected when this many messages are dropped within the throttle window
+    pub disconnect_threshold: u32,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Configuration {
     pub server: Server,
     pub peers: Vec<PeerConfig>,
+    // peers are banned when their misbehaviour score reaches this value
+    #[serde(default = "default_peer_misbehaviour_threshold")]
+    pub peer_misbehaviour_threshold: u32,
+    // duration of a ban in milliseconds
+    #[serde(default = "default_peer_ban_duration")]
+    pub peer_ban_duration: u64,
+    // number of outgoing connections to maintain using the peers from the address book
+    #[serde(default = "default_max_outbound_peers")]
+    pub max_outbound_peers: usize,
+    // maximum delay in milliseconds between the reconnection attempts to a static peer
+    #[serde(default = "default_peer_reconnect_max_delay")]
+    pub peer_reconnect_max_delay: u64,
+    // download and validate the header chain before fetching the block bodies when the peer supports it
+    #[serde(default = "default_headers_first_sync")]
+    pub headers_first_sync: bool,
+    #[serde(default = "default_peer_rate_limits")]
+    pub peer_rate_limits: PeerRateLimits,
+    // root directory for the blocks, wallets, issuance files and the address book
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+    // advertise the http block fetch url to the peers. when disabled the peers fetch the blocks over the connection
+    #[serde(default = "default_serve_http_blocks")]
+    pub serve_http_blocks: bool,
+    // backend used to store the blocks and the metadata. "file" or "kv"
+    #[serde(default = "default_storage_backend")]
+    pub storage_backend: String,
+}
+
+fn default_peer_misbehaviour_threshold() -> u32 {
+    100
+}
+
+fn default_peer_ban_duration() -> u64 {
+    3_600_000
+}
+
+fn default_max_outbound_peers() -> usize {
+    8
+}
+
+fn default_peer_reconnect_max_delay() -> u64 {
+    60_000
+}
+
+fn default_headers_first_sync() -> bool {
+    true
+}
+
+fn default_peer_rate_limits() -> PeerRateLimits {
+    let mut message_types = HashMap::new();
+    message_types.insert(
+        MessageType::Transaction as u8,
+        RateLimit {
+            rate: 100,
+            burst: 500,
+        },
+    );
+    // requests which make us send large responses
+    for message_type in [
+        MessageType::BlockchainRequest,
+        MessageType::BlockHeadersRequest,
+        MessageType::GetPeers,
+    ] {
+        message_types.insert(message_type as u8, RateLimit { rate: 1, burst: 5 });
+    }
+    message_types.insert(MessageType::Ping as u8, RateLimit { rate: 1, burst: 5 });
+    message_types.insert(
+        MessageType::BlockFetchRequest as u8,
+        RateLimit {
+            rate: 10,
+            burst: 50,
+        },
+    );
+    PeerRateLimits {
+        messages: RateLimit {
+            rate: 200,
+            burst: 1000,
+        },
+        bytes: RateLimit {
+            rate: 4_000_000,
+            burst: 16_000_000,
+        },
+        message_types,
+        disconnect_threshold: 1000,
+    }
+}
+
+fn default_data_dir() -> String {
+    "./data/".to_string()
+}
+
+fn default_serve_http_blocks() -> bool {
+    true
+}
+
+fn default_storage_backend() -> String {
+    STORAGE_BACKEND_FILE.to_string()
 }
 
 impl Configuration {
@@ -41,9 +179,27 @@ impl Configuration {
                     port: 12101,
                     protocol: "http".to_string(),
                 },
+                tls_certificate: None,
+                tls_private_key: None,
             },
             peers: vec![],
+            peer_misbehaviour_threshold: default_peer_misbehaviour_threshold(),
+            peer_ban_duration: default_peer_ban_duration(),
+            max_outbound_peers: default_max_outbound_peers(),
+            peer_reconnect_max_delay: default_peer_reconnect_max_delay(),
+            headers_first_sync: default_headers_first_sync(),
+            peer_rate_limits: default_peer_rate_limits(),
+            data_dir: default_data_dir(),
+            serve_http_blocks: default_serve_http_blocks(),
+            storage_backend: default_storage_backend(),
+        }
+    }
+    /// Data directory with a trailing separator, so the file names can be appended to it
+    pub fn get_data_dir(&self) -> String {
+        if self.data_dir.ends_with('/') {
+            return self.data_dir.clone();
         }
+        self.data_dir.clone() + "/"
     }
     pub fn get_block_fetch_url(&self) -> String {
         let endpoint = &self.server.endpoint;
@@ -54,4 +210,66 @@ impl Configuration {
             + endpoint.port.to_string().as_str()
             + "/block/"
     }
+    /// Checks if the given peer config points to this node
+    pub fn is_own_address(&self, peer: &PeerConfig) -> bool {
+        (peer.host == self.server.host || peer.host == self.server.endpoint.host)
+            && (peer.port == self.server.port || peer.port == self.server.endpoint.port)
+    }
+    /// Node runs as a lite node if all of its configured peers use the lite sync type. Lite nodes only keep the block
+    /// headers and the transactions related to the wallet keys. The sync type applies to the whole node, so mixing
+    /// the sync types of the peers is rejected by [`Configuration::validate`]
+    pub fn is_lite_node(&self) -> bool {
+        !self.peers.is_empty()
+            && self
+                .peers
+                .iter()
+                .all(|peer| peer.synctype == SYNC_TYPE_LITE)
+    }
+    /// Checks the loaded configurations for the settings which can't be applied together
+    ///
+    /// # Arguments
+    ///
+    /// returns: Result<(), Error> InvalidInput error if the peers are configured with both the lite and the full
+    /// sync types, or if the storage backend is unknown
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn validate(&self) -> Result<(), Error> {
+        let lite_peer_count = self
+            .peers
+            .iter()
+            .filter(|peer| peer.synctype == SYNC_TYPE_LITE)
+            .count();
+        if lite_peer_count != 0 && lite_peer_count != self.peers.len() {
+            error!(
+                "mixed peer sync types are not supported. {:?} of {:?} peers use the lite sync type",
+                lite_peer_count,
+                self.peers.len()
+            );
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+        if self.storage_backend != STORAGE_BACKEND_FILE
+            && self.storage_backend != STORAGE_BACKEND_KV
+        {
+            error!(
+                "unknown storage backend : {:?}. should be {:?} or {:?}",
+                self.storage_backend, STORAGE_BACKEND_FILE, STORAGE_BACKEND_KV
+            );
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+        Ok(())
+    }
+    /// Services advertised to the peers in the handshake
+    pub fn get_services(&self) -> u64 {
+        if self.is_lite_node() {
+            return SERVICE_LITE_NODE;
+        }
+        if self.serve_http_blocks {
+            return SERVICE_FULL_NODE | SERVICE_SERVES_HTTP_BLOCKS | SERVICE_SERVES_SOCKET_BLOCKS;
+        }
+        SERVICE_FULL_NODE | SERVICE_SERVES_SOCKET_BLOCKS
+    }
 }
@@ -4,6 +4,7 @@ use blake3::Hasher;
 use block_modes::block_padding::Pkcs7;
 use block_modes::{BlockMode, Cbc};
 pub use merkle::MerkleTree;
+pub use secp256k1::ecdh::SharedSecret;
 pub use secp256k1::{Message, PublicKey, SecretKey, Signature, SECP256K1};
 
 use crate::common::defs::{SaitoHash, SaitoPrivateKey, SaitoPublicKey, SaitoSignature};
@@ -112,6 +113,21 @@ pub fn verify(msg: &[u8], sig: SaitoSignature, publickey: SaitoPublicKey) -> boo
     }
 }
 
+/// Secret shared by the two keys using ECDH. Both sides get the same secret using their own private key and the
+/// public key of the other side
+pub fn generate_shared_secret(
+    publickey: &SaitoPublicKey,
+    privatekey: &SaitoPrivateKey,
+) -> Option<SaitoHash> {
+    let p = PublicKey::from_slice(publickey);
+    let s = SecretKey::from_slice(privatekey);
+    if p.is_err() || s.is_err() {
+        return None;
+    }
+    let secret = SharedSecret::new(&p.unwrap(), &s.unwrap());
+    Some(secret[..].try_into().unwrap())
+}
+
 #[cfg(test)]
 
 mod tests {
@@ -156,4 +172,20 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn shared_secret_test() {
+        let (publickey_1, privatekey_1) = generate_keys();
+        let (publickey_2, privatekey_2) = generate_keys();
+        let secret = generate_shared_secret(&publickey_2, &privatekey_1).unwrap();
+        assert_eq!(
+            secret,
+            generate_shared_secret(&publickey_1, &privatekey_2).unwrap()
+        );
+        assert_ne!(
+            secret,
+            generate_shared_secret(&publickey_1, &privatekey_1).unwrap()
+        );
+        assert!(generate_shared_secret(&[0; 33], &privatekey_1).is_none());
+    }
 }
@@ -0,0 +1,342 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+
+use ahash::AHashMap;
+
+use crate::common::defs::SaitoHash;
+use crate::core::data::block::Block;
+
+// headers-first synchronisation is abandoned if no block is received for this many milliseconds
+pub const HEADER_SYNC_TIMEOUT: u64 = 60_000;
+
+/// State of a headers-first synchronisation with a peer. Block bodies of the validated header chain are fetched in
+/// parallel, so they can arrive in any order. They are kept here until all the previous blocks of the chain are
+/// received, to be added to the blockchain in the chain order.
+///
+/// Lite nodes use the same process, but fetch only the transactions related to their keys instead of the bodies.
+pub struct HeaderSync {
+    pub peer_index: u64,
+    // true if syncing as a lite node
+    pub is_lite: bool,
+    // headers of the blocks which are not yet released, in the chain order
+    pending_headers: VecDeque<Block>,
+    // fetched blocks waiting for the previous blocks in the chain
+    fetched_blocks: AHashMap<SaitoHash, Block>,
+    // hash of the last header of the chain
+    pub last_block_hash: SaitoHash,
+    // true if the peer had more headers than it could send in a single message
+    pub has_more_headers: bool,
+    // timestamp of the last received block in milliseconds
+    pub last_progress_time: u64,
+}
+
+impl HeaderSync {
+    pub fn new(
+        peer_index: u64,
+        is_lite: bool,
+        headers: Vec<Block>,
+        has_more_headers: bool,
+        current_time: u64,
+    ) -> HeaderSync {
+        HeaderSync {
+            peer_index,
+            is_lite,
+            last_block_hash: headers.last().map_or([0; 32], |header| header.get_hash()),
+            pending_headers: headers.into(),
+            fetched_blocks: Default::default(),
+            has_more_headers,
+            last_progress_time: current_time,
+        }
+    }
+    pub fn get_pending_hashes(&self) -> Vec<SaitoHash> {
+        self.pending_headers
+            .iter()
+            .map(|header| header.get_hash())
+            .collect()
+    }
+    pub fn get_pending_header(&self, block_hash: &SaitoHash) -> Option<&Block> {
+        if self.fetched_blocks.contains_key(block_hash) {
+            return None;
+        }
+        self.pending_headers
+            .iter()
+            .find(|header| header.get_hash() == *block_hash)
+    }
+    pub fn is_pending(&self, block_hash: &SaitoHash) -> bool {
+        self.get_pending_header(block_hash).is_some()
+    }
+    pub fn add_fetched_block(&mut self, block: Block, current_time: u64) {
+        self.last_progress_time = current_time;
+        self.fetched_blocks.insert(block.get_hash(), block);
+    }
+    /// Removes and returns the fetched blocks which have all their previous blocks in the chain released
+    pub fn get_ready_blocks(&mut self) -> Vec<Block> {
+        let mut blocks = vec![];
+        while let Some(header) = self.pending_headers.front() {
+            match self.fetched_blocks.remove(&header.get_hash()) {
+                Some(block) => blocks.push(block),
+                None => break,
+            }
+            self.pending_headers.pop_front();
+        }
+        blocks
+    }
+    pub fn is_complete(&self) -> bool {
+        self.pending_headers.is_empty()
+    }
+}
+
+/// Selects the headers extending our chain from the received headers and validates them
+///
+/// # Arguments
+///
+/// * `headers`: received headers starting from the last shared ancestor
+/// * `latest_block_id`: id of the latest block in our chain
+/// * `get_block`: returns the block (or header) from our chain with the given hash
+/// * `get_longest_chain_block_hash`: returns the hash of the block in our longest chain with the given id
+///
+/// returns: Result<Vec<Block>, Error> : headers to be synced. empty if the chain isn't longer than ours.
+/// NotFound error if the first header isn't the last shared ancestor in our longest chain and InvalidData if the
+/// headers don't validate
+///
+/// # Examples
+///
+/// ```
+///
+/// ```
+pub fn get_new_header_chain<'a, F, G>(
+    headers: Vec<Block>,
+    latest_block_id: u64,
+    get_block: F,
+    get_longest_chain_block_hash: G,
+) -> Result<Vec<Block>, Error>
+where
+    F: Fn(&SaitoHash) -> Option<&'a Block>,
+    G: Fn(u64) -> SaitoHash,
+{
+    if headers.is_empty() {
+        return Ok(vec![]);
+    }
+    // the peer sends the headers starting from the last shared ancestor, which is in our longest chain unless we
+    // don't have any blocks yet
+    if latest_block_id != 0
+        && get_longest_chain_block_hash(headers[0].get_id()) != headers[0].get_hash()
+    {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+    // so we skip the ones we already have
+    let headers: Vec<Block> = headers
+        .into_iter()
+        .skip_while(|header| get_block(&header.get_hash()).is_some())
+        .collect();
+    if headers.is_empty() || headers.last().unwrap().get_id() <= latest_block_id {
+        return Ok(vec![]);
+    }
+    let previous_block = get_block(&headers[0].get_previous_block_hash());
+    if previous_block.is_none() && latest_block_id != 0 {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+    if !validate_header_chain(&headers, previous_block) {
+        return Err(Error::from(ErrorKind::InvalidData));
+    }
+    Ok(headers)
+}
+
+/// Validates the linkage, signatures, burn fees and difficulties of all the headers in a header chain
+///
+/// # Arguments
+///
+/// * `headers`: consecutive headers in the chain order
+/// * `previous_block`: block the first header builds on. None if the chain starts from the first block
+///
+/// returns: bool
+///
+/// # Examples
+///
+/// ```
+///
+/// ```
+pub fn validate_header_chain(headers: &[Block], previous_block: Option<&Block>) -> bool {
+    let mut previous_block = previous_block;
+    for header in headers.iter() {
+        if !header.validate_header(previous_block) {
+            return false;
+        }
+        previous_block = Some(header);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::core::data::block::Block;
+    use crate::core::data::burnfee::BurnFee;
+    use crate::core::data::crypto::generate_keys;
+    use crate::core::data::header_sync::{get_new_header_chain, validate_header_chain, HeaderSync};
+
+    fn create_header_chain(count: u64) -> Vec<Block> {
+        let (public_key, private_key) = generate_keys();
+        let mut headers: Vec<Block> = vec![];
+        let mut genesis = Block::new();
+        genesis.set_id(1);
+        genesis.set_timestamp(1_000_000);
+        genesis.set_burnfee(50_000_000);
+        genesis.set_difficulty(0);
+        genesis.sign(public_key, private_key);
+        headers.push(genesis);
+        for _ in 1..count {
+            let previous = headers.last().unwrap();
+            let mut block = Block::new();
+            block.set_id(previous.get_id() + 1);
+            block.set_timestamp(previous.get_timestamp() + 10_000);
+            block.set_previous_block_hash(previous.get_hash());
+            block.set_burnfee(
+                BurnFee::return_burnfee_for_block_produced_at_current_timestamp_in_nolan(
+                    previous.get_burnfee(),
+                    block.get_timestamp(),
+                    previous.get_timestamp(),
+                ),
+            );
+            block.set_difficulty(previous.get_difficulty() + 1);
+            block.sign(public_key, private_key);
+            headers.push(block);
+        }
+        headers
+    }
+
+    #[test]
+    fn test_validate_header_chain() {
+        let headers = create_header_chain(5);
+        assert!(validate_header_chain(&headers, None));
+        assert!(validate_header_chain(&headers[1..], Some(&headers[0])));
+
+        // chain not linked to the given previous block
+        assert!(!validate_header_chain(&headers[2..], Some(&headers[0])));
+
+        // modified headers don't match the creator signature
+        let mut modified = headers.clone();
+        modified[3].set_burnfee(1);
+        modified[3].generate_hashes();
+        assert!(!validate_header_chain(&modified, None));
+
+        let (public_key, private_key) = generate_keys();
+        let mut modified = headers.clone();
+        modified[2].set_difficulty(headers[1].get_difficulty() + 2);
+        modified[2].sign(public_key, private_key);
+        assert!(!validate_header_chain(&modified[0..3], None));
+
+        let mut modified = headers.clone();
+        modified[2].set_burnfee(headers[2].get_burnfee() + 1);
+        modified[2].sign(public_key, private_key);
+        assert!(!validate_header_chain(&modified[0..3], None));
+
+        // first header is validated without a previous block too
+        let mut modified = headers.clone();
+        modified[0].set_difficulty(1);
+        modified[0].sign(public_key, private_key);
+        assert!(!validate_header_chain(&modified[0..1], None));
+
+        let mut modified = headers.clone();
+        modified[0].set_burnfee(1);
+        modified[0].sign(public_key, private_key);
+        assert!(!validate_header_chain(&modified[0..1], None));
+
+        let mut modified = headers.clone();
+        modified[0].set_timestamp(2_000_000);
+        modified[0].generate_hashes();
+        assert!(!validate_header_chain(&modified[0..1], None));
+    }
+
+    #[test]
+    fn test_get_new_header_chain() {
+        let headers = create_header_chain(5);
+
+        let longest_chain = |count: usize| {
+            let headers = headers.clone();
+            move |block_id: u64| {
+                headers[0..count]
+                    .iter()
+                    .find(|block| block.get_id() == block_id)
+                    .map_or([0; 32], |block| block.get_hash())
+            }
+        };
+
+        // we have the first 2 blocks
+        let result = get_new_header_chain(
+            headers.clone(),
+            2,
+            |hash| headers[0..2].iter().find(|block| block.get_hash() == *hash),
+            longest_chain(2),
+        );
+        let result = result.unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].get_id(), 3);
+
+        // our chain is already as long
+        let result = get_new_header_chain(
+            headers[0..3].to_vec(),
+            3,
+            |hash| headers[0..2].iter().find(|block| block.get_hash() == *hash),
+            longest_chain(2),
+        );
+        assert!(result.unwrap().is_empty());
+
+        // headers don't connect to our chain
+        let result = get_new_header_chain(
+            headers[2..].to_vec(),
+            1,
+            |hash| headers[0..1].iter().find(|block| block.get_hash() == *hash),
+            longest_chain(1),
+        );
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::NotFound);
+
+        // first header is a block we have, but not in our longest chain
+        let result = get_new_header_chain(
+            headers[1..].to_vec(),
+            1,
+            |hash| headers[0..2].iter().find(|block| block.get_hash() == *hash),
+            longest_chain(1),
+        );
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::NotFound);
+
+        let mut modified = headers.clone();
+        modified[4].set_difficulty(0);
+        modified[4].generate_hashes();
+        let result = get_new_header_chain(modified, 0, |_| None, |_| [0; 32]);
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+
+        // without any blocks of our own, the first header is validated too
+        let (public_key, private_key) = generate_keys();
+        let mut modified = headers.clone();
+        modified[0].set_difficulty(3);
+        modified[0].sign(public_key, private_key);
+        let result = get_new_header_chain(modified[0..1].to_vec(), 0, |_| None, |_| [0; 32]);
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_header_sync_block_order() {
+        let headers = create_header_chain(3);
+        let mut sync = HeaderSync::new(1, false, headers.clone(), false, 0);
+        assert!(sync.is_pending(&headers[1].get_hash()));
+
+        sync.add_fetched_block(headers[1].clone(), 10);
+        assert!(!sync.is_pending(&headers[1].get_hash()));
+        assert!(sync.get_ready_blocks().is_empty());
+
+        sync.add_fetched_block(headers[0].clone(), 20);
+        let blocks = sync.get_ready_blocks();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].get_hash(), headers[0].get_hash());
+        assert_eq!(blocks[1].get_hash(), headers[1].get_hash());
+        assert!(!sync.is_complete());
+
+        sync.add_fetched_block(headers[2].clone(), 30);
+        assert_eq!(sync.get_ready_blocks().len(), 1);
+        assert!(sync.is_complete());
+        assert_eq!(sync.last_block_hash, headers[2].get_hash());
+        assert_eq!(sync.last_progress_time, 30);
+    }
+}
@@ -6,6 +6,7 @@ use tokio::sync::RwLock;
 
 use crate::common::defs::{SaitoHash, SaitoPublicKey, SaitoSignature};
 use crate::core::data::crypto::sign;
+use crate::core::data::serialize::{check_length, DeserializationError};
 use crate::core::data::wallet::Wallet;
 
 pub const HOP_SIZE: usize = 130;
@@ -71,7 +72,8 @@ impl Hop {
         self.sig = sig
     }
 
-    pub fn deserialize_from_net(bytes: Vec<u8>) -> Hop {
+    pub fn deserialize_from_net(bytes: Vec<u8>) -> Result<Hop, DeserializationError> {
+        check_length(&bytes, HOP_SIZE)?;
         let from: SaitoPublicKey = bytes[..33].try_into().unwrap();
         let to: SaitoPublicKey = bytes[33..66].try_into().unwrap();
         let sig: SaitoSignature = bytes[66..130].try_into().unwrap();
@@ -81,7 +83,7 @@ impl Hop {
         hop.set_to(to);
         hop.set_sig(sig);
 
-        hop
+        Ok(hop)
     }
 
     pub fn serialize_for_net(&self) -> Vec<u8> {
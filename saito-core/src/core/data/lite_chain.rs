@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+
+use log::{debug, warn};
+
+use crate::common::defs::SaitoHash;
+use crate::core::data::block::{Block, BlockType};
+use crate::core::data::blockchain::GENESIS_PERIOD;
+use crate::core::data::serialize::{check_length, check_min_length, Serialize};
+use crate::core::data::wallet::Wallet;
+
+// lite chain is kept in the storage metadata with this key
+pub const LITE_CHAIN_METADATA_KEY: &str = "lite_chain";
+
+/// Longest chain kept by a lite node. Blocks only have the header fields and the transactions related to the wallet
+/// keys, which are verified against the merkle roots of the headers before being added.
+#[derive(Default)]
+pub struct LiteChain {
+    // blocks in the longest chain order. only the blocks within the genesis period are kept
+    blocks: VecDeque<Block>,
+}
+
+impl LiteChain {
+    pub fn new() -> LiteChain {
+        LiteChain {
+            blocks: Default::default(),
+        }
+    }
+    pub fn get_latest_block(&self) -> Option<&Block> {
+        self.blocks.back()
+    }
+    pub fn get_latest_block_id(&self) -> u64 {
+        self.blocks.back().map_or(0, |block| block.get_id())
+    }
+    pub fn get_latest_block_hash(&self) -> SaitoHash {
+        self.blocks.back().map_or([0; 32], |block| block.get_hash())
+    }
+    pub fn get_block(&self, block_hash: &SaitoHash) -> Option<&Block> {
+        self.blocks
+            .iter()
+            .find(|block| block.get_hash() == *block_hash)
+    }
+    pub fn get_block_hash_by_id(&self, block_id: u64) -> SaitoHash {
+        self.blocks
+            .iter()
+            .find(|block| block.get_id() == block_id)
+            .map_or([0; 32], |block| block.get_hash())
+    }
+    pub fn contains(&self, block_hash: &SaitoHash) -> bool {
+        self.get_block(block_hash).is_some()
+    }
+    /// Adds the block to the tip of the chain. If the block builds on an earlier block, the blocks after that are
+    /// removed from the chain and their transactions are rolled back from the wallet.
+    ///
+    /// # Arguments
+    ///
+    /// * `block`: block header with the transactions related to the wallet keys
+    /// * `wallet`:
+    ///
+    /// returns: false if the block doesn't connect to the chain
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn add_block(&mut self, block: Block, wallet: &mut Wallet) -> bool {
+        if !self.blocks.is_empty() {
+            let position = self
+                .blocks
+                .iter()
+                .position(|existing| existing.get_hash() == block.get_previous_block_hash());
+            if position.is_none() {
+                warn!(
+                    "block : {:?} doesn't connect to the lite chain",
+                    hex::encode(block.get_hash())
+                );
+                return false;
+            }
+            while self.blocks.len() > position.unwrap() + 1 {
+                let removed = self.blocks.pop_back().unwrap();
+                debug!(
+                    "removing block : {:?} from the lite chain",
+                    hex::encode(removed.get_hash())
+                );
+                wallet.on_chain_reorganization(&removed, false);
+            }
+        }
+        debug!(
+            "adding block : {:?} with {:?} transactions to the lite chain",
+            hex::encode(block.get_hash()),
+            block.get_transactions().len()
+        );
+        wallet.on_chain_reorganization(&block, true);
+        self.blocks.push_back(block);
+        while self.blocks.len() > GENESIS_PERIOD as usize {
+            self.blocks.pop_front();
+        }
+        true
+    }
+    /// Adds the loaded blocks to an empty chain in the chain order. wallet slips are not stored, so they are added
+    /// from the transactions of the blocks again
+    ///
+    /// # Arguments
+    ///
+    /// * `loaded_chain`: chain deserialized from the storage
+    /// * `wallet`:
+    ///
+    /// returns: false if the loaded blocks don't form a chain
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn restore(&mut self, loaded_chain: LiteChain, wallet: &mut Wallet) -> bool {
+        for block in loaded_chain.blocks {
+            if !self.add_block(block, wallet) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// [block count - 4 bytes - u32]
+/// [for each block in the chain order]
+///     [block size - 4 bytes - u32]
+///     [block serialized for the network with the transactions related to the wallet keys]
+impl Serialize<Self> for LiteChain {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = (self.blocks.len() as u32).to_be_bytes().to_vec();
+        for block in self.blocks.iter() {
+            let block_buffer = block.serialize_for_net(BlockType::Full);
+            buffer.extend((block_buffer.len() as u32).to_be_bytes());
+            buffer.extend(block_buffer);
+        }
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        check_min_length(buffer, 4)?;
+        let block_count = u32::from_be_bytes(buffer[0..4].try_into().unwrap());
+        if block_count as u64 > GENESIS_PERIOD {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let mut blocks = VecDeque::with_capacity(block_count as usize);
+        let mut start = 4;
+        for _ in 0..block_count {
+            check_min_length(buffer, start + 4)?;
+            let size = u32::from_be_bytes(buffer[start..start + 4].try_into().unwrap()) as usize;
+            start += 4;
+            check_min_length(buffer, start + size)?;
+            let mut block = Block::deserialize_for_net(&buffer[start..start + size].to_vec())?;
+            block.generate_hashes();
+            let creator = block.get_creator();
+            for transaction in block.transactions.iter_mut() {
+                transaction.generate_metadata(creator);
+            }
+            blocks.push_back(block);
+            start += size;
+        }
+        check_length(buffer, start)?;
+        Ok(LiteChain { blocks })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::block::Block;
+    use crate::core::data::blockchain::GENESIS_PERIOD;
+    use crate::core::data::lite_chain::LiteChain;
+    use crate::core::data::serialize::Serialize;
+    use crate::core::data::slip::Slip;
+    use crate::core::data::transaction::Transaction;
+    use crate::core::data::wallet::Wallet;
+
+    fn create_block(id: u64, previous_block_hash: [u8; 32], fork: u8) -> Block {
+        let mut block = Block::new();
+        block.set_id(id);
+        block.set_timestamp(id * 1000 + fork as u64);
+        block.set_previous_block_hash(previous_block_hash);
+        block.generate_hashes();
+        block
+    }
+
+    #[test]
+    fn test_lite_chain_reorganization() {
+        let mut wallet = Wallet::new();
+        let mut chain = LiteChain::new();
+
+        let block_1 = create_block(1, [0; 32], 0);
+        assert!(chain.add_block(block_1.clone(), &mut wallet));
+
+        let mut block_2 = create_block(2, block_1.get_hash(), 0);
+        let mut transaction = Transaction::new();
+        transaction.set_timestamp(2000);
+        let mut output = Slip::new();
+        output.set_publickey(wallet.get_publickey());
+        output.set_amount(100);
+        transaction.add_output(output);
+        transaction.generate_metadata(block_2.get_creator());
+        block_2.set_transactions(&mut vec![transaction]);
+        assert!(chain.add_block(block_2.clone(), &mut wallet));
+        assert_eq!(chain.get_latest_block_hash(), block_2.get_hash());
+        assert_eq!(wallet.get_available_balance(), 100);
+
+        // a fork from the first block removes the second block
+        let block_2_fork = create_block(2, block_1.get_hash(), 1);
+        assert!(chain.add_block(block_2_fork.clone(), &mut wallet));
+        assert_eq!(chain.get_latest_block_hash(), block_2_fork.get_hash());
+        assert!(!chain.contains(&block_2.get_hash()));
+        assert_eq!(wallet.get_available_balance(), 0);
+
+        assert!(!chain.add_block(create_block(5, [9; 32], 0), &mut wallet));
+    }
+
+    #[test]
+    fn test_lite_chain_serialize() {
+        let mut wallet = Wallet::new();
+        let mut chain = LiteChain::new();
+
+        let block_1 = create_block(1, [0; 32], 0);
+        assert!(chain.add_block(block_1.clone(), &mut wallet));
+        let mut block_2 = create_block(2, block_1.get_hash(), 0);
+        let mut transaction = Transaction::new();
+        transaction.set_timestamp(2000);
+        let mut output = Slip::new();
+        output.set_publickey(wallet.get_publickey());
+        output.set_amount(100);
+        transaction.add_output(output);
+        transaction.generate_metadata(block_2.get_creator());
+        block_2.set_transactions(&mut vec![transaction]);
+        assert!(chain.add_block(block_2.clone(), &mut wallet));
+
+        let buffer = chain.serialize();
+        let loaded_chain = LiteChain::deserialize(&buffer).unwrap();
+        assert_eq!(loaded_chain.get_latest_block_hash(), block_2.get_hash());
+
+        // wallet slips are added again from the loaded blocks
+        let mut restored_wallet = Wallet::new();
+        restored_wallet.deserialize_for_disk(&wallet.serialize_for_disk());
+        let mut restored_chain = LiteChain::new();
+        assert!(restored_chain.restore(loaded_chain, &mut restored_wallet));
+        assert_eq!(restored_chain.get_latest_block_id(), 2);
+        assert!(restored_chain.contains(&block_1.get_hash()));
+        assert_eq!(restored_wallet.get_available_balance(), 100);
+
+        assert!(LiteChain::deserialize(&buffer[0..buffer.len() - 1].to_vec()).is_err());
+        let mut buffer = buffer;
+        buffer.push(0);
+        assert!(LiteChain::deserialize(&buffer).is_err());
+    }
+
+    #[test]
+    fn test_lite_chain_pruning() {
+        let mut wallet = Wallet::new();
+        let mut chain = LiteChain::new();
+        let mut previous_block_hash = [0; 32];
+        for id in 1..(GENESIS_PERIOD + 5) {
+            let block = create_block(id, previous_block_hash, 0);
+            previous_block_hash = block.get_hash();
+            assert!(chain.add_block(block, &mut wallet));
+        }
+        assert_eq!(chain.get_latest_block_id(), GENESIS_PERIOD + 4);
+        assert_eq!(chain.blocks.len(), GENESIS_PERIOD as usize);
+    }
+}
@@ -90,21 +90,27 @@ impl Mempool {
         &mut self,
         transaction: Transaction,
         blockchain: &Blockchain,
-    ) {
+    ) -> bool {
         trace!(
             "add transaction if validates : {:?}",
             hex::encode(transaction.get_hash_for_signature().unwrap())
         );
+        if self.transaction_exists(transaction.get_hash_for_signature()) {
+            trace!("transaction already in mempool");
+            return false;
+        }
         //
         // validate
         //
         if transaction.validate(&blockchain.utxoset, &blockchain.staking) {
             self.add_transaction(transaction).await;
+            true
         } else {
             debug!(
                 "transaction not valid : {:?}",
                 transaction.get_hash_for_signature().unwrap()
             );
+            false
         }
     }
     pub async fn add_transaction(&mut self, mut transaction: Transaction) {
@@ -1,5 +1,13 @@
+use std::io::{Error, ErrorKind};
+
+use rayon::prelude::*;
+
 use crate::common::defs::SaitoHash;
 use crate::core::data::crypto::hash;
+use crate::core::data::serialize::Serialize;
+
+// maximum number of hashes in a merkle proof. enough for any number of transactions a block can have
+pub const MAX_MERKLE_PROOF_LENGTH: usize = 64;
 
 //
 // MerkleTreeLayer is a short implementation that uses the default
@@ -35,4 +43,295 @@ impl MerkleTreeLayer {
     pub fn get_hash(&self) -> SaitoHash {
         self.hash
     }
+
+    pub fn get_left(&self) -> SaitoHash {
+        self.left
+    }
+
+    pub fn get_right(&self) -> SaitoHash {
+        self.right
+    }
+}
+
+/// Merkle tree of the transactions in a block. The tree is kept after generating the root, so inclusion proofs can be
+/// generated for the transactions.
+///
+/// Each transaction hash is paired with the next one in the first layer (the last one with an empty hash), and the
+/// node hashes are paired in the following layers until a single node is left. Hash of that node is the merkle root.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    layers: Vec<Vec<MerkleTreeLayer>>,
+}
+
+impl MerkleTree {
+    /// Generates the tree for the given transaction hashes (hash_for_signature of the transactions in block order)
+    pub fn generate(tx_sig_hashes: &[SaitoHash]) -> MerkleTree {
+        let mut layers: Vec<Vec<MerkleTreeLayer>> = vec![];
+        if tx_sig_hashes.is_empty() {
+            return MerkleTree { layers };
+        }
+
+        let mut leaf_depth = 0;
+        let mut layer: Vec<MerkleTreeLayer> = (0..tx_sig_hashes.len())
+            .map(|i| {
+                MerkleTreeLayer::new(
+                    tx_sig_hashes[i],
+                    *tx_sig_hashes.get(i + 1).unwrap_or(&[0; 32]),
+                    leaf_depth,
+                )
+            })
+            .collect();
+
+        loop {
+            // hash the parent in parallel
+            layer.par_iter_mut().all(|leaf| leaf.hash());
+
+            leaf_depth += 1;
+            let next_layer: Vec<MerkleTreeLayer> = layer
+                .chunks(2)
+                .map(|pair| {
+                    MerkleTreeLayer::new(
+                        pair[0].get_hash(),
+                        pair.get(1).map_or([0; 32], |node| node.get_hash()),
+                        leaf_depth,
+                    )
+                })
+                .collect();
+            layers.push(layer);
+            layer = next_layer;
+            if layer.len() == 1 {
+                break;
+            }
+        }
+
+        //
+        // hash the final leaf
+        //
+        layer[0].hash();
+        layers.push(layer);
+        MerkleTree { layers }
+    }
+
+    pub fn get_root(&self) -> SaitoHash {
+        self.layers
+            .last()
+            .map_or([0; 32], |layer| layer[0].get_hash())
+    }
+
+    /// Generates the inclusion proof for the transaction at the given index. returns None if the index is out of range
+    pub fn generate_proof(&self, index: usize) -> Option<MerkleProof> {
+        if self.layers.is_empty() || index >= self.layers[0].len() {
+            return None;
+        }
+        let mut hashes = vec![self.layers[0][index].get_right()];
+        let mut node_index = index;
+        for layer in self.layers[1..].iter() {
+            let parent = &layer[node_index / 2];
+            if node_index.is_multiple_of(2) {
+                hashes.push(parent.get_right());
+            } else {
+                hashes.push(parent.get_left());
+            }
+            node_index /= 2;
+        }
+        Some(MerkleProof {
+            index: index as u32,
+            hashes,
+        })
+    }
+}
+
+/// Generates the merkle root of the given transaction hashes (hash_for_signature of the transactions in block order)
+pub fn generate_merkle_root(tx_sig_hashes: &[SaitoHash]) -> SaitoHash {
+    MerkleTree::generate(tx_sig_hashes).get_root()
+}
+
+/// Proof that a transaction hash is included in a merkle root. Contains the sibling hashes from the first layer up to
+/// the root. Whether a sibling is on the left or the right is derived from the transaction index. Lets lite (SPV) nodes
+/// verify transactions without the full block
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    pub index: u32,
+    pub hashes: Vec<SaitoHash>,
+}
+
+impl MerkleProof {
+    /// Checks if the given transaction hash (hash_for_signature) is included in the merkle root
+    pub fn verify(&self, tx_sig_hash: &SaitoHash, merkle_root: &SaitoHash) -> bool {
+        if self.hashes.is_empty() {
+            return false;
+        }
+        // transaction is always on the left side in the first layer
+        let mut current = hash_pair(tx_sig_hash, &self.hashes[0]);
+        let mut node_index = self.index;
+        for sibling in self.hashes[1..].iter() {
+            if node_index.is_multiple_of(2) {
+                current = hash_pair(&current, sibling);
+            } else {
+                current = hash_pair(sibling, &current);
+            }
+            node_index /= 2;
+        }
+        current == *merkle_root
+    }
+}
+
+fn hash_pair(left: &SaitoHash, right: &SaitoHash) -> SaitoHash {
+    let mut vbytes: Vec<u8> = vec![];
+    vbytes.extend(left);
+    vbytes.extend(right);
+    hash(&vbytes)
+}
+
+/// [index - 4 bytes - u32]
+/// [hash count - 1 byte - u8]
+/// [empty hash bitmap - 1 bit per hash, set if the hash is empty]
+/// [non empty hashes - 32 bytes each]
+impl Serialize<Self> for MerkleProof {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.index.to_be_bytes().to_vec();
+        buffer.push(self.hashes.len() as u8);
+        let mut bitmap = vec![0u8; self.hashes.len().div_ceil(8)];
+        for (i, hash) in self.hashes.iter().enumerate() {
+            if *hash == [0; 32] {
+                bitmap[i / 8] |= 1 << (i % 8);
+            }
+        }
+        buffer.extend(bitmap);
+        for hash in self.hashes.iter() {
+            if *hash != [0; 32] {
+                buffer.extend(hash);
+            }
+        }
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.len() < 5 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let index = u32::from_be_bytes(buffer[0..4].try_into().unwrap());
+        let count = buffer[4] as usize;
+        if count > MAX_MERKLE_PROOF_LENGTH {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let bitmap_length = count.div_ceil(8);
+        if buffer.len() < 5 + bitmap_length {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let bitmap = &buffer[5..5 + bitmap_length];
+        let mut offset = 5 + bitmap_length;
+        let mut hashes = vec![];
+        for i in 0..count {
+            if bitmap[i / 8] & (1 << (i % 8)) != 0 {
+                hashes.push([0; 32]);
+                continue;
+            }
+            if buffer.len() < offset + 32 {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            hashes.push(buffer[offset..offset + 32].try_into().unwrap());
+            offset += 32;
+        }
+        if offset != buffer.len() {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        Ok(MerkleProof { index, hashes })
+    }
+}
+
+impl MerkleProof {
+    /// Size of the serialized proof, used to read the proofs from a larger buffer
+    pub fn get_serialized_size(buffer: &[u8]) -> Result<usize, Error> {
+        if buffer.len() < 5 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let count = buffer[4] as usize;
+        let bitmap_length = count.div_ceil(8);
+        if count > MAX_MERKLE_PROOF_LENGTH || buffer.len() < 5 + bitmap_length {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let empty_hashes: usize = buffer[5..5 + bitmap_length]
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum();
+        if empty_hashes > count {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        Ok(5 + bitmap_length + (count - empty_hashes) * 32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::defs::SaitoHash;
+    use crate::core::data::crypto::hash;
+    use crate::core::data::merkle::{MerkleProof, MerkleTree};
+    use crate::core::data::serialize::Serialize;
+
+    fn create_hashes(count: usize) -> Vec<SaitoHash> {
+        (0..count).map(|i| hash(&vec![i as u8])).collect()
+    }
+
+    fn hash_pair(left: SaitoHash, right: SaitoHash) -> SaitoHash {
+        hash(&[left.to_vec(), right.to_vec()].concat())
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        assert_eq!(MerkleTree::generate(&[]).get_root(), [0; 32]);
+
+        let hashes = create_hashes(1);
+        assert_eq!(
+            MerkleTree::generate(&hashes).get_root(),
+            hash_pair(hash_pair(hashes[0], [0; 32]), [0; 32])
+        );
+
+        let hashes = create_hashes(3);
+        let layer_0 = [
+            hash_pair(hashes[0], hashes[1]),
+            hash_pair(hashes[1], hashes[2]),
+            hash_pair(hashes[2], [0; 32]),
+        ];
+        let layer_1 = [
+            hash_pair(layer_0[0], layer_0[1]),
+            hash_pair(layer_0[2], [0; 32]),
+        ];
+        assert_eq!(
+            MerkleTree::generate(&hashes).get_root(),
+            hash_pair(layer_1[0], layer_1[1])
+        );
+    }
+
+    #[test]
+    fn test_merkle_proof() {
+        for count in 1..20 {
+            let hashes = create_hashes(count);
+            let tree = MerkleTree::generate(&hashes);
+            let root = tree.get_root();
+            for (index, tx_hash) in hashes.iter().enumerate() {
+                let proof = tree.generate_proof(index).unwrap();
+                assert!(proof.verify(tx_hash, &root));
+                assert!(!proof.verify(&[1; 32], &root));
+
+                let buffer = proof.serialize();
+                assert_eq!(
+                    MerkleProof::get_serialized_size(&buffer).unwrap(),
+                    buffer.len()
+                );
+                let deserialized = MerkleProof::deserialize(&buffer).unwrap();
+                assert_eq!(deserialized, proof);
+            }
+            assert!(tree.generate_proof(count).is_none());
+        }
+
+        let hashes = create_hashes(5);
+        let tree = MerkleTree::generate(&hashes);
+        let mut proof = tree.generate_proof(2).unwrap();
+        proof.index = 3;
+        assert!(!proof.verify(&hashes[2], &tree.get_root()));
+
+        let buffer = tree.generate_proof(4).unwrap().serialize();
+        assert!(MerkleProof::deserialize(&buffer[0..buffer.len() - 1].to_vec()).is_err());
+    }
 }
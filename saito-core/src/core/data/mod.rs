@@ -1,4 +1,8 @@
+pub mod application_module;
 pub mod block;
+pub mod block_download;
+pub mod block_index;
+pub mod block_reconstruction;
 pub mod blockchain;
 pub mod blockring;
 pub mod burnfee;
@@ -6,7 +10,9 @@ pub mod configuration;
 pub mod context;
 pub mod crypto;
 pub mod golden_ticket;
+pub mod header_sync;
 pub mod hop;
+pub mod lite_chain;
 pub mod mempool;
 pub mod merkle;
 pub mod miner;
@@ -14,10 +20,14 @@ pub mod msg;
 pub mod network;
 pub mod peer;
 pub mod peer_collection;
+pub mod rate_limiter;
 pub mod ringitem;
 pub mod serialize;
+pub mod session;
 pub mod slip;
 pub mod staking;
 pub mod storage;
+pub mod storage_backend;
 pub mod transaction;
+pub mod utxo_snapshot;
 pub mod wallet;
@@ -0,0 +1,73 @@
+use std::io::{Error, ErrorKind};
+
+use crate::core::data::serialize::Serialize;
+
+pub const MAX_APPLICATION_ID_LENGTH: usize = u8::MAX as usize;
+
+/// Message exchanged directly between the application modules of the nodes without being written to the chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplicationMessage {
+    // identifier of the module which handles the message
+    pub application_id: String,
+    pub data: Vec<u8>,
+}
+
+/// [application id length - 1 byte - u8]
+/// [application id - utf8]
+/// [data]
+impl Serialize<Self> for ApplicationMessage {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = vec![self.application_id.len() as u8];
+        buffer.extend(self.application_id.as_bytes());
+        buffer.extend(&self.data);
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.is_empty() {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let length = buffer[0] as usize;
+        if length == 0 || buffer.len() < 1 + length {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let application_id = String::from_utf8(buffer[1..1 + length].to_vec());
+        if application_id.is_err() {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        Ok(ApplicationMessage {
+            application_id: application_id.unwrap(),
+            data: buffer[1 + length..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::msg::application_message::ApplicationMessage;
+    use crate::core::data::serialize::Serialize;
+
+    #[test]
+    fn test_application_message_serialize() {
+        let message = ApplicationMessage {
+            application_id: "chat".to_string(),
+            data: vec![1, 2, 3],
+        };
+        let buffer = message.serialize();
+        assert_eq!(ApplicationMessage::deserialize(&buffer).unwrap(), message);
+
+        let message = ApplicationMessage {
+            application_id: "game".to_string(),
+            data: vec![],
+        };
+        assert_eq!(
+            ApplicationMessage::deserialize(&message.serialize()).unwrap(),
+            message
+        );
+
+        assert!(ApplicationMessage::deserialize(&vec![]).is_err());
+        assert!(ApplicationMessage::deserialize(&vec![0, 1]).is_err());
+        assert!(ApplicationMessage::deserialize(&buffer[0..3].to_vec()).is_err());
+        assert!(ApplicationMessage::deserialize(&vec![1, 0xff]).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::{Error, ErrorKind};
+
+use crate::common::defs::SaitoHash;
+use crate::core::data::serialize::Serialize;
+
+/// Request for a full block over the peer connection. used with the peers which don't serve the blocks over http
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockFetchRequest {
+    pub block_hash: SaitoHash,
+}
+
+/// Answer to a block fetch request
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockFetchResponse {
+    pub block_hash: SaitoHash,
+    // full block serialized for the network. empty if the peer doesn't have the block
+    pub block: Vec<u8>,
+}
+
+impl BlockFetchResponse {
+    pub fn is_found(&self) -> bool {
+        !self.block.is_empty()
+    }
+}
+
+/// [block hash - 32 bytes]
+impl Serialize<Self> for BlockFetchRequest {
+    fn serialize(&self) -> Vec<u8> {
+        self.block_hash.to_vec()
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.len() != 32 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        Ok(BlockFetchRequest {
+            block_hash: buffer[0..32].try_into().unwrap(),
+        })
+    }
+}
+
+/// [block hash - 32 bytes]
+/// [block buffer]
+impl Serialize<Self> for BlockFetchResponse {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.block_hash.to_vec();
+        buffer.extend(&self.block);
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.len() < 32 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        Ok(BlockFetchResponse {
+            block_hash: buffer[0..32].try_into().unwrap(),
+            block: buffer[32..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::msg::block_fetch::{BlockFetchRequest, BlockFetchResponse};
+    use crate::core::data::serialize::Serialize;
+
+    #[test]
+    fn test_block_fetch_serialize() {
+        let request = BlockFetchRequest {
+            block_hash: [3; 32],
+        };
+        let buffer = request.serialize();
+        assert_eq!(BlockFetchRequest::deserialize(&buffer).unwrap(), request);
+        assert!(BlockFetchRequest::deserialize(&buffer[0..31].to_vec()).is_err());
+        assert!(BlockFetchRequest::deserialize(&[buffer, vec![1]].concat()).is_err());
+
+        let response = BlockFetchResponse {
+            block_hash: [3; 32],
+            block: vec![1, 2, 3],
+        };
+        let response2 = BlockFetchResponse::deserialize(&response.serialize()).unwrap();
+        assert_eq!(response2, response);
+        assert!(response2.is_found());
+
+        let response = BlockFetchResponse {
+            block_hash: [4; 32],
+            block: vec![],
+        };
+        let response2 = BlockFetchResponse::deserialize(&response.serialize()).unwrap();
+        assert_eq!(response2, response);
+        assert!(!response2.is_found());
+        assert!(BlockFetchResponse::deserialize(&vec![1; 20]).is_err());
+    }
+}
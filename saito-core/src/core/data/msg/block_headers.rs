@@ -0,0 +1,84 @@
+use std::io::{Error, ErrorKind};
+
+use crate::core::data::block::{Block, BlockType, BLOCK_HEADER_SIZE};
+use crate::core::data::serialize::Serialize;
+
+// maximum number of block headers sent in a single network message
+pub const MAX_HEADERS_PER_MESSAGE: usize = 500;
+
+/// Consecutive block headers of the longest chain, sent for headers-first synchronisation
+#[derive(Debug)]
+pub struct BlockHeaders {
+    pub headers: Vec<Block>,
+}
+
+impl Serialize<Self> for BlockHeaders {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = (self.headers.len() as u32).to_be_bytes().to_vec();
+        for header in self.headers.iter() {
+            buffer.extend(header.serialize_for_net(BlockType::Header));
+        }
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.len() < 4 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let count = u32::from_be_bytes(buffer[0..4].try_into().unwrap()) as usize;
+        if count > MAX_HEADERS_PER_MESSAGE || buffer.len() != 4 + count * BLOCK_HEADER_SIZE {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let mut headers = vec![];
+        for i in 0..count {
+            let start = 4 + i * BLOCK_HEADER_SIZE;
+            let header = buffer[start..start + BLOCK_HEADER_SIZE].to_vec();
+            // headers must not carry any transactions
+            if header[0..4] != [0; 4] {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            headers.push(Block::deserialize_for_net(&header)?);
+        }
+        Ok(BlockHeaders { headers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::block::{Block, BlockType};
+    use crate::core::data::msg::block_headers::BlockHeaders;
+    use crate::core::data::serialize::Serialize;
+
+    #[test]
+    fn test_block_headers_serialize() {
+        let mut headers = vec![];
+        for i in 1..4 {
+            let mut block = Block::new();
+            block.set_id(i);
+            block.set_timestamp(1000 * i);
+            block.set_burnfee(20);
+            block.generate_hashes();
+            headers.push(block);
+        }
+        let buffer = BlockHeaders {
+            headers: headers.clone(),
+        }
+        .serialize();
+        let result = BlockHeaders::deserialize(&buffer);
+        assert!(result.is_ok());
+        let result = result.unwrap();
+        assert_eq!(result.headers.len(), 3);
+        for (header, block) in result.headers.iter().zip(headers.iter()) {
+            assert_eq!(header.get_hash(), block.get_hash());
+            assert_eq!(header.get_block_type(), BlockType::Header);
+        }
+
+        let result = BlockHeaders::deserialize(&buffer[0..buffer.len() - 1].to_vec());
+        assert!(result.is_err());
+
+        // a header with a transaction count is rejected
+        let mut buffer = buffer;
+        buffer[7] = 1;
+        assert!(BlockHeaders::deserialize(&buffer).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use std::io::{Error, ErrorKind};
+
+use crate::common::defs::{SaitoHash, SaitoPublicKey};
+use crate::core::data::merkle::MerkleProof;
+use crate::core::data::serialize::Serialize;
+use crate::core::data::transaction::{Transaction, TRANSACTION_SIZE};
+
+// maximum number of keys a lite node can request the transactions for
+pub const MAX_KEYS_IN_TRANSACTIONS_REQUEST: usize = 100;
+
+/// Request from a lite node for the transactions of a block which touch the given keys
+#[derive(Debug)]
+pub struct BlockTransactionsRequest {
+    pub block_hash: SaitoHash,
+    pub public_keys: Vec<SaitoPublicKey>,
+}
+
+/// Transactions of a block which touch the keys of a lite node. Each transaction comes with a merkle proof, so the
+/// lite node can check it against the merkle root of the block header
+#[derive(Debug)]
+pub struct BlockTransactions {
+    pub block_hash: SaitoHash,
+    pub transactions: Vec<Transaction>,
+    // merkle proofs of the transactions, in the same order
+    pub proofs: Vec<MerkleProof>,
+}
+
+impl Serialize<Self> for BlockTransactionsRequest {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.block_hash.to_vec();
+        buffer.extend((self.public_keys.len() as u32).to_be_bytes());
+        for public_key in self.public_keys.iter() {
+            buffer.extend(public_key);
+        }
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.len() < 36 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let block_hash: SaitoHash = buffer[0..32].try_into().unwrap();
+        let count = u32::from_be_bytes(buffer[32..36].try_into().unwrap()) as usize;
+        if count > MAX_KEYS_IN_TRANSACTIONS_REQUEST || buffer.len() != 36 + count * 33 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let public_keys = buffer[36..]
+            .chunks(33)
+            .map(|key| key.try_into().unwrap())
+            .collect();
+        Ok(BlockTransactionsRequest {
+            block_hash,
+            public_keys,
+        })
+    }
+}
+
+impl Serialize<Self> for BlockTransactions {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.block_hash.to_vec();
+        buffer.extend((self.transactions.len() as u32).to_be_bytes());
+        for transaction in self.transactions.iter() {
+            let transaction_buffer = transaction.serialize_for_net();
+            buffer.extend((transaction_buffer.len() as u32).to_be_bytes());
+            buffer.extend(transaction_buffer);
+        }
+        for proof in self.proofs.iter() {
+            buffer.extend(proof.serialize());
+        }
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.len() < 36 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let block_hash: SaitoHash = buffer[0..32].try_into().unwrap();
+        let count = u32::from_be_bytes(buffer[32..36].try_into().unwrap()) as usize;
+        // each transaction has at least a length and the fixed transaction fields
+        if count.saturating_mul(4 + TRANSACTION_SIZE) > buffer.len() - 36 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let mut index = 36;
+        let mut transactions = vec![];
+        for _ in 0..count {
+            if buffer.len() < index + 4 {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            let length = u32::from_be_bytes(buffer[index..index + 4].try_into().unwrap()) as usize;
+            index += 4;
+            if length < TRANSACTION_SIZE || buffer.len() < index + length {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            transactions.push(Transaction::deserialize_from_net(
+                buffer[index..index + length].to_vec(),
+            )?);
+            index += length;
+        }
+        // one proof for each transaction
+        let mut proofs = vec![];
+        for _ in 0..count {
+            let length = MerkleProof::get_serialized_size(&buffer[index..])?;
+            if buffer.len() < index + length {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            proofs.push(MerkleProof::deserialize(
+                &buffer[index..index + length].to_vec(),
+            )?);
+            index += length;
+        }
+        if index != buffer.len() {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        Ok(BlockTransactions {
+            block_hash,
+            transactions,
+            proofs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::merkle::MerkleProof;
+    use crate::core::data::msg::block_transactions::{BlockTransactions, BlockTransactionsRequest};
+    use crate::core::data::serialize::Serialize;
+    use crate::core::data::transaction::Transaction;
+
+    #[test]
+    fn test_block_transactions_serialize() {
+        let request = BlockTransactionsRequest {
+            block_hash: [1; 32],
+            public_keys: vec![[2; 33], [3; 33]],
+        };
+        let result = BlockTransactionsRequest::deserialize(&request.serialize());
+        assert!(result.is_ok());
+        let result = result.unwrap();
+        assert_eq!(result.block_hash, [1; 32]);
+        assert_eq!(result.public_keys, request.public_keys);
+
+        let mut transaction = Transaction::new();
+        transaction.set_timestamp(1000);
+        transaction.set_message(vec![1, 2, 3]);
+        let transactions = BlockTransactions {
+            block_hash: [4; 32],
+            transactions: vec![transaction],
+            proofs: vec![MerkleProof {
+                index: 3,
+                hashes: vec![[5; 32], [0; 32], [6; 32]],
+            }],
+        };
+        let buffer = transactions.serialize();
+        let result = BlockTransactions::deserialize(&buffer);
+        assert!(result.is_ok());
+        let result = result.unwrap();
+        assert_eq!(result.block_hash, [4; 32]);
+        assert_eq!(result.transactions.len(), 1);
+        assert_eq!(result.transactions[0].get_message(), &vec![1, 2, 3]);
+        assert_eq!(result.proofs, transactions.proofs);
+
+        let result = BlockTransactions::deserialize(&buffer[0..buffer.len() - 1].to_vec());
+        assert!(result.is_err());
+    }
+}
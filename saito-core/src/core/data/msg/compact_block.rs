@@ -0,0 +1,283 @@
+use std::io::{Error, ErrorKind};
+
+use crate::common::defs::SaitoHash;
+use crate::core::data::block::{Block, BlockType, BLOCK_HEADER_SIZE};
+use crate::core::data::crypto::hash;
+use crate::core::data::hop::{Hop, HOP_SIZE};
+use crate::core::data::serialize::{DeserializationError, Serialize};
+use crate::core::data::transaction::{Transaction, TRANSACTION_SIZE};
+
+/// Block header with short ids of the transactions, relayed instead of the full block. Receiver rebuilds the block
+/// from the transactions in its mempool and only requests the missing ones.
+///
+/// Routing paths are not covered by the transaction hashes and differ for each node, so the paths of the block
+/// creator's copies are sent along with the short ids.
+#[derive(Debug)]
+pub struct CompactBlock {
+    pub header: Block,
+    pub short_ids: Vec<u64>,
+    pub paths: Vec<Vec<Hop>>,
+}
+
+/// Request for the transactions of a compact block which couldn't be found in the mempool
+#[derive(Debug)]
+pub struct CompactBlockTransactionsRequest {
+    pub block_hash: SaitoHash,
+    // positions of the transactions in the block
+    pub indices: Vec<u32>,
+}
+
+/// Requested transactions of a compact block, in the same order as the requested indices
+#[derive(Debug)]
+pub struct CompactBlockTransactions {
+    pub block_hash: SaitoHash,
+    pub transactions: Vec<Transaction>,
+}
+
+/// Short id of a transaction in a compact block. Salted with the block hash so colliding transactions can't be
+/// crafted in advance for every block.
+pub fn get_short_transaction_id(block_hash: &SaitoHash, tx_sig_hash: &SaitoHash) -> u64 {
+    let mut vbytes: Vec<u8> = vec![];
+    vbytes.extend(block_hash);
+    vbytes.extend(tx_sig_hash);
+    u64::from_be_bytes(hash(&vbytes)[0..8].try_into().unwrap())
+}
+
+impl CompactBlock {
+    /// Creates the compact block from a full block. transactions should have the hash for signature generated
+    pub fn new(block: &Block) -> CompactBlock {
+        let block_hash = block.get_hash();
+        CompactBlock {
+            header: block.get_header(),
+            short_ids: block
+                .get_transactions()
+                .iter()
+                .map(|transaction| {
+                    get_short_transaction_id(
+                        &block_hash,
+                        &transaction.get_hash_for_signature().unwrap(),
+                    )
+                })
+                .collect(),
+            paths: block
+                .get_transactions()
+                .iter()
+                .map(|transaction| transaction.get_path().clone())
+                .collect(),
+        }
+    }
+}
+
+/// [header - 213 bytes]
+/// [transaction count - 4 bytes - u32]
+/// for each transaction :
+/// [short id - 8 bytes - u64]
+/// [path length - 4 bytes - u32]
+/// [hop][hop][hop]...
+impl Serialize<Self> for CompactBlock {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.header.serialize_for_net(BlockType::Header);
+        buffer.extend((self.short_ids.len() as u32).to_be_bytes());
+        for (short_id, path) in self.short_ids.iter().zip(self.paths.iter()) {
+            buffer.extend(short_id.to_be_bytes());
+            buffer.extend((path.len() as u32).to_be_bytes());
+            for hop in path.iter() {
+                buffer.extend(hop.serialize_for_net());
+            }
+        }
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.len() < BLOCK_HEADER_SIZE + 4 || buffer[0..4] != [0; 4] {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let header = Block::deserialize_for_net(&buffer[0..BLOCK_HEADER_SIZE].to_vec())?;
+        let mut index = BLOCK_HEADER_SIZE;
+        let count = u32::from_be_bytes(buffer[index..index + 4].try_into().unwrap()) as usize;
+        index += 4;
+        // each transaction has at least a short id and a path length
+        if count.saturating_mul(12) > buffer.len() - index {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let mut short_ids = vec![];
+        let mut paths = vec![];
+        for _ in 0..count {
+            if buffer.len() < index + 12 {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            short_ids.push(u64::from_be_bytes(
+                buffer[index..index + 8].try_into().unwrap(),
+            ));
+            let path_length =
+                u32::from_be_bytes(buffer[index + 8..index + 12].try_into().unwrap()) as usize;
+            index += 12;
+            if path_length.saturating_mul(HOP_SIZE) > buffer.len() - index {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            let path = buffer[index..index + path_length * HOP_SIZE]
+                .chunks(HOP_SIZE)
+                .map(|hop| Hop::deserialize_from_net(hop.to_vec()))
+                .collect::<Result<Vec<Hop>, DeserializationError>>()?;
+            paths.push(path);
+            index += path_length * HOP_SIZE;
+        }
+        if index != buffer.len() {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        Ok(CompactBlock {
+            header,
+            short_ids,
+            paths,
+        })
+    }
+}
+
+impl Serialize<Self> for CompactBlockTransactionsRequest {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.block_hash.to_vec();
+        buffer.extend((self.indices.len() as u32).to_be_bytes());
+        for index in self.indices.iter() {
+            buffer.extend(index.to_be_bytes());
+        }
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.len() < 36 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let block_hash: SaitoHash = buffer[0..32].try_into().unwrap();
+        let count = u32::from_be_bytes(buffer[32..36].try_into().unwrap()) as usize;
+        if buffer.len() - 36 != count.saturating_mul(4) {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let indices = buffer[36..]
+            .chunks(4)
+            .map(|index| u32::from_be_bytes(index.try_into().unwrap()))
+            .collect();
+        Ok(CompactBlockTransactionsRequest {
+            block_hash,
+            indices,
+        })
+    }
+}
+
+impl Serialize<Self> for CompactBlockTransactions {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.block_hash.to_vec();
+        buffer.extend((self.transactions.len() as u32).to_be_bytes());
+        for transaction in self.transactions.iter() {
+            let transaction_buffer = transaction.serialize_for_net();
+            buffer.extend((transaction_buffer.len() as u32).to_be_bytes());
+            buffer.extend(transaction_buffer);
+        }
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.len() < 36 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let block_hash: SaitoHash = buffer[0..32].try_into().unwrap();
+        let count = u32::from_be_bytes(buffer[32..36].try_into().unwrap()) as usize;
+        // each transaction has at least a length and the fixed transaction fields
+        if count.saturating_mul(4 + TRANSACTION_SIZE) > buffer.len() - 36 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let mut index = 36;
+        let mut transactions = vec![];
+        for _ in 0..count {
+            if buffer.len() < index + 4 {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            let length = u32::from_be_bytes(buffer[index..index + 4].try_into().unwrap()) as usize;
+            index += 4;
+            if length < TRANSACTION_SIZE || buffer.len() < index + length {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            transactions.push(Transaction::deserialize_from_net(
+                buffer[index..index + length].to_vec(),
+            )?);
+            index += length;
+        }
+        if index != buffer.len() {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        Ok(CompactBlockTransactions {
+            block_hash,
+            transactions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::block::Block;
+    use crate::core::data::hop::Hop;
+    use crate::core::data::msg::compact_block::{
+        get_short_transaction_id, CompactBlock, CompactBlockTransactions,
+        CompactBlockTransactionsRequest,
+    };
+    use crate::core::data::serialize::Serialize;
+    use crate::core::data::transaction::Transaction;
+
+    #[test]
+    fn test_compact_block_serialize() {
+        let mut transactions = vec![];
+        for i in 0..3 {
+            let mut transaction = Transaction::new();
+            transaction.set_timestamp(1000 + i);
+            transaction.generate_metadata_hashes();
+            transactions.push(transaction);
+        }
+        let mut hop = Hop::new();
+        hop.set_from([1; 33]);
+        transactions[1].set_path(vec![hop]);
+
+        let mut block = Block::new();
+        block.set_id(10);
+        block.set_timestamp(1000);
+        block.set_transactions(&mut transactions);
+        block.set_merkle_root(block.generate_merkle_root());
+        block.generate_hashes();
+
+        let compact_block = CompactBlock::new(&block);
+        assert_eq!(
+            compact_block.short_ids[2],
+            get_short_transaction_id(
+                &block.get_hash(),
+                &transactions[2].get_hash_for_signature().unwrap()
+            )
+        );
+        let buffer = compact_block.serialize();
+        let result = CompactBlock::deserialize(&buffer).unwrap();
+        assert_eq!(result.header.get_hash(), block.get_hash());
+        assert_eq!(result.short_ids, compact_block.short_ids);
+        assert_eq!(result.paths.len(), 3);
+        assert!(result.paths[0].is_empty());
+        assert_eq!(result.paths[1][0].get_from(), [1; 33]);
+        assert!(CompactBlock::deserialize(&buffer[0..buffer.len() - 1].to_vec()).is_err());
+
+        let request = CompactBlockTransactionsRequest {
+            block_hash: [2; 32],
+            indices: vec![0, 2],
+        };
+        let result = CompactBlockTransactionsRequest::deserialize(&request.serialize()).unwrap();
+        assert_eq!(result.block_hash, [2; 32]);
+        assert_eq!(result.indices, vec![0, 2]);
+
+        let response = CompactBlockTransactions {
+            block_hash: [3; 32],
+            transactions: vec![transactions[0].clone()],
+        };
+        let buffer = response.serialize();
+        let result = CompactBlockTransactions::deserialize(&buffer).unwrap();
+        assert_eq!(result.block_hash, [3; 32]);
+        assert_eq!(result.transactions.len(), 1);
+        assert_eq!(result.transactions[0].get_timestamp(), 1000);
+        assert!(
+            CompactBlockTransactions::deserialize(&buffer[0..buffer.len() - 1].to_vec()).is_err()
+        );
+    }
+}
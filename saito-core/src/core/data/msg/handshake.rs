@@ -3,12 +3,39 @@ use std::io::{Error, ErrorKind};
 use log::warn;
 
 use crate::common::defs::{SaitoHash, SaitoPublicKey, SaitoSignature};
+use crate::core::data::crypto::hash;
 use crate::core::data::serialize::Serialize;
 
+/// Version of the wire protocol implemented by this node. Increment when the message formats change, and use the
+/// services bitfield to advertise the optional features.
+///
+/// Nodes from before the versioned handshake can't be talked to. they don't send a version and can't decode the
+/// version, services and signatures in our handshake messages, so the nodes of a network need to be upgraded together
+/// to this version.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest protocol version of a peer this node can still talk to
+pub const MIN_SUPPORTED_PROTOCOL_VERSION: u32 = PROTOCOL_VERSION;
+
+//
+// services (capabilities) bitfield exchanged in the handshake
+//
+// node keeps the full blockchain and validates blocks
+pub const SERVICE_FULL_NODE: u64 = 1 << 0;
+// node only keeps the headers and the data related to its keys
+pub const SERVICE_LITE_NODE: u64 = 1 << 1;
+// node keeps the blocks after they are pruned from the rest of the network
+pub const SERVICE_ARCHIVE: u64 = 1 << 2;
+// node serves blocks through the http block fetch url
+pub const SERVICE_SERVES_HTTP_BLOCKS: u64 = 1 << 3;
+// node answers the block fetch requests sent over the peer connection
+pub const SERVICE_SERVES_SOCKET_BLOCKS: u64 = 1 << 4;
+
 #[derive(Debug)]
 pub struct HandshakeChallenge {
     pub public_key: SaitoPublicKey,
     pub challenge: SaitoHash,
+    pub protocol_version: u32,
+    pub services: u64,
     pub block_fetch_url: String,
 }
 
@@ -18,6 +45,8 @@ pub struct HandshakeResponse {
     pub public_key: SaitoPublicKey,
     pub signature: SaitoSignature,
     pub challenge: SaitoHash,
+    pub protocol_version: u32,
+    pub services: u64,
     pub block_fetch_url: String,
 }
 
@@ -26,22 +55,58 @@ pub struct HandshakeCompletion {
     pub signature: SaitoSignature,
 }
 
+/// Data signed to answer a handshake challenge. The block fetch url and the services of the signer, and the protocol
+/// versions advertised by both sides are included so they can't be replaced on the way
+///
+/// # Arguments
+///
+/// * `challenge`: challenge received from the other side
+/// * `block_fetch_url`: block fetch url advertised by the signer
+/// * `services`: services advertised by the signer
+/// * `signer_protocol_version`: protocol version advertised by the signer
+/// * `receiver_protocol_version`: protocol version advertised by the other side
+///
+/// returns: [u8; 32]
+///
+/// # Examples
+///
+/// ```
+///
+/// ```
+pub fn get_handshake_signature_data(
+    challenge: &SaitoHash,
+    block_fetch_url: &str,
+    services: u64,
+    signer_protocol_version: u32,
+    receiver_protocol_version: u32,
+) -> SaitoHash {
+    let mut vbytes: Vec<u8> = vec![];
+    vbytes.extend(challenge);
+    vbytes.extend(signer_protocol_version.to_be_bytes());
+    vbytes.extend(receiver_protocol_version.to_be_bytes());
+    vbytes.extend(services.to_be_bytes());
+    vbytes.extend(block_fetch_url.as_bytes());
+    hash(&vbytes)
+}
+
 impl Serialize<Self> for HandshakeChallenge {
     fn serialize(&self) -> Vec<u8> {
         let buffer = [
             self.public_key.to_vec(),
             self.challenge.to_vec(),
+            self.protocol_version.to_be_bytes().to_vec(),
+            self.services.to_be_bytes().to_vec(),
             self.block_fetch_url.as_bytes().to_vec(),
         ]
         .concat();
         return buffer;
     }
     fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
-        if buffer.len() <= 65 {
+        if buffer.len() <= 77 {
             warn!("buffer size is :{:?}", buffer.len());
             return Err(Error::from(ErrorKind::InvalidData));
         }
-        let result = String::from_utf8(buffer[65..].to_vec());
+        let result = String::from_utf8(buffer[77..].to_vec());
         if result.is_err() {
             warn!(
                 "failed decoding block fetch url. {:?}",
@@ -52,10 +117,14 @@ impl Serialize<Self> for HandshakeChallenge {
         let mut challenge = HandshakeChallenge {
             public_key: [0; 33],
             challenge: [0; 32],
+            protocol_version: 0,
+            services: 0,
             block_fetch_url: "".to_string(),
         };
         challenge.public_key = buffer[0..33].to_vec().try_into().unwrap();
         challenge.challenge = buffer[33..65].to_vec().try_into().unwrap();
+        challenge.protocol_version = u32::from_be_bytes(buffer[65..69].try_into().unwrap());
+        challenge.services = u64::from_be_bytes(buffer[69..77].try_into().unwrap());
         challenge.block_fetch_url = result.unwrap();
         return Ok(challenge);
     }
@@ -67,16 +136,18 @@ impl Serialize<Self> for HandshakeResponse {
             self.public_key.to_vec(),
             self.signature.to_vec(),
             self.challenge.to_vec(),
+            self.protocol_version.to_be_bytes().to_vec(),
+            self.services.to_be_bytes().to_vec(),
             self.block_fetch_url.as_bytes().to_vec(),
         ]
         .concat()
     }
     fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
-        if buffer.len() <= 129 {
+        if buffer.len() <= 141 {
             warn!("buffer size is :{:?}", buffer.len());
             return Err(Error::from(ErrorKind::InvalidData));
         }
-        let result = String::from_utf8(buffer[129..].to_vec());
+        let result = String::from_utf8(buffer[141..].to_vec());
         if result.is_err() {
             warn!(
                 "failed decoding block fetch url. {:?}",
@@ -88,6 +159,8 @@ impl Serialize<Self> for HandshakeResponse {
             public_key: buffer[0..33].to_vec().try_into().unwrap(),
             signature: buffer[33..97].to_vec().try_into().unwrap(),
             challenge: buffer[97..129].to_vec().try_into().unwrap(),
+            protocol_version: u32::from_be_bytes(buffer[129..133].try_into().unwrap()),
+            services: u64::from_be_bytes(buffer[133..141].try_into().unwrap()),
             block_fetch_url: result.unwrap(),
         })
     }
@@ -112,7 +185,8 @@ impl Serialize<Self> for HandshakeCompletion {
 mod tests {
 
     use crate::core::data::msg::handshake::{
-        HandshakeChallenge, HandshakeCompletion, HandshakeResponse,
+        get_handshake_signature_data, HandshakeChallenge, HandshakeCompletion, HandshakeResponse,
+        PROTOCOL_VERSION, SERVICE_FULL_NODE, SERVICE_LITE_NODE, SERVICE_SERVES_HTTP_BLOCKS,
     };
     use crate::core::data::serialize::Serialize;
 
@@ -127,13 +201,17 @@ mod tests {
         let challenge = HandshakeChallenge {
             public_key: public_key_1.serialize(),
             challenge: rand::random(),
+            protocol_version: PROTOCOL_VERSION,
+            services: SERVICE_FULL_NODE | SERVICE_SERVES_HTTP_BLOCKS,
             block_fetch_url: "http://url/test".to_string(),
         };
         let buffer = challenge.serialize();
-        assert_eq!(buffer.len(), 80);
+        assert_eq!(buffer.len(), 92);
         let challenge2 = HandshakeChallenge::deserialize(&buffer).expect("deserialization failed");
         assert_eq!(challenge.challenge, challenge2.challenge);
         assert_eq!(challenge.public_key, challenge2.public_key);
+        assert_eq!(challenge.protocol_version, challenge2.protocol_version);
+        assert_eq!(challenge.services, challenge2.services);
         assert_eq!(challenge.block_fetch_url, challenge2.block_fetch_url);
 
         let signature = crypto.sign(
@@ -144,13 +222,17 @@ mod tests {
             public_key: public_key_2.serialize(),
             signature: signature.serialize_compact(),
             challenge: rand::random(),
+            protocol_version: PROTOCOL_VERSION,
+            services: SERVICE_LITE_NODE,
             block_fetch_url: "http://url/test2".to_string(),
         };
         let buffer = response.serialize();
-        assert_eq!(buffer.len(), 145);
+        assert_eq!(buffer.len(), 157);
         let response2 = HandshakeResponse::deserialize(&buffer).expect("deserialization failed");
         assert_eq!(response.challenge, response2.challenge);
         assert_eq!(response.public_key, response2.public_key);
+        assert_eq!(response.protocol_version, response2.protocol_version);
+        assert_eq!(response.services, response2.services);
         assert_eq!(response.block_fetch_url, response2.block_fetch_url);
 
         assert_eq!(response.signature, response2.signature);
@@ -162,4 +244,60 @@ mod tests {
         let response2 = HandshakeCompletion::deserialize(&buffer).expect("deserialization failed");
         assert_eq!(response.signature, response2.signature);
     }
+
+    #[test]
+    fn test_handshake_signature_data() {
+        let challenge = [1; 32];
+        let url = "http://127.0.0.1:12101/block/";
+        let data = get_handshake_signature_data(
+            &challenge,
+            url,
+            SERVICE_FULL_NODE,
+            PROTOCOL_VERSION,
+            PROTOCOL_VERSION,
+        );
+        assert_ne!(data, challenge);
+        // changing the url or the services changes the signed data
+        assert_ne!(
+            data,
+            get_handshake_signature_data(
+                &challenge,
+                "http://10.0.0.1:12101/block/",
+                SERVICE_FULL_NODE,
+                PROTOCOL_VERSION,
+                PROTOCOL_VERSION
+            )
+        );
+        assert_ne!(
+            data,
+            get_handshake_signature_data(
+                &challenge,
+                url,
+                SERVICE_LITE_NODE,
+                PROTOCOL_VERSION,
+                PROTOCOL_VERSION
+            )
+        );
+        // changing the version of either side changes the signed data
+        assert_ne!(
+            data,
+            get_handshake_signature_data(
+                &challenge,
+                url,
+                SERVICE_FULL_NODE,
+                PROTOCOL_VERSION + 1,
+                PROTOCOL_VERSION
+            )
+        );
+        assert_ne!(
+            data,
+            get_handshake_signature_data(
+                &challenge,
+                url,
+                SERVICE_FULL_NODE,
+                PROTOCOL_VERSION,
+                PROTOCOL_VERSION + 1
+            )
+        );
+    }
 }
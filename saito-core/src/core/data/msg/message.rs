@@ -1,26 +1,104 @@
+// FromPrimitive derive of the num-derive version in use expands to a non-local impl
+#![allow(non_local_definitions)]
+
 use std::io::{Error, ErrorKind};
 
 use log::{info, warn};
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
 
 use crate::common::defs::SaitoHash;
-use crate::core::data::block::{Block, BlockType};
+use crate::core::data::block::{Block, BlockType, MAX_BLOCK_SIZE};
+use crate::core::data::msg::application_message::ApplicationMessage;
+use crate::core::data::msg::block_fetch::{BlockFetchRequest, BlockFetchResponse};
+use crate::core::data::msg::block_headers::BlockHeaders;
 use crate::core::data::msg::block_request::BlockchainRequest;
+use crate::core::data::msg::block_transactions::{BlockTransactions, BlockTransactionsRequest};
+use crate::core::data::msg::compact_block::{
+    CompactBlock, CompactBlockTransactions, CompactBlockTransactionsRequest,
+};
 use crate::core::data::msg::handshake::{
     HandshakeChallenge, HandshakeCompletion, HandshakeResponse,
 };
-use crate::core::data::serialize::Serialize;
+use crate::core::data::msg::peer_list::PeerList;
+use crate::core::data::serialize::{DeserializationError, Serialize};
 use crate::core::data::transaction::Transaction;
 
+// largest message is a block fetch response with a full block, the block hash and the type byte
+pub const MAX_MESSAGE_SIZE: usize = MAX_BLOCK_SIZE + 33;
+
+/// Type byte which prefixes every message on the wire. New types must only be appended so older peers can skip them
+#[derive(Debug, Copy, Clone, PartialEq, FromPrimitive)]
+pub enum MessageType {
+    HandshakeChallenge = 1,
+    HandshakeResponse = 2,
+    HandshakeCompletion = 3,
+    ApplicationMessage = 4,
+    Block = 5,
+    Transaction = 6,
+    BlockchainRequest = 7,
+    BlockHeaderHash = 8,
+    GetPeers = 9,
+    Peers = 10,
+    Ping = 11,
+    Pong = 12,
+    BlockHeadersRequest = 13,
+    BlockHeaders = 14,
+    GetBlockTransactions = 15,
+    BlockTransactions = 16,
+    CompactBlock = 17,
+    GetCompactBlockTransactions = 18,
+    CompactBlockTransactions = 19,
+    BlockFetchRequest = 20,
+    BlockFetchResponse = 21,
+}
+
+impl MessageType {
+    /// Reads the type of a serialized message. returns None for the empty buffers and the unknown types
+    pub fn from_buffer(buffer: &[u8]) -> Option<MessageType> {
+        buffer
+            .first()
+            .and_then(|value| FromPrimitive::from_u8(*value))
+    }
+    /// Handshake messages are the only ones accepted from a peer before its handshake is done
+    pub fn is_handshake(&self) -> bool {
+        matches!(
+            self,
+            MessageType::HandshakeChallenge
+                | MessageType::HandshakeResponse
+                | MessageType::HandshakeCompletion
+        )
+    }
+}
+
 #[derive(Debug)]
 pub enum Message {
     HandshakeChallenge(HandshakeChallenge),
     HandshakeResponse(HandshakeResponse),
     HandshakeCompletion(HandshakeCompletion),
-    ApplicationMessage(Vec<u8>),
+    ApplicationMessage(ApplicationMessage),
     Block(Block),
     Transaction(Transaction),
     BlockchainRequest(BlockchainRequest),
     BlockHeaderHash(SaitoHash),
+    GetPeers,
+    Peers(PeerList),
+    // timestamp of the sender, echoed back in the pong to measure the round trip time
+    Ping(u64),
+    Pong(u64),
+    // same as the blockchain request, but answered with the block headers instead of the block hashes
+    BlockHeadersRequest(BlockchainRequest),
+    BlockHeaders(BlockHeaders),
+    // used by the lite nodes to get the transactions related to their keys
+    GetBlockTransactions(BlockTransactionsRequest),
+    BlockTransactions(BlockTransactions),
+    // new block relayed with the short ids of the transactions instead of the transactions
+    CompactBlock(CompactBlock),
+    GetCompactBlockTransactions(CompactBlockTransactionsRequest),
+    CompactBlockTransactions(CompactBlockTransactions),
+    // block fetch over the peer connection, used when the peer doesn't serve the blocks over http
+    BlockFetchRequest(BlockFetchRequest),
+    BlockFetchResponse(BlockFetchResponse),
 }
 
 impl Message {
@@ -30,68 +108,288 @@ impl Message {
             Message::HandshakeChallenge(data) => data.serialize(),
             Message::HandshakeResponse(data) => data.serialize(),
             Message::HandshakeCompletion(data) => data.serialize(),
-            Message::ApplicationMessage(data) => data.clone(),
+            Message::ApplicationMessage(data) => data.serialize(),
             Message::Block(data) => data.serialize_for_net(BlockType::Full),
             Message::Transaction(data) => data.serialize_for_net(),
             Message::BlockchainRequest(data) => data.serialize(),
             Message::BlockHeaderHash(data) => data.to_vec(),
+            Message::GetPeers => vec![],
+            Message::Peers(data) => data.serialize(),
+            Message::Ping(data) => data.to_be_bytes().to_vec(),
+            Message::Pong(data) => data.to_be_bytes().to_vec(),
+            Message::BlockHeadersRequest(data) => data.serialize(),
+            Message::BlockHeaders(data) => data.serialize(),
+            Message::GetBlockTransactions(data) => data.serialize(),
+            Message::BlockTransactions(data) => data.serialize(),
+            Message::CompactBlock(data) => data.serialize(),
+            Message::GetCompactBlockTransactions(data) => data.serialize(),
+            Message::CompactBlockTransactions(data) => data.serialize(),
+            Message::BlockFetchRequest(data) => data.serialize(),
+            Message::BlockFetchResponse(data) => data.serialize(),
         };
         [vec![message_type], internal_buffer].concat()
     }
     pub fn deserialize(buffer: Vec<u8>) -> Result<Message, Error> {
-        let message_type = buffer[0];
+        if buffer.is_empty() {
+            warn!("received an empty message buffer");
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        if buffer.len() > MAX_MESSAGE_SIZE {
+            warn!("message size : {:?} is over the limit", buffer.len());
+            return Err(Error::from(DeserializationError::SizeLimitExceeded {
+                size: buffer.len() as u64,
+                limit: MAX_MESSAGE_SIZE,
+            }));
+        }
+        let message_type: Option<MessageType> = FromPrimitive::from_u8(buffer[0]);
+        if message_type.is_none() {
+            // message types added in newer protocol versions are skipped instead of failing the peer
+            warn!("message type : {:?} not supported", buffer[0]);
+            return Err(Error::from(ErrorKind::Unsupported));
+        }
+        let message_type = message_type.unwrap();
         let buffer = buffer[1..].to_vec();
 
         info!("buffer size = {:?}", buffer.len());
 
-        // TODO : remove hardcoded values into an enum
         match message_type {
-            1 => {
+            MessageType::HandshakeChallenge => {
                 let result = HandshakeChallenge::deserialize(&buffer)?;
                 return Ok(Message::HandshakeChallenge(result));
             }
-            2 => {
+            MessageType::HandshakeResponse => {
                 let result = HandshakeResponse::deserialize(&buffer)?;
                 return Ok(Message::HandshakeResponse(result));
             }
-            3 => {
+            MessageType::HandshakeCompletion => {
                 let result = HandshakeCompletion::deserialize(&buffer)?;
                 return Ok(Message::HandshakeCompletion(result));
             }
-            4 => {
-                todo!()
+            MessageType::ApplicationMessage => {
+                let result = ApplicationMessage::deserialize(&buffer)?;
+                Ok(Message::ApplicationMessage(result))
             }
-            5 => {
-                todo!()
+            MessageType::Block => {
+                let block = Block::deserialize_for_net(&buffer)?;
+                Ok(Message::Block(block))
             }
-            6 => {
-                todo!()
+            MessageType::Transaction => {
+                let transaction = Transaction::deserialize_from_net(buffer)?;
+                Ok(Message::Transaction(transaction))
             }
-            7 => {
+            MessageType::BlockchainRequest => {
                 let result = BlockchainRequest::deserialize(&buffer)?;
                 return Ok(Message::BlockchainRequest(result));
             }
-            8 => {
-                assert_eq!(buffer.len(), 32);
+            MessageType::BlockHeaderHash => {
+                if buffer.len() != 32 {
+                    warn!("invalid block hash buffer size : {:?}", buffer.len());
+                    return Err(Error::from(ErrorKind::InvalidData));
+                }
                 let result = buffer[0..32].to_vec().try_into().unwrap();
                 return Ok(Message::BlockHeaderHash(result));
             }
-            _ => {
-                warn!("message type : {:?} not valid", message_type);
-                return Err(Error::from(ErrorKind::InvalidData));
+            MessageType::GetPeers => Ok(Message::GetPeers),
+            MessageType::Peers => {
+                let result = PeerList::deserialize(&buffer)?;
+                Ok(Message::Peers(result))
+            }
+            MessageType::Ping | MessageType::Pong => {
+                if buffer.len() != 8 {
+                    warn!("invalid ping/pong buffer size : {:?}", buffer.len());
+                    return Err(Error::from(ErrorKind::InvalidData));
+                }
+                let timestamp = u64::from_be_bytes(buffer[0..8].try_into().unwrap());
+                if message_type == MessageType::Ping {
+                    return Ok(Message::Ping(timestamp));
+                }
+                Ok(Message::Pong(timestamp))
+            }
+            MessageType::BlockHeadersRequest => {
+                let result = BlockchainRequest::deserialize(&buffer)?;
+                Ok(Message::BlockHeadersRequest(result))
+            }
+            MessageType::BlockHeaders => {
+                let result = BlockHeaders::deserialize(&buffer)?;
+                Ok(Message::BlockHeaders(result))
+            }
+            MessageType::GetBlockTransactions => {
+                let result = BlockTransactionsRequest::deserialize(&buffer)?;
+                Ok(Message::GetBlockTransactions(result))
+            }
+            MessageType::BlockTransactions => {
+                let result = BlockTransactions::deserialize(&buffer)?;
+                Ok(Message::BlockTransactions(result))
+            }
+            MessageType::CompactBlock => {
+                let result = CompactBlock::deserialize(&buffer)?;
+                Ok(Message::CompactBlock(result))
+            }
+            MessageType::GetCompactBlockTransactions => {
+                let result = CompactBlockTransactionsRequest::deserialize(&buffer)?;
+                Ok(Message::GetCompactBlockTransactions(result))
+            }
+            MessageType::CompactBlockTransactions => {
+                let result = CompactBlockTransactions::deserialize(&buffer)?;
+                Ok(Message::CompactBlockTransactions(result))
+            }
+            MessageType::BlockFetchRequest => {
+                let result = BlockFetchRequest::deserialize(&buffer)?;
+                Ok(Message::BlockFetchRequest(result))
+            }
+            MessageType::BlockFetchResponse => {
+                let result = BlockFetchResponse::deserialize(&buffer)?;
+                Ok(Message::BlockFetchResponse(result))
             }
         }
     }
-    pub fn get_type_value(&self) -> u8 {
+    pub fn get_type(&self) -> MessageType {
         match self {
-            Message::HandshakeChallenge(_) => 1,
-            Message::HandshakeResponse(_) => 2,
-            Message::HandshakeCompletion(_) => 3,
-            Message::ApplicationMessage(_) => 4,
-            Message::Block(_) => 5,
-            Message::Transaction(_) => 6,
-            Message::BlockchainRequest(_) => 7,
-            Message::BlockHeaderHash(_) => 8,
+            Message::HandshakeChallenge(_) => MessageType::HandshakeChallenge,
+            Message::HandshakeResponse(_) => MessageType::HandshakeResponse,
+            Message::HandshakeCompletion(_) => MessageType::HandshakeCompletion,
+            Message::ApplicationMessage(_) => MessageType::ApplicationMessage,
+            Message::Block(_) => MessageType::Block,
+            Message::Transaction(_) => MessageType::Transaction,
+            Message::BlockchainRequest(_) => MessageType::BlockchainRequest,
+            Message::BlockHeaderHash(_) => MessageType::BlockHeaderHash,
+            Message::GetPeers => MessageType::GetPeers,
+            Message::Peers(_) => MessageType::Peers,
+            Message::Ping(_) => MessageType::Ping,
+            Message::Pong(_) => MessageType::Pong,
+            Message::BlockHeadersRequest(_) => MessageType::BlockHeadersRequest,
+            Message::BlockHeaders(_) => MessageType::BlockHeaders,
+            Message::GetBlockTransactions(_) => MessageType::GetBlockTransactions,
+            Message::BlockTransactions(_) => MessageType::BlockTransactions,
+            Message::CompactBlock(_) => MessageType::CompactBlock,
+            Message::GetCompactBlockTransactions(_) => MessageType::GetCompactBlockTransactions,
+            Message::CompactBlockTransactions(_) => MessageType::CompactBlockTransactions,
+            Message::BlockFetchRequest(_) => MessageType::BlockFetchRequest,
+            Message::BlockFetchResponse(_) => MessageType::BlockFetchResponse,
         }
     }
+    pub fn get_type_value(&self) -> u8 {
+        self.get_type() as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::core::data::block::Block;
+    use crate::core::data::msg::message::{Message, MessageType, MAX_MESSAGE_SIZE};
+    use crate::core::data::serialize::DeserializationError;
+    use crate::core::data::transaction::Transaction;
+
+    #[test]
+    fn test_block_message_serialize() {
+        let mut block = Block::new();
+        block.set_id(10);
+        block.set_timestamp(1000);
+        block.set_burnfee(20);
+        block.generate_hashes();
+
+        let buffer = Message::Block(block.clone()).serialize();
+        assert_eq!(buffer[0], 5);
+        let message = Message::deserialize(buffer).expect("deserialization failed");
+        match message {
+            Message::Block(new_block) => {
+                assert_eq!(new_block.get_id(), block.get_id());
+                assert_eq!(new_block.get_hash(), block.get_hash());
+            }
+            _ => panic!("unexpected message type"),
+        }
+    }
+
+    #[test]
+    fn test_transaction_message_serialize() {
+        let mut transaction = Transaction::new();
+        transaction.set_timestamp(1000);
+        transaction.set_message(vec![1, 2, 3, 4]);
+
+        let buffer = Message::Transaction(transaction.clone()).serialize();
+        assert_eq!(buffer[0], 6);
+        let message = Message::deserialize(buffer).expect("deserialization failed");
+        match message {
+            Message::Transaction(new_transaction) => {
+                assert_eq!(new_transaction.get_timestamp(), 1000);
+                assert_eq!(new_transaction.get_message(), &vec![1, 2, 3, 4]);
+            }
+            _ => panic!("unexpected message type"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_message_type() {
+        let result = Message::deserialize(vec![200, 1, 2, 3]);
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::Unsupported);
+
+        let result = Message::deserialize(vec![]);
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_ping_pong_serialize() {
+        let buffer = Message::Ping(1234).serialize();
+        assert_eq!(buffer.len(), 9);
+        assert!(matches!(
+            Message::deserialize(buffer),
+            Ok(Message::Ping(1234))
+        ));
+
+        let buffer = Message::Pong(5678).serialize();
+        assert!(matches!(
+            Message::deserialize(buffer),
+            Ok(Message::Pong(5678))
+        ));
+
+        let result = Message::deserialize(vec![MessageType::Ping as u8, 1, 2]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_invalid_message_buffers() {
+        let mut block = Block::new();
+        block.set_id(10);
+        let buffer = Message::Block(block).serialize();
+        let result = Message::deserialize(buffer[0..100].to_vec());
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+
+        let mut transaction = Transaction::new();
+        transaction.set_message(vec![1, 2, 3, 4]);
+        let buffer = Message::Transaction(transaction).serialize();
+        let result = Message::deserialize(buffer[0..buffer.len() - 2].to_vec());
+        let error = result.err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(matches!(
+            error
+                .get_ref()
+                .unwrap()
+                .downcast_ref::<DeserializationError>(),
+            Some(DeserializationError::BufferTooShort { .. })
+        ));
+
+        let result = Message::deserialize(vec![MessageType::BlockHeaderHash as u8, 1, 2]);
+        assert!(result.is_err());
+
+        let mut buffer = vec![0; MAX_MESSAGE_SIZE + 1];
+        buffer[0] = MessageType::Block as u8;
+        assert!(Message::deserialize(buffer).is_err());
+    }
+
+    #[test]
+    fn test_message_type_values() {
+        assert_eq!(MessageType::HandshakeChallenge as u8, 1);
+        assert_eq!(MessageType::BlockHeaderHash as u8, 8);
+        assert_eq!(MessageType::Peers as u8, 10);
+        assert_eq!(Message::GetPeers.serialize(), vec![9]);
+        assert_eq!(
+            Message::BlockHeaderHash([1; 32]).get_type(),
+            MessageType::BlockHeaderHash
+        );
+        assert_eq!(MessageType::BlockFetchResponse as u8, 21);
+    }
 }
@@ -1,3 +1,9 @@
+pub mod application_message;
+pub mod block_fetch;
+pub mod block_headers;
 pub mod block_request;
+pub mod block_transactions;
+pub mod compact_block;
 pub mod handshake;
 pub mod message;
+pub mod peer_list;
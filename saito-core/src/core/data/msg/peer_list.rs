@@ -0,0 +1,115 @@
+use std::io::{Error, ErrorKind};
+
+use crate::core::data::configuration::PeerConfig;
+use crate::core::data::serialize::Serialize;
+
+// maximum number of peers shared in a single network message
+pub const MAX_PEERS_IN_PEER_LIST: usize = 100;
+
+/// List of known peer addresses shared with other peers for peer discovery
+#[derive(Debug)]
+pub struct PeerList {
+    pub peers: Vec<PeerConfig>,
+}
+
+fn serialize_string(value: &String) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    [(bytes.len() as u16).to_be_bytes().to_vec(), bytes.to_vec()].concat()
+}
+
+fn deserialize_string(buffer: &[u8], index: &mut usize) -> Result<String, Error> {
+    if buffer.len() < *index + 2 {
+        return Err(Error::from(ErrorKind::InvalidData));
+    }
+    let length = u16::from_be_bytes(buffer[*index..*index + 2].try_into().unwrap()) as usize;
+    *index += 2;
+    if buffer.len() < *index + length {
+        return Err(Error::from(ErrorKind::InvalidData));
+    }
+    let result = String::from_utf8(buffer[*index..*index + length].to_vec());
+    if result.is_err() {
+        return Err(Error::from(ErrorKind::InvalidData));
+    }
+    *index += length;
+    Ok(result.unwrap())
+}
+
+impl Serialize<Self> for PeerList {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = (self.peers.len() as u32).to_be_bytes().to_vec();
+        for peer in self.peers.iter() {
+            buffer.extend(peer.port.to_be_bytes());
+            buffer.extend(serialize_string(&peer.host));
+            buffer.extend(serialize_string(&peer.protocol));
+            buffer.extend(serialize_string(&peer.synctype));
+        }
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        if buffer.len() < 4 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let count = u32::from_be_bytes(buffer[0..4].try_into().unwrap()) as usize;
+        // each entry has at least a port and 3 string lengths
+        if count.saturating_mul(8) > buffer.len() - 4 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let mut index = 4;
+        let mut peers = vec![];
+        for _ in 0..count {
+            if buffer.len() < index + 2 {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            let port = u16::from_be_bytes(buffer[index..index + 2].try_into().unwrap());
+            index += 2;
+            let host = deserialize_string(buffer, &mut index)?;
+            let protocol = deserialize_string(buffer, &mut index)?;
+            let synctype = deserialize_string(buffer, &mut index)?;
+            peers.push(PeerConfig {
+                host,
+                port,
+                protocol,
+                synctype,
+            });
+        }
+        if index != buffer.len() {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        Ok(PeerList { peers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::configuration::PeerConfig;
+    use crate::core::data::msg::peer_list::PeerList;
+    use crate::core::data::serialize::Serialize;
+
+    #[test]
+    fn test_peer_list_serialize() {
+        let list = PeerList {
+            peers: vec![
+                PeerConfig {
+                    host: "127.0.0.1".to_string(),
+                    port: 12101,
+                    protocol: "http".to_string(),
+                    synctype: "full".to_string(),
+                },
+                PeerConfig {
+                    host: "saito.io".to_string(),
+                    port: 443,
+                    protocol: "https".to_string(),
+                    synctype: "lite".to_string(),
+                },
+            ],
+        };
+        let buffer = list.serialize();
+        let result = PeerList::deserialize(&buffer);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().peers, list.peers);
+
+        let result = PeerList::deserialize(&buffer[0..buffer.len() - 1].to_vec());
+        assert!(result.is_err());
+    }
+}
@@ -1,29 +1,49 @@
-use std::io::Error;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 
-use log::{debug, info, trace};
+use ahash::AHashSet;
+
+use log::{debug, info, trace, warn};
+use rand::seq::SliceRandom;
 use tokio::sync::RwLock;
 
 use crate::common::defs::{SaitoHash, SaitoPublicKey};
 use crate::common::interface_io::InterfaceIO;
 use crate::core::data;
-use crate::core::data::block::Block;
+use crate::core::data::block::{Block, BlockType};
 use crate::core::data::blockchain::Blockchain;
-use crate::core::data::configuration::Configuration;
+use crate::core::data::configuration::{Configuration, PeerRateLimits};
+use crate::core::data::msg::application_message::ApplicationMessage;
+use crate::core::data::msg::block_fetch::BlockFetchRequest;
 use crate::core::data::msg::block_request::BlockchainRequest;
+use crate::core::data::msg::block_transactions::BlockTransactionsRequest;
+use crate::core::data::msg::compact_block::{CompactBlock, CompactBlockTransactionsRequest};
 use crate::core::data::msg::handshake::{
-    HandshakeChallenge, HandshakeCompletion, HandshakeResponse,
+    HandshakeChallenge, HandshakeCompletion, HandshakeResponse, SERVICE_FULL_NODE,
+    SERVICE_SERVES_HTTP_BLOCKS,
 };
-use crate::core::data::msg::message::Message;
-use crate::core::data::peer::Peer;
+use crate::core::data::msg::message::{Message, MessageType};
+use crate::core::data::msg::peer_list::PeerList;
+use crate::core::data::peer::{Peer, PeerMisbehaviour};
 use crate::core::data::peer_collection::PeerCollection;
+use crate::core::data::rate_limiter::RateLimitResult;
 use crate::core::data::transaction::Transaction;
 use crate::core::data::wallet::Wallet;
 
+// number of transaction hashes remembered to stop transactions from looping around the network
+pub const RECENTLY_SEEN_TRANSACTIONS_LIMIT: usize = 10_000;
+// peers are disconnected if nothing is received from them within this many milliseconds
+pub const PEER_TIMEOUT: u64 = 30_000;
+
 pub struct Network {
     // TODO : manage peers from network
     peers: Arc<RwLock<PeerCollection>>,
     pub io_interface: Box<dyn InterfaceIO + Send + Sync>,
+    recently_seen_transactions: AHashSet<SaitoHash>,
+    recently_seen_transactions_order: VecDeque<SaitoHash>,
+    // addresses of the outgoing connections which are not established yet
+    pending_connections: AHashSet<String>,
 }
 
 impl Network {
@@ -34,22 +54,182 @@ impl Network {
         Network {
             peers,
             io_interface: io_handler,
+            recently_seen_transactions: Default::default(),
+            recently_seen_transactions_order: Default::default(),
+            pending_connections: Default::default(),
+        }
+    }
+    /// Sends the message to the peer and adds it to the bytes sent to the peer. Message is encrypted if there is a
+    /// session with the peer
+    pub async fn send_message(&self, peer_index: u64, buffer: Vec<u8>) -> Result<(), Error> {
+        trace!("waiting for the peers write lock");
+        let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
+        let mut buffer = buffer;
+        if let Some(peer) = peers.index_to_peers.get_mut(&peer_index) {
+            if let Some(session) = peer.session.as_mut() {
+                buffer = session.encrypt(&buffer);
+            }
+            peer.bytes_sent += buffer.len() as u64;
+        }
+        // lock is kept until the message is queued so the encrypted messages are sent in the sequence order
+        self.io_interface.send_message(peer_index, buffer).await
+    }
+    /// Sends the message to all the handshaken peers except the excluded ones and adds it to the bytes sent to each
+    /// peer. Peers with a session get a separately encrypted copy
+    pub async fn send_message_to_all(
+        &self,
+        buffer: Vec<u8>,
+        excluded_peers: Vec<u64>,
+    ) -> Result<(), Error> {
+        trace!("waiting for the peers write lock");
+        let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
+        let mut excluded_peers = excluded_peers;
+        let mut encrypted_messages = vec![];
+        for (peer_index, peer) in peers.index_to_peers.iter_mut() {
+            if excluded_peers.contains(peer_index) {
+                continue;
+            }
+            if !peer.handshake_done {
+                excluded_peers.push(*peer_index);
+                continue;
+            }
+            if let Some(session) = peer.session.as_mut() {
+                let encrypted_buffer = session.encrypt(&buffer);
+                peer.bytes_sent += encrypted_buffer.len() as u64;
+                encrypted_messages.push((*peer_index, encrypted_buffer));
+                excluded_peers.push(*peer_index);
+                continue;
+            }
+            peer.bytes_sent += buffer.len() as u64;
+        }
+        for (peer_index, encrypted_buffer) in encrypted_messages {
+            self.io_interface
+                .send_message(peer_index, encrypted_buffer)
+                .await?;
+        }
+        self.io_interface
+            .send_message_to_all(buffer, excluded_peers)
+            .await
+    }
+    /// Decrypts the message if there is a session with the peer. Before the session is set up, only the handshake
+    /// messages are accepted and they are returned as they are. fails with ErrorKind::PermissionDenied for the other
+    /// plaintext messages. the received message is added to the bytes received from the peer with its size on the wire
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_index`: index of the sending peer
+    /// * `buffer`: message as received from the peer
+    ///
+    /// returns: Result<Vec<u8>, Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn decrypt_message(
+        &self,
+        peer_index: u64,
+        buffer: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        trace!("waiting for the peers write lock");
+        let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
+        let mut session = None;
+        if let Some(peer) = peers.index_to_peers.get_mut(&peer_index) {
+            peer.bytes_received += buffer.len() as u64;
+            session = peer.session.as_mut();
+        }
+        if let Some(session) = session {
+            return session.decrypt(&buffer);
         }
+        let message_type = MessageType::from_buffer(&buffer);
+        if message_type.is_some_and(|message_type| message_type.is_handshake()) {
+            return Ok(buffer);
+        }
+        warn!(
+            "received message type : {:?} from peer : {:?} before the handshake",
+            buffer.first(),
+            peer_index
+        );
+        Err(Error::from(ErrorKind::PermissionDenied))
     }
+    /// Records the decrypted message against the limit of its type. message and byte limits are checked by the io
+    /// handler before the message is queued
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_index`: index of the sending peer
+    /// * `message_type`: type value of the message
+    /// * `limits`: configured limits for the peers
+    /// * `current_time`: timestamp in milliseconds
+    ///
+    /// returns: RateLimitResult
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn check_rate_limits(
+        &self,
+        peer_index: u64,
+        message_type: u8,
+        limits: &PeerRateLimits,
+        current_time: u64,
+    ) -> RateLimitResult {
+        trace!("waiting for the peers write lock");
+        let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
+        let peer = peers.index_to_peers.get_mut(&peer_index);
+        if peer.is_none() {
+            // message will be handled as coming from an unknown peer
+            return RateLimitResult::Allowed;
+        }
+        peer.unwrap()
+            .rate_limiter
+            .check_message_type(message_type, limits, current_time)
+    }
+    /// Sends the new block to the peers. Full nodes get the compact block, and the rest get the block hash to fetch the
+    /// block from us.
     pub async fn propagate_block(&self, block: &Block) {
         debug!("propagating block : {:?}", hex::encode(block.get_hash()));
 
         let mut excluded_peers = vec![];
-        // finding block sender to avoid resending the block to that node
-        if block.source_connection_id.is_some() {
+        let mut compact_block_peers = vec![];
+        {
             trace!("waiting for the peers read lock");
             let peers = self.peers.read().await;
             trace!("acquired the peers read lock");
-            let peer = peers
-                .address_to_peers
-                .get(&block.source_connection_id.unwrap());
-            if peer.is_some() {
-                excluded_peers.push(*peer.unwrap());
+            // finding block sender to avoid resending the block to that node
+            if let Some(source_connection_id) = block.source_connection_id.as_ref() {
+                if let Some(peer) = peers.address_to_peers.get(source_connection_id) {
+                    excluded_peers.push(*peer);
+                }
+            }
+            if block.get_block_type() == BlockType::Full {
+                for (peer_index, peer) in peers.index_to_peers.iter() {
+                    if peer.handshake_done
+                        && peer.has_service(SERVICE_FULL_NODE)
+                        && !excluded_peers.contains(peer_index)
+                    {
+                        compact_block_peers.push(*peer_index);
+                    }
+                }
+            }
+        }
+        if !compact_block_peers.is_empty() {
+            debug!(
+                "sending compact block : {:?} to {:?} peers",
+                hex::encode(block.get_hash()),
+                compact_block_peers.len()
+            );
+            let buffer = Message::CompactBlock(CompactBlock::new(block)).serialize();
+            for peer_index in compact_block_peers {
+                self.send_message(peer_index, buffer.clone()).await.unwrap();
+                excluded_peers.push(peer_index);
             }
         }
         debug!(
@@ -57,15 +237,84 @@ impl Network {
             hex::encode(block.get_hash())
         );
         let message = Message::BlockHeaderHash(block.get_hash());
-        self.io_interface
-            .send_message_to_all(message.serialize(), excluded_peers)
+        self.send_message_to_all(message.serialize(), excluded_peers)
             .await
             .unwrap();
     }
-    pub async fn propagate_transaction(&self, _transaction: &Transaction) {
-        debug!("propagating transaction");
-        todo!()
+    /// Forwards the transaction to all the handshaken peers except the sender and the peers already in its routing path.
+    /// A hop signed by this node is added to the path separately for each receiving peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction`: transaction to be propagated. should have the hash for signature generated
+    /// * `sender_peer_index`: index of the peer which sent us the transaction. None if created locally
+    /// * `wallet`:
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn propagate_transaction(
+        &self,
+        transaction: &Transaction,
+        sender_peer_index: Option<u64>,
+        wallet: Arc<RwLock<Wallet>>,
+    ) {
+        debug!(
+            "propagating transaction : {:?}",
+            hex::encode(transaction.get_hash_for_signature().unwrap_or([0; 32]))
+        );
+
+        let mut receivers = vec![];
+        {
+            trace!("waiting for the peers read lock");
+            let peers = self.peers.read().await;
+            trace!("acquired the peers read lock");
+            for (peer_index, peer) in peers.index_to_peers.iter() {
+                if !peer.handshake_done || sender_peer_index == Some(*peer_index) {
+                    continue;
+                }
+                // peers in the routing path have already seen this transaction
+                if transaction
+                    .get_path()
+                    .iter()
+                    .any(|hop| hop.get_from() == peer.peer_public_key)
+                {
+                    continue;
+                }
+                receivers.push((*peer_index, peer.peer_public_key));
+            }
+        }
+
+        for (peer_index, peer_public_key) in receivers {
+            let mut transaction = transaction.clone();
+            transaction
+                .add_hop_to_path(wallet.clone(), peer_public_key)
+                .await;
+            let buffer = Message::Transaction(transaction).serialize();
+            self.send_message(peer_index, buffer).await.unwrap();
+        }
+    }
+    pub fn is_transaction_seen(&self, transaction_hash: &SaitoHash) -> bool {
+        self.recently_seen_transactions.contains(transaction_hash)
+    }
+    /// Records the transaction hash as seen. returns false if the transaction was already seen recently
+    pub fn mark_transaction_as_seen(&mut self, transaction_hash: SaitoHash) -> bool {
+        if !self.recently_seen_transactions.insert(transaction_hash) {
+            return false;
+        }
+        self.recently_seen_transactions_order
+            .push_back(transaction_hash);
+        if self.recently_seen_transactions_order.len() > RECENTLY_SEEN_TRANSACTIONS_LIMIT {
+            let oldest = self.recently_seen_transactions_order.pop_front().unwrap();
+            self.recently_seen_transactions.remove(&oldest);
+        }
+        true
     }
+    /// Schedules the previous block of a received block to be fetched from the peer which sent it
     pub async fn fetch_missing_block(
         &self,
         block_hash: SaitoHash,
@@ -73,73 +322,271 @@ impl Network {
     ) -> Result<(), Error> {
         debug!(
             "fetch missing block : block : {:?} from : {:?}",
-            block_hash, public_key
+            hex::encode(block_hash),
+            hex::encode(public_key)
         );
-        let peer_index;
-        let url;
-        {
-            let peers = self.peers.read().await;
-            let peer = peers.find_peer_by_address(public_key);
-            let peer = peer.unwrap();
-            url = peer.get_block_fetch_url(block_hash);
-            peer_index = peer.peer_index;
+        trace!("waiting for the peers write lock");
+        let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
+        let peer_index = peers.address_to_peers.get(public_key).cloned();
+        if peer_index.is_none() {
+            debug!(
+                "peer : {:?} not found to fetch the missing block",
+                hex::encode(public_key)
+            );
+            return Err(Error::from(ErrorKind::NotFound));
+        }
+        peers
+            .block_downloads
+            .add_block_announcement(block_hash, peer_index.unwrap());
+        Ok(())
+    }
+    /// Schedules the blocks lost from the storage to be fetched from the connected full nodes, and from the full nodes
+    /// connecting later until the blocks are fetched
+    pub async fn fetch_lost_blocks(&self, block_hashes: Vec<SaitoHash>) {
+        if block_hashes.is_empty() {
+            return;
+        }
+        debug!("fetching {:?} lost blocks from peers", block_hashes.len());
+        trace!("waiting for the peers write lock");
+        let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
+        let peer_indices: Vec<u64> = peers
+            .index_to_peers
+            .values()
+            .filter(|peer| peer.handshake_done && peer.has_service(SERVICE_FULL_NODE))
+            .map(|peer| peer.peer_index)
+            .collect();
+        for block_hash in block_hashes {
+            peers.block_downloads.add_lost_block(block_hash);
+        }
+        for peer_index in peer_indices {
+            peers.block_downloads.add_peer_for_lost_blocks(peer_index);
         }
-
-        self.io_interface
-            .fetch_block_from_peer(block_hash, peer_index, url)
-            .await
     }
     pub async fn handle_peer_disconnect(&mut self, peer_index: u64) {
         trace!("handling peer disconnect, peer_index = {}", peer_index);
-        let peers = self.peers.read().await;
-        let result = peers.find_peer_by_index(peer_index);
+        let peer;
+        {
+            trace!("waiting for the peers write lock");
+            let mut peers = self.peers.write().await;
+            trace!("acquired the peers write lock");
+            let result = peers.index_to_peers.remove(&peer_index);
+            if result.is_none() {
+                // disconnections initiated by us can be reported again by the io layer
+                debug!("unknown peer : {:?} disconnected", peer_index);
+                return;
+            }
+            peer = result.unwrap();
+            if peers.address_to_peers.get(&peer.peer_public_key) == Some(&peer_index) {
+                peers.address_to_peers.remove(&peer.peer_public_key);
+            }
+            peers.block_downloads.remove_peer(peer_index);
+        }
 
-        if result.is_some() {
-            let peer = result.unwrap();
+        // releasing the connection resources in the io layer
+        let result = self.io_interface.disconnect_from_peer(peer_index).await;
+        if result.is_err() {
+            warn!(
+                "failed disconnecting from peer : {:?} : {:?}",
+                peer_index,
+                result.err().unwrap()
+            );
+        }
 
-            if peer.static_peer_config.is_some() {
-                // This means the connection has been initiated from this side, therefore we must
-                // try to re-establish the connection again
-                // TODO : Add a delay so that there won't be a runaway issue with connects and
-                // disconnects, check the best place to add (here or network_controller)
-                info!(
-                    "Static peer disconnected, reconnecting .., Peer ID = {}, Public Key = {:?}",
-                    peer.peer_index,
-                    hex::encode(peer.peer_public_key)
+        if peer.static_peer_config.is_some() {
+            // This means the connection has been initiated from this side. static peers from the configs are
+            // reconnected by the routing event processor and others are replaced from the address book
+            info!(
+                "Outgoing peer disconnected, Peer ID = {}, Address = {:?}, Public Key = {:?}",
+                peer.peer_index,
+                peer.get_connection_address().unwrap(),
+                hex::encode(peer.peer_public_key)
+            );
+        } else {
+            info!("Peer disconnected, expecting a reconnection from the other side, Peer ID = {}, Public Key = {:?}",
+                peer.peer_index, hex::encode(peer.peer_public_key));
+        }
+    }
+    /// Closes the connection to the peer and removes it from the peer collection
+    pub async fn disconnect_from_peer(&mut self, peer_index: u64) {
+        debug!("disconnecting from peer : {:?}", peer_index);
+        self.handle_peer_disconnect(peer_index).await;
+    }
+    /// Increases the misbehaviour score of the peer. If the score reaches the configured threshold,
+    /// peer's key and address are banned for the configured duration and the peer is disconnected.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_index`:
+    /// * `misbehaviour`: type of the misbehaviour to be penalized
+    /// * `configs`:
+    /// * `current_time`: current timestamp in milliseconds
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn report_misbehaviour(
+        &mut self,
+        peer_index: u64,
+        misbehaviour: PeerMisbehaviour,
+        configs: Arc<RwLock<Configuration>>,
+        current_time: u64,
+    ) {
+        let threshold;
+        let ban_duration;
+        {
+            trace!("waiting for the configs read lock");
+            let configs = configs.read().await;
+            trace!("acquired the configs read lock");
+            threshold = configs.peer_misbehaviour_threshold;
+            ban_duration = configs.peer_ban_duration;
+        }
+        {
+            trace!("waiting for the peers write lock");
+            let mut peers = self.peers.write().await;
+            trace!("acquired the peers write lock");
+            let peer = peers.index_to_peers.get_mut(&peer_index);
+            if peer.is_none() {
+                debug!(
+                    "peer : {:?} not found to report misbehaviour : {:?}",
+                    peer_index, misbehaviour
                 );
-
-                self.io_interface
-                    .connect_to_peer(peer.static_peer_config.as_ref().unwrap().clone())
-                    .await
-                    .unwrap();
-            } else {
-                info!("Peer disconnected, expecting a reconnection from the other side, Peer ID = {}, Public Key = {:?}",
-                    peer.peer_index, hex::encode(peer.peer_public_key));
+                return;
             }
-        } else {
-            todo!("Handle the unknown peer disconnect");
+            let peer = peer.unwrap();
+            peer.misbehaviour_score += misbehaviour.get_score();
+            warn!(
+                "peer : {:?} misbehaved : {:?}, score : {:?}",
+                peer_index, misbehaviour, peer.misbehaviour_score
+            );
+            if peer.misbehaviour_score < threshold {
+                return;
+            }
+            warn!(
+                "banning peer : {:?} with key : {:?} until : {:?}",
+                peer_index,
+                hex::encode(peer.peer_public_key),
+                current_time + ban_duration
+            );
+            peers.remove_expired_bans(current_time);
+            peers.ban_peer(peer_index, current_time + ban_duration);
+        }
+        self.disconnect_from_peer(peer_index).await;
+    }
+    pub async fn update_last_seen(&self, peer_index: u64, current_time: u64) {
+        let mut peers = self.peers.write().await;
+        let peer = peers.index_to_peers.get_mut(&peer_index);
+        if let Some(peer) = peer {
+            peer.last_seen = current_time;
         }
     }
+    pub async fn handle_pong(&self, peer_index: u64, timestamp: u64, current_time: u64) {
+        let mut peers = self.peers.write().await;
+        let peer = peers.index_to_peers.get_mut(&peer_index);
+        if let Some(peer) = peer {
+            peer.handle_pong(timestamp, current_time);
+        }
+    }
+    /// Disconnects the peers which haven't sent anything within the timeout and pings the rest
+    ///
+    /// # Arguments
+    ///
+    /// * `current_time`: current timestamp in milliseconds
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn check_peer_liveness(&mut self, current_time: u64) {
+        let mut dead_peers = vec![];
+        let mut peers_to_ping = vec![];
+        {
+            trace!("waiting for the peers write lock");
+            let mut peers = self.peers.write().await;
+            trace!("acquired the peers write lock");
+            for (peer_index, peer) in peers.index_to_peers.iter_mut() {
+                if current_time.saturating_sub(peer.last_seen) > PEER_TIMEOUT {
+                    dead_peers.push(*peer_index);
+                    continue;
+                }
+                if peer.handshake_done {
+                    peer.ping_sent_at = Some(current_time);
+                    peers_to_ping.push(*peer_index);
+                }
+            }
+        }
+        for peer_index in dead_peers {
+            warn!(
+                "peer : {:?} didn't respond within {:?}ms. disconnecting",
+                peer_index, PEER_TIMEOUT
+            );
+            self.disconnect_from_peer(peer_index).await;
+        }
+        for peer_index in peers_to_ping {
+            self.send_message(peer_index, Message::Ping(current_time).serialize())
+                .await
+                .unwrap();
+        }
+    }
+    pub async fn is_address_banned(&self, address: &String, current_time: u64) -> bool {
+        trace!("waiting for the peers read lock");
+        let peers = self.peers.read().await;
+        trace!("acquired the peers read lock");
+        peers.is_address_banned(address, current_time)
+    }
+    pub async fn is_connected_to(&self, address: &String) -> bool {
+        trace!("waiting for the peers read lock");
+        let peers = self.peers.read().await;
+        trace!("acquired the peers read lock");
+        peers.is_connected_to(address)
+    }
+    pub async fn is_key_banned(&self, public_key: &SaitoPublicKey, current_time: u64) -> bool {
+        trace!("waiting for the peers read lock");
+        let peers = self.peers.read().await;
+        trace!("acquired the peers read lock");
+        peers.is_key_banned(public_key, current_time)
+    }
     pub async fn handle_new_peer(
         &mut self,
         peer_data: Option<data::configuration::PeerConfig>,
         peer_index: u64,
         wallet: Arc<RwLock<Wallet>>,
         configs: Arc<RwLock<Configuration>>,
+        current_time: u64,
     ) {
-        // TODO : if an incoming peer is same as static peer, handle the scenario
         debug!("handing new peer : {:?}", peer_index);
         trace!("waiting for the peers write lock");
         let mut peers = self.peers.write().await;
         trace!("acquired the peers write lock");
         let mut peer = Peer::new(peer_index);
         peer.static_peer_config = peer_data;
+        peer.last_seen = current_time;
+        if peer.static_peer_config.is_some() {
+            let address = peer.get_connection_address().unwrap();
+            self.pending_connections.remove(&address);
+            peers.clear_connection_failures(&address);
+        }
 
         if peer.static_peer_config.is_none() {
             // if we don't have peer data it means this is an incoming connection. so we initiate the handshake
-            peer.initiate_handshake(&self.io_interface, wallet.clone(), configs.clone())
-                .await
-                .unwrap();
+            let result = peer
+                .initiate_handshake(&self.io_interface, wallet.clone(), configs.clone())
+                .await;
+            if result.is_err() {
+                warn!(
+                    "failed initiating handshake with peer : {:?} : {:?}",
+                    peer_index,
+                    result.err().unwrap()
+                );
+            }
         }
 
         peers.index_to_peers.insert(peer_index, peer);
@@ -151,84 +598,221 @@ impl Network {
         challenge: HandshakeChallenge,
         wallet: Arc<RwLock<Wallet>>,
         configs: Arc<RwLock<Configuration>>,
-    ) {
+    ) -> Result<(), Error> {
+        trace!("waiting for the peers write lock");
         let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
         let peer = peers.index_to_peers.get_mut(&peer_index);
         if peer.is_none() {
-            todo!()
+            warn!("handshake challenge from unknown peer : {:?}", peer_index);
+            return Err(Error::from(ErrorKind::NotFound));
         }
         let peer = peer.unwrap();
-        peer.handle_handshake_challenge(
-            challenge,
-            &self.io_interface,
-            wallet.clone(),
-            configs.clone(),
-        )
-        .await
-        .unwrap();
+        let result = peer
+            .handle_handshake_challenge(
+                challenge,
+                &self.io_interface,
+                wallet.clone(),
+                configs.clone(),
+            )
+            .await;
+        if result.is_err() {
+            warn!(
+                "handshake challenge from peer : {:?} rejected : {:?}",
+                peer_index,
+                result.as_ref().err().unwrap()
+            );
+        }
+        result
     }
+    /// Verifies the handshake response of the peer and completes the handshake
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_index`:
+    /// * `response`:
+    /// * `wallet`:
+    /// * `configs`:
+    ///
+    /// returns: Result<Option<u64>, Error> index of an older connection to the same peer which should be closed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
     pub async fn handle_handshake_response(
         &self,
         peer_index: u64,
         response: HandshakeResponse,
         wallet: Arc<RwLock<Wallet>>,
-        blockchain: Arc<RwLock<Blockchain>>,
-    ) {
+        configs: Arc<RwLock<Configuration>>,
+    ) -> Result<Option<u64>, Error> {
         debug!("received handshake response");
-        let mut peers = self.peers.write().await;
-        let peer = peers.index_to_peers.get_mut(&peer_index);
-        if peer.is_none() {
-            todo!()
-        }
-        let peer = peer.unwrap();
-        peer.handle_handshake_response(response, &self.io_interface, wallet.clone())
-            .await
-            .unwrap();
-        if peer.handshake_done {
-            debug!(
-                "peer : {:?} handshake successful for peer : {:?}",
-                peer.peer_index,
-                hex::encode(peer.peer_public_key)
-            );
-            // start block syncing here
-            self.request_blockchain_from_peer(peer_index, blockchain.clone())
+        {
+            trace!("waiting for the peers write lock");
+            let mut peers = self.peers.write().await;
+            trace!("acquired the peers write lock");
+            let peer = peers.index_to_peers.get_mut(&peer_index);
+            if peer.is_none() {
+                warn!("handshake response from unknown peer : {:?}", peer_index);
+                return Err(Error::from(ErrorKind::NotFound));
+            }
+            let peer = peer.unwrap();
+            let result = peer
+                .handle_handshake_response(
+                    response,
+                    &self.io_interface,
+                    wallet.clone(),
+                    configs.clone(),
+                )
                 .await;
+            if result.is_err() {
+                warn!(
+                    "handshake response from peer : {:?} rejected : {:?}",
+                    peer_index,
+                    result.as_ref().err().unwrap()
+                );
+                return Err(result.err().unwrap());
+            }
         }
+        self.complete_handshake(peer_index, wallet).await
     }
+    /// Verifies the handshake completion of the peer
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_index`:
+    /// * `response`:
+    /// * `wallet`:
+    ///
+    /// returns: Result<Option<u64>, Error> index of an older connection to the same peer which should be closed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
     pub async fn handle_handshake_completion(
         &self,
         peer_index: u64,
         response: HandshakeCompletion,
-        blockchain: Arc<RwLock<Blockchain>>,
-    ) {
+        wallet: Arc<RwLock<Wallet>>,
+    ) -> Result<Option<u64>, Error> {
         debug!("received handshake completion");
-        let mut peers = self.peers.write().await;
-        let peer = peers.index_to_peers.get_mut(&peer_index);
-        if peer.is_none() {
-            todo!()
+        {
+            trace!("waiting for the peers write lock");
+            let mut peers = self.peers.write().await;
+            trace!("acquired the peers write lock");
+            let peer = peers.index_to_peers.get_mut(&peer_index);
+            if peer.is_none() {
+                warn!("handshake completion from unknown peer : {:?}", peer_index);
+                return Err(Error::from(ErrorKind::NotFound));
+            }
+            let peer = peer.unwrap();
+            let result = peer
+                .handle_handshake_completion(response, &self.io_interface, wallet.clone())
+                .await;
+            if result.is_err() {
+                warn!(
+                    "handshake completion from peer : {:?} rejected : {:?}",
+                    peer_index,
+                    result.as_ref().err().unwrap()
+                );
+                return Err(result.err().unwrap());
+            }
         }
-        let peer = peer.unwrap();
-        let result = peer
-            .handle_handshake_completion(response, &self.io_interface)
-            .await;
-        if peer.handshake_done {
+        self.complete_handshake(peer_index, wallet).await
+    }
+    /// Registers the verified key of the peer and requests its peers. fails if there is already a connection to the
+    /// same peer which is kept instead of this one
+    async fn complete_handshake(
+        &self,
+        peer_index: u64,
+        wallet: Arc<RwLock<Wallet>>,
+    ) -> Result<Option<u64>, Error> {
+        let own_public_key;
+        {
+            trace!("waiting for the wallet read lock");
+            let wallet = wallet.read().await;
+            trace!("acquired the wallet read lock");
+            own_public_key = wallet.get_publickey();
+        }
+        let replaced_peer;
+        {
+            trace!("waiting for the peers write lock");
+            let mut peers = self.peers.write().await;
+            trace!("acquired the peers write lock");
+            let peer = peers.find_peer_by_index(peer_index);
+            if peer.is_none() || !peer.unwrap().handshake_done {
+                return Ok(None);
+            }
             debug!(
                 "peer : {:?} handshake successful for peer : {:?}",
-                peer.peer_index,
-                hex::encode(peer.peer_public_key)
+                peer_index,
+                hex::encode(peer.unwrap().peer_public_key)
             );
-            // start block syncing here
-            self.request_blockchain_from_peer(peer_index, blockchain.clone())
-                .await;
+            let is_full_node = peer.unwrap().has_service(SERVICE_FULL_NODE);
+            replaced_peer = peers.register_peer_key(peer_index, &own_public_key);
+            if is_full_node && replaced_peer != Some(peer_index) {
+                peers.block_downloads.add_peer_for_lost_blocks(peer_index);
+            }
         }
+        if replaced_peer == Some(peer_index) {
+            debug!(
+                "peer : {:?} is already connected through another connection",
+                peer_index
+            );
+            return Err(Error::from(ErrorKind::AlreadyExists));
+        }
+        self.request_peers_from_peer(peer_index).await;
+        Ok(replaced_peer)
     }
-    async fn request_blockchain_from_peer(
+    /// Starts syncing the blockchain from the peer. Block headers are requested instead of the block hashes if
+    /// headers-first sync is enabled and the peer supports it
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_index`:
+    /// * `blockchain`:
+    /// * `headers_first_sync`: true if headers-first sync is enabled in the configs
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn request_blockchain_from_peer(
         &self,
         peer_index: u64,
         blockchain: Arc<RwLock<Blockchain>>,
+        headers_first_sync: bool,
     ) {
         debug!("requesting blockchain from peer : {:?}", peer_index);
 
+        let request_headers;
+        {
+            trace!("waiting for the peers read lock");
+            let peers = self.peers.read().await;
+            trace!("acquired the peers read lock");
+            let peer = peers.find_peer_by_index(peer_index);
+            if peer.is_none() {
+                debug!("peer : {:?} not found to request blockchain", peer_index);
+                return;
+            }
+            let peer = peer.unwrap();
+            if !peer.has_service(SERVICE_FULL_NODE) {
+                debug!(
+                    "peer : {:?} is not a full node. not requesting blockchain",
+                    peer_index
+                );
+                return;
+            }
+            request_headers = headers_first_sync;
+        }
+
         // TODO : should this be moved inside peer ?
         let request;
         {
@@ -240,11 +824,206 @@ impl Network {
             };
         }
 
-        let buffer = Message::BlockchainRequest(request).serialize();
-        self.io_interface
-            .send_message(peer_index, buffer)
-            .await
-            .unwrap();
+        let buffer = if request_headers {
+            Message::BlockHeadersRequest(request).serialize()
+        } else {
+            Message::BlockchainRequest(request).serialize()
+        };
+        self.send_message(peer_index, buffer).await.unwrap();
+    }
+    /// Requests the block headers after the given block from a full node, to be synced as a lite node
+    pub async fn request_lite_sync_from_peer(
+        &self,
+        peer_index: u64,
+        latest_block_id: u64,
+        latest_block_hash: SaitoHash,
+    ) {
+        {
+            trace!("waiting for the peers read lock");
+            let peers = self.peers.read().await;
+            trace!("acquired the peers read lock");
+            let peer = peers.find_peer_by_index(peer_index);
+            if peer.is_none() {
+                debug!("peer : {:?} not found to request lite sync", peer_index);
+                return;
+            }
+            let peer = peer.unwrap();
+            if !peer.has_service(SERVICE_FULL_NODE) {
+                debug!(
+                    "peer : {:?} doesn't support lite sync. services : {:?}",
+                    peer_index, peer.services
+                );
+                return;
+            }
+        }
+        debug!("requesting lite sync from peer : {:?}", peer_index);
+        // lite nodes don't keep the blockring to generate a fork id. the peer uses our latest block if it's in
+        // the longest chain
+        let request = BlockchainRequest {
+            latest_block_id,
+            latest_block_hash,
+            fork_id: [0; 32],
+        };
+        self.send_message(
+            peer_index,
+            Message::BlockHeadersRequest(request).serialize(),
+        )
+        .await
+        .unwrap();
+    }
+    /// Requests the transactions of the block which are related to the given keys
+    pub async fn request_block_transactions(
+        &self,
+        peer_index: u64,
+        block_hash: SaitoHash,
+        public_keys: Vec<SaitoPublicKey>,
+    ) -> Result<(), Error> {
+        let request = BlockTransactionsRequest {
+            block_hash,
+            public_keys,
+        };
+        self.send_message(
+            peer_index,
+            Message::GetBlockTransactions(request).serialize(),
+        )
+        .await
+    }
+    /// Requests the transactions of a compact block which are not in our mempool
+    pub async fn request_compact_block_transactions(
+        &self,
+        peer_index: u64,
+        block_hash: SaitoHash,
+        indices: Vec<u32>,
+    ) -> Result<(), Error> {
+        debug!(
+            "requesting {:?} transactions of compact block : {:?} from peer : {:?}",
+            indices.len(),
+            hex::encode(block_hash),
+            peer_index
+        );
+        let request = CompactBlockTransactionsRequest {
+            block_hash,
+            indices,
+        };
+        self.send_message(
+            peer_index,
+            Message::GetCompactBlockTransactions(request).serialize(),
+        )
+        .await
+    }
+    /// Schedules the block to be fetched from the block fetch url of the given peer. Requests are sent by
+    /// process_block_downloads
+    pub async fn fetch_block(&self, block_hash: SaitoHash, peer_index: u64) -> Result<(), Error> {
+        trace!("waiting for the peers write lock");
+        let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
+        if peers.find_peer_by_index(peer_index).is_none() {
+            debug!("peer : {:?} not found to fetch block", peer_index);
+            return Err(Error::from(ErrorKind::NotFound));
+        }
+        peers
+            .block_downloads
+            .add_block_announcement(block_hash, peer_index);
+        Ok(())
+    }
+    /// Sends the scheduled block fetch requests to the peers, and retries the timed out ones from other peers
+    ///
+    /// # Arguments
+    ///
+    /// * `current_time`: timestamp in milliseconds
+    ///
+    /// returns: Vec<SaitoHash> : hashes of the blocks which are abandoned since no peer could send them
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn process_block_downloads(&self, current_time: u64) -> Vec<SaitoHash> {
+        let mut requests = vec![];
+        let abandoned;
+        {
+            trace!("waiting for the peers write lock");
+            let mut peers = self.peers.write().await;
+            trace!("acquired the peers write lock");
+            abandoned = peers.block_downloads.check_timeouts(current_time);
+            for (block_hash, peer_index) in peers.block_downloads.schedule(current_time) {
+                let mut url = None;
+                let mut over_socket = false;
+                if let Some(peer) = peers.find_peer_by_index(peer_index) {
+                    // http is used whenever the peer serves it, since the block doesn't have to wait behind the
+                    // other messages of the connection. peers older than the services always serve it
+                    if peer.serves_socket_blocks() && !peer.has_service(SERVICE_SERVES_HTTP_BLOCKS)
+                    {
+                        over_socket = true;
+                    } else {
+                        url = Some(peer.get_block_fetch_url(block_hash));
+                    }
+                }
+                requests.push((block_hash, peer_index, url, over_socket));
+            }
+        }
+        for (block_hash, peer_index, url, over_socket) in requests {
+            debug!(
+                "fetching block : {:?} from peer : {:?} over socket : {:?}",
+                hex::encode(block_hash),
+                peer_index,
+                over_socket
+            );
+            let mut result = Err(Error::from(ErrorKind::NotFound));
+            if over_socket {
+                let buffer =
+                    Message::BlockFetchRequest(BlockFetchRequest { block_hash }).serialize();
+                result = self.send_message(peer_index, buffer).await;
+            } else if let Some(url) = url {
+                result = self
+                    .io_interface
+                    .fetch_block_from_peer(block_hash, peer_index, url)
+                    .await;
+            }
+            if result.is_err() {
+                warn!(
+                    "failed requesting block : {:?} from peer : {:?}",
+                    hex::encode(block_hash),
+                    peer_index
+                );
+                self.handle_block_fetch_failure(block_hash, peer_index)
+                    .await;
+            }
+        }
+        abandoned
+    }
+    pub async fn handle_block_fetched(
+        &self,
+        block_hash: SaitoHash,
+        peer_index: u64,
+        current_time: u64,
+    ) {
+        trace!("waiting for the peers write lock");
+        let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
+        peers
+            .block_downloads
+            .on_block_fetched(&block_hash, peer_index, current_time);
+    }
+    /// Checks if the block is currently requested from the given peer, so the unrequested responses can be dropped
+    pub async fn is_block_requested_from(&self, block_hash: &SaitoHash, peer_index: u64) -> bool {
+        trace!("waiting for the peers read lock");
+        let peers = self.peers.read().await;
+        trace!("acquired the peers read lock");
+        peers
+            .block_downloads
+            .is_requested_from(block_hash, peer_index)
+    }
+    /// Marks the fetch as failed so the block is requested from another peer.
+    /// returns false if there are no more peers to fetch the block from
+    pub async fn handle_block_fetch_failure(&self, block_hash: SaitoHash, peer_index: u64) -> bool {
+        trace!("waiting for the peers write lock");
+        let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
+        peers
+            .block_downloads
+            .on_block_fetch_failed(&block_hash, peer_index)
     }
     pub async fn process_incoming_block_hash(
         &self,
@@ -254,37 +1033,238 @@ impl Network {
     ) {
         let block_exists;
         {
+            trace!("waiting for the blockchain read lock");
             let blockchain = blockchain.read().await;
+            trace!("acquired the blockchain read lock");
             block_exists = blockchain.is_block_indexed(block_hash);
         }
-        let url;
+        if block_exists {
+            trace!("block : {:?} already exists", hex::encode(block_hash));
+            return;
+        }
+        let result = self.fetch_block(block_hash, peer_index).await;
+        if result.is_err() {
+            warn!(
+                "failed fetching block : {:?} from peer : {:?}",
+                hex::encode(block_hash),
+                peer_index
+            );
+        }
+    }
+    pub async fn connect_to_peer(&mut self, peer: data::configuration::PeerConfig) {
+        self.pending_connections
+            .insert(peer.host.clone() + ":" + peer.port.to_string().as_str());
+        self.io_interface.connect_to_peer(peer).await.unwrap();
+    }
+    /// Records the failed connection attempt, so the address book entry of the peer is backed off or removed
+    ///
+    /// # Arguments
+    ///
+    /// * `peer`:
+    /// * `current_time`: current timestamp in milliseconds
+    ///
+    /// returns: bool true if the peer is removed from the address book
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn handle_failed_connection(
+        &mut self,
+        peer: &data::configuration::PeerConfig,
+        current_time: u64,
+    ) -> bool {
+        debug!("connecting to peer : {:?} failed", peer);
+        self.pending_connections
+            .remove(&(peer.host.clone() + ":" + peer.port.to_string().as_str()));
+        trace!("waiting for the peers write lock");
+        let mut peers = self.peers.write().await;
+        trace!("acquired the peers write lock");
+        peers.record_connection_failure(peer, current_time)
+    }
+    /// Public key of the peer if the handshake with it is done
+    pub async fn get_handshaken_peer_key(&self, peer_index: u64) -> Option<SaitoPublicKey> {
+        trace!("waiting for the peers read lock");
+        let peers = self.peers.read().await;
+        trace!("acquired the peers read lock");
+        peers
+            .find_peer_by_index(peer_index)
+            .filter(|peer| peer.handshake_done)
+            .map(|peer| peer.peer_public_key)
+    }
+    /// Sends the application message to the peer with the given public key, or to all the handshaken peers if no key is
+    /// given
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient`: public key of the receiving peer. None to send to all the peers
+    /// * `message`:
+    ///
+    /// returns: Result<(), Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn send_application_message(
+        &self,
+        recipient: Option<SaitoPublicKey>,
+        message: ApplicationMessage,
+    ) -> Result<(), Error> {
+        let mut receivers = vec![];
         {
+            trace!("waiting for the peers read lock");
             let peers = self.peers.read().await;
-            let peer = peers
-                .index_to_peers
-                .get(&peer_index)
-                .expect("peer not found");
-            url = peer.get_block_fetch_url(block_hash);
+            trace!("acquired the peers read lock");
+            for (peer_index, peer) in peers.index_to_peers.iter() {
+                if !peer.handshake_done {
+                    continue;
+                }
+                if recipient.is_some() && recipient != Some(peer.peer_public_key) {
+                    continue;
+                }
+                receivers.push(*peer_index);
+            }
         }
-        if !block_exists {
-            self.io_interface
-                .fetch_block_from_peer(block_hash, peer_index, url)
-                .await
-                .unwrap();
+        if let Some(recipient) = recipient {
+            if receivers.is_empty() {
+                debug!("peer : {:?} is not connected", hex::encode(recipient));
+                return Err(Error::from(ErrorKind::NotFound));
+            }
+        }
+        let buffer = Message::ApplicationMessage(message).serialize();
+        for peer_index in receivers {
+            self.send_message(peer_index, buffer.clone()).await?;
         }
+        Ok(())
     }
-    pub async fn connect_to_static_peers(&mut self, configs: Arc<RwLock<Configuration>>) {
-        debug!("connect to peers from config",);
+    pub async fn request_peers_from_peer(&self, peer_index: u64) {
+        debug!("requesting peers from peer : {:?}", peer_index);
+        self.send_message(peer_index, Message::GetPeers.serialize())
+            .await
+            .unwrap();
+    }
+    pub async fn process_incoming_get_peers(&self, peer_index: u64) {
+        debug!("processing peer list request from peer : {:?}", peer_index);
+        let peers;
+        {
+            trace!("waiting for the peers read lock");
+            let peer_collection = self.peers.read().await;
+            trace!("acquired the peers read lock");
+            let peer = peer_collection.find_peer_by_index(peer_index);
+            if peer.is_none() || !peer.unwrap().handshake_done {
+                warn!(
+                    "peer list requested by peer : {:?} before handshake",
+                    peer_index
+                );
+                return;
+            }
+            peers = peer_collection.get_shareable_peers();
+        }
+        let buffer = Message::Peers(PeerList { peers }).serialize();
+        self.send_message(peer_index, buffer).await.unwrap();
+    }
+    /// Adds the valid peer addresses to the address book. returns true if the address book was changed
+    pub async fn add_to_address_book(
+        &self,
+        peers: Vec<data::configuration::PeerConfig>,
+        configs: Arc<RwLock<Configuration>>,
+    ) -> bool {
         trace!("waiting for the configs read lock");
         let configs = configs.read().await;
         trace!("acquired the configs read lock");
+        trace!("waiting for the peers write lock");
+        let mut peer_collection = self.peers.write().await;
+        trace!("acquired the peers write lock");
 
-        for peer in &configs.peers {
-            self.io_interface
-                .connect_to_peer(peer.clone())
-                .await
-                .unwrap();
+        let mut changed = false;
+        for peer in peers {
+            if peer.host.is_empty()
+                || peer.port == 0
+                || (peer.protocol != "http" && peer.protocol != "https")
+                || configs.is_own_address(&peer)
+            {
+                trace!("skipping peer address : {:?}", peer);
+                continue;
+            }
+            if peer_collection.add_to_address_book(peer) {
+                changed = true;
+            }
+        }
+        changed
+    }
+    pub async fn get_address_book(&self) -> Vec<data::configuration::PeerConfig> {
+        trace!("waiting for the peers read lock");
+        let peers = self.peers.read().await;
+        trace!("acquired the peers read lock");
+        peers.address_book.clone()
+    }
+    /// Connects to randomly selected peers from the address book until the configured number of outgoing connections
+    /// is reached. entries backed off after failed connection attempts are skipped
+    ///
+    /// # Arguments
+    ///
+    /// * `configs`:
+    /// * `current_time`: current timestamp in milliseconds. used to skip the banned and the backed off peers
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn fill_outbound_slots(
+        &mut self,
+        configs: Arc<RwLock<Configuration>>,
+        current_time: u64,
+    ) {
+        let max_outbound_peers;
+        let static_peers;
+        {
+            trace!("waiting for the configs read lock");
+            let configs = configs.read().await;
+            trace!("acquired the configs read lock");
+            max_outbound_peers = configs.max_outbound_peers;
+            static_peers = configs.peers.clone();
+        }
+        let mut candidates = vec![];
+        {
+            trace!("waiting for the peers read lock");
+            let peers = self.peers.read().await;
+            trace!("acquired the peers read lock");
+            let outbound_count = peers.get_outbound_peer_count() + self.pending_connections.len();
+            if outbound_count >= max_outbound_peers {
+                return;
+            }
+            for entry in peers.address_book.iter() {
+                // static peers are reconnected separately with their own schedule
+                if static_peers
+                    .iter()
+                    .any(|peer| peer.host == entry.host && peer.port == entry.port)
+                {
+                    continue;
+                }
+                let address = entry.host.clone() + ":" + entry.port.to_string().as_str();
+                if self.pending_connections.contains(&address)
+                    || peers.is_connected_to(&address)
+                    || peers.is_address_banned(&address, current_time)
+                    || peers.is_address_backed_off(&address, current_time)
+                {
+                    continue;
+                }
+                candidates.push(entry.clone());
+            }
+            // random selection, so the same unreachable entries at the start of the address book don't take all the
+            // attempts
+            candidates.shuffle(&mut rand::thread_rng());
+            candidates.truncate(max_outbound_peers - outbound_count);
+        }
+        for peer in candidates {
+            debug!("connecting to peer : {:?} from address book", peer);
+            self.connect_to_peer(peer).await;
         }
-        debug!("connected to peers");
     }
 }
@@ -1,20 +1,45 @@
-use std::io::Error;
+use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 
-use log::{debug, warn};
+use log::{debug, trace, warn};
 use tokio::sync::RwLock;
 
-use crate::common::defs::{SaitoHash, SaitoPublicKey};
+use crate::common::defs::{SaitoHash, SaitoPrivateKey, SaitoPublicKey};
 use crate::common::interface_io::InterfaceIO;
 use crate::core::data;
 use crate::core::data::configuration::Configuration;
-use crate::core::data::crypto::{generate_random_bytes, sign, verify};
+use crate::core::data::crypto::{generate_random_bytes, generate_shared_secret, sign, verify};
 use crate::core::data::msg::handshake::{
-    HandshakeChallenge, HandshakeCompletion, HandshakeResponse,
+    get_handshake_signature_data, HandshakeChallenge, HandshakeCompletion, HandshakeResponse,
+    MIN_SUPPORTED_PROTOCOL_VERSION, PROTOCOL_VERSION, SERVICE_SERVES_SOCKET_BLOCKS,
 };
 use crate::core::data::msg::message::Message;
+use crate::core::data::rate_limiter::PeerRateLimiter;
+use crate::core::data::session::PeerSession;
 use crate::core::data::wallet::Wallet;
 
+/// Misbehaviours which increase the misbehaviour score of a peer
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PeerMisbehaviour {
+    InvalidMessage,
+    InvalidHandshake,
+    InvalidBlock,
+    BlockFetchFailed,
+    RateLimitExceeded,
+}
+
+impl PeerMisbehaviour {
+    pub fn get_score(&self) -> u32 {
+        match self {
+            PeerMisbehaviour::InvalidMessage => 20,
+            PeerMisbehaviour::InvalidHandshake => 100,
+            PeerMisbehaviour::InvalidBlock => 50,
+            PeerMisbehaviour::BlockFetchFailed => 10,
+            PeerMisbehaviour::RateLimitExceeded => 20,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Peer {
     pub peer_index: u64,
@@ -23,7 +48,30 @@ pub struct Peer {
     // if this is None(), it means an incoming connection. else a connection which we started from the data from config file
     pub static_peer_config: Option<data::configuration::PeerConfig>,
     pub challenge_for_peer: Option<SaitoHash>,
+    // challenge received from the peer which initiated the handshake. used to derive the session keys
+    pub challenge_from_peer: Option<SaitoHash>,
     pub handshake_done: bool,
+    // protocol version agreed with the peer (lowest of the two versions)
+    pub protocol_version: u32,
+    // protocol version advertised by the peer in the handshake. signed by both sides
+    pub peer_protocol_version: u32,
+    // services bitfield advertised by the peer in the handshake
+    pub services: u64,
+    // accumulated score of the misbehaviours of the peer. peer is banned when this reaches the configured threshold
+    pub misbehaviour_score: u32,
+    // timestamp of the last message received from the peer
+    pub last_seen: u64,
+    // round trip time in milliseconds measured with the last ping
+    pub round_trip_time: u64,
+    // timestamp of the ping waiting for a pong from the peer
+    pub ping_sent_at: Option<u64>,
+    // total bytes of the messages received from the peer
+    pub bytes_received: u64,
+    // total bytes of the messages sent to the peer
+    pub bytes_sent: u64,
+    pub rate_limiter: PeerRateLimiter,
+    // encrypted session set up after the handshake. None until the handshake is done
+    pub session: Option<PeerSession>,
 }
 
 impl Peer {
@@ -34,8 +82,111 @@ impl Peer {
             block_fetch_url: "".to_string(),
             static_peer_config: None,
             challenge_for_peer: None,
+            challenge_from_peer: None,
             handshake_done: false,
+            protocol_version: 0,
+            peer_protocol_version: 0,
+            services: 0,
+            misbehaviour_score: 0,
+            last_seen: 0,
+            round_trip_time: 0,
+            ping_sent_at: None,
+            bytes_received: 0,
+            bytes_sent: 0,
+            rate_limiter: Default::default(),
+            session: None,
+        }
+    }
+    /// Checks if the peer has advertised the given service (SERVICE_* flags in handshake module)
+    pub fn has_service(&self, service: u64) -> bool {
+        self.services & service == service
+    }
+    /// Checks if the blocks can be requested from the peer over the connection
+    pub fn serves_socket_blocks(&self) -> bool {
+        self.has_service(SERVICE_SERVES_SOCKET_BLOCKS)
+    }
+    /// Records the round trip time if the pong is for the last ping we sent
+    pub fn handle_pong(&mut self, timestamp: u64, current_time: u64) {
+        if self.ping_sent_at != Some(timestamp) {
+            debug!(
+                "unexpected pong : {:?} from peer : {:?}",
+                timestamp, self.peer_index
+            );
+            return;
+        }
+        self.ping_sent_at = None;
+        self.round_trip_time = current_time.saturating_sub(timestamp);
+        trace!(
+            "peer : {:?} round trip time : {:?}ms",
+            self.peer_index,
+            self.round_trip_time
+        );
+    }
+    /// Address used to connect to the peer. only available for static peers
+    pub fn get_connection_address(&self) -> Option<String> {
+        self.static_peer_config
+            .as_ref()
+            .map(|config| config.host.clone() + ":" + config.port.to_string().as_str())
+    }
+    fn set_peer_capabilities(&mut self, protocol_version: u32, services: u64) -> Result<(), Error> {
+        if protocol_version < MIN_SUPPORTED_PROTOCOL_VERSION {
+            warn!(
+                "peer : {:?} protocol version : {:?} is not supported. minimum supported version : {:?}",
+                self.peer_index, protocol_version, MIN_SUPPORTED_PROTOCOL_VERSION
+            );
+            return Err(Error::from(ErrorKind::Unsupported));
+        }
+        self.peer_protocol_version = protocol_version;
+        self.protocol_version = std::cmp::min(protocol_version, PROTOCOL_VERSION);
+        self.services = services;
+        debug!(
+            "peer : {:?} protocol version : {:?} services : {:?}",
+            self.peer_index, self.protocol_version, self.services
+        );
+        Ok(())
+    }
+    /// Sets up the encrypted session once the handshake is done. all the messages after the handshake go through it
+    ///
+    /// # Arguments
+    ///
+    /// * `privatekey`: private key of our wallet
+    /// * `initiator_challenge`: challenge sent by the side which initiated the handshake
+    /// * `responder_challenge`: challenge sent by the other side
+    /// * `is_initiator`: true if we initiated the handshake
+    ///
+    /// returns: Result<(), Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    fn start_session(
+        &mut self,
+        privatekey: &SaitoPrivateKey,
+        initiator_challenge: &SaitoHash,
+        responder_challenge: &SaitoHash,
+        is_initiator: bool,
+    ) -> Result<(), Error> {
+        let shared_secret = generate_shared_secret(&self.peer_public_key, privatekey);
+        if shared_secret.is_none() {
+            warn!(
+                "failed generating the shared secret for peer : {:?}",
+                self.peer_index
+            );
+            return Err(Error::from(ErrorKind::InvalidData));
         }
+        self.session = Some(PeerSession::new(
+            &shared_secret.unwrap(),
+            initiator_challenge,
+            responder_challenge,
+            is_initiator,
+        ));
+        debug!(
+            "encrypted session started with peer : {:?}",
+            self.peer_index
+        );
+        Ok(())
     }
     pub async fn initiate_handshake(
         &mut self,
@@ -46,21 +197,23 @@ impl Peer {
         debug!("initiating handshake : {:?}", self.peer_index);
         let wallet = wallet.read().await;
         let block_fetch_url;
+        let services;
         {
             let configs = configs.read().await;
             block_fetch_url = configs.get_block_fetch_url();
+            services = configs.get_services();
         }
         let challenge = HandshakeChallenge {
             public_key: wallet.publickey,
             challenge: generate_random_bytes(32).try_into().unwrap(),
+            protocol_version: PROTOCOL_VERSION,
+            services,
             block_fetch_url,
         };
         self.challenge_for_peer = Some(challenge.challenge);
-        let message = Message::HandshakeChallenge(challenge);
-        io_handler
-            .send_message(self.peer_index, message.serialize())
-            .await
-            .unwrap();
+        let buffer = Message::HandshakeChallenge(challenge).serialize();
+        self.bytes_sent += buffer.len() as u64;
+        io_handler.send_message(self.peer_index, buffer).await?;
         debug!("handshake challenge sent for peer: {:?}", self.peer_index);
 
         Ok(())
@@ -78,29 +231,42 @@ impl Peer {
             hex::encode(challenge.public_key)
         );
         let block_fetch_url;
+        let services;
         {
             let configs = configs.read().await;
             block_fetch_url = configs.get_block_fetch_url();
+            services = configs.get_services();
         }
 
+        let wallet = wallet.read().await;
+        if challenge.public_key == wallet.publickey {
+            warn!("peer : {:?} is a connection to ourselves", self.peer_index);
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+        self.set_peer_capabilities(challenge.protocol_version, challenge.services)?;
         self.peer_public_key = challenge.public_key;
         self.block_fetch_url = challenge.block_fetch_url;
-        let wallet = wallet.read().await;
+        self.challenge_from_peer = Some(challenge.challenge);
+        let signature_data = get_handshake_signature_data(
+            &challenge.challenge,
+            &block_fetch_url,
+            services,
+            PROTOCOL_VERSION,
+            challenge.protocol_version,
+        );
         let response = HandshakeResponse {
             public_key: wallet.publickey,
-            signature: sign(&challenge.challenge.to_vec(), wallet.privatekey),
+            signature: sign(&signature_data, wallet.privatekey),
             challenge: generate_random_bytes(32).try_into().unwrap(),
+            protocol_version: PROTOCOL_VERSION,
+            services,
             block_fetch_url,
         };
 
         self.challenge_for_peer = Some(response.challenge);
-        io_handler
-            .send_message(
-                self.peer_index,
-                Message::HandshakeResponse(response).serialize(),
-            )
-            .await
-            .unwrap();
+        let buffer = Message::HandshakeResponse(response).serialize();
+        self.bytes_sent += buffer.len() as u64;
+        io_handler.send_message(self.peer_index, buffer).await?;
         debug!("handshake response sent for peer: {:?}", self.peer_index);
 
         Ok(())
@@ -110,6 +276,7 @@ impl Peer {
         response: HandshakeResponse,
         io_handler: &Box<dyn InterfaceIO + Send + Sync>,
         wallet: Arc<RwLock<Wallet>>,
+        configs: Arc<RwLock<Configuration>>,
     ) -> Result<(), Error> {
         debug!(
             "handling handshake response :{:?} with address : {:?}",
@@ -121,30 +288,59 @@ impl Peer {
                 "we don't have a challenge to verify for peer : {:?}",
                 self.peer_index
             );
-            // TODO : handle the scenario.
-            todo!()
+            return Err(Error::from(ErrorKind::InvalidData));
         }
         let sent_challenge = self.challenge_for_peer.unwrap();
-        let result = verify(&sent_challenge, response.signature, response.public_key);
+        let wallet = wallet.read().await;
+        if response.public_key == wallet.publickey {
+            warn!("peer : {:?} is a connection to ourselves", self.peer_index);
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+        // a changed version of either side fails the signature check
+        self.set_peer_capabilities(response.protocol_version, response.services)?;
+        let signature_data = get_handshake_signature_data(
+            &sent_challenge,
+            &response.block_fetch_url,
+            response.services,
+            response.protocol_version,
+            PROTOCOL_VERSION,
+        );
+        let result = verify(&signature_data, response.signature, response.public_key);
         if !result {
             warn!("handshake failed. signature is not valid");
-            todo!()
+            return Err(Error::from(ErrorKind::InvalidData));
         }
         self.challenge_for_peer = None;
         self.peer_public_key = response.public_key;
         self.block_fetch_url = response.block_fetch_url;
+        self.start_session(
+            &wallet.privatekey,
+            &sent_challenge,
+            &response.challenge,
+            true,
+        )?;
         self.handshake_done = true;
-        let wallet = wallet.read().await;
+        let block_fetch_url;
+        let services;
+        {
+            let configs = configs.read().await;
+            block_fetch_url = configs.get_block_fetch_url();
+            services = configs.get_services();
+        }
+        let signature_data = get_handshake_signature_data(
+            &response.challenge,
+            &block_fetch_url,
+            services,
+            PROTOCOL_VERSION,
+            self.peer_protocol_version,
+        );
         let response = HandshakeCompletion {
-            signature: sign(&response.challenge, wallet.privatekey),
+            signature: sign(&signature_data, wallet.privatekey),
         };
-        io_handler
-            .send_message(
-                self.peer_index,
-                Message::HandshakeCompletion(response).serialize(),
-            )
-            .await
-            .unwrap();
+        // completion is sent in plaintext since the peer sets up the session only after verifying it
+        let buffer = Message::HandshakeCompletion(response).serialize();
+        self.bytes_sent += buffer.len() as u64;
+        io_handler.send_message(self.peer_index, buffer).await?;
         debug!("handshake completion sent for peer: {:?}", self.peer_index);
         Ok(())
     }
@@ -152,6 +348,7 @@ impl Peer {
         &mut self,
         response: HandshakeCompletion,
         _io_handler: &Box<dyn InterfaceIO + Send + Sync>,
+        wallet: Arc<RwLock<Wallet>>,
     ) -> Result<(), Error> {
         debug!("handling handshake completion : {:?}", self.peer_index);
         if self.challenge_for_peer.is_none() {
@@ -159,16 +356,41 @@ impl Peer {
                 "we don't have a challenge to verify for peer : {:?}",
                 self.peer_index
             );
-            // TODO : handle the scenario.
-            todo!()
+            return Err(Error::from(ErrorKind::InvalidData));
         }
         let sent_challenge = self.challenge_for_peer.unwrap();
-        let result = verify(&sent_challenge, response.signature, self.peer_public_key);
+        // url, services and the version were received with the challenge of the peer
+        let signature_data = get_handshake_signature_data(
+            &sent_challenge,
+            &self.block_fetch_url,
+            self.services,
+            self.peer_protocol_version,
+            PROTOCOL_VERSION,
+        );
+        let result = verify(&signature_data, response.signature, self.peer_public_key);
         if !result {
             warn!("handshake failed. signature is not valid");
-            todo!()
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        if self.challenge_from_peer.is_none() {
+            warn!(
+                "we don't have the challenge of peer : {:?} to start the session",
+                self.peer_index
+            );
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let received_challenge = self.challenge_from_peer.unwrap();
+        {
+            let wallet = wallet.read().await;
+            self.start_session(
+                &wallet.privatekey,
+                &received_challenge,
+                &sent_challenge,
+                false,
+            )?;
         }
         self.challenge_for_peer = None;
+        self.challenge_from_peer = None;
         self.handshake_done = true;
         Ok(())
     }
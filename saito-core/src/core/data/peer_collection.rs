@@ -1,12 +1,45 @@
 use std::collections::HashMap;
 
+use log::debug;
+
 use crate::common::defs::SaitoPublicKey;
+use crate::core::data::block_download::BlockDownloadScheduler;
+use crate::core::data::configuration::PeerConfig;
+use crate::core::data::msg::peer_list::MAX_PEERS_IN_PEER_LIST;
 use crate::core::data::peer::Peer;
 
+// maximum number of peer addresses remembered in the address book
+pub const MAX_ADDRESS_BOOK_SIZE: usize = 1000;
+// address book entries are removed after this many consecutive failed connection attempts
+pub const MAX_ADDRESS_CONNECTION_FAILURES: u32 = 5;
+// delay in milliseconds before retrying an address book entry after a failed connection attempt. doubled after each
+// consecutive failure
+pub const ADDRESS_RETRY_BASE_DELAY: u64 = 30_000;
+
+/// Consecutive failed connection attempts to an address book entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionFailures {
+    pub count: u32,
+    // entry is not tried again before this timestamp
+    pub retry_time: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct PeerCollection {
     pub index_to_peers: HashMap<u64, Peer>,
     pub address_to_peers: HashMap<SaitoPublicKey, u64>,
+    // verified keys of the peers at the addresses we connected to. used to find the peers which are connected to us
+    // through a connection started from their side
+    pub connection_address_keys: HashMap<String, SaitoPublicKey>,
+    // banned peer keys and addresses with the timestamp the ban expires at
+    pub banned_keys: HashMap<SaitoPublicKey, u64>,
+    pub banned_addresses: HashMap<String, u64>,
+    // addresses of the peers we have learned about. persisted to disk so they can be used after a restart
+    pub address_book: Vec<PeerConfig>,
+    // failed connection attempts to the address book entries, keyed by the address. not persisted
+    pub connection_failures: HashMap<String, ConnectionFailures>,
+    // block fetches from all the event processors are scheduled here, since the results are received by the router
+    pub block_downloads: BlockDownloadScheduler,
 }
 
 impl PeerCollection {
@@ -14,6 +47,12 @@ impl PeerCollection {
         PeerCollection {
             index_to_peers: Default::default(),
             address_to_peers: Default::default(),
+            connection_address_keys: Default::default(),
+            banned_keys: Default::default(),
+            banned_addresses: Default::default(),
+            address_book: vec![],
+            connection_failures: Default::default(),
+            block_downloads: BlockDownloadScheduler::new(),
         }
     }
 
@@ -29,4 +68,356 @@ impl PeerCollection {
     pub fn find_peer_by_index(&self, peer_index: u64) -> Option<&Peer> {
         return self.index_to_peers.get(&peer_index);
     }
+
+    /// Records the verified key of the peer after the handshake, so the peer can be found by its key. If there is
+    /// another connection with the same key only one of them is kept. When the connections are started from different
+    /// sides, the one started by the node with the lower key is kept so both nodes close the same connection.
+    /// Otherwise the newer connection is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_index`: index of the peer which completed the handshake
+    /// * `own_public_key`: public key of our wallet
+    ///
+    /// returns: Option<u64> index of the connection to be closed if there is a duplicate connection
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn register_peer_key(
+        &mut self,
+        peer_index: u64,
+        own_public_key: &SaitoPublicKey,
+    ) -> Option<u64> {
+        let peer = self.index_to_peers.get(&peer_index)?;
+        let public_key = peer.peer_public_key;
+        let is_outgoing = peer.static_peer_config.is_some();
+        if let Some(address) = peer.get_connection_address() {
+            self.connection_address_keys.insert(address, public_key);
+        }
+
+        let existing_peer = self
+            .address_to_peers
+            .get(&public_key)
+            .and_then(|index| self.index_to_peers.get(index))
+            .filter(|existing_peer| existing_peer.peer_index != peer_index);
+        if existing_peer.is_none() {
+            self.address_to_peers.insert(public_key, peer_index);
+            return None;
+        }
+        let existing_peer = existing_peer.unwrap();
+        let existing_index = existing_peer.peer_index;
+        let keep_new_connection = if is_outgoing == existing_peer.static_peer_config.is_some() {
+            true
+        } else {
+            // new connection is kept if it was started by the node with the lower key
+            is_outgoing == (*own_public_key < public_key)
+        };
+        debug!(
+            "peer : {:?} has connections : {:?} and : {:?}. keeping : {:?}",
+            hex::encode(public_key),
+            existing_index,
+            peer_index,
+            if keep_new_connection {
+                peer_index
+            } else {
+                existing_index
+            }
+        );
+        if keep_new_connection {
+            self.address_to_peers.insert(public_key, peer_index);
+            return Some(existing_index);
+        }
+        Some(peer_index)
+    }
+
+    /// Bans the public key and the connection address (if known) of the peer until the given time
+    pub fn ban_peer(&mut self, peer_index: u64, banned_until: u64) {
+        let peer = self.index_to_peers.get(&peer_index);
+        if peer.is_none() {
+            return;
+        }
+        let peer = peer.unwrap();
+        if peer.peer_public_key != [0; 33] {
+            self.banned_keys.insert(peer.peer_public_key, banned_until);
+        }
+        if let Some(address) = peer.get_connection_address() {
+            self.banned_addresses.insert(address, banned_until);
+        }
+    }
+
+    pub fn is_key_banned(&self, public_key: &SaitoPublicKey, current_time: u64) -> bool {
+        let result = self.banned_keys.get(public_key);
+        result.is_some() && *result.unwrap() > current_time
+    }
+
+    pub fn is_address_banned(&self, address: &String, current_time: u64) -> bool {
+        let result = self.banned_addresses.get(address);
+        result.is_some() && *result.unwrap() > current_time
+    }
+
+    /// Adds the peer to the address book. returns true if the address book was changed
+    pub fn add_to_address_book(&mut self, peer: PeerConfig) -> bool {
+        if self.address_book.len() >= MAX_ADDRESS_BOOK_SIZE {
+            return false;
+        }
+        if self
+            .address_book
+            .iter()
+            .any(|entry| entry.host == peer.host && entry.port == peer.port)
+        {
+            return false;
+        }
+        self.address_book.push(peer);
+        true
+    }
+
+    /// Records a failed connection attempt to an address book entry. The entry is tried again after an exponential
+    /// backoff, and removed from the address book after [`MAX_ADDRESS_CONNECTION_FAILURES`] consecutive failures
+    ///
+    /// # Arguments
+    ///
+    /// * `peer`: address book entry we failed to connect to
+    /// * `current_time`: current timestamp in milliseconds
+    ///
+    /// returns: bool true if the entry is removed from the address book
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn record_connection_failure(&mut self, peer: &PeerConfig, current_time: u64) -> bool {
+        let position = self
+            .address_book
+            .iter()
+            .position(|entry| entry.host == peer.host && entry.port == peer.port);
+        if position.is_none() {
+            return false;
+        }
+        let address = peer.host.clone() + ":" + peer.port.to_string().as_str();
+        let count = self
+            .connection_failures
+            .get(&address)
+            .map_or(0, |failures| failures.count)
+            + 1;
+        if count >= MAX_ADDRESS_CONNECTION_FAILURES {
+            debug!(
+                "removing {:?} from the address book after {:?} failed connection attempts",
+                address, count
+            );
+            self.connection_failures.remove(&address);
+            self.address_book.remove(position.unwrap());
+            return true;
+        }
+        self.connection_failures.insert(
+            address,
+            ConnectionFailures {
+                count,
+                retry_time: current_time + ADDRESS_RETRY_BASE_DELAY * (1 << (count - 1)),
+            },
+        );
+        false
+    }
+
+    pub fn clear_connection_failures(&mut self, address: &String) {
+        self.connection_failures.remove(address);
+    }
+
+    /// Checks if the address book entry is waiting to be retried after a failed connection attempt
+    pub fn is_address_backed_off(&self, address: &String, current_time: u64) -> bool {
+        self.connection_failures
+            .get(address)
+            .is_some_and(|failures| failures.retry_time > current_time)
+    }
+
+    /// Returns the addresses which can be shared with other peers. Connected outgoing peers are listed first
+    pub fn get_shareable_peers(&self) -> Vec<PeerConfig> {
+        let mut peers: Vec<PeerConfig> = self
+            .index_to_peers
+            .values()
+            .filter(|peer| peer.handshake_done && peer.static_peer_config.is_some())
+            .map(|peer| peer.static_peer_config.clone().unwrap())
+            .collect();
+        for entry in self.address_book.iter() {
+            if peers.len() >= MAX_PEERS_IN_PEER_LIST {
+                break;
+            }
+            if !peers.contains(entry) {
+                peers.push(entry.clone());
+            }
+        }
+        peers.truncate(MAX_PEERS_IN_PEER_LIST);
+        peers
+    }
+
+    /// Checks if there is a connection to the given address, or to the peer we know to be at that address
+    pub fn is_connected_to(&self, address: &String) -> bool {
+        if self
+            .index_to_peers
+            .values()
+            .any(|peer| peer.get_connection_address().as_ref() == Some(address))
+        {
+            return true;
+        }
+        // peer can be connected through a connection started from its side
+        let public_key = self.connection_address_keys.get(address);
+        public_key.is_some() && self.find_peer_by_address(public_key.unwrap()).is_some()
+    }
+
+    pub fn get_outbound_peer_count(&self) -> usize {
+        self.index_to_peers
+            .values()
+            .filter(|peer| peer.static_peer_config.is_some())
+            .count()
+    }
+
+    pub fn remove_expired_bans(&mut self, current_time: u64) {
+        self.banned_keys
+            .retain(|_, banned_until| *banned_until > current_time);
+        self.banned_addresses
+            .retain(|_, banned_until| *banned_until > current_time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::configuration::PeerConfig;
+    use crate::core::data::peer::Peer;
+    use crate::core::data::peer_collection::{
+        PeerCollection, ADDRESS_RETRY_BASE_DELAY, MAX_ADDRESS_CONNECTION_FAILURES,
+    };
+
+    #[test]
+    fn test_ban_peer() {
+        let mut peers = PeerCollection::new();
+        let mut peer = Peer::new(1);
+        peer.peer_public_key = [1; 33];
+        peer.static_peer_config = Some(PeerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 12101,
+            protocol: "http".to_string(),
+            synctype: "full".to_string(),
+        });
+        peers.index_to_peers.insert(1, peer);
+
+        peers.ban_peer(1, 1000);
+
+        assert!(peers.is_key_banned(&[1; 33], 500));
+        assert!(!peers.is_key_banned(&[2; 33], 500));
+        assert!(peers.is_address_banned(&"127.0.0.1:12101".to_string(), 500));
+        assert!(!peers.is_key_banned(&[1; 33], 1000));
+
+        peers.remove_expired_bans(1000);
+        assert!(peers.banned_keys.is_empty());
+        assert!(peers.banned_addresses.is_empty());
+    }
+
+    #[test]
+    fn test_address_book() {
+        let mut peers = PeerCollection::new();
+        let config = PeerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 12101,
+            protocol: "http".to_string(),
+            synctype: "full".to_string(),
+        };
+        assert!(peers.add_to_address_book(config.clone()));
+        assert!(!peers.add_to_address_book(config.clone()));
+        assert_eq!(peers.get_shareable_peers(), vec![config.clone()]);
+
+        let mut peer = Peer::new(1);
+        peer.static_peer_config = Some(config);
+        peers.index_to_peers.insert(1, peer);
+        assert!(peers.is_connected_to(&"127.0.0.1:12101".to_string()));
+        assert_eq!(peers.get_outbound_peer_count(), 1);
+    }
+
+    #[test]
+    fn test_address_book_connection_failures() {
+        let mut peers = PeerCollection::new();
+        let config = PeerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 12101,
+            protocol: "http".to_string(),
+            synctype: "full".to_string(),
+        };
+        let address = "127.0.0.1:12101".to_string();
+        assert!(peers.add_to_address_book(config.clone()));
+
+        // retries are backed off exponentially
+        assert!(!peers.record_connection_failure(&config, 1000));
+        assert!(peers.is_address_backed_off(&address, 1000));
+        assert!(!peers.is_address_backed_off(&address, 1000 + ADDRESS_RETRY_BASE_DELAY));
+        assert!(!peers.record_connection_failure(&config, 2000));
+        assert!(peers.is_address_backed_off(&address, 2000 + ADDRESS_RETRY_BASE_DELAY));
+        assert!(!peers.is_address_backed_off(&address, 2000 + 2 * ADDRESS_RETRY_BASE_DELAY));
+
+        // a successful connection resets the failures
+        peers.clear_connection_failures(&address);
+        assert!(!peers.is_address_backed_off(&address, 2000));
+
+        for _ in 1..MAX_ADDRESS_CONNECTION_FAILURES {
+            assert!(!peers.record_connection_failure(&config, 3000));
+        }
+        assert!(peers.record_connection_failure(&config, 3000));
+        assert!(peers.address_book.is_empty());
+        assert!(peers.connection_failures.is_empty());
+
+        // failures of the addresses which are not in the address book are not recorded
+        assert!(!peers.record_connection_failure(&config, 4000));
+        assert!(peers.connection_failures.is_empty());
+    }
+
+    #[test]
+    fn test_register_peer_key() {
+        let config = PeerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 12101,
+            protocol: "http".to_string(),
+            synctype: "full".to_string(),
+        };
+        let create_peer = |peer_index: u64, is_outgoing: bool| {
+            let mut peer = Peer::new(peer_index);
+            peer.peer_public_key = [5; 33];
+            peer.handshake_done = true;
+            if is_outgoing {
+                peer.static_peer_config = Some(config.clone());
+            }
+            peer
+        };
+
+        // connection started by the node with the lower key is kept
+        let mut peers = PeerCollection::new();
+        peers.index_to_peers.insert(1, create_peer(1, true));
+        peers.index_to_peers.insert(2, create_peer(2, false));
+        assert_eq!(peers.register_peer_key(1, &[2; 33]), None);
+        assert_eq!(peers.find_peer_by_address(&[5; 33]).unwrap().peer_index, 1);
+        assert_eq!(peers.register_peer_key(2, &[2; 33]), Some(2));
+        assert_eq!(peers.find_peer_by_address(&[5; 33]).unwrap().peer_index, 1);
+
+        let mut peers = PeerCollection::new();
+        peers.index_to_peers.insert(1, create_peer(1, true));
+        peers.index_to_peers.insert(2, create_peer(2, false));
+        assert_eq!(peers.register_peer_key(1, &[7; 33]), None);
+        assert_eq!(peers.register_peer_key(2, &[7; 33]), Some(1));
+        assert_eq!(peers.find_peer_by_address(&[5; 33]).unwrap().peer_index, 2);
+
+        // static peer address is connected through the incoming connection
+        peers.index_to_peers.remove(&1);
+        assert!(peers.is_connected_to(&"127.0.0.1:12101".to_string()));
+        peers.index_to_peers.remove(&2);
+        peers.address_to_peers.remove(&[5; 33]);
+        assert!(!peers.is_connected_to(&"127.0.0.1:12101".to_string()));
+
+        // newer connection is kept if both are from the same side
+        let mut peers = PeerCollection::new();
+        peers.index_to_peers.insert(1, create_peer(1, false));
+        peers.index_to_peers.insert(2, create_peer(2, false));
+        assert_eq!(peers.register_peer_key(1, &[2; 33]), None);
+        assert_eq!(peers.register_peer_key(2, &[2; 33]), Some(1));
+        assert_eq!(peers.register_peer_key(2, &[2; 33]), None);
+    }
 }
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use crate::core::data::configuration::{PeerRateLimits, RateLimit};
+
+// period in milliseconds in which the dropped messages of a peer are counted against the disconnect threshold
+pub const THROTTLE_WINDOW: u64 = 60_000;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RateLimitResult {
+    Allowed,
+    // message should be dropped
+    Throttled,
+    // peer dropped too many messages and should be disconnected
+    Exceeded,
+}
+
+/// Token bucket which refills at a fixed rate up to its capacity
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    // tokens added per second
+    rate: u64,
+    capacity: u64,
+    tokens: u64,
+    // timestamp in milliseconds up to which the tokens are added
+    last_refill: u64,
+}
+
+impl TokenBucket {
+    pub fn new(limit: &RateLimit, current_time: u64) -> TokenBucket {
+        TokenBucket {
+            rate: limit.rate,
+            capacity: limit.burst,
+            tokens: limit.burst,
+            last_refill: current_time,
+        }
+    }
+    fn refill(&mut self, current_time: u64) {
+        if self.tokens >= self.capacity || self.rate == 0 {
+            self.last_refill = current_time;
+            return;
+        }
+        let elapsed = current_time.saturating_sub(self.last_refill);
+        let added = elapsed.saturating_mul(self.rate) / 1000;
+        if added == 0 {
+            return;
+        }
+        self.tokens = self.capacity.min(self.tokens.saturating_add(added));
+        // only the time used for the added tokens is consumed, so the fractions are not lost
+        self.last_refill += added * 1000 / self.rate;
+    }
+    pub fn has_tokens(&mut self, amount: u64, current_time: u64) -> bool {
+        self.refill(current_time);
+        // amounts larger than the capacity are allowed with a full bucket, so large messages are not blocked forever
+        self.tokens >= amount.min(self.capacity)
+    }
+    pub fn consume(&mut self, amount: u64) {
+        self.tokens = self.tokens.saturating_sub(amount);
+    }
+}
+
+/// Rate limits of the messages received from a single peer. Buckets are created with the configured limits when the
+/// first message is received.
+///
+/// The message and byte limits are checked with the wire size of the messages before they are queued and decrypted, so
+/// a noisy peer can't flood the routing thread. the message type limits are checked after decrypting, since the type
+/// is encrypted with the rest of the message
+#[derive(Debug, Clone, Default)]
+pub struct PeerRateLimiter {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+    message_types: HashMap<u8, TokenBucket>,
+    throttled_messages: u32,
+    throttle_window_start: u64,
+}
+
+impl PeerRateLimiter {
+    /// Checks if a message can be received under the message and byte limits and consumes the tokens if so
+    ///
+    /// # Arguments
+    ///
+    /// * `size`: size of the message in bytes, as received from the socket
+    /// * `limits`: configured limits
+    /// * `current_time`: timestamp in milliseconds
+    ///
+    /// returns: RateLimitResult
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn check_message(
+        &mut self,
+        size: u64,
+        limits: &PeerRateLimits,
+        current_time: u64,
+    ) -> RateLimitResult {
+        let messages = self
+            .messages
+            .get_or_insert_with(|| TokenBucket::new(&limits.messages, current_time));
+        let mut allowed = messages.has_tokens(1, current_time);
+
+        let bytes = self
+            .bytes
+            .get_or_insert_with(|| TokenBucket::new(&limits.bytes, current_time));
+        allowed = allowed && bytes.has_tokens(size, current_time);
+
+        if allowed {
+            self.messages.as_mut().unwrap().consume(1);
+            self.bytes.as_mut().unwrap().consume(size);
+            return RateLimitResult::Allowed;
+        }
+        self.throttle(limits, current_time)
+    }
+    /// Checks if a message of the given type can be processed under the limit of the type and consumes the token if so
+    ///
+    /// # Arguments
+    ///
+    /// * `message_type`: type value of the message
+    /// * `limits`: configured limits
+    /// * `current_time`: timestamp in milliseconds
+    ///
+    /// returns: RateLimitResult
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn check_message_type(
+        &mut self,
+        message_type: u8,
+        limits: &PeerRateLimits,
+        current_time: u64,
+    ) -> RateLimitResult {
+        let type_limit = limits.message_types.get(&message_type);
+        if type_limit.is_none() {
+            return RateLimitResult::Allowed;
+        }
+        let bucket = self
+            .message_types
+            .entry(message_type)
+            .or_insert_with(|| TokenBucket::new(type_limit.unwrap(), current_time));
+        if bucket.has_tokens(1, current_time) {
+            bucket.consume(1);
+            return RateLimitResult::Allowed;
+        }
+        self.throttle(limits, current_time)
+    }
+    /// Counts the dropped message against the disconnect threshold
+    fn throttle(&mut self, limits: &PeerRateLimits, current_time: u64) -> RateLimitResult {
+        if current_time.saturating_sub(self.throttle_window_start) > THROTTLE_WINDOW {
+            self.throttle_window_start = current_time;
+            self.throttled_messages = 0;
+        }
+        self.throttled_messages += 1;
+        if self.throttled_messages > limits.disconnect_threshold {
+            return RateLimitResult::Exceeded;
+        }
+        RateLimitResult::Throttled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::data::configuration::{PeerRateLimits, RateLimit};
+    use crate::core::data::rate_limiter::{PeerRateLimiter, RateLimitResult, TokenBucket};
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(&RateLimit { rate: 3, burst: 2 }, 0);
+        assert!(bucket.has_tokens(2, 0));
+        bucket.consume(2);
+        assert!(!bucket.has_tokens(1, 100));
+
+        // a token is added every 333ms. fractions are carried over to the next refill
+        assert!(bucket.has_tokens(1, 334));
+        bucket.consume(1);
+        assert!(!bucket.has_tokens(1, 500));
+        assert!(bucket.has_tokens(1, 667));
+        bucket.consume(1);
+
+        // amounts larger than the capacity need a full bucket
+        assert!(!bucket.has_tokens(10, 700));
+        assert!(bucket.has_tokens(10, 10_000));
+    }
+
+    #[test]
+    fn test_peer_rate_limiter() {
+        let mut message_types = HashMap::new();
+        message_types.insert(9, RateLimit { rate: 1, burst: 1 });
+        let limits = PeerRateLimits {
+            messages: RateLimit { rate: 10, burst: 5 },
+            bytes: RateLimit {
+                rate: 1000,
+                burst: 1000,
+            },
+            message_types,
+            disconnect_threshold: 2,
+        };
+        let mut limiter = PeerRateLimiter::default();
+        assert_eq!(
+            limiter.check_message_type(9, &limits, 0),
+            RateLimitResult::Allowed
+        );
+        // limited message type is throttled while the others are allowed
+        assert_eq!(
+            limiter.check_message_type(9, &limits, 0),
+            RateLimitResult::Throttled
+        );
+        assert_eq!(
+            limiter.check_message_type(6, &limits, 0),
+            RateLimitResult::Allowed
+        );
+        assert_eq!(
+            limiter.check_message(10, &limits, 0),
+            RateLimitResult::Allowed
+        );
+        // message over the remaining bytes is throttled
+        assert_eq!(
+            limiter.check_message(995, &limits, 0),
+            RateLimitResult::Throttled
+        );
+        assert_eq!(
+            limiter.check_message(10, &limits, 0),
+            RateLimitResult::Allowed
+        );
+        assert_eq!(
+            limiter.check_message_type(9, &limits, 1000),
+            RateLimitResult::Allowed
+        );
+        assert_eq!(
+            limiter.check_message(10, &limits, 1000),
+            RateLimitResult::Allowed
+        );
+
+        // peer is disconnected after too many throttled messages
+        for _ in 0..5 {
+            limiter.check_message(10, &limits, 1000);
+        }
+        assert_eq!(
+            limiter.check_message(10, &limits, 1000),
+            RateLimitResult::Exceeded
+        );
+    }
+}
@@ -1,6 +1,77 @@
-use std::io::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind};
 
 pub trait Serialize<T> {
     fn serialize(&self) -> Vec<u8>;
     fn deserialize(buffer: &Vec<u8>) -> Result<T, Error>;
 }
+
+/// Reasons for rejecting a serialized block, transaction, slip or hop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeserializationError {
+    // buffer ends before all the fields are read
+    BufferTooShort { expected: usize, actual: usize },
+    // buffer has bytes left after the last field
+    TrailingBytes { expected: usize, actual: usize },
+    // buffer or the size given by its length fields is over the allowed maximum
+    SizeLimitExceeded { size: u64, limit: usize },
+    InvalidTransactionType(u8),
+    InvalidSlipType(u8),
+}
+
+impl Display for DeserializationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeserializationError::BufferTooShort { expected, actual } => write!(
+                f,
+                "buffer too short. expected at least {} bytes but got {}",
+                expected, actual
+            ),
+            DeserializationError::TrailingBytes { expected, actual } => write!(
+                f,
+                "trailing bytes in buffer. expected {} bytes but got {}",
+                expected, actual
+            ),
+            DeserializationError::SizeLimitExceeded { size, limit } => {
+                write!(f, "size : {} exceeds the limit : {}", size, limit)
+            }
+            DeserializationError::InvalidTransactionType(value) => {
+                write!(f, "invalid transaction type : {}", value)
+            }
+            DeserializationError::InvalidSlipType(value) => {
+                write!(f, "invalid slip type : {}", value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeserializationError {}
+
+impl From<DeserializationError> for Error {
+    fn from(error: DeserializationError) -> Self {
+        Error::new(ErrorKind::InvalidData, error)
+    }
+}
+
+/// Checks the buffer has at least the given number of bytes
+pub fn check_min_length(buffer: &[u8], expected: usize) -> Result<(), DeserializationError> {
+    if buffer.len() < expected {
+        return Err(DeserializationError::BufferTooShort {
+            expected,
+            actual: buffer.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Checks the buffer has exactly the given number of bytes
+pub fn check_length(buffer: &[u8], expected: usize) -> Result<(), DeserializationError> {
+    check_min_length(buffer, expected)?;
+    if buffer.len() > expected {
+        return Err(DeserializationError::TrailingBytes {
+            expected,
+            actual: buffer.len(),
+        });
+    }
+    Ok(())
+}
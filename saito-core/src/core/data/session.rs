@@ -0,0 +1,230 @@
+use std::fmt::{Debug, Formatter};
+use std::io::{Error, ErrorKind};
+
+use aes::Aes128;
+use block_modes::block_padding::Pkcs7;
+use block_modes::{BlockMode, Cbc};
+
+use crate::common::defs::SaitoHash;
+use crate::core::data::crypto::hash;
+
+type Aes128Cbc = Cbc<Aes128, Pkcs7>;
+
+// sequence number and the authentication tag added to each encrypted message
+pub const SESSION_MESSAGE_OVERHEAD: usize = 8 + 32;
+
+/// Keys used for the messages sent in one direction
+#[derive(Clone)]
+struct SessionKeys {
+    encryption_key: [u8; 16],
+    authentication_key: SaitoHash,
+    // sequence number of the next message
+    sequence: u64,
+}
+
+impl SessionKeys {
+    fn new(
+        shared_secret: &SaitoHash,
+        initiator_challenge: &SaitoHash,
+        responder_challenge: &SaitoHash,
+        direction: u8,
+    ) -> SessionKeys {
+        let derive_key = |purpose: u8| {
+            let mut vbytes: Vec<u8> = vec![];
+            vbytes.extend(shared_secret);
+            vbytes.extend(initiator_challenge);
+            vbytes.extend(responder_challenge);
+            vbytes.push(direction);
+            vbytes.push(purpose);
+            hash(&vbytes)
+        };
+        SessionKeys {
+            encryption_key: derive_key(0)[0..16].try_into().unwrap(),
+            authentication_key: derive_key(1),
+            sequence: 0,
+        }
+    }
+    // each message uses a different iv derived from the sequence number
+    fn get_iv(&self) -> [u8; 16] {
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(self.encryption_key);
+        vbytes.extend(self.sequence.to_be_bytes());
+        hash(&vbytes)[0..16].try_into().unwrap()
+    }
+    fn get_tag(&self, sequence: &[u8], ciphertext: &[u8]) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new_keyed(&self.authentication_key);
+        hasher.update(sequence);
+        hasher.update(ciphertext);
+        hasher.finalize()
+    }
+}
+
+/// Encrypted session with a peer, set up after the handshake. Keys are derived from the ECDH secret of the two
+/// node keys and the two challenges of the handshake, so each connection gets new keys.
+///
+/// Messages are encrypted with AES-128-CBC and authenticated with a keyed blake3 hash. Sequence numbers stop the
+/// messages from being replayed, dropped or reordered since the transport is expected to deliver them in order.
+#[derive(Clone)]
+pub struct PeerSession {
+    sending: SessionKeys,
+    receiving: SessionKeys,
+}
+
+impl Debug for PeerSession {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // keys are not printed to the logs
+        f.debug_struct("PeerSession")
+            .field("sending_sequence", &self.sending.sequence)
+            .field("receiving_sequence", &self.receiving.sequence)
+            .finish()
+    }
+}
+
+impl PeerSession {
+    /// Creates the session keys for one side of the connection
+    ///
+    /// # Arguments
+    ///
+    /// * `shared_secret`: ECDH secret of our private key and the peer's public key
+    /// * `initiator_challenge`: challenge sent by the side which initiated the handshake
+    /// * `responder_challenge`: challenge sent by the other side
+    /// * `is_initiator`: true if we initiated the handshake
+    ///
+    /// returns: PeerSession
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn new(
+        shared_secret: &SaitoHash,
+        initiator_challenge: &SaitoHash,
+        responder_challenge: &SaitoHash,
+        is_initiator: bool,
+    ) -> PeerSession {
+        let initiator_keys =
+            SessionKeys::new(shared_secret, initiator_challenge, responder_challenge, 0);
+        let responder_keys =
+            SessionKeys::new(shared_secret, initiator_challenge, responder_challenge, 1);
+        if is_initiator {
+            PeerSession {
+                sending: initiator_keys,
+                receiving: responder_keys,
+            }
+        } else {
+            PeerSession {
+                sending: responder_keys,
+                receiving: initiator_keys,
+            }
+        }
+    }
+    /// [sequence - 8 bytes - u64]
+    /// [encrypted message]
+    /// [authentication tag - 32 bytes]
+    pub fn encrypt(&mut self, buffer: &[u8]) -> Vec<u8> {
+        let keys = &mut self.sending;
+        let sequence = keys.sequence.to_be_bytes();
+        let cipher = Aes128Cbc::new_from_slices(&keys.encryption_key, &keys.get_iv()).unwrap();
+        let ciphertext = cipher.encrypt_vec(buffer);
+        let tag = keys.get_tag(&sequence, &ciphertext);
+        keys.sequence += 1;
+
+        let mut vbytes: Vec<u8> = Vec::with_capacity(ciphertext.len() + SESSION_MESSAGE_OVERHEAD);
+        vbytes.extend(sequence);
+        vbytes.extend(ciphertext);
+        vbytes.extend(tag.as_bytes());
+        vbytes
+    }
+    /// Verifies and decrypts the message. fails if the message is modified or not the next one in the sequence
+    pub fn decrypt(&mut self, buffer: &[u8]) -> Result<Vec<u8>, Error> {
+        if buffer.len() < SESSION_MESSAGE_OVERHEAD {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let keys = &mut self.receiving;
+        let sequence = &buffer[0..8];
+        let ciphertext = &buffer[8..buffer.len() - 32];
+        let tag: [u8; 32] = buffer[buffer.len() - 32..].try_into().unwrap();
+        // blake3::Hash comparison is constant time
+        if keys.get_tag(sequence, ciphertext) != blake3::Hash::from(tag) {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        if u64::from_be_bytes(sequence.try_into().unwrap()) != keys.sequence {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let cipher = Aes128Cbc::new_from_slices(&keys.encryption_key, &keys.get_iv()).unwrap();
+        let result = cipher.decrypt_vec(ciphertext);
+        if result.is_err() {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        keys.sequence += 1;
+        Ok(result.unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::crypto::{generate_keys, generate_shared_secret};
+    use crate::core::data::session::PeerSession;
+
+    fn create_sessions() -> (PeerSession, PeerSession) {
+        let (publickey_1, privatekey_1) = generate_keys();
+        let (publickey_2, privatekey_2) = generate_keys();
+        let initiator = PeerSession::new(
+            &generate_shared_secret(&publickey_2, &privatekey_1).unwrap(),
+            &[1; 32],
+            &[2; 32],
+            true,
+        );
+        let responder = PeerSession::new(
+            &generate_shared_secret(&publickey_1, &privatekey_2).unwrap(),
+            &[1; 32],
+            &[2; 32],
+            false,
+        );
+        (initiator, responder)
+    }
+
+    #[test]
+    fn test_session_encryption() {
+        let (mut initiator, mut responder) = create_sessions();
+
+        let message = vec![6, 1, 2, 3];
+        let buffer = initiator.encrypt(&message);
+        assert_ne!(&buffer[8..12], message.as_slice());
+        assert_eq!(responder.decrypt(&buffer).unwrap(), message);
+
+        // same message gets a different ciphertext
+        let buffer_2 = initiator.encrypt(&message);
+        assert_ne!(buffer[8..], buffer_2[8..]);
+        assert_eq!(responder.decrypt(&buffer_2).unwrap(), message);
+
+        let buffer = responder.encrypt(&[]);
+        assert!(responder.decrypt(&buffer).is_err());
+        assert!(initiator.decrypt(&buffer).unwrap().is_empty());
+
+        // sessions of other keys can't read the messages
+        let (mut other, _) = create_sessions();
+        assert!(other.decrypt(&initiator.encrypt(&message)).is_err());
+    }
+
+    #[test]
+    fn test_session_rejects_modified_messages() {
+        let (mut initiator, mut responder) = create_sessions();
+
+        let buffer = initiator.encrypt(&[1, 2, 3]);
+        let mut modified = buffer.clone();
+        modified[10] ^= 1;
+        assert!(responder.decrypt(&modified).is_err());
+        assert!(responder.decrypt(&buffer[0..20]).is_err());
+        assert!(responder.decrypt(&buffer).is_ok());
+
+        // replayed message
+        assert!(responder.decrypt(&buffer).is_err());
+
+        // skipped message
+        initiator.encrypt(&[4]);
+        let buffer = initiator.encrypt(&[5]);
+        assert!(responder.decrypt(&buffer).is_err());
+    }
+}
@@ -5,6 +5,7 @@ use num_traits::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
 use crate::common::defs::{SaitoHash, SaitoPublicKey, SaitoUTXOSetKey, UtxoSet};
+use crate::core::data::serialize::{check_length, DeserializationError};
 
 /// The size of a serilized slip in bytes.
 pub const SLIP_SIZE: usize = 75;
@@ -286,12 +287,17 @@ impl Slip {
         //        res
     }
 
-    pub fn deserialize_from_net(bytes: Vec<u8>) -> Slip {
+    pub fn deserialize_from_net(bytes: Vec<u8>) -> Result<Slip, DeserializationError> {
+        check_length(&bytes, SLIP_SIZE)?;
         let publickey: SaitoPublicKey = bytes[..33].try_into().unwrap();
         let uuid: SaitoHash = bytes[33..65].try_into().unwrap();
         let amount: u64 = u64::from_be_bytes(bytes[65..73].try_into().unwrap());
         let slip_ordinal: u8 = bytes[73];
-        let slip_type: SlipType = FromPrimitive::from_u8(bytes[SLIP_SIZE - 1]).unwrap();
+        let slip_type: Option<SlipType> = FromPrimitive::from_u8(bytes[SLIP_SIZE - 1]);
+        if slip_type.is_none() {
+            return Err(DeserializationError::InvalidSlipType(bytes[SLIP_SIZE - 1]));
+        }
+        let slip_type = slip_type.unwrap();
         let mut slip = Slip::new();
 
         slip.set_publickey(publickey);
@@ -300,7 +306,7 @@ impl Slip {
         slip.set_slip_ordinal(slip_ordinal);
         slip.set_slip_type(slip_type);
 
-        slip
+        Ok(slip)
     }
     pub fn serialize_for_net(&self) -> Vec<u8> {
         let mut vbytes: Vec<u8> = vec![];
@@ -367,8 +373,13 @@ mod tests {
         let slip = Slip::new();
         let serialized_slip = slip.serialize_for_net();
         assert_eq!(serialized_slip.len(), 75);
-        let deserilialized_slip = Slip::deserialize_from_net(serialized_slip);
+        let deserilialized_slip = Slip::deserialize_from_net(serialized_slip.clone()).unwrap();
         assert_eq!(slip, deserilialized_slip);
+
+        assert!(Slip::deserialize_from_net(serialized_slip[0..74].to_vec()).is_err());
+        let mut buffer = serialized_slip;
+        buffer[SLIP_SIZE - 1] = 50;
+        assert!(Slip::deserialize_from_net(buffer).is_err());
     }
 
     #[tokio::test]
@@ -1,42 +1,130 @@
+use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 
-use log::{debug, error, trace};
+use log::{debug, error, trace, warn};
 use tokio::sync::RwLock;
 
+use crate::common::defs::SaitoHash;
 use crate::common::interface_io::InterfaceIO;
-use crate::core::data::block::{Block, BlockType};
+use crate::core::data::block::{Block, BlockType, BLOCK_HEADER_SIZE};
+use crate::core::data::block_index::BlockIndexEntry;
 use crate::core::data::blockchain::Blockchain;
+use crate::core::data::blockring::RING_BUFFER_LENGTH;
+use crate::core::data::configuration::PeerConfig;
+use crate::core::data::crypto::hash;
+use crate::core::data::lite_chain::{LiteChain, LITE_CHAIN_METADATA_KEY};
+use crate::core::data::msg::peer_list::PeerList;
 use crate::core::data::network::Network;
+use crate::core::data::serialize::{check_min_length, Serialize};
 use crate::core::data::slip::Slip;
+use crate::core::data::storage_backend::{FileStorageBackend, StorageBackend, StorageOperation};
+use crate::core::data::utxo_snapshot::{UtxoSnapshot, UTXO_SNAPSHOT_METADATA_KEY};
 use crate::core::mining_event_processor::MiningEvent;
 
 pub struct Storage {
-    pub io_interface: Box<dyn InterfaceIO + Send + Sync>,
+    pub io_interface: Arc<dyn InterfaceIO + Send + Sync>,
+    // keeps the blocks and the metadata. other files (wallets, address book) are written through the io interface.
+    // shared by the storages of a node, so the blocks written or deleted through one of them are seen by the others
+    pub backend: Arc<dyn StorageBackend + Send + Sync>,
 }
 
-pub const ISSUANCE_FILE_PATH: &'static str = "./data/issuance/issuance";
-pub const EARLYBIRDS_FILE_PATH: &'static str = "./data/issuance/earlybirds";
-pub const DEFAULT_FILE_PATH: &'static str = "./data/issuance/default";
+// file paths relative to the data directory
+pub const ISSUANCE_FILE_PATH: &str = "issuance/issuance";
+pub const EARLYBIRDS_FILE_PATH: &str = "issuance/earlybirds";
+pub const DEFAULT_FILE_PATH: &str = "issuance/default";
+pub const ADDRESS_BOOK_FILE_PATH: &str = "peers/address_book";
+pub const WALLETS_DIR: &str = "wallets/";
+
+// stored blocks start with the format marker (4 bytes), the format version (1 byte), the checksum of the block buffer
+// (32 bytes) and the length of it (8 bytes)
+pub const BLOCK_FILE_HEADER_SIZE: usize = 45;
+// marks the block files with the header. legacy files start with the transaction count of the block instead
+pub const BLOCK_FILE_MAGIC: [u8; 4] = *b"SBLK";
+pub const BLOCK_FILE_VERSION: u8 = 1;
 
 pub struct StorageConfigurer {}
 
-pub fn configure_storage() -> String {
-    if cfg!(test) {
-        String::from("./data/test/blocks/")
-    } else {
-        String::from("./data/blocks/")
+/// Adds the checksum and the length of the block buffer in front of it, so the stored block can be verified when loading
+pub fn add_block_file_header(buffer: Vec<u8>) -> Vec<u8> {
+    let mut file_buffer = Vec::with_capacity(BLOCK_FILE_HEADER_SIZE + buffer.len());
+    file_buffer.extend(BLOCK_FILE_MAGIC);
+    file_buffer.push(BLOCK_FILE_VERSION);
+    file_buffer.extend(hash(&buffer));
+    file_buffer.extend((buffer.len() as u64).to_be_bytes());
+    file_buffer.extend(buffer);
+    file_buffer
+}
+
+/// Checks if the stored block was written without the header, before the block files had checksums
+pub fn is_legacy_block_file(file_buffer: &[u8]) -> bool {
+    !file_buffer.starts_with(&BLOCK_FILE_MAGIC)
+}
+
+/// Verifies the checksum and the length of a stored block. returns the block buffer without the header, or
+/// ErrorKind::InvalidData if the stored block is truncated or corrupted. legacy files are returned as they are, since
+/// they can only be checked by decoding the block
+pub fn verify_block_file(file_buffer: &[u8]) -> Result<&[u8], Error> {
+    if is_legacy_block_file(file_buffer) {
+        return Ok(file_buffer);
+    }
+    if file_buffer.len() < BLOCK_FILE_HEADER_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "block file is truncated",
+        ));
+    }
+    if file_buffer[4] != BLOCK_FILE_VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unknown block file version : {:?}", file_buffer[4]),
+        ));
     }
+    let length = u64::from_be_bytes(file_buffer[37..45].try_into().unwrap());
+    let buffer = &file_buffer[BLOCK_FILE_HEADER_SIZE..];
+    if buffer.len() as u64 != length {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "block length : {:?} doesn't match the stored length : {:?}",
+                buffer.len(),
+                length
+            ),
+        ));
+    }
+    if hash(&buffer.to_vec()) != file_buffer[5..37] {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "block checksum doesn't match",
+        ));
+    }
+    Ok(buffer)
 }
 
 impl Storage {
+    /// Creates the storage keeping the blocks as files in the block directory
     pub fn new(io_interface: Box<dyn InterfaceIO + Send + Sync>) -> Storage {
-        Storage { io_interface }
+        let io_interface: Arc<dyn InterfaceIO + Send + Sync> = Arc::from(io_interface);
+        Storage {
+            io_interface: io_interface.clone(),
+            backend: Arc::new(FileStorageBackend::new(io_interface)),
+        }
     }
-    /// read from a path to a Vec<u8>
+    pub fn new_with_backend(
+        io_interface: Box<dyn InterfaceIO + Send + Sync>,
+        backend: Arc<dyn StorageBackend + Send + Sync>,
+    ) -> Storage {
+        Storage {
+            io_interface: Arc::from(io_interface),
+            backend,
+        }
+    }
+    /// read from a path to a Vec<u8>. returns the error of the io interface if the file can't be read
     pub async fn read(&self, path: &str) -> std::io::Result<Vec<u8>> {
         let buffer = self.io_interface.read_value(path.to_string()).await;
         if buffer.is_err() {
-            todo!()
+            let error = buffer.err().unwrap();
+            warn!("failed reading file : {:?} : {:?}", path, error);
+            return Err(error);
         }
         let buffer = buffer.unwrap();
         Ok(buffer)
@@ -49,6 +137,11 @@ impl Storage {
             .expect("writing to storage failed");
     }
 
+    /// Path of the file under the data directory
+    pub fn get_file_path(&self, relative_path: &str) -> String {
+        self.io_interface.get_data_dir() + relative_path
+    }
+
     pub async fn file_exists(&self, filename: &str) -> bool {
         return self
             .io_interface
@@ -56,29 +149,84 @@ impl Storage {
             .await;
     }
 
-    pub fn generate_block_filename(&self, block: &Block) -> String {
-        let timestamp = block.get_timestamp();
-        let block_hash = block.get_hash();
-        self.io_interface.get_block_dir().to_string()
-            + timestamp.to_string().as_str()
-            + "-"
-            + hex::encode(block_hash).as_str()
-            + ".block"
+    fn create_put_block_operation(block: &Block) -> StorageOperation {
+        StorageOperation::PutBlock {
+            block_hash: block.get_hash(),
+            block_id: block.get_id(),
+            timestamp: block.get_timestamp(),
+            buffer: add_block_file_header(block.serialize_for_net(BlockType::Full)),
+        }
     }
-    pub async fn write_block_to_disk(&mut self, block: &Block) -> String {
-        let buffer = block.serialize_for_net(BlockType::Full);
-        let filename = self.generate_block_filename(block);
 
+    pub async fn write_block_to_disk(&self, block: &Block) {
         let result = self
-            .io_interface
-            .write_value(filename.clone(), buffer)
+            .backend
+            .write_batch(vec![Storage::create_put_block_operation(block)])
             .await;
         if result.is_err() {
-            todo!()
+            error!(
+                "failed writing block : {:?} to disk : {:?}",
+                hex::encode(block.get_hash()),
+                result.err().unwrap()
+            );
         }
-        filename
     }
 
+    /// Writes the blocks and the block index entries in a single batch, so backends supporting atomic batches don't
+    /// keep a block without its index entry after a crash
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks`: blocks to be stored
+    /// * `entries`: index entries of the stored blocks and the blocks whose longest chain status changed
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn write_blocks_with_index(&self, blocks: &[&Block], entries: Vec<BlockIndexEntry>) {
+        if blocks.is_empty() && entries.is_empty() {
+            return;
+        }
+        let mut operations: Vec<StorageOperation> = blocks
+            .iter()
+            .map(|block| Storage::create_put_block_operation(block))
+            .collect();
+        operations.extend(
+            entries
+                .into_iter()
+                .map(StorageOperation::PutBlockIndexEntry),
+        );
+        let result = self.backend.write_batch(operations).await;
+        if result.is_err() {
+            error!(
+                "failed writing {:?} blocks with the block index : {:?}",
+                blocks.len(),
+                result.err().unwrap()
+            );
+        }
+    }
+
+    /// Loads the stored blocks into the blockchain. blocks are restored from the block index if it matches the stored
+    /// blocks, otherwise all the stored blocks are added to the blockchain again in the order of the block ids.
+    /// corrupted and unreadable blocks are moved to the quarantine and fetched again from the peers.
+    ///
+    /// # Arguments
+    ///
+    /// * `blockchain_lock`:
+    /// * `network`:
+    /// * `sender_to_miner`:
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
     pub async fn load_blocks_from_disk(
         &mut self,
         blockchain_lock: Arc<RwLock<Blockchain>>,
@@ -86,46 +234,447 @@ impl Storage {
         sender_to_miner: tokio::sync::mpsc::Sender<MiningEvent>,
     ) {
         debug!("loading blocks from disk");
-        let file_names = self.io_interface.load_block_file_list().await;
+        let entries = self.backend.load_block_index().await;
         trace!("waiting for the blockchain write lock");
         let mut blockchain = blockchain_lock.write().await;
         trace!("acquired the blockchain write lock");
+        let mut lost_blocks = vec![];
+
+        match entries {
+            Ok(entries) if !entries.is_empty() => {
+                let blocks = self.load_indexed_blocks(&entries, &mut lost_blocks).await;
+                if let Some(blocks) = blocks {
+                    // snapshot is only usable if its block is still in the longest chain
+                    let snapshot = self.load_utxo_snapshot().await.filter(|snapshot| {
+                        blocks.iter().any(|block| {
+                            block.is_in_longest_chain()
+                                && block.get_id() == snapshot.block_id
+                                && block.get_hash() == snapshot.block_hash
+                        })
+                    });
+                    let latest_block = blocks
+                        .iter()
+                        .rev()
+                        .find(|block| block.is_in_longest_chain())
+                        .unwrap();
+                    let latest_block_hash = latest_block.get_hash();
+                    let difficulty = latest_block.get_difficulty();
+                    blockchain
+                        .add_blocks_from_index(blocks, snapshot, self)
+                        .await;
+                    sender_to_miner
+                        .send(MiningEvent::LongestChainBlockAdded {
+                            hash: latest_block_hash,
+                            difficulty,
+                        })
+                        .await
+                        .unwrap();
+                    network.fetch_lost_blocks(lost_blocks).await;
+                    return;
+                }
+                warn!("block index doesn't match the stored blocks. adding all the stored blocks again");
+                // entries are written again as the blocks are added
+                let result = self
+                    .backend
+                    .write_batch(
+                        entries
+                            .iter()
+                            .map(|entry| StorageOperation::DeleteBlockIndexEntry {
+                                block_hash: entry.block_hash,
+                                block_id: entry.block_id,
+                            })
+                            .collect(),
+                    )
+                    .await;
+                if result.is_err() {
+                    error!("failed clearing block index : {:?}", result.err().unwrap());
+                }
+            }
+            Ok(_) => {
+                debug!("block index is empty. adding all the stored blocks");
+            }
+            Err(error) => {
+                warn!(
+                    "failed loading block index : {:?}. adding all the stored blocks",
+                    error
+                );
+            }
+        }
 
-        if file_names.is_err() {
-            error!("{:?}", file_names.err().unwrap());
+        let block_hashes = self.backend.get_block_hashes().await;
+        if block_hashes.is_err() {
+            error!("{:?}", block_hashes.err().unwrap());
+            network.fetch_lost_blocks(lost_blocks).await;
             return;
         }
-        let file_names = file_names.unwrap();
-        debug!("block file names : {:?}", file_names);
-        for file_name in file_names {
-            let result = self
-                .io_interface
-                .read_value(self.io_interface.get_block_dir() + file_name.as_str())
-                .await;
+        let block_hashes = block_hashes.unwrap();
+        debug!("loading {:?} blocks", block_hashes.len());
+        let mut blocks = vec![];
+        for block_hash in block_hashes {
+            let result = self.read_block_with_metadata(&block_hash).await;
             if result.is_err() {
-                todo!()
+                let error = result.err().unwrap();
+                error!(
+                    "failed loading block : {:?} : {:?}",
+                    hex::encode(block_hash),
+                    error
+                );
+                if error.kind() != ErrorKind::NotFound {
+                    self.quarantine_block(&block_hash).await;
+                    lost_blocks.push(block_hash);
+                }
+                continue;
             }
-            let buffer = result.unwrap();
-            let mut block = Block::deserialize_for_net(&buffer);
-            block.generate_metadata();
+            blocks.push(result.unwrap());
+        }
+        // parents are added before their children, regardless of the order the backend lists the blocks in
+        blocks.sort_by_key(|block| block.get_id());
+        for block in blocks {
             blockchain
                 .add_block(block, network, self, sender_to_miner.clone())
                 .await;
         }
+        network.fetch_lost_blocks(lost_blocks).await;
+    }
+
+    /// Moves a corrupted block out of the stored blocks, so it's not loaded again
+    async fn quarantine_block(&self, block_hash: &SaitoHash) {
+        warn!("moving block : {:?} to quarantine", hex::encode(block_hash));
+        let result = self.backend.quarantine_block(block_hash).await;
+        if result.is_err() {
+            error!(
+                "failed moving block : {:?} to quarantine : {:?}",
+                hex::encode(block_hash),
+                result.err().unwrap()
+            );
+        }
+    }
+
+    pub async fn write_utxo_snapshot(&self, snapshot: &UtxoSnapshot) {
+        debug!("writing utxo snapshot at block : {:?}", snapshot.block_id);
+        let result = self
+            .backend
+            .write_batch(vec![StorageOperation::PutMetadata {
+                key: UTXO_SNAPSHOT_METADATA_KEY.to_string(),
+                value: snapshot.serialize(),
+            }])
+            .await;
+        if result.is_err() {
+            error!("failed writing utxo snapshot : {:?}", result.err().unwrap());
+        }
+    }
+
+    /// Latest utxo snapshot as it is stored. snapshots are served to the peers without decoding them
+    pub async fn read_utxo_snapshot_buffer(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.backend.read_metadata(UTXO_SNAPSHOT_METADATA_KEY).await
+    }
+
+    pub async fn load_utxo_snapshot(&self) -> Option<UtxoSnapshot> {
+        let buffer = match self.read_utxo_snapshot_buffer().await {
+            Ok(Some(buffer)) => buffer,
+            Ok(None) => {
+                debug!("no utxo snapshot found");
+                return None;
+            }
+            Err(error) => {
+                warn!("failed reading utxo snapshot : {:?}", error);
+                return None;
+            }
+        };
+        let snapshot = UtxoSnapshot::deserialize(&buffer);
+        if snapshot.is_err() {
+            warn!(
+                "discarding invalid utxo snapshot : {:?}",
+                snapshot.err().unwrap()
+            );
+            return None;
+        }
+        snapshot.ok()
+    }
+
+    pub async fn write_lite_chain(&self, lite_chain: &LiteChain) {
+        debug!(
+            "writing lite chain at block : {:?}",
+            lite_chain.get_latest_block_id()
+        );
+        let result = self
+            .backend
+            .write_batch(vec![StorageOperation::PutMetadata {
+                key: LITE_CHAIN_METADATA_KEY.to_string(),
+                value: lite_chain.serialize(),
+            }])
+            .await;
+        if result.is_err() {
+            error!("failed writing lite chain : {:?}", result.err().unwrap());
+        }
     }
 
-    pub async fn load_block_from_disk(&self, file_name: String) -> Result<Block, std::io::Error> {
-        debug!("loading block {:?} from disk", file_name);
-        let result = self.io_interface.read_value(file_name).await;
+    pub async fn load_lite_chain(&self) -> Option<LiteChain> {
+        let buffer = match self.backend.read_metadata(LITE_CHAIN_METADATA_KEY).await {
+            Ok(Some(buffer)) => buffer,
+            Ok(None) => {
+                debug!("no lite chain found");
+                return None;
+            }
+            Err(error) => {
+                warn!("failed reading lite chain : {:?}", error);
+                return None;
+            }
+        };
+        let lite_chain = LiteChain::deserialize(&buffer);
+        if lite_chain.is_err() {
+            warn!(
+                "discarding invalid lite chain : {:?}",
+                lite_chain.err().unwrap()
+            );
+            return None;
+        }
+        lite_chain.ok()
+    }
+
+    async fn read_block_with_metadata(&self, block_hash: &SaitoHash) -> Result<Block, Error> {
+        let mut block = self.load_block_from_disk(block_hash).await?;
+        block.generate_metadata();
+        Ok(block)
+    }
+
+    /// Reads the blocks listed in the block index. returns None if the longest chain of the index is broken or any of
+    /// its blocks can't be read. other blocks which can't be read are skipped. corrupted and unreadable blocks are moved
+    /// to the quarantine, and the index entries of the missing blocks outside the longest chain are removed
+    ///
+    /// # Arguments
+    ///
+    /// * `entries`: block index entries ordered by the block id
+    /// * `lost_blocks`: hashes of the quarantined blocks and the missing longest chain blocks are added to this, to be
+    ///   fetched again from the peers
+    ///
+    /// returns: Option<Vec<Block, Global>>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    async fn load_indexed_blocks(
+        &self,
+        entries: &[BlockIndexEntry],
+        lost_blocks: &mut Vec<SaitoHash>,
+    ) -> Option<Vec<Block>> {
+        let longest_chain: Vec<&BlockIndexEntry> = entries
+            .iter()
+            .filter(|entry| entry.in_longest_chain)
+            .collect();
+        if longest_chain.is_empty() {
+            warn!("block index doesn't have a longest chain");
+            return None;
+        }
+        for pair in longest_chain.windows(2) {
+            if pair[1].block_id != pair[0].block_id + 1
+                || pair[1].previous_block_hash != pair[0].block_hash
+            {
+                warn!(
+                    "longest chain in the block index is broken at block : {:?}",
+                    hex::encode(pair[1].block_hash)
+                );
+                return None;
+            }
+        }
+        let latest_block_id = longest_chain.last().unwrap().block_id;
+
+        let mut blocks = vec![];
+        let mut stale_entries = vec![];
+        for entry in entries {
+            if entry.block_id + RING_BUFFER_LENGTH <= latest_block_id {
+                // block should have been deleted with the genesis period update
+                stale_entries.push(StorageOperation::DeleteBlock {
+                    block_hash: entry.block_hash,
+                    block_id: entry.block_id,
+                    timestamp: entry.timestamp,
+                });
+                continue;
+            }
+            match self.read_block_with_metadata(&entry.block_hash).await {
+                Ok(mut block)
+                    if block.get_hash() == entry.block_hash && block.get_id() == entry.block_id =>
+                {
+                    block.set_in_longest_chain(entry.in_longest_chain);
+                    blocks.push(block);
+                    continue;
+                }
+                Ok(_) => {
+                    warn!(
+                        "stored block : {:?} doesn't match the block index",
+                        hex::encode(entry.block_hash)
+                    );
+                }
+                Err(error) => {
+                    warn!(
+                        "failed loading indexed block : {:?} : {:?}",
+                        hex::encode(entry.block_hash),
+                        error
+                    );
+                    if error.kind() == ErrorKind::NotFound {
+                        if !entry.in_longest_chain {
+                            // missing fork blocks are not needed to restore the chain
+                            stale_entries.push(StorageOperation::DeleteBlockIndexEntry {
+                                block_hash: entry.block_hash,
+                                block_id: entry.block_id,
+                            });
+                            continue;
+                        }
+                    } else {
+                        // corrupted or unreadable
+                        self.quarantine_block(&entry.block_hash).await;
+                    }
+                    lost_blocks.push(entry.block_hash);
+                }
+            }
+            if entry.in_longest_chain {
+                return None;
+            }
+        }
+        if !stale_entries.is_empty() {
+            debug!("deleting {:?} stale blocks", stale_entries.len());
+            if let Err(error) = self.backend.write_batch(stale_entries).await {
+                warn!("failed deleting stale blocks : {:?}", error);
+            }
+        }
+        Some(blocks)
+    }
+
+    /// Reads the stored block in the network format without decoding it, so the blocks can be served to the peers
+    /// without the blockchain
+    ///
+    /// # Arguments
+    ///
+    /// * `block_hash`:
+    /// * `block_type`: Header or Full. ErrorKind::InvalidInput for the other types
+    ///
+    /// returns: Result<Vec<u8, Global>, Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn read_block_buffer(
+        &self,
+        block_hash: &SaitoHash,
+        block_type: BlockType,
+    ) -> Result<Vec<u8>, Error> {
+        if block_type != BlockType::Header && block_type != BlockType::Full {
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+        let buffer = self.backend.read_block(block_hash).await?;
+        let buffer = verify_block_file(&buffer)?;
+        if block_type == BlockType::Full {
+            return Ok(buffer.to_vec());
+        }
+        check_min_length(buffer, BLOCK_HEADER_SIZE)?;
+        // header is the start of the full block, without the transaction count
+        let mut header = buffer[0..BLOCK_HEADER_SIZE].to_vec();
+        header[0..4].copy_from_slice(&0_u32.to_be_bytes());
+        Ok(header)
+    }
+
+    /// Reads and decodes the stored block. legacy block files are accepted if they decode to the block with the given
+    /// hash, and they are written again with the header
+    ///
+    /// # Arguments
+    ///
+    /// * `block_hash`:
+    ///
+    /// returns: Result<Block, Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub async fn load_block_from_disk(&self, block_hash: &SaitoHash) -> Result<Block, Error> {
+        debug!("loading block {:?} from disk", hex::encode(block_hash));
+        let file_buffer = self.backend.read_block(block_hash).await?;
+        let buffer = verify_block_file(&file_buffer)?;
+        let mut block = Block::deserialize_for_net(&buffer.to_vec())?;
+        if is_legacy_block_file(&file_buffer) {
+            if block.generate_hashes() != *block_hash {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "legacy block file doesn't match the block hash",
+                ));
+            }
+            self.migrate_legacy_block_file(&block, file_buffer).await;
+        }
+        Ok(block)
+    }
+
+    async fn migrate_legacy_block_file(&self, block: &Block, file_buffer: Vec<u8>) {
+        debug!(
+            "adding the header to the legacy block file of : {:?}",
+            hex::encode(block.get_hash())
+        );
+        let result = self
+            .backend
+            .write_batch(vec![StorageOperation::PutBlock {
+                block_hash: block.get_hash(),
+                block_id: block.get_id(),
+                timestamp: block.get_timestamp(),
+                buffer: add_block_file_header(file_buffer),
+            }])
+            .await;
+        if result.is_err() {
+            warn!(
+                "failed migrating the legacy block file of : {:?} : {:?}",
+                hex::encode(block.get_hash()),
+                result.err().unwrap()
+            );
+        }
+    }
+
+    pub async fn delete_block_from_disk(&self, block: &Block) -> bool {
+        self.backend
+            .write_batch(vec![StorageOperation::DeleteBlock {
+                block_hash: block.get_hash(),
+                block_id: block.get_id(),
+                timestamp: block.get_timestamp(),
+            }])
+            .await
+            .is_ok()
+    }
+
+    pub async fn load_address_book(&self) -> Vec<PeerConfig> {
+        debug!("loading address book from disk");
+        let path = self.get_file_path(ADDRESS_BOOK_FILE_PATH);
+        if !self.file_exists(&path).await {
+            debug!("address book not found");
+            return vec![];
+        }
+        let result = self.io_interface.read_value(path).await;
+        if result.is_err() {
+            warn!("failed reading address book : {:?}", result.err().unwrap());
+            return vec![];
+        }
+        let result = PeerList::deserialize(&result.unwrap());
         if result.is_err() {
-            todo!()
+            warn!("failed decoding address book : {:?}", result.err().unwrap());
+            return vec![];
         }
-        let buffer = result.unwrap();
-        Ok(Block::deserialize_for_net(&buffer))
+        result.unwrap().peers
     }
 
-    pub async fn delete_block_from_disk(&self, filename: String) -> bool {
-        self.io_interface.remove_value(filename).await.is_ok()
+    pub async fn save_address_book(&mut self, peers: &[PeerConfig]) {
+        debug!("saving address book with {:?} entries", peers.len());
+        let buffer = PeerList {
+            peers: peers.to_vec(),
+        }
+        .serialize();
+        let result = self
+            .io_interface
+            .write_value(self.get_file_path(ADDRESS_BOOK_FILE_PATH), buffer)
+            .await;
+        if result.is_err() {
+            warn!("failed saving address book : {:?}", result.err().unwrap());
+        }
     }
 
     //
@@ -207,3 +756,48 @@ impl Storage {
     //     return slip;
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::core::data::storage::{
+        add_block_file_header, is_legacy_block_file, verify_block_file, BLOCK_FILE_HEADER_SIZE,
+    };
+
+    #[test]
+    fn test_block_file_header() {
+        let buffer = add_block_file_header(vec![1, 2, 3, 4]);
+        assert_eq!(buffer.len(), BLOCK_FILE_HEADER_SIZE + 4);
+        assert_eq!(verify_block_file(&buffer).unwrap(), &[1, 2, 3, 4]);
+
+        // truncated
+        assert_eq!(
+            verify_block_file(&buffer[0..buffer.len() - 1])
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::InvalidData
+        );
+        assert!(verify_block_file(&buffer[0..10]).is_err());
+        // corrupted
+        let mut corrupted = buffer.clone();
+        corrupted[BLOCK_FILE_HEADER_SIZE + 1] = 5;
+        assert_eq!(
+            verify_block_file(&corrupted).err().unwrap().kind(),
+            ErrorKind::InvalidData
+        );
+        // unknown format version
+        let mut newer = buffer.clone();
+        newer[4] += 1;
+        assert!(verify_block_file(&newer).is_err());
+
+        // legacy files without the header are left to be checked by decoding the block
+        assert!(!is_legacy_block_file(&buffer));
+        assert!(is_legacy_block_file(&[0, 0, 0, 2, 1]));
+        assert_eq!(
+            verify_block_file(&[0, 0, 0, 2, 1]).unwrap(),
+            &[0, 0, 0, 2, 1]
+        );
+    }
+}
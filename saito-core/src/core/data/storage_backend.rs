@@ -0,0 +1,427 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::{debug, warn};
+use tokio::sync::Mutex;
+
+use crate::common::defs::SaitoHash;
+use crate::common::interface_io::InterfaceIO;
+use crate::core::data::block_index::{BlockIndexEntry, BLOCK_INDEX_ENTRY_SIZE};
+use crate::core::data::serialize::Serialize;
+
+// metadata values are kept under this directory by the file backend, relative to the data directory
+pub const METADATA_DIR: &str = "metadata/";
+// block index entries are kept in this file by the file backend, relative to the metadata directory
+pub const BLOCK_INDEX_FILE_NAME: &str = "block_index";
+// corrupted blocks are moved to this directory by the file backend, relative to the data directory
+pub const QUARANTINE_DIR: &str = "quarantine/";
+
+/// Write operation applied by a storage backend as part of a batch
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageOperation {
+    // full block serialized for the network. id and timestamp are used for the height index and the file name
+    PutBlock {
+        block_hash: SaitoHash,
+        block_id: u64,
+        timestamp: u64,
+        buffer: Vec<u8>,
+    },
+    // removes the block index entry of the block too
+    DeleteBlock {
+        block_hash: SaitoHash,
+        block_id: u64,
+        timestamp: u64,
+    },
+    // adds or replaces the index entry of a stored block
+    PutBlockIndexEntry(BlockIndexEntry),
+    DeleteBlockIndexEntry {
+        block_hash: SaitoHash,
+        block_id: u64,
+    },
+    PutMetadata {
+        key: String,
+        value: Vec<u8>,
+    },
+    DeleteMetadata {
+        key: String,
+    },
+}
+
+/// Persistent store of the blocks, the block index and the node metadata
+#[async_trait]
+pub trait StorageBackend {
+    /// Applies the operations in the given order. backends which support it apply all of them or none
+    ///
+    /// # Arguments
+    ///
+    /// * `operations`:
+    ///
+    /// returns: Result<(), Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    async fn write_batch(&self, operations: Vec<StorageOperation>) -> Result<(), Error>;
+    /// Reads the stored block buffer. fails with ErrorKind::NotFound if the block is not stored
+    async fn read_block(&self, block_hash: &SaitoHash) -> Result<Vec<u8>, Error>;
+    /// Hashes of the stored blocks with the given id
+    async fn get_block_hashes_by_id(&self, block_id: u64) -> Result<Vec<SaitoHash>, Error>;
+    /// Hashes of all the stored blocks in the order they should be loaded in
+    async fn get_block_hashes(&self) -> Result<Vec<SaitoHash>, Error>;
+    /// Index entries of the stored blocks, ordered by the block id
+    async fn load_block_index(&self) -> Result<Vec<BlockIndexEntry>, Error>;
+    /// Moves the stored block away from the other blocks, so it's not loaded again but can still be inspected. blocks
+    /// which can't be read are removed without a copy. removes the block index entry of the block too
+    async fn quarantine_block(&self, block_hash: &SaitoHash) -> Result<(), Error>;
+    async fn read_metadata(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+}
+
+pub fn generate_block_filename(block_dir: &str, timestamp: u64, block_hash: &SaitoHash) -> String {
+    block_dir.to_string()
+        + timestamp.to_string().as_str()
+        + "-"
+        + hex::encode(block_hash).as_str()
+        + ".block"
+}
+
+/// Reads the block hash from a file name in the "timestamp-hash.block" format
+pub fn parse_block_filename(file_name: &str) -> Option<SaitoHash> {
+    let name = file_name.strip_suffix(".block")?;
+    let (_, hash) = name.split_once('-')?;
+    let hash = hex::decode(hash).ok()?;
+    hash.try_into().ok()
+}
+
+#[derive(Debug, Default)]
+struct FileIndex {
+    // block hash to the file name of the block
+    files: HashMap<SaitoHash, String>,
+    // block ids to the block hashes
+    ids: BTreeMap<u64, Vec<SaitoHash>>,
+    // true once the files are loaded from the block index or the block directory. blocks missing from the index
+    // after that are not stored
+    complete: bool,
+}
+
+/// Keeps each block in a separate "timestamp-hash.block" file in the block directory and each metadata value in a
+/// file under the metadata directory.
+///
+/// The block index is kept in a single file of concatenated entries, which is rewritten by the batches changing it.
+///
+/// Files are written one by one, so the batches are not atomic with this backend. The height index is kept in memory
+/// and only covers the blocks written since the start or loaded from the block index, since the file names don't have
+/// the block ids.
+///
+/// File names are loaded from the block index, or by listing the block directory once when there is no index, and kept
+/// up to date by the batches afterwards. So the backend should be shared by all the storages of a node instead of
+/// opening one per storage.
+pub struct FileStorageBackend {
+    io_interface: Arc<dyn InterfaceIO + Send + Sync>,
+    index: Mutex<FileIndex>,
+}
+
+impl FileStorageBackend {
+    pub fn new(io_interface: Arc<dyn InterfaceIO + Send + Sync>) -> FileStorageBackend {
+        FileStorageBackend {
+            io_interface,
+            index: Mutex::new(FileIndex::default()),
+        }
+    }
+    fn get_metadata_path(&self, key: &str) -> String {
+        self.io_interface.get_data_dir() + METADATA_DIR + key
+    }
+    async fn read_block_index_file(&self) -> Result<Vec<BlockIndexEntry>, Error> {
+        let path = self.get_metadata_path(BLOCK_INDEX_FILE_NAME);
+        if !self.io_interface.is_existing_file(path.clone()).await {
+            return Ok(vec![]);
+        }
+        let buffer = self.io_interface.read_value(path).await?;
+        if buffer.len() % BLOCK_INDEX_ENTRY_SIZE != 0 {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        buffer
+            .chunks(BLOCK_INDEX_ENTRY_SIZE)
+            .map(|chunk| BlockIndexEntry::deserialize(&chunk.to_vec()))
+            .collect()
+    }
+    /// Reads the block index file when a batch first changes the index. an unreadable index is started over, since the
+    /// blocks added afterwards write their entries again
+    async fn get_block_index_for_update<'a>(
+        &self,
+        block_index: &'a mut Option<BTreeMap<(u64, SaitoHash), BlockIndexEntry>>,
+    ) -> &'a mut BTreeMap<(u64, SaitoHash), BlockIndexEntry> {
+        if block_index.is_none() {
+            let entries = self.read_block_index_file().await.unwrap_or_else(|error| {
+                warn!("discarding unreadable block index : {:?}", error);
+                vec![]
+            });
+            *block_index = Some(
+                entries
+                    .into_iter()
+                    .map(|entry| ((entry.block_id, entry.block_hash), entry))
+                    .collect(),
+            );
+        }
+        block_index.as_mut().unwrap()
+    }
+    /// Lists the block directory to find the file names of the blocks stored before the start
+    async fn refresh_file_index(&self) -> Result<Vec<SaitoHash>, Error> {
+        let file_names = self.io_interface.load_block_file_list().await?;
+        let mut block_hashes = vec![];
+        let mut index = self.index.lock().await;
+        index.complete = true;
+        for file_name in file_names {
+            let block_hash = parse_block_filename(&file_name);
+            if block_hash.is_none() {
+                warn!("skipping invalid block file name : {:?}", file_name);
+                continue;
+            }
+            let block_hash = block_hash.unwrap();
+            index.files.insert(
+                block_hash,
+                self.io_interface.get_block_dir() + file_name.as_str(),
+            );
+            block_hashes.push(block_hash);
+        }
+        Ok(block_hashes)
+    }
+    async fn find_block_file(&self, block_hash: &SaitoHash) -> Option<String> {
+        {
+            let index = self.index.lock().await;
+            if let Some(file_name) = index.files.get(block_hash) {
+                return Some(file_name.clone());
+            }
+            // directory is not listed again for each unknown block, since anyone can ask for any hash
+            if index.complete {
+                return None;
+            }
+        }
+        if self.refresh_file_index().await.is_err() {
+            return None;
+        }
+        self.index.lock().await.files.get(block_hash).cloned()
+    }
+}
+
+#[async_trait]
+impl StorageBackend for FileStorageBackend {
+    async fn write_batch(&self, operations: Vec<StorageOperation>) -> Result<(), Error> {
+        let mut block_index = None;
+        let mut block_index_changed = false;
+        for operation in operations {
+            match operation {
+                StorageOperation::PutBlock {
+                    block_hash,
+                    block_id,
+                    timestamp,
+                    buffer,
+                } => {
+                    let file_name = generate_block_filename(
+                        &self.io_interface.get_block_dir(),
+                        timestamp,
+                        &block_hash,
+                    );
+                    self.io_interface
+                        .write_value(file_name.clone(), buffer)
+                        .await?;
+                    let mut index = self.index.lock().await;
+                    index.files.insert(block_hash, file_name);
+                    let hashes = index.ids.entry(block_id).or_default();
+                    if !hashes.contains(&block_hash) {
+                        hashes.push(block_hash);
+                    }
+                }
+                StorageOperation::DeleteBlock {
+                    block_hash,
+                    block_id,
+                    timestamp,
+                } => {
+                    let file_name = generate_block_filename(
+                        &self.io_interface.get_block_dir(),
+                        timestamp,
+                        &block_hash,
+                    );
+                    self.io_interface.remove_value(file_name).await?;
+                    let mut index = self.index.lock().await;
+                    index.files.remove(&block_hash);
+                    if let Some(hashes) = index.ids.get_mut(&block_id) {
+                        hashes.retain(|hash| *hash != block_hash);
+                        if hashes.is_empty() {
+                            index.ids.remove(&block_id);
+                        }
+                    }
+                    block_index_changed |= self
+                        .get_block_index_for_update(&mut block_index)
+                        .await
+                        .remove(&(block_id, block_hash))
+                        .is_some();
+                }
+                StorageOperation::PutBlockIndexEntry(entry) => {
+                    self.get_block_index_for_update(&mut block_index)
+                        .await
+                        .insert((entry.block_id, entry.block_hash), entry);
+                    block_index_changed = true;
+                }
+                StorageOperation::DeleteBlockIndexEntry {
+                    block_hash,
+                    block_id,
+                } => {
+                    block_index_changed |= self
+                        .get_block_index_for_update(&mut block_index)
+                        .await
+                        .remove(&(block_id, block_hash))
+                        .is_some();
+                }
+                StorageOperation::PutMetadata { key, value } => {
+                    self.io_interface
+                        .write_value(self.get_metadata_path(&key), value)
+                        .await?;
+                }
+                StorageOperation::DeleteMetadata { key } => {
+                    let path = self.get_metadata_path(&key);
+                    if self.io_interface.is_existing_file(path.clone()).await {
+                        self.io_interface.remove_value(path).await?;
+                    }
+                }
+            }
+        }
+        if block_index_changed {
+            let buffer: Vec<u8> = block_index
+                .unwrap()
+                .values()
+                .flat_map(|entry| entry.serialize())
+                .collect();
+            self.io_interface
+                .write_value(self.get_metadata_path(BLOCK_INDEX_FILE_NAME), buffer)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn read_block(&self, block_hash: &SaitoHash) -> Result<Vec<u8>, Error> {
+        let file_name = self.find_block_file(block_hash).await;
+        if file_name.is_none() {
+            debug!("block file not found for : {:?}", hex::encode(block_hash));
+            return Err(Error::from(ErrorKind::NotFound));
+        }
+        self.io_interface.read_value(file_name.unwrap()).await
+    }
+
+    async fn get_block_hashes_by_id(&self, block_id: u64) -> Result<Vec<SaitoHash>, Error> {
+        let index = self.index.lock().await;
+        Ok(index.ids.get(&block_id).cloned().unwrap_or_default())
+    }
+
+    async fn get_block_hashes(&self) -> Result<Vec<SaitoHash>, Error> {
+        self.refresh_file_index().await
+    }
+
+    async fn load_block_index(&self) -> Result<Vec<BlockIndexEntry>, Error> {
+        let entries = self.read_block_index_file().await?;
+        // file names are known from the index, so the block directory doesn't need to be listed to read these blocks
+        let mut index = self.index.lock().await;
+        index.complete = true;
+        for entry in entries.iter() {
+            index.files.insert(
+                entry.block_hash,
+                generate_block_filename(
+                    &self.io_interface.get_block_dir(),
+                    entry.timestamp,
+                    &entry.block_hash,
+                ),
+            );
+            let hashes = index.ids.entry(entry.block_id).or_default();
+            if !hashes.contains(&entry.block_hash) {
+                hashes.push(entry.block_hash);
+            }
+        }
+        Ok(entries)
+    }
+
+    async fn quarantine_block(&self, block_hash: &SaitoHash) -> Result<(), Error> {
+        let file_name = self.find_block_file(block_hash).await;
+        if file_name.is_none() {
+            return Err(Error::from(ErrorKind::NotFound));
+        }
+        let file_name = file_name.unwrap();
+        match self.io_interface.read_value(file_name.clone()).await {
+            Ok(buffer) => {
+                let quarantine_file_name = self.io_interface.get_data_dir()
+                    + QUARANTINE_DIR
+                    + file_name
+                        .strip_prefix(self.io_interface.get_block_dir().as_str())
+                        .unwrap_or(file_name.as_str());
+                debug!(
+                    "moving block file : {:?} to : {:?}",
+                    file_name, quarantine_file_name
+                );
+                self.io_interface
+                    .write_value(quarantine_file_name, buffer)
+                    .await?;
+            }
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                warn!(
+                    "block file : {:?} can't be read : {:?}. removing it without a copy",
+                    file_name, error
+                );
+            }
+            Err(error) => return Err(error),
+        }
+        self.io_interface.remove_value(file_name).await?;
+        {
+            let mut index = self.index.lock().await;
+            index.files.remove(block_hash);
+            index.ids.retain(|_, hashes| {
+                hashes.retain(|hash| hash != block_hash);
+                !hashes.is_empty()
+            });
+        }
+        let mut block_index = None;
+        let entries = self.get_block_index_for_update(&mut block_index).await;
+        let size = entries.len();
+        entries.retain(|(_, hash), _| hash != block_hash);
+        if entries.len() != size {
+            let buffer: Vec<u8> = entries
+                .values()
+                .flat_map(|entry| entry.serialize())
+                .collect();
+            self.io_interface
+                .write_value(self.get_metadata_path(BLOCK_INDEX_FILE_NAME), buffer)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn read_metadata(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let path = self.get_metadata_path(key);
+        if !self.io_interface.is_existing_file(path.clone()).await {
+            return Ok(None);
+        }
+        Ok(Some(self.io_interface.read_value(path).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::storage_backend::{generate_block_filename, parse_block_filename};
+
+    #[test]
+    fn test_block_filename() {
+        let file_name = generate_block_filename("data/blocks/", 1000, &[5; 32]);
+        assert_eq!(
+            file_name,
+            "data/blocks/1000-".to_string() + hex::encode([5; 32]).as_str() + ".block"
+        );
+        assert_eq!(
+            parse_block_filename(file_name.strip_prefix("data/blocks/").unwrap()),
+            Some([5; 32])
+        );
+        assert!(parse_block_filename("1000-0102.block").is_none());
+        assert!(parse_block_filename("1000.block").is_none());
+        assert!(parse_block_filename(&(hex::encode([5; 32]) + ".txt")).is_none());
+    }
+}
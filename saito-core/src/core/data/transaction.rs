@@ -11,11 +11,14 @@ use tokio::sync::RwLock;
 use crate::common::defs::{SaitoHash, SaitoPrivateKey, SaitoPublicKey, SaitoSignature, UtxoSet};
 use crate::core::data::crypto::{generate_random_bytes, hash, sign, verify};
 use crate::core::data::hop::{Hop, HOP_SIZE};
+use crate::core::data::serialize::{check_length, check_min_length, DeserializationError};
 use crate::core::data::slip::{Slip, SlipType, SLIP_SIZE};
 use crate::core::data::staking::Staking;
 use crate::core::data::wallet::Wallet;
 
 pub const TRANSACTION_SIZE: usize = 89;
+// maximum size of a serialized transaction including its slips, message and path
+pub const MAX_TRANSACTION_SIZE: usize = 16 * 1024 * 1024;
 
 #[derive(Serialize, Deserialize, Debug, Copy, PartialEq, Clone, FromPrimitive)]
 pub enum TransactionType {
@@ -419,14 +422,19 @@ impl Transaction {
     /// [output][output][output]...
     /// [message]
     /// [hop][hop][hop]...
-    pub fn deserialize_from_net(bytes: Vec<u8>) -> Transaction {
+    pub fn deserialize_from_net(bytes: Vec<u8>) -> Result<Transaction, DeserializationError> {
+        check_length(&bytes, Transaction::get_serialized_size(&bytes)?)?;
         let inputs_len: u32 = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
         let outputs_len: u32 = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
         let message_len: usize = u32::from_be_bytes(bytes[8..12].try_into().unwrap()) as usize;
         let path_len: usize = u32::from_be_bytes(bytes[12..16].try_into().unwrap()) as usize;
         let signature: SaitoSignature = bytes[16..80].try_into().unwrap();
         let timestamp: u64 = u64::from_be_bytes(bytes[80..88].try_into().unwrap());
-        let transaction_type: TransactionType = FromPrimitive::from_u8(bytes[88]).unwrap();
+        let transaction_type: Option<TransactionType> = FromPrimitive::from_u8(bytes[88]);
+        if transaction_type.is_none() {
+            return Err(DeserializationError::InvalidTransactionType(bytes[88]));
+        }
+        let transaction_type = transaction_type.unwrap();
         let start_of_inputs = TRANSACTION_SIZE;
         let start_of_outputs = start_of_inputs + inputs_len as usize * SLIP_SIZE;
         let start_of_message = start_of_outputs + outputs_len as usize * SLIP_SIZE;
@@ -435,24 +443,22 @@ impl Transaction {
         for n in 0..inputs_len {
             let start_of_data: usize = start_of_inputs as usize + n as usize * SLIP_SIZE;
             let end_of_data: usize = start_of_data + SLIP_SIZE;
-            let input = Slip::deserialize_from_net(bytes[start_of_data..end_of_data].to_vec());
+            let input = Slip::deserialize_from_net(bytes[start_of_data..end_of_data].to_vec())?;
             inputs.push(input);
         }
         let mut outputs: Vec<Slip> = vec![];
         for n in 0..outputs_len {
             let start_of_data: usize = start_of_outputs as usize + n as usize * SLIP_SIZE;
             let end_of_data: usize = start_of_data + SLIP_SIZE;
-            let output = Slip::deserialize_from_net(bytes[start_of_data..end_of_data].to_vec());
+            let output = Slip::deserialize_from_net(bytes[start_of_data..end_of_data].to_vec())?;
             outputs.push(output);
         }
-        let message = bytes[start_of_message..start_of_message + message_len]
-            .try_into()
-            .unwrap();
+        let message = bytes[start_of_message..start_of_message + message_len].to_vec();
         let mut path: Vec<Hop> = vec![];
         for n in 0..path_len {
             let start_of_data: usize = start_of_path as usize + n as usize * HOP_SIZE;
             let end_of_data: usize = start_of_data + HOP_SIZE;
-            let hop = Hop::deserialize_from_net(bytes[start_of_data..end_of_data].to_vec());
+            let hop = Hop::deserialize_from_net(bytes[start_of_data..end_of_data].to_vec())?;
             path.push(hop);
         }
 
@@ -464,7 +470,41 @@ impl Transaction {
         transaction.set_transaction_type(transaction_type);
         transaction.set_signature(signature);
         transaction.set_path(path);
-        transaction
+        Ok(transaction)
+    }
+    /// Reads the size of the serialized transaction at the start of the buffer from its length fields. fails if the
+    /// buffer is shorter than that or the size is over the limit
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes`: buffer starting with a serialized transaction
+    ///
+    /// returns: Result<usize, DeserializationError>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn get_serialized_size(bytes: &[u8]) -> Result<usize, DeserializationError> {
+        check_min_length(bytes, TRANSACTION_SIZE)?;
+        let inputs_len = u32::from_be_bytes(bytes[0..4].try_into().unwrap()) as u64;
+        let outputs_len = u32::from_be_bytes(bytes[4..8].try_into().unwrap()) as u64;
+        let message_len = u32::from_be_bytes(bytes[8..12].try_into().unwrap()) as u64;
+        let path_len = u32::from_be_bytes(bytes[12..16].try_into().unwrap()) as u64;
+        // calculated in u64 so the lengths can't overflow on 32 bit targets
+        let size = TRANSACTION_SIZE as u64
+            + (inputs_len + outputs_len) * SLIP_SIZE as u64
+            + message_len
+            + path_len * HOP_SIZE as u64;
+        if size > MAX_TRANSACTION_SIZE as u64 {
+            return Err(DeserializationError::SizeLimitExceeded {
+                size,
+                limit: MAX_TRANSACTION_SIZE,
+            });
+        }
+        check_min_length(bytes, size as usize)?;
+        Ok(size as usize)
     }
     //
     // this function exists largely for testing. It attempts to attach the requested fee
@@ -1180,7 +1220,52 @@ mod tests {
 
         let serialized_tx = mock_tx.serialize_for_net();
 
-        let deserialized_tx = Transaction::deserialize_from_net(serialized_tx);
+        let deserialized_tx = Transaction::deserialize_from_net(serialized_tx).unwrap();
         assert_eq!(mock_tx, deserialized_tx);
     }
+
+    #[test]
+    fn deserialize_invalid_buffer_test() {
+        let mut mock_tx = Transaction::new();
+        mock_tx.add_input(Slip::new());
+        mock_tx.set_message(vec![1, 2, 3]);
+        mock_tx.set_path(vec![Hop::new()]);
+        let serialized_tx = mock_tx.serialize_for_net();
+        assert_eq!(
+            Transaction::get_serialized_size(&serialized_tx).unwrap(),
+            serialized_tx.len()
+        );
+
+        assert!(matches!(
+            Transaction::deserialize_from_net(serialized_tx[0..50].to_vec()),
+            Err(DeserializationError::BufferTooShort { .. })
+        ));
+        assert!(matches!(
+            Transaction::deserialize_from_net(serialized_tx[0..serialized_tx.len() - 1].to_vec()),
+            Err(DeserializationError::BufferTooShort { .. })
+        ));
+        let mut buffer = serialized_tx.clone();
+        buffer.push(0);
+        assert!(matches!(
+            Transaction::deserialize_from_net(buffer),
+            Err(DeserializationError::TrailingBytes { .. })
+        ));
+
+        // slip type of the input
+        let mut buffer = serialized_tx.clone();
+        buffer[TRANSACTION_SIZE + SLIP_SIZE - 1] = 100;
+        assert_eq!(
+            Transaction::deserialize_from_net(buffer).err(),
+            Some(DeserializationError::InvalidSlipType(100))
+        );
+
+        // lengths adding up over the limit
+        let mut buffer = serialized_tx;
+        buffer[0..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        buffer[12..16].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            Transaction::deserialize_from_net(buffer),
+            Err(DeserializationError::SizeLimitExceeded { .. })
+        ));
+    }
 }
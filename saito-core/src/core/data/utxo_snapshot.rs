@@ -0,0 +1,232 @@
+use std::io::{Error, ErrorKind};
+
+use crate::common::defs::{SaitoHash, SaitoUTXOSetKey};
+use crate::core::data::crypto::hash;
+use crate::core::data::serialize::{check_length, check_min_length, Serialize};
+use crate::core::data::slip::{Slip, SLIP_SIZE};
+use crate::core::data::staking::Staking;
+
+pub const UTXO_SNAPSHOT_VERSION: u32 = 1;
+// latest snapshot is kept in the storage metadata with this key
+pub const UTXO_SNAPSHOT_METADATA_KEY: &str = "utxo_snapshot";
+
+const UTXO_SNAPSHOT_HEADER_SIZE: usize = 72;
+const UTXO_SNAPSHOT_ENTRY_SIZE: usize = 75;
+const PURGED_UTXO_SIZE: usize = 82;
+const STAKING_SLIP_SIZE: usize = SLIP_SIZE + 8;
+const CHECKSUM_SIZE: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UtxoSnapshotEntry {
+    pub utxoset_key: SaitoUTXOSetKey,
+    pub spendable: bool,
+}
+
+/// Slip of a block the snapshot was taken with. slip is removed from the utxo set when the blocks at the id are purged,
+/// which happens after restarting if the block is already deleted from the disk
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurgedUtxo {
+    pub block_id: u64,
+    pub utxoset_key: SaitoUTXOSetKey,
+}
+
+/// Utxo set and the staking tables as they were after adding a longest chain block
+#[derive(Debug, Clone)]
+pub struct UtxoSnapshot {
+    pub block_id: u64,
+    pub block_hash: SaitoHash,
+    pub utxos: Vec<UtxoSnapshotEntry>,
+    pub purged_utxos: Vec<PurgedUtxo>,
+    pub staking: Staking,
+}
+
+fn serialize_staking_slip(slip: &Slip) -> Vec<u8> {
+    let mut buffer = slip.serialize_for_net();
+    buffer.extend(slip.get_payout().to_be_bytes());
+    buffer
+}
+
+fn deserialize_staking_slip(buffer: &[u8]) -> Result<Slip, Error> {
+    let mut slip = Slip::deserialize_from_net(buffer[0..SLIP_SIZE].to_vec())?;
+    slip.set_payout(u64::from_be_bytes(
+        buffer[SLIP_SIZE..STAKING_SLIP_SIZE].try_into().unwrap(),
+    ));
+    slip.generate_utxoset_key();
+    Ok(slip)
+}
+
+/// [version - 4 bytes - u32]
+/// [block id - 8 bytes - u64]
+/// [block hash - 32 bytes]
+/// [utxo entry count - 8 bytes - u64]
+/// [purged utxo count - 8 bytes - u64]
+/// [deposit count - 4 bytes - u32]
+/// [staker count - 4 bytes - u32]
+/// [pending count - 4 bytes - u32]
+/// [utxo entries - 75 bytes each. utxoset key 74 bytes, spendable 1 byte]
+/// [purged utxos - 82 bytes each. block id 8 bytes, utxoset key 74 bytes]
+/// [deposits, stakers and pending slips - 83 bytes each. slip 75 bytes, payout 8 bytes]
+/// [checksum - 32 bytes - hash of the preceding bytes]
+impl Serialize<Self> for UtxoSnapshot {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = UTXO_SNAPSHOT_VERSION.to_be_bytes().to_vec();
+        buffer.extend(self.block_id.to_be_bytes());
+        buffer.extend(self.block_hash);
+        buffer.extend((self.utxos.len() as u64).to_be_bytes());
+        buffer.extend((self.purged_utxos.len() as u64).to_be_bytes());
+        buffer.extend((self.staking.deposits.len() as u32).to_be_bytes());
+        buffer.extend((self.staking.stakers.len() as u32).to_be_bytes());
+        buffer.extend((self.staking.pending.len() as u32).to_be_bytes());
+        for entry in self.utxos.iter() {
+            buffer.extend(entry.utxoset_key);
+            buffer.push(entry.spendable as u8);
+        }
+        for purged_utxo in self.purged_utxos.iter() {
+            buffer.extend(purged_utxo.block_id.to_be_bytes());
+            buffer.extend(purged_utxo.utxoset_key);
+        }
+        for slip in self
+            .staking
+            .deposits
+            .iter()
+            .chain(self.staking.stakers.iter())
+            .chain(self.staking.pending.iter())
+        {
+            buffer.extend(serialize_staking_slip(slip));
+        }
+        let checksum = hash(&buffer);
+        buffer.extend(checksum);
+        buffer
+    }
+
+    fn deserialize(buffer: &Vec<u8>) -> Result<Self, Error> {
+        check_min_length(buffer, UTXO_SNAPSHOT_HEADER_SIZE + CHECKSUM_SIZE)?;
+        let version = u32::from_be_bytes(buffer[0..4].try_into().unwrap());
+        if version != UTXO_SNAPSHOT_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported utxo snapshot version : {}", version),
+            ));
+        }
+        let block_id = u64::from_be_bytes(buffer[4..12].try_into().unwrap());
+        let block_hash: SaitoHash = buffer[12..44].try_into().unwrap();
+        let utxo_count = u64::from_be_bytes(buffer[44..52].try_into().unwrap());
+        let purged_count = u64::from_be_bytes(buffer[52..60].try_into().unwrap());
+        let deposit_count = u32::from_be_bytes(buffer[60..64].try_into().unwrap()) as usize;
+        let staker_count = u32::from_be_bytes(buffer[64..68].try_into().unwrap()) as usize;
+        let pending_count = u32::from_be_bytes(buffer[68..72].try_into().unwrap()) as usize;
+
+        // counts are checked against the buffer before computing the sizes, so the sizes can't overflow
+        if utxo_count > buffer.len() as u64
+            || purged_count > buffer.len() as u64
+            || deposit_count + staker_count + pending_count > buffer.len()
+        {
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+        let utxos_end = UTXO_SNAPSHOT_HEADER_SIZE + utxo_count as usize * UTXO_SNAPSHOT_ENTRY_SIZE;
+        let purged_end = utxos_end + purged_count as usize * PURGED_UTXO_SIZE;
+        let slips_end =
+            purged_end + (deposit_count + staker_count + pending_count) * STAKING_SLIP_SIZE;
+        check_length(buffer, slips_end + CHECKSUM_SIZE)?;
+        if hash(&buffer[0..slips_end].to_vec()) != buffer[slips_end..] {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "utxo snapshot checksum doesn't match",
+            ));
+        }
+
+        let mut utxos = Vec::with_capacity(utxo_count as usize);
+        for chunk in buffer[UTXO_SNAPSHOT_HEADER_SIZE..utxos_end].chunks(UTXO_SNAPSHOT_ENTRY_SIZE) {
+            utxos.push(UtxoSnapshotEntry {
+                utxoset_key: chunk[0..74].try_into().unwrap(),
+                spendable: chunk[74] == 1,
+            });
+        }
+        let mut purged_utxos = Vec::with_capacity(purged_count as usize);
+        for chunk in buffer[utxos_end..purged_end].chunks(PURGED_UTXO_SIZE) {
+            purged_utxos.push(PurgedUtxo {
+                block_id: u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+                utxoset_key: chunk[8..82].try_into().unwrap(),
+            });
+        }
+        let mut slips = buffer[purged_end..slips_end]
+            .chunks(STAKING_SLIP_SIZE)
+            .map(deserialize_staking_slip)
+            .collect::<Result<Vec<Slip>, Error>>()?;
+        let pending = slips.split_off(deposit_count + staker_count);
+        let stakers = slips.split_off(deposit_count);
+        let mut staking = Staking::new();
+        staking.deposits = slips;
+        staking.stakers = stakers;
+        staking.pending = pending;
+
+        Ok(UtxoSnapshot {
+            block_id,
+            block_hash,
+            utxos,
+            purged_utxos,
+            staking,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::serialize::Serialize;
+    use crate::core::data::slip::{Slip, SlipType};
+    use crate::core::data::staking::Staking;
+    use crate::core::data::utxo_snapshot::{PurgedUtxo, UtxoSnapshot, UtxoSnapshotEntry};
+
+    #[test]
+    fn test_utxo_snapshot_serialize() {
+        let mut staking = Staking::new();
+        let mut slip = Slip::new();
+        slip.set_amount(100);
+        slip.set_payout(20);
+        slip.set_slip_type(SlipType::StakerDeposit);
+        slip.generate_utxoset_key();
+        staking.stakers.push(slip.clone());
+        slip.set_slip_ordinal(1);
+        slip.generate_utxoset_key();
+        staking.pending.push(slip);
+
+        let snapshot = UtxoSnapshot {
+            block_id: 30,
+            block_hash: [3; 32],
+            utxos: vec![
+                UtxoSnapshotEntry {
+                    utxoset_key: [1; 74],
+                    spendable: true,
+                },
+                UtxoSnapshotEntry {
+                    utxoset_key: [2; 74],
+                    spendable: false,
+                },
+            ],
+            purged_utxos: vec![PurgedUtxo {
+                block_id: 11,
+                utxoset_key: [1; 74],
+            }],
+            staking,
+        };
+        let buffer = snapshot.serialize();
+        let snapshot2 = UtxoSnapshot::deserialize(&buffer).unwrap();
+        assert_eq!(snapshot2.block_id, 30);
+        assert_eq!(snapshot2.block_hash, [3; 32]);
+        assert_eq!(snapshot2.utxos, snapshot.utxos);
+        assert_eq!(snapshot2.purged_utxos, snapshot.purged_utxos);
+        assert!(snapshot2.staking.deposits.is_empty());
+        assert_eq!(snapshot2.staking.stakers, snapshot.staking.stakers);
+        assert_eq!(snapshot2.staking.pending, snapshot.staking.pending);
+
+        // corrupted data
+        let mut corrupted = buffer.clone();
+        corrupted[80] ^= 1;
+        assert!(UtxoSnapshot::deserialize(&corrupted).is_err());
+        // unknown version
+        let mut corrupted = buffer.clone();
+        corrupted[3] = 2;
+        assert!(UtxoSnapshot::deserialize(&corrupted).is_err());
+        assert!(UtxoSnapshot::deserialize(&buffer[0..buffer.len() - 1].to_vec()).is_err());
+    }
+}
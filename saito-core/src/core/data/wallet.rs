@@ -10,7 +10,7 @@ use crate::core::data::crypto::{
 use crate::core::data::golden_ticket::GoldenTicket;
 use crate::core::data::slip::{Slip, SlipType};
 use crate::core::data::staking::Staking;
-use crate::core::data::storage::Storage;
+use crate::core::data::storage::{Storage, WALLETS_DIR};
 use crate::core::data::transaction::{Transaction, TransactionType};
 
 pub const WALLET_SIZE: usize = 65;
@@ -64,7 +64,7 @@ impl Wallet {
     }
 
     pub async fn load(&mut self, storage: &mut Storage) {
-        let mut filename = String::from("data/wallets/");
+        let mut filename = storage.get_file_path(WALLETS_DIR);
         filename.push_str(&self.filename);
 
         if storage.file_exists(&filename).await {
@@ -92,7 +92,7 @@ impl Wallet {
     }
 
     pub async fn save(&mut self, storage: &mut Storage) {
-        let mut filename = String::from("data/wallets/");
+        let mut filename = storage.get_file_path(WALLETS_DIR);
         filename.push_str(&self.filename);
 
         let password = self.get_password();
@@ -1,29 +1,70 @@
+use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
-use log::{debug, trace};
+use log::{debug, info, trace, warn};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::RwLock;
 
 use crate::common::command::NetworkEvent;
-use crate::common::defs::SaitoHash;
+use crate::common::defs::{SaitoHash, SaitoPublicKey};
 use crate::common::keep_time::KeepTime;
 use crate::common::process_event::ProcessEvent;
 use crate::core::consensus_event_processor::ConsensusEvent;
 use crate::core::data;
+use crate::core::data::application_module::ApplicationModules;
+use crate::core::data::block::{Block, BlockType};
 use crate::core::data::blockchain::Blockchain;
 use crate::core::data::configuration::Configuration;
+use crate::core::data::header_sync::{get_new_header_chain, HeaderSync, HEADER_SYNC_TIMEOUT};
+use crate::core::data::lite_chain::LiteChain;
+use crate::core::data::msg::application_message::ApplicationMessage;
+use crate::core::data::msg::block_fetch::{BlockFetchRequest, BlockFetchResponse};
+use crate::core::data::msg::block_headers::{BlockHeaders, MAX_HEADERS_PER_MESSAGE};
 use crate::core::data::msg::block_request::BlockchainRequest;
+use crate::core::data::msg::block_transactions::{BlockTransactions, BlockTransactionsRequest};
+use crate::core::data::msg::compact_block::{
+    CompactBlock, CompactBlockTransactions, CompactBlockTransactionsRequest,
+};
 use crate::core::data::msg::message::Message;
+use crate::core::data::msg::peer_list::MAX_PEERS_IN_PEER_LIST;
 use crate::core::data::network::Network;
+use crate::core::data::peer::PeerMisbehaviour;
+use crate::core::data::rate_limiter::RateLimitResult;
+use crate::core::data::storage::Storage;
 use crate::core::data::wallet::Wallet;
 use crate::core::mining_event_processor::MiningEvent;
 
-#[derive(Debug)]
-pub enum RoutingEvent {}
+// interval in microseconds to look for new peers to connect from the address book
+pub const PEER_DISCOVERY_INTERVAL: u128 = 10_000_000;
+// interval in microseconds to ping the peers and check if they are alive
+pub const PING_INTERVAL: u128 = 10_000_000;
+// interval in microseconds to check the static peers which need to be reconnected
+pub const RECONNECT_CHECK_INTERVAL: u128 = 1_000_000;
+// delay in milliseconds before the first reconnection attempt. doubled for each failed attempt
+pub const RECONNECT_BASE_DELAY: u64 = 1_000;
+// time in milliseconds a connection attempt to a static peer can take before it's considered failed
+pub const STATIC_PEER_CONNECT_TIMEOUT: u64 = 30_000;
+// interval in microseconds to check the progress of the headers-first synchronisation
+pub const HEADER_SYNC_CHECK_INTERVAL: u128 = 1_000_000;
+// interval in microseconds to send the scheduled block fetches and check for the timed out ones
+pub const BLOCK_DOWNLOAD_CHECK_INTERVAL: u128 = 100_000;
 
 #[derive(Debug)]
+pub enum RoutingEvent {
+    PeerMisbehaviour {
+        peer_index: u64,
+        misbehaviour: PeerMisbehaviour,
+    },
+    // message from an application module to the peer with the given key, or to all the peers if there is no key
+    SendApplicationMessage {
+        recipient: Option<SaitoPublicKey>,
+        message: ApplicationMessage,
+    },
+}
+
+#[derive(Debug, PartialEq)]
 pub enum PeerState {
     Connected,
     Connecting,
@@ -34,6 +75,81 @@ pub struct StaticPeer {
     pub peer_details: data::configuration::PeerConfig,
     pub peer_state: PeerState,
     pub peer_index: u64,
+    // failed connection attempts since the last successful handshake
+    pub reconnect_attempts: u32,
+    // timestamp after which the next connection attempt can be made
+    pub next_reconnect_time: u64,
+    // timestamp of the last connection attempt
+    pub connecting_since: u64,
+}
+
+impl StaticPeer {
+    pub fn new(peer_details: data::configuration::PeerConfig) -> StaticPeer {
+        StaticPeer {
+            peer_details,
+            peer_state: PeerState::Disconnected,
+            peer_index: 0,
+            reconnect_attempts: 0,
+            next_reconnect_time: 0,
+            connecting_since: 0,
+        }
+    }
+    fn get_address(&self) -> String {
+        self.peer_details.host.clone() + ":" + self.peer_details.port.to_string().as_str()
+    }
+    /// Marks the peer as disconnected and schedules the next connection attempt
+    fn schedule_reconnect(&mut self, current_time: u64, max_delay: u64) {
+        let delay = get_reconnect_delay(self.reconnect_attempts, max_delay);
+        self.reconnect_attempts += 1;
+        self.peer_state = PeerState::Disconnected;
+        self.next_reconnect_time = current_time + delay;
+        info!(
+            "reconnecting to static peer : {:?} in {:?}ms, attempt : {:?}",
+            self.get_address(),
+            delay,
+            self.reconnect_attempts
+        );
+    }
+    fn start_connecting(&mut self, current_time: u64) {
+        self.peer_state = PeerState::Connecting;
+        self.connecting_since = current_time;
+    }
+    /// Moves the peer back to disconnected if the connection attempt didn't complete within the deadline
+    ///
+    /// # Arguments
+    ///
+    /// * `current_time`:
+    /// * `max_delay`: maximum delay in milliseconds before the next attempt
+    ///
+    /// returns: bool true if the connection attempt timed out
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    fn check_connect_timeout(&mut self, current_time: u64, max_delay: u64) -> bool {
+        if self.peer_state != PeerState::Connecting
+            || current_time < self.connecting_since + STATIC_PEER_CONNECT_TIMEOUT
+        {
+            return false;
+        }
+        warn!(
+            "connection attempt to static peer : {:?} timed out",
+            self.get_address()
+        );
+        self.schedule_reconnect(current_time, max_delay);
+        true
+    }
+}
+
+/// Delay in milliseconds before the next reconnection attempt. Grows exponentially with the attempt count up to the
+/// given maximum, and a random jitter of up to half of the delay is applied so the peers don't reconnect in lockstep.
+pub fn get_reconnect_delay(attempts: u32, max_delay: u64) -> u64 {
+    let delay = RECONNECT_BASE_DELAY
+        .saturating_mul(1 << attempts.min(32))
+        .min(max_delay);
+    delay / 2 + rand::random::<u64>() % (delay / 2 + 1)
 }
 
 /// Manages peers and routes messages to correct controller
@@ -41,12 +157,22 @@ pub struct RoutingEventProcessor {
     pub blockchain: Arc<RwLock<Blockchain>>,
     pub sender_to_mempool: Sender<ConsensusEvent>,
     pub sender_to_miner: Sender<MiningEvent>,
-    // TODO : remove this if not needed
     pub static_peers: Vec<StaticPeer>,
     pub configs: Arc<RwLock<Configuration>>,
     pub time_keeper: Box<dyn KeepTime + Send + Sync>,
     pub wallet: Arc<RwLock<Wallet>>,
     pub network: Network,
+    pub storage: Storage,
+    pub peer_discovery_timer: u128,
+    pub ping_timer: u128,
+    pub reconnect_timer: u128,
+    // ongoing headers-first synchronisation. only one peer is synced at a time
+    pub header_sync: Option<HeaderSync>,
+    pub header_sync_timer: u128,
+    pub block_download_timer: u128,
+    // chain of headers and wallet transactions kept when running as a lite node
+    pub lite_chain: LiteChain,
+    pub application_modules: ApplicationModules,
 }
 
 impl RoutingEventProcessor {
@@ -70,10 +196,20 @@ impl RoutingEventProcessor {
             message.get_type_value(),
             peer_index
         );
+        self.network
+            .update_last_seen(peer_index, self.time_keeper.get_timestamp())
+            .await;
         match message {
             Message::HandshakeChallenge(challenge) => {
                 debug!("received handshake challenge");
-                self.network
+                if self
+                    .disconnect_if_banned(peer_index, &challenge.public_key)
+                    .await
+                {
+                    return;
+                }
+                let result = self
+                    .network
                     .handle_handshake_challenge(
                         peer_index,
                         challenge,
@@ -81,50 +217,313 @@ impl RoutingEventProcessor {
                         self.configs.clone(),
                     )
                     .await;
+                self.handle_handshake_result(peer_index, result).await;
             }
             Message::HandshakeResponse(response) => {
                 debug!("received handshake response");
-                self.network
+                if self
+                    .disconnect_if_banned(peer_index, &response.public_key)
+                    .await
+                {
+                    return;
+                }
+                let result = self
+                    .network
                     .handle_handshake_response(
                         peer_index,
                         response,
                         self.wallet.clone(),
-                        self.blockchain.clone(),
+                        self.configs.clone(),
                     )
                     .await;
+                let result = self.close_replaced_connection(result).await;
+                if result.is_ok() {
+                    self.reset_reconnect_attempts(peer_index);
+                    self.request_blockchain_from_peer(peer_index).await;
+                }
+                self.handle_handshake_result(peer_index, result).await;
             }
             Message::HandshakeCompletion(response) => {
                 debug!("received handshake completion");
-                self.network
-                    .handle_handshake_completion(peer_index, response, self.blockchain.clone())
+                let result = self
+                    .network
+                    .handle_handshake_completion(peer_index, response, self.wallet.clone())
                     .await;
+                let result = self.close_replaced_connection(result).await;
+                if result.is_ok() {
+                    self.reset_reconnect_attempts(peer_index);
+                    self.request_blockchain_from_peer(peer_index).await;
+                }
+                self.handle_handshake_result(peer_index, result).await;
             }
-            Message::ApplicationMessage(_) => {
-                debug!("received buffer");
+            Message::ApplicationMessage(message) => {
+                self.process_incoming_application_message(message, peer_index)
+                    .await;
             }
-            Message::Block(_) => {
-                debug!("received block");
+            Message::Block(block) => {
+                debug!("received block : {:?}", hex::encode(block.get_hash()));
+                if self.is_lite_node().await {
+                    debug!("lite node doesn't keep full blocks. ignoring the block");
+                    return;
+                }
+                self.sender_to_mempool
+                    .send(ConsensusEvent::NewBlock { peer_index, block })
+                    .await
+                    .unwrap();
             }
-            Message::Transaction(_) => {
+            Message::Transaction(transaction) => {
                 debug!("received transaction");
+                self.sender_to_mempool
+                    .send(ConsensusEvent::NewTransaction {
+                        peer_index,
+                        transaction,
+                    })
+                    .await
+                    .unwrap();
             }
             Message::BlockchainRequest(request) => {
                 self.process_incoming_blockchain_request(request, peer_index)
                     .await;
             }
             Message::BlockHeaderHash(hash) => {
+                if self.is_lite_node().await {
+                    // lite nodes sync the new blocks using the headers
+                    if self.header_sync.is_none() && !self.lite_chain.contains(&hash) {
+                        self.request_blockchain_from_peer(peer_index).await;
+                    }
+                    return;
+                }
                 self.process_incoming_block_hash(hash, peer_index).await;
             }
+            Message::GetPeers => {
+                self.network.process_incoming_get_peers(peer_index).await;
+            }
+            Message::Peers(peer_list) => {
+                debug!(
+                    "received {:?} peer addresses from peer : {:?}",
+                    peer_list.peers.len(),
+                    peer_index
+                );
+                if peer_list.peers.len() > MAX_PEERS_IN_PEER_LIST {
+                    self.report_misbehaviour(peer_index, PeerMisbehaviour::InvalidMessage)
+                        .await;
+                    return;
+                }
+                self.add_to_address_book(peer_list.peers).await;
+            }
+            Message::Ping(timestamp) => {
+                trace!("received ping from peer : {:?}", peer_index);
+                self.network
+                    .send_message(peer_index, Message::Pong(timestamp).serialize())
+                    .await
+                    .unwrap();
+            }
+            Message::Pong(timestamp) => {
+                trace!("received pong from peer : {:?}", peer_index);
+                self.network
+                    .handle_pong(peer_index, timestamp, self.time_keeper.get_timestamp())
+                    .await;
+            }
+            Message::BlockHeadersRequest(request) => {
+                self.process_incoming_block_headers_request(request, peer_index)
+                    .await;
+            }
+            Message::BlockHeaders(headers) => {
+                self.process_incoming_block_headers(headers, peer_index)
+                    .await;
+            }
+            Message::GetBlockTransactions(request) => {
+                self.process_incoming_block_transactions_request(request, peer_index)
+                    .await;
+            }
+            Message::BlockTransactions(transactions) => {
+                self.process_incoming_block_transactions(transactions, peer_index)
+                    .await;
+            }
+            Message::CompactBlock(compact_block) => {
+                self.process_incoming_compact_block(compact_block, peer_index)
+                    .await;
+            }
+            Message::GetCompactBlockTransactions(request) => {
+                self.process_incoming_compact_block_transactions_request(request, peer_index)
+                    .await;
+            }
+            Message::CompactBlockTransactions(response) => {
+                self.sender_to_mempool
+                    .send(ConsensusEvent::CompactBlockTransactionsReceived {
+                        peer_index,
+                        response,
+                    })
+                    .await
+                    .unwrap();
+            }
+            Message::BlockFetchRequest(request) => {
+                self.process_incoming_block_fetch_request(request, peer_index)
+                    .await;
+            }
+            Message::BlockFetchResponse(response) => {
+                self.process_incoming_block_fetch_response(response, peer_index)
+                    .await;
+            }
         }
         debug!("incoming message processed");
     }
 
-    async fn connect_to_static_peers(&mut self) {
-        debug!("connect to peers from config",);
+    /// Disconnects the peer if the given public key is currently banned. returns true if disconnected
+    async fn disconnect_if_banned(&mut self, peer_index: u64, public_key: &SaitoPublicKey) -> bool {
+        let current_time = self.time_keeper.get_timestamp();
+        if !self.network.is_key_banned(public_key, current_time).await {
+            return false;
+        }
+        warn!(
+            "peer : {:?} with key : {:?} is banned. disconnecting",
+            peer_index,
+            hex::encode(public_key)
+        );
+        self.network.disconnect_from_peer(peer_index).await;
+        true
+    }
+    /// Closes the older connection to a peer when the new connection with it is kept instead
+    async fn close_replaced_connection(
+        &mut self,
+        result: Result<Option<u64>, Error>,
+    ) -> Result<(), Error> {
+        let replaced_peer = result?;
+        if let Some(replaced_peer) = replaced_peer {
+            debug!(
+                "closing connection : {:?} replaced by a new connection to the same peer",
+                replaced_peer
+            );
+            self.stop_header_sync(replaced_peer);
+            self.network.disconnect_from_peer(replaced_peer).await;
+        }
+        Ok(())
+    }
+    async fn handle_handshake_result(&mut self, peer_index: u64, result: Result<(), Error>) {
+        if result.is_ok() {
+            return;
+        }
+        match result.err().unwrap().kind() {
+            ErrorKind::InvalidData => {
+                self.report_misbehaviour(peer_index, PeerMisbehaviour::InvalidHandshake)
+                    .await;
+            }
+            _ => {
+                // peer is either unknown or not compatible with us. so we just close the connection
+                self.network.disconnect_from_peer(peer_index).await;
+            }
+        }
+    }
+    async fn report_misbehaviour(&mut self, peer_index: u64, misbehaviour: PeerMisbehaviour) {
         self.network
-            .connect_to_static_peers(self.configs.clone())
+            .report_misbehaviour(
+                peer_index,
+                misbehaviour,
+                self.configs.clone(),
+                self.time_keeper.get_timestamp(),
+            )
             .await;
     }
+
+    async fn add_to_address_book(&mut self, peers: Vec<data::configuration::PeerConfig>) {
+        if self
+            .network
+            .add_to_address_book(peers, self.configs.clone())
+            .await
+        {
+            let address_book = self.network.get_address_book().await;
+            self.storage.save_address_book(&address_book).await;
+        }
+    }
+
+    async fn connect_to_static_peers(&mut self) {
+        debug!("connect to peers from config",);
+        let current_time = self.time_keeper.get_timestamp();
+        for static_peer in self.static_peers.iter_mut() {
+            static_peer.start_connecting(current_time);
+            self.network
+                .connect_to_peer(static_peer.peer_details.clone())
+                .await;
+        }
+        debug!("connected to peers");
+    }
+    fn reset_reconnect_attempts(&mut self, peer_index: u64) {
+        for static_peer in self.static_peers.iter_mut() {
+            if static_peer.peer_state == PeerState::Connected
+                && static_peer.peer_index == peer_index
+            {
+                static_peer.reconnect_attempts = 0;
+            }
+        }
+    }
+    async fn handle_static_peer_connection_result(
+        &mut self,
+        peer_details: &data::configuration::PeerConfig,
+        result: &Result<u64, Error>,
+    ) {
+        let max_delay;
+        {
+            trace!("waiting for the configs read lock");
+            let configs = self.configs.read().await;
+            trace!("acquired the configs read lock");
+            max_delay = configs.peer_reconnect_max_delay;
+        }
+        let current_time = self.time_keeper.get_timestamp();
+        for static_peer in self.static_peers.iter_mut() {
+            if static_peer.peer_details != *peer_details {
+                continue;
+            }
+            if result.is_ok() {
+                static_peer.peer_state = PeerState::Connected;
+                static_peer.peer_index = *result.as_ref().unwrap();
+            } else {
+                warn!(
+                    "failed connecting to static peer : {:?}",
+                    static_peer.get_address()
+                );
+                static_peer.schedule_reconnect(current_time, max_delay);
+            }
+        }
+    }
+    /// Schedules reconnections for the static peers which got disconnected and connects to the ones which are due
+    async fn reconnect_static_peers(&mut self) {
+        let max_delay;
+        {
+            trace!("waiting for the configs read lock");
+            let configs = self.configs.read().await;
+            trace!("acquired the configs read lock");
+            max_delay = configs.peer_reconnect_max_delay;
+        }
+        let current_time = self.time_keeper.get_timestamp();
+        for static_peer in self.static_peers.iter_mut() {
+            let address = static_peer.get_address();
+            match static_peer.peer_state {
+                PeerState::Connected => {
+                    // peers can be disconnected from either side or by the network (timeouts, bans,...)
+                    if !self.network.is_connected_to(&address).await {
+                        static_peer.schedule_reconnect(current_time, max_delay);
+                    }
+                }
+                PeerState::Connecting => {
+                    // the connection result might never arrive if the connection attempt hangs
+                    static_peer.check_connect_timeout(current_time, max_delay);
+                }
+                PeerState::Disconnected => {
+                    if current_time < static_peer.next_reconnect_time {
+                        continue;
+                    }
+                    if self.network.is_address_banned(&address, current_time).await {
+                        continue;
+                    }
+                    debug!("reconnecting to static peer : {:?}", address);
+                    static_peer.start_connecting(current_time);
+                    self.network
+                        .connect_to_peer(static_peer.peer_details.clone())
+                        .await;
+                }
+            }
+        }
+    }
     async fn handle_new_peer(
         &mut self,
         peer_data: Option<data::configuration::PeerConfig>,
@@ -133,16 +532,32 @@ impl RoutingEventProcessor {
         trace!("handling new peer : {:?}", peer_index);
         self.network
             .handle_new_peer(
-                peer_data,
+                peer_data.clone(),
                 peer_index,
                 self.wallet.clone(),
                 self.configs.clone(),
+                self.time_keeper.get_timestamp(),
             )
             .await;
+        if let Some(peer_data) = peer_data {
+            // remembering the peers we could connect to, so they can be used after a restart
+            self.add_to_address_book(vec![peer_data]).await;
+        }
+    }
+
+    async fn handle_rate_limit_exceeded(&mut self, peer_index: u64) {
+        warn!(
+            "peer : {:?} exceeded the rate limits. disconnecting",
+            peer_index
+        );
+        self.report_misbehaviour(peer_index, PeerMisbehaviour::RateLimitExceeded)
+            .await;
+        self.network.disconnect_from_peer(peer_index).await;
     }
 
     async fn handle_peer_disconnect(&mut self, peer_index: u64) {
         trace!("handling peer disconnect, peer_index = {}", peer_index);
+        self.stop_header_sync(peer_index);
         self.network.handle_peer_disconnect(peer_index).await;
     }
 
@@ -175,14 +590,549 @@ impl RoutingEventProcessor {
                 continue;
             }
             let buffer = Message::BlockHeaderHash(block_hash).serialize();
-            self.network
-                .io_interface
-                .send_message(peer_index, buffer)
+            self.network.send_message(peer_index, buffer).await.unwrap();
+        }
+    }
+    pub async fn process_incoming_block_headers_request(
+        &self,
+        request: BlockchainRequest,
+        peer_index: u64,
+    ) {
+        debug!(
+            "processing incoming block headers request : {:?}-{:?}-{:?} from peer : {:?}",
+            request.latest_block_id,
+            hex::encode(request.latest_block_hash),
+            hex::encode(request.fork_id),
+            peer_index
+        );
+        let mut headers = vec![];
+        {
+            trace!("waiting for the blockchain read lock");
+            let blockchain = self.blockchain.read().await;
+            trace!("acquired the blockchain read lock");
+
+            let mut last_shared_ancestor =
+                blockchain.generate_last_shared_ancestor(request.latest_block_id, request.fork_id);
+            // if the requester's latest block is in our longest chain, it's a closer ancestor than the fork id one
+            if request.latest_block_id > last_shared_ancestor
+                && blockchain
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(request.latest_block_id)
+                    == request.latest_block_hash
+            {
+                last_shared_ancestor = request.latest_block_id;
+            }
+            debug!("last shared ancestor = {:?}", last_shared_ancestor);
+
+            for i in last_shared_ancestor..(blockchain.blockring.get_latest_block_id() + 1) {
+                if headers.len() >= MAX_HEADERS_PER_MESSAGE {
+                    break;
+                }
+                let block_hash = blockchain
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(i);
+                let block = blockchain.blocks.get(&block_hash);
+                if block.is_none() {
+                    continue;
+                }
+                headers.push(block.unwrap().get_header());
+            }
+        }
+        debug!(
+            "sending {:?} block headers to peer : {:?}",
+            headers.len(),
+            peer_index
+        );
+        let buffer = Message::BlockHeaders(BlockHeaders { headers }).serialize();
+        self.network.send_message(peer_index, buffer).await.unwrap();
+    }
+    /// Validates the received header chain and fetches the block bodies in parallel if it's longer than ours.
+    /// Lite nodes fetch the transactions related to the wallet keys instead of the bodies
+    async fn process_incoming_block_headers(&mut self, headers: BlockHeaders, peer_index: u64) {
+        let headers = headers.headers;
+        debug!(
+            "received {:?} block headers from peer : {:?}",
+            headers.len(),
+            peer_index
+        );
+        if let Some(header_sync) = &self.header_sync {
+            debug!(
+                "headers-first sync is in progress with peer : {:?}. ignoring the headers from peer : {:?}",
+                header_sync.peer_index, peer_index
+            );
+            return;
+        }
+        let has_more_headers = headers.len() == MAX_HEADERS_PER_MESSAGE;
+        let is_lite = self.is_lite_node().await;
+        let result;
+        if is_lite {
+            result = get_new_header_chain(
+                headers,
+                self.lite_chain.get_latest_block_id(),
+                |hash| self.lite_chain.get_block(hash),
+                |block_id| self.lite_chain.get_block_hash_by_id(block_id),
+            );
+        } else {
+            trace!("waiting for the blockchain read lock");
+            let blockchain = self.blockchain.read().await;
+            trace!("acquired the blockchain read lock");
+            result = get_new_header_chain(
+                headers,
+                blockchain.get_latest_block_id(),
+                |hash| blockchain.blocks.get(hash),
+                |block_id| {
+                    blockchain
+                        .blockring
+                        .get_longest_chain_block_hash_by_block_id(block_id)
+                },
+            );
+        }
+        if result.is_err() {
+            let error = result.err().unwrap();
+            if error.kind() == ErrorKind::InvalidData {
+                warn!("invalid header chain received from peer : {:?}", peer_index);
+                self.report_misbehaviour(peer_index, PeerMisbehaviour::InvalidBlock)
+                    .await;
+            } else {
+                warn!(
+                    "header chain from peer : {:?} doesn't connect to our chain",
+                    peer_index
+                );
+            }
+            return;
+        }
+        let new_headers = result.unwrap();
+        if new_headers.is_empty() {
+            debug!("already in sync with peer : {:?}", peer_index);
+            return;
+        }
+
+        info!(
+            "syncing {:?} blocks from peer : {:?} using headers. lite : {:?}",
+            new_headers.len(),
+            peer_index,
+            is_lite
+        );
+        let header_sync = HeaderSync::new(
+            peer_index,
+            is_lite,
+            new_headers,
+            has_more_headers,
+            self.time_keeper.get_timestamp(),
+        );
+        let public_key;
+        {
+            trace!("waiting for the wallet read lock");
+            let wallet = self.wallet.read().await;
+            trace!("acquired the wallet read lock");
+            public_key = wallet.get_publickey();
+        }
+        for block_hash in header_sync.get_pending_hashes() {
+            let result = if is_lite {
+                self.network
+                    .request_block_transactions(peer_index, block_hash, vec![public_key])
+                    .await
+            } else {
+                self.network.fetch_block(block_hash, peer_index).await
+            };
+            if result.is_err() {
+                warn!(
+                    "failed fetching block : {:?} from peer : {:?}",
+                    hex::encode(block_hash),
+                    peer_index
+                );
+                return;
+            }
+        }
+        self.header_sync = Some(header_sync);
+        if !is_lite {
+            self.process_block_downloads().await;
+        }
+    }
+    /// Sends the transactions of the block which are related to the requested keys, with their merkle proofs so the
+    /// lite node can verify them against the merkle root
+    async fn process_incoming_block_transactions_request(
+        &self,
+        request: BlockTransactionsRequest,
+        peer_index: u64,
+    ) {
+        debug!(
+            "processing block transactions request for block : {:?} from peer : {:?}",
+            hex::encode(request.block_hash),
+            peer_index
+        );
+        let response;
+        {
+            trace!("waiting for the blockchain read lock");
+            let blockchain = self.blockchain.read().await;
+            trace!("acquired the blockchain read lock");
+            let block = blockchain.blocks.get(&request.block_hash);
+            if block.is_none() || block.unwrap().get_block_type() != BlockType::Full {
+                debug!(
+                    "block : {:?} is not available to send the transactions",
+                    hex::encode(request.block_hash)
+                );
+                return;
+            }
+            let block = block.unwrap();
+            let merkle_tree = block.generate_merkle_tree();
+            let mut transactions = vec![];
+            let mut proofs = vec![];
+            for (index, transaction) in block.get_transactions().iter().enumerate() {
+                let is_related = transaction
+                    .get_inputs()
+                    .iter()
+                    .chain(transaction.get_outputs().iter())
+                    .any(|slip| request.public_keys.contains(&slip.get_publickey()));
+                if is_related {
+                    transactions.push(transaction.clone());
+                    proofs.push(merkle_tree.generate_proof(index).unwrap());
+                }
+            }
+            response = BlockTransactions {
+                block_hash: request.block_hash,
+                transactions,
+                proofs,
+            };
+        }
+        self.network
+            .send_message(peer_index, Message::BlockTransactions(response).serialize())
+            .await
+            .unwrap();
+    }
+    /// Verifies the received transactions against the merkle root of the header and adds them to the lite chain
+    /// once all the previous blocks are received
+    async fn process_incoming_block_transactions(
+        &mut self,
+        block_transactions: BlockTransactions,
+        peer_index: u64,
+    ) {
+        let block_hash = block_transactions.block_hash;
+        if self.header_sync.is_none() {
+            debug!(
+                "unexpected block transactions : {:?} from peer : {:?}",
+                hex::encode(block_hash),
+                peer_index
+            );
+            return;
+        }
+        let header_sync = self.header_sync.as_mut().unwrap();
+        if !header_sync.is_lite || header_sync.peer_index != peer_index {
+            debug!(
+                "unexpected block transactions : {:?} from peer : {:?}",
+                hex::encode(block_hash),
+                peer_index
+            );
+            return;
+        }
+        let header = header_sync.get_pending_header(&block_hash);
+        if header.is_none() {
+            debug!(
+                "block transactions : {:?} from peer : {:?} are not pending",
+                hex::encode(block_hash),
+                peer_index
+            );
+            return;
+        }
+        let mut block = header.unwrap().clone();
+        let mut transactions = block_transactions.transactions;
+        let mut is_valid = transactions.len() == block_transactions.proofs.len();
+        for (transaction, proof) in transactions
+            .iter_mut()
+            .zip(block_transactions.proofs.iter())
+        {
+            transaction.generate_metadata(block.get_creator());
+            let hash = transaction.get_hash_for_signature().unwrap();
+            if !proof.verify(&hash, &block.get_merkle_root()) {
+                is_valid = false;
+            }
+        }
+        if !is_valid {
+            warn!(
+                "transactions of block : {:?} from peer : {:?} don't match the merkle root",
+                hex::encode(block_hash),
+                peer_index
+            );
+            self.header_sync = None;
+            self.report_misbehaviour(peer_index, PeerMisbehaviour::InvalidBlock)
+                .await;
+            return;
+        }
+        block.set_transactions(&mut transactions);
+        header_sync.add_fetched_block(block, self.time_keeper.get_timestamp());
+        let blocks = header_sync.get_ready_blocks();
+
+        if blocks.is_empty() {
+            return;
+        }
+        {
+            trace!("waiting for the wallet write lock");
+            let mut wallet = self.wallet.write().await;
+            trace!("acquired the wallet write lock");
+            for block in blocks {
+                self.lite_chain.add_block(block, &mut wallet);
+            }
+        }
+        self.storage.write_lite_chain(&self.lite_chain).await;
+    }
+    /// Keeps the blocks fetched for the headers-first sync until they can be added in the chain order.
+    /// returns false if the block is not part of the sync
+    async fn handle_header_sync_block(
+        &mut self,
+        block_hash: SaitoHash,
+        peer_index: u64,
+        buffer: &Vec<u8>,
+    ) -> bool {
+        if self.header_sync.is_none() {
+            return false;
+        }
+        let header_sync = self.header_sync.as_mut().unwrap();
+        if header_sync.peer_index != peer_index || !header_sync.is_pending(&block_hash) {
+            return false;
+        }
+        let block = Block::deserialize_for_net(buffer);
+        if block.is_err() {
+            warn!(
+                "failed decoding block : {:?} from peer : {:?} : {:?}",
+                hex::encode(block_hash),
+                peer_index,
+                block.err().unwrap()
+            );
+            self.header_sync = None;
+            self.report_misbehaviour(peer_index, PeerMisbehaviour::InvalidBlock)
+                .await;
+            return true;
+        }
+        let block = block.unwrap();
+        if block.get_hash() != block_hash {
+            warn!(
+                "block : {:?} from peer : {:?} doesn't match the header",
+                hex::encode(block_hash),
+                peer_index
+            );
+            self.header_sync = None;
+            self.report_misbehaviour(peer_index, PeerMisbehaviour::InvalidBlock)
+                .await;
+            return true;
+        }
+        header_sync.add_fetched_block(block, self.time_keeper.get_timestamp());
+        for block in header_sync.get_ready_blocks() {
+            self.sender_to_mempool
+                .send(ConsensusEvent::NewBlock { peer_index, block })
                 .await
                 .unwrap();
         }
+        true
+    }
+    /// Finishes the headers-first sync once all the blocks are added, or abandons it if it's not progressing
+    async fn check_header_sync(&mut self) {
+        if self.header_sync.is_none() {
+            return;
+        }
+        let header_sync = self.header_sync.as_ref().unwrap();
+        let peer_index = header_sync.peer_index;
+        if header_sync.is_complete() {
+            let is_added;
+            if header_sync.is_lite {
+                is_added = self.lite_chain.contains(&header_sync.last_block_hash);
+            } else {
+                trace!("waiting for the blockchain read lock");
+                let blockchain = self.blockchain.read().await;
+                trace!("acquired the blockchain read lock");
+                is_added = blockchain.is_block_indexed(header_sync.last_block_hash);
+            }
+            if is_added {
+                debug!("headers-first sync with peer : {:?} completed", peer_index);
+                let has_more_headers = header_sync.has_more_headers;
+                self.header_sync = None;
+                if has_more_headers {
+                    self.request_blockchain_from_peer(peer_index).await;
+                }
+                return;
+            }
+        }
+        let current_time = self.time_keeper.get_timestamp();
+        if current_time.saturating_sub(header_sync.last_progress_time) > HEADER_SYNC_TIMEOUT {
+            warn!("headers-first sync with peer : {:?} timed out", peer_index);
+            self.header_sync = None;
+        }
+    }
+    fn stop_header_sync(&mut self, peer_index: u64) {
+        if self.header_sync.is_some() && self.header_sync.as_ref().unwrap().peer_index == peer_index
+        {
+            debug!("stopping headers-first sync with peer : {:?}", peer_index);
+            self.header_sync = None;
+        }
+    }
+    /// Loads the lite chain stored by an earlier run and adds its transactions to the wallet
+    async fn load_lite_chain(&mut self) {
+        let loaded_chain = self.storage.load_lite_chain().await;
+        if loaded_chain.is_none() {
+            return;
+        }
+        trace!("waiting for the wallet write lock");
+        let mut wallet = self.wallet.write().await;
+        trace!("acquired the wallet write lock");
+        let mut lite_chain = LiteChain::new();
+        if !lite_chain.restore(loaded_chain.unwrap(), &mut wallet) {
+            warn!("discarding the stored lite chain since its blocks don't form a chain");
+            lite_chain = LiteChain::new();
+        }
+        info!(
+            "loaded the lite chain up to block : {:?}",
+            lite_chain.get_latest_block_id()
+        );
+        self.lite_chain = lite_chain;
+    }
+    async fn is_lite_node(&self) -> bool {
+        trace!("waiting for the configs read lock");
+        let configs = self.configs.read().await;
+        trace!("acquired the configs read lock");
+        configs.is_lite_node()
+    }
+    async fn request_blockchain_from_peer(&self, peer_index: u64) {
+        let headers_first_sync;
+        let is_lite_node;
+        {
+            trace!("waiting for the configs read lock");
+            let configs = self.configs.read().await;
+            trace!("acquired the configs read lock");
+            headers_first_sync = configs.headers_first_sync;
+            is_lite_node = configs.is_lite_node();
+        }
+        if is_lite_node {
+            self.network
+                .request_lite_sync_from_peer(
+                    peer_index,
+                    self.lite_chain.get_latest_block_id(),
+                    self.lite_chain.get_latest_block_hash(),
+                )
+                .await;
+            return;
+        }
+        self.network
+            .request_blockchain_from_peer(peer_index, self.blockchain.clone(), headers_first_sync)
+            .await;
     }
-    async fn process_incoming_block_hash(&self, block_hash: SaitoHash, peer_index: u64) {
+    /// Forwards the compact block to be reconstructed from the mempool, if we don't have the block already
+    async fn process_incoming_compact_block(
+        &mut self,
+        compact_block: CompactBlock,
+        peer_index: u64,
+    ) {
+        let block_hash = compact_block.header.get_hash();
+        debug!(
+            "received compact block : {:?} from peer : {:?}",
+            hex::encode(block_hash),
+            peer_index
+        );
+        if self.is_lite_node().await {
+            // lite nodes sync the new blocks using the headers
+            if self.header_sync.is_none() && !self.lite_chain.contains(&block_hash) {
+                self.request_blockchain_from_peer(peer_index).await;
+            }
+            return;
+        }
+        {
+            trace!("waiting for the blockchain read lock");
+            let blockchain = self.blockchain.read().await;
+            trace!("acquired the blockchain read lock");
+            if blockchain.is_block_indexed(block_hash) {
+                trace!(
+                    "compact block : {:?} already exists",
+                    hex::encode(block_hash)
+                );
+                return;
+            }
+        }
+        self.sender_to_mempool
+            .send(ConsensusEvent::NewCompactBlock {
+                peer_index,
+                compact_block,
+            })
+            .await
+            .unwrap();
+    }
+    /// Sends the requested transactions of a block we have relayed as a compact block
+    async fn process_incoming_compact_block_transactions_request(
+        &mut self,
+        request: CompactBlockTransactionsRequest,
+        peer_index: u64,
+    ) {
+        debug!(
+            "processing request for {:?} transactions of compact block : {:?} from peer : {:?}",
+            request.indices.len(),
+            hex::encode(request.block_hash),
+            peer_index
+        );
+        let mut transactions = vec![];
+        {
+            trace!("waiting for the blockchain read lock");
+            let blockchain = self.blockchain.read().await;
+            trace!("acquired the blockchain read lock");
+            let block = blockchain.blocks.get(&request.block_hash);
+            if block.is_none() || block.unwrap().get_block_type() != BlockType::Full {
+                debug!(
+                    "block : {:?} is not available to send the transactions",
+                    hex::encode(request.block_hash)
+                );
+                return;
+            }
+            let block_transactions = block.unwrap().get_transactions();
+            for index in request.indices.iter() {
+                let transaction = block_transactions.get(*index as usize);
+                if transaction.is_none() {
+                    warn!(
+                        "peer : {:?} requested transaction : {:?} out of range for block : {:?}",
+                        peer_index,
+                        index,
+                        hex::encode(request.block_hash)
+                    );
+                    drop(blockchain);
+                    self.report_misbehaviour(peer_index, PeerMisbehaviour::InvalidMessage)
+                        .await;
+                    return;
+                }
+                transactions.push(transaction.unwrap().clone());
+            }
+        }
+        let response = CompactBlockTransactions {
+            block_hash: request.block_hash,
+            transactions,
+        };
+        self.network
+            .send_message(
+                peer_index,
+                Message::CompactBlockTransactions(response).serialize(),
+            )
+            .await
+            .unwrap();
+    }
+    /// Passes the application message to the registered module, along with the public key of the sender
+    async fn process_incoming_application_message(
+        &mut self,
+        message: ApplicationMessage,
+        peer_index: u64,
+    ) {
+        debug!(
+            "received application message for : {:?} from peer : {:?}",
+            message.application_id, peer_index
+        );
+        let public_key = self.network.get_handshaken_peer_key(peer_index).await;
+        if public_key.is_none() {
+            warn!(
+                "received application message from peer : {:?} before the handshake",
+                peer_index
+            );
+            self.report_misbehaviour(peer_index, PeerMisbehaviour::InvalidMessage)
+                .await;
+            return;
+        }
+        self.application_modules
+            .handle_message(public_key.unwrap(), message)
+            .await;
+    }
+    async fn process_incoming_block_hash(&mut self, block_hash: SaitoHash, peer_index: u64) {
         debug!(
             "processing incoming block hash : {:?} from peer : {:?}",
             hex::encode(block_hash),
@@ -191,6 +1141,153 @@ impl RoutingEventProcessor {
         self.network
             .process_incoming_block_hash(block_hash, peer_index, self.blockchain.clone())
             .await;
+        self.process_block_downloads().await;
+    }
+    /// Passes the fetched block to the headers-first sync or to consensus, and sends the waiting fetches
+    async fn handle_fetched_block(
+        &mut self,
+        block_hash: SaitoHash,
+        peer_index: u64,
+        buffer: Vec<u8>,
+    ) {
+        debug!("block received : {:?}", hex::encode(block_hash));
+        self.network
+            .handle_block_fetched(block_hash, peer_index, self.time_keeper.get_timestamp())
+            .await;
+        // a slot is freed for the waiting fetches
+        self.process_block_downloads().await;
+        if self
+            .handle_header_sync_block(block_hash, peer_index, &buffer)
+            .await
+        {
+            return;
+        }
+        self.sender_to_mempool
+            .send(ConsensusEvent::BlockFetched { peer_index, buffer })
+            .await
+            .unwrap();
+    }
+    async fn handle_failed_block_fetch(&mut self, block_hash: SaitoHash, peer_index: u64) {
+        warn!(
+            "failed fetching block : {:?} from peer : {:?}",
+            hex::encode(block_hash),
+            peer_index
+        );
+        if !self
+            .network
+            .handle_block_fetch_failure(block_hash, peer_index)
+            .await
+        {
+            self.stop_header_sync(peer_index);
+        }
+        self.report_misbehaviour(peer_index, PeerMisbehaviour::BlockFetchFailed)
+            .await;
+        self.process_block_downloads().await;
+    }
+    /// Sends the requested full block over the connection. blocks which are pruned in memory are loaded from the disk
+    async fn process_incoming_block_fetch_request(
+        &mut self,
+        request: BlockFetchRequest,
+        peer_index: u64,
+    ) {
+        debug!(
+            "processing block fetch request for : {:?} from peer : {:?}",
+            hex::encode(request.block_hash),
+            peer_index
+        );
+        let mut buffer = vec![];
+        let mut is_pruned = false;
+        {
+            trace!("waiting for the blockchain read lock");
+            let blockchain = self.blockchain.read().await;
+            trace!("acquired the blockchain read lock");
+            if let Some(block) = blockchain.get_block(&request.block_hash).await {
+                if block.get_block_type() == BlockType::Full {
+                    buffer = block.serialize_for_net(BlockType::Full);
+                } else {
+                    is_pruned = true;
+                }
+            }
+        }
+        if is_pruned {
+            match self.storage.load_block_from_disk(&request.block_hash).await {
+                Ok(block) => buffer = block.serialize_for_net(BlockType::Full),
+                Err(error) => warn!(
+                    "failed loading block : {:?} from disk : {:?}",
+                    hex::encode(request.block_hash),
+                    error
+                ),
+            }
+        }
+        if buffer.is_empty() {
+            debug!(
+                "block : {:?} requested by peer : {:?} not found",
+                hex::encode(request.block_hash),
+                peer_index
+            );
+        }
+        let response = BlockFetchResponse {
+            block_hash: request.block_hash,
+            block: buffer,
+        };
+        let result = self
+            .network
+            .send_message(
+                peer_index,
+                Message::BlockFetchResponse(response).serialize(),
+            )
+            .await;
+        if result.is_err() {
+            warn!(
+                "failed sending block : {:?} to peer : {:?} : {:?}",
+                hex::encode(request.block_hash),
+                peer_index,
+                result.err().unwrap()
+            );
+        }
+    }
+    /// Handles the block sent over the connection the same way as a block fetched over http
+    async fn process_incoming_block_fetch_response(
+        &mut self,
+        response: BlockFetchResponse,
+        peer_index: u64,
+    ) {
+        if !self
+            .network
+            .is_block_requested_from(&response.block_hash, peer_index)
+            .await
+        {
+            // response can arrive after the request is timed out and sent to another peer
+            debug!(
+                "dropping unrequested block : {:?} from peer : {:?}",
+                hex::encode(response.block_hash),
+                peer_index
+            );
+            return;
+        }
+        if !response.is_found() {
+            self.handle_failed_block_fetch(response.block_hash, peer_index)
+                .await;
+            return;
+        }
+        self.handle_fetched_block(response.block_hash, peer_index, response.block)
+            .await;
+    }
+    /// Sends the scheduled block fetches. headers-first sync is stopped if any of its blocks can't be fetched
+    async fn process_block_downloads(&mut self) {
+        let abandoned = self
+            .network
+            .process_block_downloads(self.time_keeper.get_timestamp())
+            .await;
+        if let Some(header_sync) = &self.header_sync {
+            if abandoned
+                .iter()
+                .any(|block_hash| header_sync.is_pending(block_hash))
+            {
+                let peer_index = header_sync.peer_index;
+                self.stop_header_sync(peer_index);
+            }
+        }
     }
 }
 
@@ -208,9 +1305,65 @@ impl ProcessEvent<RoutingEvent> for RoutingEventProcessor {
             }
             NetworkEvent::IncomingNetworkMessage { peer_index, buffer } => {
                 debug!("incoming message received from peer : {:?}", peer_index);
+                let buffer = self.network.decrypt_message(peer_index, buffer).await;
+                if buffer.is_err() {
+                    // session can't continue once a message is lost or modified, and only the handshake is accepted
+                    // before the session is set up
+                    warn!(
+                        "rejecting message from peer : {:?} : {:?}. disconnecting",
+                        peer_index,
+                        buffer.err().unwrap()
+                    );
+                    self.report_misbehaviour(peer_index, PeerMisbehaviour::InvalidMessage)
+                        .await;
+                    self.network.disconnect_from_peer(peer_index).await;
+                    return None;
+                }
+                let buffer = buffer.unwrap();
+                let result;
+                {
+                    trace!("waiting for the configs read lock");
+                    let configs = self.configs.read().await;
+                    trace!("acquired the configs read lock");
+                    result = self
+                        .network
+                        .check_rate_limits(
+                            peer_index,
+                            buffer.first().cloned().unwrap_or(0),
+                            &configs.peer_rate_limits,
+                            self.time_keeper.get_timestamp(),
+                        )
+                        .await;
+                }
+                match result {
+                    RateLimitResult::Allowed => {}
+                    RateLimitResult::Throttled => {
+                        trace!(
+                            "dropping message from peer : {:?} over the rate limit",
+                            peer_index
+                        );
+                        return None;
+                    }
+                    RateLimitResult::Exceeded => {
+                        self.handle_rate_limit_exceeded(peer_index).await;
+                        return None;
+                    }
+                }
                 let message = Message::deserialize(buffer);
                 if message.is_err() {
-                    todo!()
+                    let error = message.err().unwrap();
+                    if error.kind() == ErrorKind::Unsupported {
+                        // probably a message from a newer protocol version. so we just skip it
+                        debug!("ignoring unsupported message from peer : {:?}", peer_index);
+                        return None;
+                    }
+                    warn!(
+                        "failed decoding message from peer : {:?} : {:?}",
+                        peer_index, error
+                    );
+                    self.report_misbehaviour(peer_index, PeerMisbehaviour::InvalidMessage)
+                        .await;
+                    return None;
                 }
                 self.process_incoming_message(peer_index, message.unwrap())
                     .await;
@@ -219,13 +1372,32 @@ impl ProcessEvent<RoutingEvent> for RoutingEventProcessor {
                 peer_details,
                 result,
             } => {
+                if let Some(peer_details) = peer_details.as_ref() {
+                    self.handle_static_peer_connection_result(peer_details, &result)
+                        .await;
+                }
                 if result.is_ok() {
                     self.handle_new_peer(peer_details, result.unwrap()).await;
+                } else if peer_details.is_some() {
+                    let removed = self
+                        .network
+                        .handle_failed_connection(
+                            peer_details.as_ref().unwrap(),
+                            self.time_keeper.get_timestamp(),
+                        )
+                        .await;
+                    if removed {
+                        let address_book = self.network.get_address_book().await;
+                        self.storage.save_address_book(&address_book).await;
+                    }
                 }
             }
             NetworkEvent::PeerDisconnected { peer_index } => {
                 self.handle_peer_disconnect(peer_index).await;
             }
+            NetworkEvent::PeerRateLimitExceeded { peer_index } => {
+                self.handle_rate_limit_exceeded(peer_index).await;
+            }
 
             NetworkEvent::OutgoingNetworkMessageForAll { .. } => {
                 unreachable!()
@@ -233,6 +1405,9 @@ impl ProcessEvent<RoutingEvent> for RoutingEventProcessor {
             NetworkEvent::ConnectToPeer { .. } => {
                 unreachable!()
             }
+            NetworkEvent::DisconnectFromPeer { .. } => {
+                unreachable!()
+            }
             NetworkEvent::BlockFetchRequest { .. } => {
                 unreachable!()
             }
@@ -241,31 +1416,111 @@ impl ProcessEvent<RoutingEvent> for RoutingEventProcessor {
                 peer_index,
                 buffer,
             } => {
-                debug!("block received : {:?}", hex::encode(block_hash));
-                self.sender_to_mempool
-                    .send(ConsensusEvent::BlockFetched { peer_index, buffer })
-                    .await
-                    .unwrap();
+                self.handle_fetched_block(block_hash, peer_index, buffer)
+                    .await;
+            }
+            NetworkEvent::BlockFetchFailed {
+                block_hash,
+                peer_index,
+            } => {
+                self.handle_failed_block_fetch(block_hash, peer_index).await;
             }
         }
         None
     }
-    async fn process_timer_event(&mut self, _duration: Duration) -> Option<()> {
+    async fn process_timer_event(&mut self, duration: Duration) -> Option<()> {
         // trace!("processing timer event : {:?}", duration.as_micros());
+        let mut work_done = false;
+        let duration_value = duration.as_micros();
+
+        self.reconnect_timer += duration_value;
+        if self.reconnect_timer >= RECONNECT_CHECK_INTERVAL {
+            self.reconnect_timer = 0;
+            self.reconnect_static_peers().await;
+            work_done = true;
+        }
+
+        self.ping_timer += duration_value;
+        if self.ping_timer >= PING_INTERVAL {
+            self.ping_timer = 0;
+            self.network
+                .check_peer_liveness(self.time_keeper.get_timestamp())
+                .await;
+            work_done = true;
+        }
 
+        self.block_download_timer += duration_value;
+        if self.block_download_timer >= BLOCK_DOWNLOAD_CHECK_INTERVAL {
+            self.block_download_timer = 0;
+            self.process_block_downloads().await;
+            work_done = true;
+        }
+
+        self.header_sync_timer += duration_value;
+        if self.header_sync_timer >= HEADER_SYNC_CHECK_INTERVAL {
+            self.header_sync_timer = 0;
+            self.check_header_sync().await;
+            work_done = true;
+        }
+
+        self.peer_discovery_timer += duration_value;
+        if self.peer_discovery_timer >= PEER_DISCOVERY_INTERVAL {
+            self.peer_discovery_timer = 0;
+            self.network
+                .fill_outbound_slots(self.configs.clone(), self.time_keeper.get_timestamp())
+                .await;
+            work_done = true;
+        }
+
+        if work_done {
+            return Some(());
+        }
         None
     }
 
-    async fn process_event(&mut self, _event: RoutingEvent) -> Option<()> {
+    async fn process_event(&mut self, event: RoutingEvent) -> Option<()> {
         debug!("processing blockchain event");
 
-        // match event {}
+        match event {
+            RoutingEvent::PeerMisbehaviour {
+                peer_index,
+                misbehaviour,
+            } => {
+                self.report_misbehaviour(peer_index, misbehaviour).await;
+            }
+            RoutingEvent::SendApplicationMessage { recipient, message } => {
+                let result = self
+                    .network
+                    .send_application_message(recipient, message)
+                    .await;
+                if result.is_err() {
+                    warn!(
+                        "failed sending application message to : {:?} : {:?}",
+                        recipient.map(hex::encode),
+                        result.err().unwrap()
+                    );
+                }
+            }
+        }
 
         debug!("blockchain event processed successfully");
-        None
+        Some(())
     }
 
     async fn on_init(&mut self) {
+        if self.is_lite_node().await {
+            self.load_lite_chain().await;
+        }
+
+        let address_book = self.storage.load_address_book().await;
+        debug!(
+            "loaded {:?} peers from the address book",
+            address_book.len()
+        );
+        self.network
+            .add_to_address_book(address_book, self.configs.clone())
+            .await;
+
         // connect to peers
         self.connect_to_static_peers().await;
     }
@@ -273,6 +1528,51 @@ impl ProcessEvent<RoutingEvent> for RoutingEventProcessor {
 
 #[cfg(test)]
 mod tests {
+    use crate::core::data::configuration::PeerConfig;
+    use crate::core::routing_event_processor::{
+        get_reconnect_delay, PeerState, StaticPeer, RECONNECT_BASE_DELAY,
+        STATIC_PEER_CONNECT_TIMEOUT,
+    };
+
     #[tokio::test]
     async fn process_new_transaction() {}
+
+    #[test]
+    fn test_reconnect_delay() {
+        for attempts in 0..10 {
+            let expected = RECONNECT_BASE_DELAY * (1 << attempts);
+            let delay = get_reconnect_delay(attempts, u64::MAX);
+            assert!(delay >= expected / 2);
+            assert!(delay <= expected);
+        }
+        let delay = get_reconnect_delay(100, 60_000);
+        assert!(delay >= 30_000);
+        assert!(delay <= 60_000);
+    }
+
+    #[test]
+    fn test_static_peer_connect_timeout() {
+        let mut static_peer = StaticPeer::new(PeerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 12101,
+            protocol: "http".to_string(),
+            synctype: "full".to_string(),
+        });
+        static_peer.start_connecting(1_000);
+        assert_eq!(static_peer.peer_state, PeerState::Connecting);
+
+        assert!(!static_peer.check_connect_timeout(1_000 + STATIC_PEER_CONNECT_TIMEOUT - 1, 60_000));
+        assert_eq!(static_peer.peer_state, PeerState::Connecting);
+        assert_eq!(static_peer.reconnect_attempts, 0);
+
+        let current_time = 1_000 + STATIC_PEER_CONNECT_TIMEOUT;
+        assert!(static_peer.check_connect_timeout(current_time, 60_000));
+        assert_eq!(static_peer.peer_state, PeerState::Disconnected);
+        assert_eq!(static_peer.reconnect_attempts, 1);
+        assert!(static_peer.next_reconnect_time > current_time);
+
+        // a disconnected peer is not affected
+        assert!(!static_peer.check_connect_timeout(current_time * 2, 60_000));
+        assert_eq!(static_peer.reconnect_attempts, 1);
+    }
 }
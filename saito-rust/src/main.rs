@@ -11,18 +11,20 @@ use tracing_subscriber;
 use saito_core::common::command::NetworkEvent;
 use saito_core::common::process_event::ProcessEvent;
 use saito_core::core::consensus_event_processor::{ConsensusEvent, ConsensusEventProcessor};
-use saito_core::core::data::configuration::Configuration;
+use saito_core::core::data::application_module::ApplicationModules;
+use saito_core::core::data::configuration::{Configuration, STORAGE_BACKEND_KV};
 use saito_core::core::data::context::Context;
+use saito_core::core::data::lite_chain::LiteChain;
 use saito_core::core::data::network::Network;
 use saito_core::core::data::peer_collection::PeerCollection;
 use saito_core::core::data::storage::Storage;
+use saito_core::core::data::storage_backend::{FileStorageBackend, StorageBackend};
 use saito_core::core::mining_event_processor::{MiningEvent, MiningEventProcessor};
-use saito_core::core::routing_event_processor::{
-    PeerState, RoutingEvent, RoutingEventProcessor, StaticPeer,
-};
+use saito_core::core::routing_event_processor::{RoutingEvent, RoutingEventProcessor, StaticPeer};
 
 use crate::saito::config_handler::ConfigHandler;
 use crate::saito::io_event::IoEvent;
+use crate::saito::kv_storage_backend::{open_database, KvStorageBackend};
 use crate::saito::network_controller::run_network_controller;
 use crate::saito::rust_io_handler::RustIOHandler;
 use crate::saito::time_keeper::TimeKeeper;
@@ -33,6 +35,8 @@ mod test;
 const ROUTING_EVENT_PROCESSOR_ID: u8 = 1;
 const CONSENSUS_EVENT_PROCESSOR_ID: u8 = 2;
 const MINING_EVENT_PROCESSOR_ID: u8 = 3;
+// directory of the key-value database under the data directory
+const STORAGE_DATABASE_DIR: &str = "kv";
 
 async fn run_thread<T>(
     mut event_processor: Box<(dyn ProcessEvent<T> + Send + 'static)>,
@@ -117,8 +121,30 @@ async fn run_mining_event_processor(
     (interface_sender_to_miner, _miner_handle)
 }
 
+/// Creates the storage backend selected in the configs
+fn create_storage_backend(
+    io_handler: RustIOHandler,
+    database: &Option<sled::Db>,
+) -> Arc<dyn StorageBackend + Send + Sync> {
+    if let Some(database) = database {
+        let backend =
+            KvStorageBackend::new(database.clone()).expect("opening storage database failed");
+        return Arc::new(backend);
+    }
+    Arc::new(FileStorageBackend::new(Arc::new(io_handler)))
+}
+
+/// Creates a storage using the backend shared by all the storages of the node
+fn create_storage(
+    io_handler: RustIOHandler,
+    storage_backend: &Arc<dyn StorageBackend + Send + Sync>,
+) -> Storage {
+    Storage::new_with_backend(Box::new(io_handler), storage_backend.clone())
+}
+
 async fn run_consensus_event_processor(
     context: &Context,
+    storage_backend: &Arc<dyn StorageBackend + Send + Sync>,
     peers: Arc<RwLock<PeerCollection>>,
     receiver_for_blockchain: Receiver<ConsensusEvent>,
     sender_to_routing: &Sender<RoutingEvent>,
@@ -130,6 +156,13 @@ async fn run_consensus_event_processor(
     if result.is_ok() {
         generate_test_tx = result.unwrap().eq("1");
     }
+    let data_dir;
+    {
+        trace!("waiting for the configs read lock");
+        let configs = context.configuration.read().await;
+        trace!("acquired the configs read lock");
+        data_dir = configs.get_data_dir();
+    }
     let consensus_event_processor = ConsensusEventProcessor {
         mempool: context.mempool.clone(),
         blockchain: context.blockchain.clone(),
@@ -142,16 +175,23 @@ async fn run_consensus_event_processor(
             Box::new(RustIOHandler::new(
                 sender_to_network_controller.clone(),
                 CONSENSUS_EVENT_PROCESSOR_ID,
+                data_dir.clone(),
             )),
             peers.clone(),
         ),
         block_producing_timer: 0,
         tx_producing_timer: 0,
         generate_test_tx,
-        storage: Storage::new(Box::new(RustIOHandler::new(
-            sender_to_network_controller.clone(),
-            CONSENSUS_EVENT_PROCESSOR_ID,
-        ))),
+        storage: create_storage(
+            RustIOHandler::new(
+                sender_to_network_controller.clone(),
+                CONSENSUS_EVENT_PROCESSOR_ID,
+                data_dir,
+            ),
+            storage_backend,
+        ),
+        block_reconstructions: Default::default(),
+        configs: context.configuration.clone(),
     };
     let (interface_sender_to_blockchain, interface_receiver_for_mempool) =
         tokio::sync::mpsc::channel::<NetworkEvent>(1000);
@@ -166,15 +206,24 @@ async fn run_consensus_event_processor(
     (interface_sender_to_blockchain, blockchain_handle)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_routing_event_processor(
     sender_to_io_controller: Sender<IoEvent>,
     configs: Arc<RwLock<Configuration>>,
     context: &Context,
+    storage_backend: &Arc<dyn StorageBackend + Send + Sync>,
     peers: Arc<RwLock<PeerCollection>>,
     sender_to_mempool: &Sender<ConsensusEvent>,
     receiver_for_routing: Receiver<RoutingEvent>,
     sender_to_miner: &Sender<MiningEvent>,
 ) -> (Sender<NetworkEvent>, JoinHandle<()>) {
+    let data_dir;
+    {
+        trace!("waiting for the configs read lock");
+        let configs = configs.read().await;
+        trace!("acquired the configs read lock");
+        data_dir = configs.get_data_dir();
+    }
     let mut routing_event_processor = RoutingEventProcessor {
         blockchain: context.blockchain.clone(),
         sender_to_mempool: sender_to_mempool.clone(),
@@ -187,9 +236,26 @@ async fn run_routing_event_processor(
             Box::new(RustIOHandler::new(
                 sender_to_io_controller.clone(),
                 ROUTING_EVENT_PROCESSOR_ID,
+                data_dir.clone(),
             )),
             peers.clone(),
         ),
+        storage: create_storage(
+            RustIOHandler::new(
+                sender_to_io_controller.clone(),
+                ROUTING_EVENT_PROCESSOR_ID,
+                data_dir,
+            ),
+            storage_backend,
+        ),
+        peer_discovery_timer: 0,
+        ping_timer: 0,
+        reconnect_timer: 0,
+        header_sync: None,
+        header_sync_timer: 0,
+        block_download_timer: 0,
+        lite_chain: LiteChain::new(),
+        application_modules: ApplicationModules::new(),
     };
     {
         trace!("waiting for the configs write lock");
@@ -197,11 +263,9 @@ async fn run_routing_event_processor(
         trace!("acquired the configs write lock");
         let peers = &configs.peers;
         for peer in peers {
-            routing_event_processor.static_peers.push(StaticPeer {
-                peer_details: (*peer).clone(),
-                peer_state: PeerState::Disconnected,
-                peer_index: 0,
-            });
+            routing_event_processor
+                .static_peers
+                .push(StaticPeer::new((*peer).clone()));
         }
     }
 
@@ -301,10 +365,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // install global subscriber configured based on RUST_LOG envvar.
     tracing_subscriber::fmt::init();
 
-    let configs = Arc::new(RwLock::new(
-        ConfigHandler::load_configs("configs/saito.config.json".to_string())
-            .expect("loading configs failed"),
-    ));
+    // separate config files can be used to run multiple nodes from the same directory
+    let config_path =
+        std::env::var("SAITO_CONFIG").unwrap_or_else(|_| "configs/saito.config.json".to_string());
+    let configs = ConfigHandler::load_configs(config_path)?;
+    // invalid tls configs stop the node here instead of failing the server after the start
+    let tls_acceptor = ConfigHandler::load_tls_acceptor(&configs)?;
+    let configs = Arc::new(RwLock::new(configs));
 
     let (event_sender_to_loop, event_receiver_in_loop) =
         tokio::sync::mpsc::channel::<IoEvent>(1000);
@@ -315,6 +382,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("running saito controllers");
 
     let context = Context::new(configs.clone());
+    let mut database = None;
+    let data_dir;
+    {
+        trace!("waiting for the configs read lock");
+        let configs = configs.read().await;
+        trace!("acquired the configs read lock");
+        data_dir = configs.get_data_dir();
+        if configs.storage_backend == STORAGE_BACKEND_KV {
+            database = Some(
+                open_database((configs.get_data_dir() + STORAGE_DATABASE_DIR).as_str())
+                    .expect("opening storage database failed"),
+            );
+        }
+    }
+    let storage_backend = create_storage_backend(
+        RustIOHandler::new(
+            sender_to_network_controller.clone(),
+            CONSENSUS_EVENT_PROCESSOR_ID,
+            data_dir.clone(),
+        ),
+        &database,
+    );
     let peers = Arc::new(RwLock::new(PeerCollection::new()));
 
     let (sender_to_mempool, receiver_for_mempool) =
@@ -329,6 +418,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         sender_to_network_controller.clone(),
         configs.clone(),
         &context,
+        &storage_backend,
         peers.clone(),
         &sender_to_mempool,
         receiver_for_routing,
@@ -338,6 +428,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let (network_event_sender_to_blockchain, blockchain_handle) = run_consensus_event_processor(
         &context,
+        &storage_backend,
         peers.clone(),
         receiver_for_mempool,
         &sender_to_routing,
@@ -361,11 +452,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         network_event_sender_to_miner,
     );
 
+    // storage of the network controller doesn't send any events through the io handler
+    let network_storage = create_storage(
+        RustIOHandler::new(
+            sender_to_network_controller.clone(),
+            ROUTING_EVENT_PROCESSOR_ID,
+            data_dir,
+        ),
+        &storage_backend,
+    );
     let network_handle = tokio::spawn(run_network_controller(
         receiver_in_network_controller,
         event_sender_to_loop.clone(),
         configs.clone(),
-        context.blockchain.clone(),
+        Arc::new(network_storage),
+        tls_acceptor,
     ));
 
     let result = tokio::join!(
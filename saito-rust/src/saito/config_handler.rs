@@ -3,6 +3,7 @@ use std::io::{Error, ErrorKind};
 use figment::providers::{Format, Json};
 use figment::Figment;
 use log::{debug, error};
+use tokio_native_tls::TlsAcceptor;
 
 use saito_core::core::data::configuration::Configuration;
 
@@ -25,14 +26,76 @@ impl ConfigHandler {
             return Err(std::io::Error::from(ErrorKind::InvalidInput));
         }
 
-        Ok(configs.unwrap())
+        let configs = configs.unwrap();
+        configs.validate()?;
+
+        Ok(configs)
+    }
+    /// Creates the TLS acceptor from the certificate and the private key files in PEM format, if the server is
+    /// configured to serve https. Loaded at startup so a bad TLS config stops the node before it starts serving
+    ///
+    /// # Arguments
+    ///
+    /// * `configs`:
+    ///
+    /// returns: Result<Option<TlsAcceptor>, Error> None if the server doesn't use TLS. InvalidInput error if the
+    /// certificate or the private key is not configured, InvalidData if they are not valid, or the error of reading
+    /// the files
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    pub fn load_tls_acceptor(configs: &Configuration) -> Result<Option<TlsAcceptor>, Error> {
+        if configs.server.protocol != "https" {
+            return Ok(None);
+        }
+        if configs.server.tls_certificate.is_none() || configs.server.tls_private_key.is_none() {
+            error!("tls certificate and private key should be configured to serve https");
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+        let certificate_path = configs.server.tls_certificate.as_ref().unwrap();
+        let private_key_path = configs.server.tls_private_key.as_ref().unwrap();
+        let certificate = std::fs::read(certificate_path).map_err(|error| {
+            error!(
+                "failed reading the tls certificate : {:?} : {:?}",
+                certificate_path, error
+            );
+            error
+        })?;
+        let private_key = std::fs::read(private_key_path).map_err(|error| {
+            error!(
+                "failed reading the tls private key : {:?} : {:?}",
+                private_key_path, error
+            );
+            error
+        })?;
+        let identity =
+            native_tls::Identity::from_pkcs8(&certificate, &private_key).map_err(|error| {
+                error!("failed loading the tls certificate : {:?}", error);
+                Error::new(ErrorKind::InvalidData, error)
+            })?;
+        let acceptor = native_tls::TlsAcceptor::new(identity)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        Ok(Some(TlsAcceptor::from(acceptor)))
     }
 }
 
+#[cfg(test)]
 mod test {
     use crate::ConfigHandler;
+    use saito_core::core::data::configuration::Configuration;
     use std::io::ErrorKind;
 
+    fn create_https_configs(certificate: Option<&str>, private_key: Option<&str>) -> Configuration {
+        let mut configs = Configuration::new();
+        configs.server.protocol = "https".to_string();
+        configs.server.tls_certificate = certificate.map(|path| path.to_string());
+        configs.server.tls_private_key = private_key.map(|path| path.to_string());
+        configs
+    }
+
     #[test]
     fn load_config_from_existing_file() {
         let path = String::from("saito-rust/src/test/test_data/config_handler_tests.json");
@@ -56,6 +119,75 @@ mod test {
         assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
     }
 
+    #[test]
+    fn load_config_with_mixed_sync_types() {
+        let path =
+            String::from("saito-rust/src/test/test_data/config_handler_tests_mixed_sync.json");
+        let result = ConfigHandler::load_configs(path);
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn load_config_with_unknown_storage_backend() {
+        let path = String::from(
+            "saito-rust/src/test/test_data/config_handler_tests_unknown_storage_backend.json",
+        );
+        let result = ConfigHandler::load_configs(path);
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn load_tls_acceptor_without_https() {
+        let result = ConfigHandler::load_tls_acceptor(&Configuration::new());
+        assert!(result.unwrap().is_none());
+    }
+
+    #[test]
+    fn load_tls_acceptor_with_missing_certificate() {
+        let configs =
+            create_https_configs(None, Some("src/test/test_data/config_handler_tests.json"));
+        let result = ConfigHandler::load_tls_acceptor(&configs);
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn load_tls_acceptor_with_missing_private_key() {
+        let configs =
+            create_https_configs(Some("src/test/test_data/config_handler_tests.json"), None);
+        let result = ConfigHandler::load_tls_acceptor(&configs);
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn load_tls_acceptor_with_unreadable_certificate() {
+        let configs = create_https_configs(
+            Some("badcertificate.pem"),
+            Some("src/test/test_data/config_handler_tests.json"),
+        );
+        let result = ConfigHandler::load_tls_acceptor(&configs);
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::NotFound);
+
+        // a directory can't be read as a file
+        let configs = create_https_configs(
+            Some("src/test/test_data"),
+            Some("src/test/test_data/config_handler_tests.json"),
+        );
+        let result = ConfigHandler::load_tls_acceptor(&configs);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn load_tls_acceptor_with_invalid_certificate() {
+        let configs = create_https_configs(
+            Some("src/test/test_data/config_handler_tests_bad_format.xml"),
+            Some("src/test/test_data/config_handler_tests.json"),
+        );
+        let result = ConfigHandler::load_tls_acceptor(&configs);
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+
     #[test]
     fn load_config_from_non_existing_file() {
         let path = String::from("badfilename.json");
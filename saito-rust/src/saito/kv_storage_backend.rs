@@ -0,0 +1,353 @@
+use std::io::{Error, ErrorKind};
+
+use async_trait::async_trait;
+use log::{debug, error};
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::Transactional;
+
+use saito_core::common::defs::SaitoHash;
+use saito_core::core::data::block_index::BlockIndexEntry;
+use saito_core::core::data::serialize::Serialize;
+use saito_core::core::data::storage_backend::{StorageBackend, StorageOperation};
+
+const BLOCKS_TREE: &str = "blocks";
+const BLOCK_IDS_TREE: &str = "block_ids";
+const BLOCK_HASH_IDS_TREE: &str = "block_hash_ids";
+const BLOCK_INDEX_TREE: &str = "block_index";
+const METADATA_TREE: &str = "metadata";
+const QUARANTINE_TREE: &str = "quarantine";
+
+/// Opens the embedded database under the given directory. The database can only be opened once by the process, so the
+/// storages of all the event processors use clones of the returned handle
+pub fn open_database(path: &str) -> Result<sled::Db, Error> {
+    debug!("opening storage database : {:?}", path);
+    Ok(sled::open(path)?)
+}
+
+/// Keeps the blocks and the metadata in an embedded key-value database. Blocks are kept by hash, along with a height
+/// index keyed by the block id and the hash, so the blocks can be listed in the chain order without reading them.
+///
+/// Batches and quarantines are applied in blocking tasks, since sled blocks the thread while committing a transaction.
+pub struct KvStorageBackend {
+    database: sled::Db,
+    // block hash to the block buffer
+    blocks: sled::Tree,
+    // block id (big endian) + block hash to empty values
+    block_ids: sled::Tree,
+    // block hash to the block id (big endian), to find the height index keys of a block by its hash
+    block_hash_ids: sled::Tree,
+    // block id (big endian) + block hash to the serialized block index entries
+    block_index: sled::Tree,
+    metadata: sled::Tree,
+    // block hash to the buffers of the corrupted blocks
+    quarantine: sled::Tree,
+}
+
+impl KvStorageBackend {
+    pub fn new(database: sled::Db) -> Result<KvStorageBackend, Error> {
+        Ok(KvStorageBackend {
+            blocks: database.open_tree(BLOCKS_TREE)?,
+            block_ids: database.open_tree(BLOCK_IDS_TREE)?,
+            block_hash_ids: database.open_tree(BLOCK_HASH_IDS_TREE)?,
+            block_index: database.open_tree(BLOCK_INDEX_TREE)?,
+            metadata: database.open_tree(METADATA_TREE)?,
+            quarantine: database.open_tree(QUARANTINE_TREE)?,
+            database,
+        })
+    }
+}
+
+fn get_block_id_key(block_id: u64, block_hash: &SaitoHash) -> Vec<u8> {
+    let mut key = block_id.to_be_bytes().to_vec();
+    key.extend(block_hash);
+    key
+}
+
+#[async_trait]
+impl StorageBackend for KvStorageBackend {
+    async fn write_batch(&self, operations: Vec<StorageOperation>) -> Result<(), Error> {
+        let trees = (
+            self.blocks.clone(),
+            self.block_ids.clone(),
+            self.block_hash_ids.clone(),
+            self.block_index.clone(),
+            self.metadata.clone(),
+        );
+        let result = tokio::task::spawn_blocking(move || {
+            (&trees.0, &trees.1, &trees.2, &trees.3, &trees.4).transaction(
+                |(blocks, block_ids, block_hash_ids, block_index, metadata)| {
+                    for operation in operations.iter() {
+                        match operation {
+                            StorageOperation::PutBlock {
+                                block_hash,
+                                block_id,
+                                buffer,
+                                ..
+                            } => {
+                                blocks.insert(block_hash.as_slice(), buffer.as_slice())?;
+                                block_ids
+                                    .insert(get_block_id_key(*block_id, block_hash), vec![])?;
+                                block_hash_ids.insert(
+                                    block_hash.as_slice(),
+                                    block_id.to_be_bytes().as_slice(),
+                                )?;
+                            }
+                            StorageOperation::DeleteBlock {
+                                block_hash,
+                                block_id,
+                                ..
+                            } => {
+                                blocks.remove(block_hash.as_slice())?;
+                                block_ids.remove(get_block_id_key(*block_id, block_hash))?;
+                                block_hash_ids.remove(block_hash.as_slice())?;
+                                block_index.remove(get_block_id_key(*block_id, block_hash))?;
+                            }
+                            StorageOperation::PutBlockIndexEntry(entry) => {
+                                block_index.insert(
+                                    get_block_id_key(entry.block_id, &entry.block_hash),
+                                    entry.serialize(),
+                                )?;
+                            }
+                            StorageOperation::DeleteBlockIndexEntry {
+                                block_hash,
+                                block_id,
+                            } => {
+                                block_index.remove(get_block_id_key(*block_id, block_hash))?;
+                            }
+                            StorageOperation::PutMetadata { key, value } => {
+                                metadata.insert(key.as_bytes(), value.as_slice())?;
+                            }
+                            StorageOperation::DeleteMetadata { key } => {
+                                metadata.remove(key.as_bytes())?;
+                            }
+                        }
+                    }
+                    Ok::<(), ConflictableTransactionError<()>>(())
+                },
+            )
+        })
+        .await
+        .map_err(Error::other)?;
+        if let Err(error) = result {
+            error!("failed writing storage batch : {:?}", error);
+            return match error {
+                TransactionError::Storage(error) => Err(Error::from(error)),
+                TransactionError::Abort(_) => Err(Error::from(ErrorKind::Other)),
+            };
+        }
+        // batch is on the disk once this returns
+        self.database.flush_async().await?;
+        Ok(())
+    }
+
+    async fn read_block(&self, block_hash: &SaitoHash) -> Result<Vec<u8>, Error> {
+        let result = self.blocks.get(block_hash)?;
+        if result.is_none() {
+            debug!("block not found in storage : {:?}", hex::encode(block_hash));
+            return Err(Error::from(ErrorKind::NotFound));
+        }
+        Ok(result.unwrap().to_vec())
+    }
+
+    async fn get_block_hashes_by_id(&self, block_id: u64) -> Result<Vec<SaitoHash>, Error> {
+        let mut block_hashes = vec![];
+        for entry in self.block_ids.scan_prefix(block_id.to_be_bytes()) {
+            let (key, _) = entry?;
+            block_hashes.push(key[8..].try_into().unwrap());
+        }
+        Ok(block_hashes)
+    }
+
+    async fn get_block_hashes(&self) -> Result<Vec<SaitoHash>, Error> {
+        // keys are ordered by the block id, so the parents are loaded before their children
+        let mut block_hashes = vec![];
+        for entry in self.block_ids.iter() {
+            let (key, _) = entry?;
+            block_hashes.push(key[8..].try_into().unwrap());
+        }
+        Ok(block_hashes)
+    }
+
+    async fn load_block_index(&self) -> Result<Vec<BlockIndexEntry>, Error> {
+        let mut entries = vec![];
+        for entry in self.block_index.iter() {
+            let (_, value) = entry?;
+            entries.push(BlockIndexEntry::deserialize(&value.to_vec())?);
+        }
+        Ok(entries)
+    }
+
+    async fn quarantine_block(&self, block_hash: &SaitoHash) -> Result<(), Error> {
+        let block_hash = *block_hash;
+        let trees = (
+            self.blocks.clone(),
+            self.block_ids.clone(),
+            self.block_hash_ids.clone(),
+            self.block_index.clone(),
+            self.quarantine.clone(),
+        );
+        let result = tokio::task::spawn_blocking(move || {
+            (&trees.0, &trees.1, &trees.2, &trees.3, &trees.4).transaction(
+                |(blocks, block_ids, block_hash_ids, block_index, quarantine)| {
+                    let buffer = blocks.remove(block_hash.as_slice())?;
+                    if buffer.is_none() {
+                        return Err(ConflictableTransactionError::Abort(()));
+                    }
+                    quarantine.insert(block_hash.as_slice(), buffer.unwrap())?;
+                    // id of the block is not known by the caller, so the height index keys are found by the hash
+                    if let Some(block_id) = block_hash_ids.remove(block_hash.as_slice())? {
+                        let block_id = u64::from_be_bytes(block_id.as_ref().try_into().unwrap());
+                        block_ids.remove(get_block_id_key(block_id, &block_hash))?;
+                        block_index.remove(get_block_id_key(block_id, &block_hash))?;
+                    }
+                    Ok(())
+                },
+            )
+        })
+        .await
+        .map_err(Error::other)?;
+        if let Err(error) = result {
+            return match error {
+                TransactionError::Storage(error) => Err(Error::from(error)),
+                TransactionError::Abort(_) => Err(Error::from(ErrorKind::NotFound)),
+            };
+        }
+        self.database.flush_async().await?;
+        Ok(())
+    }
+
+    async fn read_metadata(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self
+            .metadata
+            .get(key.as_bytes())?
+            .map(|value| value.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use saito_core::core::data::block_index::BlockIndexEntry;
+    use saito_core::core::data::storage_backend::{StorageBackend, StorageOperation};
+
+    use crate::saito::kv_storage_backend::KvStorageBackend;
+
+    fn put_block(block_hash: [u8; 32], block_id: u64) -> StorageOperation {
+        StorageOperation::PutBlock {
+            block_hash,
+            block_id,
+            timestamp: 1000,
+            buffer: vec![block_id as u8; 10],
+        }
+    }
+
+    fn put_block_index_entry(block_hash: [u8; 32], block_id: u64) -> StorageOperation {
+        StorageOperation::PutBlockIndexEntry(BlockIndexEntry {
+            block_hash,
+            block_id,
+            previous_block_hash: [0; 32],
+            timestamp: 1000,
+            in_longest_chain: block_hash == [2; 32],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_kv_storage_backend() {
+        let database = sled::Config::new().temporary(true).open().unwrap();
+        let backend = KvStorageBackend::new(database).unwrap();
+
+        backend
+            .write_batch(vec![
+                put_block([3; 32], 2),
+                put_block([1; 32], 1),
+                put_block([2; 32], 2),
+                put_block_index_entry([3; 32], 2),
+                put_block_index_entry([1; 32], 1),
+                put_block_index_entry([2; 32], 2),
+                StorageOperation::PutMetadata {
+                    key: "latest".to_string(),
+                    value: vec![1, 2],
+                },
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(backend.read_block(&[1; 32]).await.unwrap(), vec![1; 10]);
+        assert_eq!(
+            backend.read_block(&[4; 32]).await.err().unwrap().kind(),
+            std::io::ErrorKind::NotFound
+        );
+        assert_eq!(
+            backend.get_block_hashes().await.unwrap(),
+            vec![[1; 32], [2; 32], [3; 32]]
+        );
+        assert_eq!(
+            backend.get_block_hashes_by_id(2).await.unwrap(),
+            vec![[2; 32], [3; 32]]
+        );
+        assert_eq!(
+            backend.read_metadata("latest").await.unwrap(),
+            Some(vec![1, 2])
+        );
+        assert!(backend.read_metadata("other").await.unwrap().is_none());
+        let entries = backend.load_block_index().await.unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.block_hash)
+                .collect::<Vec<_>>(),
+            vec![[1; 32], [2; 32], [3; 32]]
+        );
+        assert!(entries[1].in_longest_chain);
+
+        backend
+            .write_batch(vec![
+                StorageOperation::DeleteBlock {
+                    block_hash: [3; 32],
+                    block_id: 2,
+                    timestamp: 1000,
+                },
+                StorageOperation::DeleteMetadata {
+                    key: "latest".to_string(),
+                },
+            ])
+            .await
+            .unwrap();
+        assert!(backend.read_block(&[3; 32]).await.is_err());
+        assert_eq!(
+            backend.get_block_hashes_by_id(2).await.unwrap(),
+            vec![[2; 32]]
+        );
+        assert!(backend.read_metadata("latest").await.unwrap().is_none());
+        // index entry is deleted with the block
+        assert_eq!(backend.load_block_index().await.unwrap().len(), 2);
+        assert!(backend.block_hash_ids.get([3; 32]).unwrap().is_none());
+        assert_eq!(
+            backend
+                .block_hash_ids
+                .get([2; 32])
+                .unwrap()
+                .unwrap()
+                .to_vec(),
+            2u64.to_be_bytes().to_vec()
+        );
+
+        backend.quarantine_block(&[2; 32]).await.unwrap();
+        assert!(backend.read_block(&[2; 32]).await.is_err());
+        assert!(backend.get_block_hashes_by_id(2).await.unwrap().is_empty());
+        assert_eq!(backend.load_block_index().await.unwrap().len(), 1);
+        assert!(backend.block_hash_ids.get([2; 32]).unwrap().is_none());
+        assert_eq!(
+            backend.quarantine.get([2; 32]).unwrap().unwrap().to_vec(),
+            vec![2; 10]
+        );
+        assert_eq!(
+            backend
+                .quarantine_block(&[2; 32])
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            std::io::ErrorKind::NotFound
+        );
+    }
+}
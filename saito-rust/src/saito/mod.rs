@@ -2,6 +2,7 @@ pub mod config_handler;
 mod io_context;
 pub mod io_event;
 mod io_future;
+pub mod kv_storage_backend;
 pub mod network_controller;
 pub mod rust_io_handler;
 mod rust_task_runner;
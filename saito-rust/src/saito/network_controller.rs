@@ -1,13 +1,15 @@
 use std::collections::HashMap;
 
 use std::io::{Error, ErrorKind, Write};
+use std::net::SocketAddr;
 
 use std::sync::Arc;
 
 use futures::stream::{SplitSink, SplitStream};
-use futures::{SinkExt, StreamExt};
+use futures::{SinkExt, Stream, StreamExt};
 use log::{debug, error, info, trace, warn};
-use tokio::net::TcpStream;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_native_tls::{TlsAcceptor, TlsStream};
 
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::{Mutex, RwLock};
@@ -18,12 +20,15 @@ use warp::ws::WebSocket;
 use warp::Filter;
 
 use saito_core::common::defs::SaitoHash;
+use saito_core::common::keep_time::KeepTime;
 use saito_core::core::data;
 use saito_core::core::data::block::BlockType;
-use saito_core::core::data::blockchain::Blockchain;
-use saito_core::core::data::configuration::{Configuration, PeerConfig};
+use saito_core::core::data::configuration::{Configuration, PeerConfig, PeerRateLimits};
+use saito_core::core::data::rate_limiter::{PeerRateLimiter, RateLimitResult};
+use saito_core::core::data::storage::Storage;
 
 use crate::saito::rust_io_handler::{FutureState, RustIOHandler};
+use crate::saito::time_keeper::TimeKeeper;
 use crate::{IoEvent, NetworkEvent};
 
 type SocketSender = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Message>;
@@ -31,8 +36,12 @@ type SocketReceiver = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
 
 pub struct NetworkController {
     sockets: HashMap<u64, PeerSender>,
+    // tasks reading from the sockets. aborted when disconnecting since half-open sockets may never return
+    receiver_tasks: HashMap<u64, JoinHandle<()>>,
     peer_counter: Arc<Mutex<PeerCounter>>,
     pub sender_to_saito_controller: Sender<IoEvent>,
+    // limits for the messages received from each peer, checked before the messages are queued for the core
+    rate_limits: PeerRateLimits,
 }
 
 impl NetworkController {
@@ -40,7 +49,8 @@ impl NetworkController {
         debug!("sending outgoing message : peer = {:?}", peer_index);
         let socket = self.sockets.get_mut(&peer_index);
         if socket.is_none() {
-            todo!()
+            warn!("socket not found for peer : {:?}", peer_index);
+            return;
         }
         let socket = socket.unwrap();
         match socket {
@@ -83,12 +93,28 @@ impl NetworkController {
         debug!("connecting to peer : {:?}", url);
         let result = connect_async(url.clone()).await;
         if result.is_err() {
-            warn!("failed connecting to peer : {:?}", peer); //TODO : Retry connecting after an interval
+            warn!("failed connecting to peer : {:?}", peer);
             error!("{:?}", result.err());
             RustIOHandler::set_event_response(
                 event_id,
                 FutureState::PeerConnectionResult(Err(Error::from(ErrorKind::Other))),
             );
+            let sender_to_core;
+            {
+                let io_controller = io_controller.read().await;
+                sender_to_core = io_controller.sender_to_saito_controller.clone();
+            }
+            sender_to_core
+                .send(IoEvent {
+                    event_processor_id: 1,
+                    event_id,
+                    event: NetworkEvent::PeerConnectionResult {
+                        peer_details: Some(peer),
+                        result: Err(Error::from(ErrorKind::Other)),
+                    },
+                })
+                .await
+                .expect("sending failed");
             return;
         }
         debug!("connected to peer : {:?}", url);
@@ -102,8 +128,7 @@ impl NetworkController {
         let (socket_sender, socket_receiver): (SocketSender, SocketReceiver) = socket.split();
         NetworkController::send_new_peer(
             event_id,
-            io_controller.peer_counter.clone(),
-            &mut io_controller.sockets,
+            &mut io_controller,
             PeerSender::Tungstenite(socket_sender),
             PeerReceiver::Tungstenite(socket_receiver),
             sender_to_controller,
@@ -111,6 +136,28 @@ impl NetworkController {
         )
         .await;
     }
+    pub async fn disconnect_from_peer(&mut self, peer_index: u64) {
+        debug!("disconnecting from peer : {:?}", peer_index);
+        if let Some(task) = self.receiver_tasks.remove(&peer_index) {
+            task.abort();
+        }
+        let socket = self.sockets.remove(&peer_index);
+        if socket.is_none() {
+            debug!("socket not found for peer : {:?}", peer_index);
+            return;
+        }
+        let result = match socket.unwrap() {
+            PeerSender::Warp(mut sender) => sender.close().await.map_err(|e| e.to_string()),
+            PeerSender::Tungstenite(mut sender) => sender.close().await.map_err(|e| e.to_string()),
+        };
+        if result.is_err() {
+            warn!(
+                "failed closing socket for peer : {:?} : {:?}",
+                peer_index,
+                result.err().unwrap()
+            );
+        }
+    }
     pub async fn send_to_all(&mut self, buffer: Vec<u8>, exceptions: Vec<u64>) {
         debug!("sending message : {:?} to all", buffer[0]);
         for entry in self.sockets.iter_mut() {
@@ -148,12 +195,24 @@ impl NetworkController {
 
         let result = reqwest::get(url).await;
         if result.is_err() {
-            todo!()
+            warn!("failed fetching block : {:?}", result.err().unwrap());
+            NetworkController::send_block_fetch_failure(block_hash, peer_index, sender_to_core)
+                .await;
+            return;
         }
         let response = result.unwrap();
+        if !response.status().is_success() {
+            warn!("failed fetching block. status : {:?}", response.status());
+            NetworkController::send_block_fetch_failure(block_hash, peer_index, sender_to_core)
+                .await;
+            return;
+        }
         let result = response.bytes().await;
         if result.is_err() {
-            todo!()
+            warn!("failed reading block buffer : {:?}", result.err().unwrap());
+            NetworkController::send_block_fetch_failure(block_hash, peer_index, sender_to_core)
+                .await;
+            return;
         }
         let result = result.unwrap();
         let buffer = result.to_vec();
@@ -173,19 +232,35 @@ impl NetworkController {
             .unwrap();
         debug!("block buffer sent to blockchain controller");
     }
+    async fn send_block_fetch_failure(
+        block_hash: SaitoHash,
+        peer_index: u64,
+        sender_to_core: Sender<IoEvent>,
+    ) {
+        sender_to_core
+            .send(IoEvent {
+                event_processor_id: 1,
+                event_id: 0,
+                event: NetworkEvent::BlockFetchFailed {
+                    block_hash,
+                    peer_index,
+                },
+            })
+            .await
+            .unwrap();
+    }
     pub async fn send_new_peer(
         event_id: u64,
-        peer_counter: Arc<Mutex<PeerCounter>>,
-        sockets: &mut HashMap<u64, PeerSender>,
+        io_controller: &mut NetworkController,
         sender: PeerSender,
         receiver: PeerReceiver,
         sender_to_core: Sender<IoEvent>,
         peer_data: Option<PeerConfig>,
     ) {
-        let mut counter = peer_counter.lock().await;
+        let mut counter = io_controller.peer_counter.lock().await;
         let next_index = counter.get_next_index();
 
-        sockets.insert(next_index, sender);
+        io_controller.sockets.insert(next_index, sender);
         debug!("sending new peer : {:?}", next_index);
 
         sender_to_core
@@ -200,8 +275,14 @@ impl NetworkController {
             .await
             .expect("sending failed");
 
-        NetworkController::receive_message_from_peer(receiver, sender_to_core.clone(), next_index)
-            .await;
+        let task = NetworkController::receive_message_from_peer(
+            receiver,
+            sender_to_core.clone(),
+            next_index,
+            io_controller.rate_limits.clone(),
+        )
+        .await;
+        io_controller.receiver_tasks.insert(next_index, task);
     }
 
     pub async fn send_peer_disconnect(sender_to_core: Sender<IoEvent>, peer_index: u64) {
@@ -217,19 +298,74 @@ impl NetworkController {
             .expect("sending failed");
     }
 
+    /// Queues the received message for the core if the peer is within the message and byte limits. messages are checked
+    /// with their size on the wire, before they are decrypted. returns false if the peer exceeded the limits, so reading
+    /// from it should stop until the core disconnects it
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer`: message as received from the socket
+    /// * `peer_index`:
+    /// * `rate_limiter`: limiter of the peer
+    /// * `rate_limits`: configured limits
+    /// * `sender`: sender to the core
+    ///
+    /// returns: bool
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    /// ```
+    async fn forward_incoming_message(
+        buffer: Vec<u8>,
+        peer_index: u64,
+        rate_limiter: &mut PeerRateLimiter,
+        rate_limits: &PeerRateLimits,
+        sender: &Sender<IoEvent>,
+    ) -> bool {
+        let current_time = TimeKeeper {}.get_timestamp();
+        let event = match rate_limiter.check_message(buffer.len() as u64, rate_limits, current_time)
+        {
+            RateLimitResult::Allowed => NetworkEvent::IncomingNetworkMessage { peer_index, buffer },
+            RateLimitResult::Throttled => {
+                trace!(
+                    "dropping message from peer : {:?} over the rate limit",
+                    peer_index
+                );
+                return true;
+            }
+            RateLimitResult::Exceeded => NetworkEvent::PeerRateLimitExceeded { peer_index },
+        };
+        let keep_reading = matches!(event, NetworkEvent::IncomingNetworkMessage { .. });
+        sender
+            .send(IoEvent {
+                event_processor_id: 1,
+                event_id: 0,
+                event,
+            })
+            .await
+            .expect("sending failed");
+        keep_reading
+    }
+
     pub async fn receive_message_from_peer(
         receiver: PeerReceiver,
         sender: Sender<IoEvent>,
         peer_index: u64,
-    ) {
+        rate_limits: PeerRateLimits,
+    ) -> JoinHandle<()> {
         debug!("starting new task for reading from peer : {:?}", peer_index);
         tokio::spawn(async move {
             debug!("new thread started for peer receiving");
+            let mut rate_limiter = PeerRateLimiter::default();
             match receiver {
                 PeerReceiver::Warp(mut receiver) => loop {
                     let result = receiver.next().await;
                     if result.is_none() {
-                        continue;
+                        debug!("socket closed for peer : {:?}", peer_index);
+                        NetworkController::send_peer_disconnect(sender, peer_index).await;
+                        break;
                     }
                     let result = result.unwrap();
                     if result.is_err() {
@@ -241,21 +377,29 @@ impl NetworkController {
                     let result = result.unwrap();
 
                     if result.is_binary() {
-                        let buffer = result.into_bytes();
-                        let message = IoEvent {
-                            event_processor_id: 1,
-                            event_id: 0,
-                            event: NetworkEvent::IncomingNetworkMessage { peer_index, buffer },
-                        };
-                        sender.send(message).await.expect("sending failed");
-                    } else {
-                        todo!()
+                        let keep_reading = NetworkController::forward_incoming_message(
+                            result.into_bytes(),
+                            peer_index,
+                            &mut rate_limiter,
+                            &rate_limits,
+                            &sender,
+                        )
+                        .await;
+                        if !keep_reading {
+                            break;
+                        }
+                    } else if result.is_close() {
+                        debug!("socket closed by peer : {:?}", peer_index);
+                        NetworkController::send_peer_disconnect(sender, peer_index).await;
+                        break;
                     }
                 },
                 PeerReceiver::Tungstenite(mut receiver) => loop {
                     let result = receiver.next().await;
                     if result.is_none() {
-                        continue;
+                        debug!("socket closed for peer : {:?}", peer_index);
+                        NetworkController::send_peer_disconnect(sender, peer_index).await;
+                        break;
                     }
                     let result = result.unwrap();
                     if result.is_err() {
@@ -266,21 +410,30 @@ impl NetworkController {
                     let result = result.unwrap();
                     match result {
                         tokio_tungstenite::tungstenite::Message::Binary(buffer) => {
-                            let message = IoEvent {
-                                event_processor_id: 1,
-                                event_id: 0,
-                                event: NetworkEvent::IncomingNetworkMessage { peer_index, buffer },
-                            };
-                            sender.send(message).await.expect("sending failed");
+                            let keep_reading = NetworkController::forward_incoming_message(
+                                buffer,
+                                peer_index,
+                                &mut rate_limiter,
+                                &rate_limits,
+                                &sender,
+                            )
+                            .await;
+                            if !keep_reading {
+                                break;
+                            }
+                        }
+                        tokio_tungstenite::tungstenite::Message::Close(_) => {
+                            debug!("socket closed by peer : {:?}", peer_index);
+                            NetworkController::send_peer_disconnect(sender, peer_index).await;
+                            break;
                         }
                         _ => {
                             // Not handling these scenarios
-                            todo!()
                         }
                     }
                 },
             }
-        });
+        })
     }
 }
 
@@ -300,39 +453,49 @@ pub async fn run_network_controller(
     mut receiver: Receiver<IoEvent>,
     sender: Sender<IoEvent>,
     configs: Arc<RwLock<Configuration>>,
-    blockchain: Arc<RwLock<Blockchain>>,
+    storage: Arc<Storage>,
+    tls_acceptor: Option<TlsAcceptor>,
 ) {
     info!("running network handler");
     let peer_index_counter = Arc::new(Mutex::new(PeerCounter { counter: 0 }));
 
-    let url;
+    let host;
     let port;
+    let rate_limits;
     {
         trace!("waiting for the configs write lock");
         let configs = configs.read().await;
         trace!("acquired the configs write lock");
-        url = "localhost:".to_string() + configs.server.port.to_string().as_str();
+        host = configs.server.host.clone();
         port = configs.server.port;
+        rate_limits = configs.peer_rate_limits.clone();
     }
 
-    info!("starting server on : {:?}", url);
-    let peer_counter_clone = peer_index_counter.clone();
+    // host can be an ipv4 or ipv6 address (eg: 0.0.0.0 or :: to listen on all interfaces) or a host name
+    let address = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .expect("failed resolving the server host")
+        .next()
+        .expect("server host didn't resolve to an address");
+    info!("starting server on : {:?}", address);
     let sender_clone = sender.clone();
 
     let network_controller = Arc::new(RwLock::new(NetworkController {
         sockets: Default::default(),
+        receiver_tasks: Default::default(),
         sender_to_saito_controller: sender,
         peer_counter: peer_index_counter.clone(),
+        rate_limits,
     }));
 
     let network_controller_clone = network_controller.clone();
 
     let server_handle = run_websocket_server(
-        peer_counter_clone,
         sender_clone.clone(),
         network_controller_clone.clone(),
-        port,
-        blockchain.clone(),
+        address,
+        storage,
+        tls_acceptor,
     );
 
     let mut work_done = false;
@@ -379,6 +542,15 @@ pub async fn run_network_controller(
                     NetworkEvent::PeerDisconnected { peer_index: _ } => {
                         unreachable!()
                     }
+                    NetworkEvent::PeerRateLimitExceeded { .. } => {
+                        unreachable!()
+                    }
+                    NetworkEvent::DisconnectFromPeer { peer_index } => {
+                        trace!("waiting for the io controller write lock");
+                        let mut io_controller = network_controller.write().await;
+                        trace!("acquired the io controller write lock");
+                        io_controller.disconnect_from_peer(peer_index).await;
+                    }
                     NetworkEvent::IncomingNetworkMessage { .. } => {
                         unreachable!()
                     }
@@ -403,6 +575,9 @@ pub async fn run_network_controller(
                     NetworkEvent::BlockFetched { .. } => {
                         unreachable!()
                     }
+                    NetworkEvent::BlockFetchFailed { .. } => {
+                        unreachable!()
+                    }
                 }
             }
 
@@ -425,24 +600,22 @@ pub enum PeerReceiver {
 }
 
 fn run_websocket_server(
-    peer_counter: Arc<Mutex<PeerCounter>>,
     sender_clone: Sender<IoEvent>,
     io_controller: Arc<RwLock<NetworkController>>,
-    port: u16,
-    blockchain: Arc<RwLock<Blockchain>>,
+    address: SocketAddr,
+    storage: Arc<Storage>,
+    tls_acceptor: Option<TlsAcceptor>,
 ) -> JoinHandle<()> {
-    info!("running websocket server on {:?}", port);
+    info!("running websocket server on {:?}", address);
     tokio::spawn(async move {
         info!("starting websocket server");
         let io_controller = io_controller.clone();
         let sender_to_io = sender_clone.clone();
-        let peer_counter = peer_counter.clone();
         let ws_route = warp::path("wsopen")
             .and(warp::ws())
             .map(move |ws: warp::ws::Ws| {
                 debug!("incoming connection received");
                 let clone = io_controller.clone();
-                let peer_counter = peer_counter.clone();
                 let sender_to_io = sender_to_io.clone();
                 ws.on_upgrade(move |socket| async move {
                     debug!("socket connection established");
@@ -454,8 +627,7 @@ fn run_websocket_server(
 
                     NetworkController::send_new_peer(
                         0,
-                        peer_counter,
-                        &mut controller.sockets,
+                        &mut controller,
                         PeerSender::Warp(sender),
                         PeerReceiver::Warp(receiver),
                         sender_to_io,
@@ -464,43 +636,300 @@ fn run_websocket_server(
                     .await
                 })
             });
-        let http_route = warp::path!("block" / String)
-            .and(warp::any().map(move || blockchain.clone()))
-            .and_then(
-                |block_hash: String, blockchain: Arc<RwLock<Blockchain>>| async move {
-                    debug!("serving block : {:?}", block_hash);
-                    let buffer: Vec<u8>;
-                    {
-                        let block_hash = hex::decode(block_hash);
-                        if block_hash.is_err() {
-                            todo!()
-                        }
-                        let block_hash = block_hash.unwrap();
-                        if block_hash.len() != 32 {
-                            todo!()
-                        }
-                        let block_hash: SaitoHash = block_hash.try_into().unwrap();
-                        trace!("waiting for the blockchain write lock");
-                        // TODO : load disk from disk and serve rather than locking the blockchain
-                        let blockchain = blockchain.read().await;
-                        trace!("acquired the blockchain write lock");
-                        let block = blockchain.get_block(&block_hash).await;
-                        if block.is_none() {
-                            debug!("block not found : {:?}", block_hash);
-                            return Err(warp::reject::not_found());
-                        }
-                        // TODO : check if the full block is in memory or need to load from disk
-                        buffer = block.unwrap().serialize_for_net(BlockType::Full);
+        let http_route = block_route(storage.clone());
+        // latest utxo snapshot, for the peers bootstrapping from it
+        let snapshot_route = warp::path!("utxo_snapshot")
+            .and(warp::any().map(move || storage.clone()))
+            .and_then(|storage: Arc<Storage>| async move {
+                debug!("serving utxo snapshot");
+                match storage.read_utxo_snapshot_buffer().await {
+                    Ok(Some(buffer)) => Ok(warp::reply::with_status(buffer, StatusCode::OK)),
+                    Ok(None) => {
+                        debug!("no utxo snapshot to serve");
+                        Err(warp::reject::not_found())
                     }
-                    Ok(warp::reply::with_status(buffer, StatusCode::OK))
-                },
-            );
-        let routes = http_route.or(ws_route);
+                    Err(error) => {
+                        error!("failed reading utxo snapshot : {:?}", error);
+                        Err(warp::reject::not_found())
+                    }
+                }
+            });
+        let routes = http_route.or(snapshot_route).or(ws_route);
         // let (_, server) =
         //     warp::serve(ws_route).bind_with_graceful_shutdown(([127, 0, 0, 1], port), async {
         //         // tokio::signal::ctrl_c().await.ok();
         //     });
         // server.await;
-        warp::serve(routes).run(([127, 0, 0, 1], port)).await;
+        if tls_acceptor.is_none() {
+            warp::serve(routes).run(address).await;
+            return;
+        }
+        info!("serving with tls on {:?}", address);
+        let listener = TcpListener::bind(address)
+            .await
+            .expect("failed binding the server port");
+        warp::serve(routes)
+            .run_incoming(accept_tls_connections(listener, tls_acceptor.unwrap()))
+            .await;
+    })
+}
+
+/// Serves the stored blocks at "/block/{hash}" without going through the blockchain, so the pruned blocks can be served
+/// too. "type=header" query parameter gives only the block header. blocks don't change once stored, so the responses
+/// can be cached by the block hash.
+///
+/// The storage should share its backend with the storage of the blockchain, so the blocks are looked up in the block
+/// index loaded at the start and the blocks deleted afterwards are not served. unknown blocks are not searched on disk
+pub(crate) fn block_route(
+    storage: Arc<Storage>,
+) -> impl Filter<Extract = (warp::http::Response<Vec<u8>>,), Error = warp::Rejection> + Clone {
+    warp::path!("block" / String)
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(warp::any().map(move || storage.clone()))
+        .and_then(
+            |block_hash: String,
+             query: HashMap<String, String>,
+             if_none_match: Option<String>,
+             storage: Arc<Storage>| async move {
+                debug!("serving block : {:?}", block_hash);
+                let block_type = match query.get("type").map(|value| value.as_str()) {
+                    None | Some("full") => BlockType::Full,
+                    Some("header") => BlockType::Header,
+                    Some(value) => {
+                        debug!("invalid block type : {:?}", value);
+                        return Ok(reply_with_status(StatusCode::BAD_REQUEST));
+                    }
+                };
+                let block_hash: Option<SaitoHash> = hex::decode(&block_hash)
+                    .ok()
+                    .and_then(|block_hash| block_hash.try_into().ok());
+                if block_hash.is_none() {
+                    debug!("invalid block hash");
+                    return Ok(reply_with_status(StatusCode::BAD_REQUEST));
+                }
+                let block_hash = block_hash.unwrap();
+                let etag = format!("\"{}-{:?}\"", hex::encode(block_hash), block_type);
+                if if_none_match.as_ref() == Some(&etag) {
+                    return Ok(warp::http::Response::builder()
+                        .status(StatusCode::NOT_MODIFIED)
+                        .header("ETag", etag)
+                        .body(vec![])
+                        .unwrap());
+                }
+                match storage.read_block_buffer(&block_hash, block_type).await {
+                    Ok(buffer) => Ok(warp::http::Response::builder()
+                        .status(StatusCode::OK)
+                        .header("Content-Type", "application/octet-stream")
+                        .header("Cache-Control", "public, max-age=31536000, immutable")
+                        .header("ETag", etag)
+                        .body(buffer)
+                        .unwrap()),
+                    Err(error) if error.kind() == ErrorKind::NotFound => {
+                        debug!("block not found : {:?}", hex::encode(block_hash));
+                        Err(warp::reject::not_found())
+                    }
+                    Err(error) => {
+                        error!(
+                            "failed reading block : {:?} : {:?}",
+                            hex::encode(block_hash),
+                            error
+                        );
+                        Ok(reply_with_status(StatusCode::INTERNAL_SERVER_ERROR))
+                    }
+                }
+            },
+        )
+}
+
+fn reply_with_status(status: StatusCode) -> warp::http::Response<Vec<u8>> {
+    warp::http::Response::builder()
+        .status(status)
+        .body(vec![])
+        .unwrap()
+}
+
+/// Accepts the incoming connections and completes the TLS handshakes in separate tasks, so a slow client doesn't
+/// stop the other connections from being accepted
+fn accept_tls_connections(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+) -> impl Stream<Item = Result<TlsStream<TcpStream>, Error>> {
+    let (sender, receiver) = tokio::sync::mpsc::channel(100);
+    tokio::spawn(async move {
+        loop {
+            let result = listener.accept().await;
+            if result.is_err() {
+                warn!("failed accepting connection : {:?}", result.err().unwrap());
+                continue;
+            }
+            let (stream, address) = result.unwrap();
+            let acceptor = acceptor.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let result = acceptor.accept(stream).await;
+                if result.is_err() {
+                    debug!(
+                        "tls handshake failed with : {:?} : {:?}",
+                        address,
+                        result.err().unwrap()
+                    );
+                    return;
+                }
+                sender.send(result.unwrap()).await.unwrap_or(());
+            });
+        }
+    });
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver
+            .recv()
+            .await
+            .map(|stream| (Ok::<TlsStream<TcpStream>, Error>(stream), receiver))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use warp::http::StatusCode;
+
+    use saito_core::common::interface_io::InterfaceIO;
+    use saito_core::core::data::storage::{add_block_file_header, Storage};
+    use saito_core::core::data::storage_backend::{generate_block_filename, StorageOperation};
+
+    use crate::saito::network_controller::block_route;
+    use crate::test::test_io_handler::TestIOHandler;
+    use crate::test::test_manager::TestManager;
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn block_route_test() {
+        TestManager::clear_data_folder().await;
+        let storage = Arc::new(Storage::new(Box::new(TestIOHandler::new())));
+        // header is the first 213 bytes, with a zero transaction count
+        let mut block_buffer = vec![0, 0, 0, 2];
+        block_buffer.extend(vec![1; 300]);
+        storage
+            .backend
+            .write_batch(vec![StorageOperation::PutBlock {
+                block_hash: [1; 32],
+                block_id: 1,
+                timestamp: 1000,
+                buffer: add_block_file_header(block_buffer.clone()),
+            }])
+            .await
+            .unwrap();
+        let route = block_route(storage);
+        let path = "/block/".to_string() + hex::encode([1; 32]).as_str();
+
+        let response = warp::test::request().path(&path).reply(&route).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().to_vec(), block_buffer);
+        assert!(response.headers().contains_key("Cache-Control"));
+        let etag = response.headers().get("ETag").unwrap().clone();
+
+        let response = warp::test::request()
+            .path(&(path.clone() + "?type=header"))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().len(), 213);
+        assert_eq!(response.body()[0..4], [0, 0, 0, 0]);
+        assert_ne!(response.headers().get("ETag").unwrap(), &etag);
+
+        let response = warp::test::request()
+            .path(&path)
+            .header("if-none-match", etag)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(response.body().is_empty());
+
+        let response = warp::test::request()
+            .path(&(path.clone() + "?type=pruned"))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = warp::test::request()
+            .path("/block/0102")
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = warp::test::request()
+            .path(&("/block/".to_string() + hex::encode([2; 32]).as_str()))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn block_route_after_block_deleted_test() {
+        TestManager::clear_data_folder().await;
+        let io_handler = TestIOHandler::new();
+        let storage = Arc::new(Storage::new(Box::new(io_handler.clone())));
+        // storage of the blockchain, sharing the backend with the route
+        let blockchain_storage =
+            Storage::new_with_backend(Box::new(io_handler.clone()), storage.backend.clone());
+        let mut block_buffer = vec![0, 0, 0, 2];
+        block_buffer.extend(vec![1; 300]);
+        for block_hash in [[1; 32], [3; 32]] {
+            blockchain_storage
+                .backend
+                .write_batch(vec![StorageOperation::PutBlock {
+                    block_hash,
+                    block_id: 1,
+                    timestamp: 1000,
+                    buffer: add_block_file_header(block_buffer.clone()),
+                }])
+                .await
+                .unwrap();
+        }
+        let route = block_route(storage);
+        let path = "/block/".to_string() + hex::encode([1; 32]).as_str();
+        let response = warp::test::request().path(&path).reply(&route).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        blockchain_storage
+            .backend
+            .write_batch(vec![StorageOperation::DeleteBlock {
+                block_hash: [1; 32],
+                block_id: 1,
+                timestamp: 1000,
+            }])
+            .await
+            .unwrap();
+        let response = warp::test::request().path(&path).reply(&route).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // block file removed without going through the storage
+        io_handler
+            .remove_value(generate_block_filename(
+                &io_handler.get_block_dir(),
+                1000,
+                &[3; 32],
+            ))
+            .await
+            .unwrap();
+        let response = warp::test::request()
+            .path(&("/block/".to_string() + hex::encode([3; 32]).as_str()))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // block directory is not listed again for the unknown blocks
+        let path = "/block/".to_string() + hex::encode([2; 32]).as_str();
+        let response = warp::test::request().path(&path).reply(&route).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        io_handler
+            .write_value(
+                generate_block_filename(&io_handler.get_block_dir(), 1000, &[2; 32]),
+                add_block_file_header(block_buffer.clone()),
+            )
+            .await
+            .unwrap();
+        let response = warp::test::request().path(&path).reply(&route).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
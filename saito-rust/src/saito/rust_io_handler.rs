@@ -22,14 +22,6 @@ use crate::IoEvent;
 
 lazy_static! {
     pub static ref SHARED_CONTEXT: Mutex<IoContext> = Mutex::new(IoContext::new());
-    pub static ref BLOCKS_DIR_PATH: String = configure_storage();
-}
-pub fn configure_storage() -> String {
-    if cfg!(test) {
-        String::from("./data/test/blocks/")
-    } else {
-        String::from("./data/blocks/")
-    }
 }
 
 pub enum FutureState {
@@ -41,11 +33,17 @@ pub enum FutureState {
 pub struct RustIOHandler {
     sender: Sender<IoEvent>,
     handler_id: u8,
+    // data directory from the configs, with a trailing separator
+    data_dir: String,
 }
 
 impl RustIOHandler {
-    pub fn new(sender: Sender<IoEvent>, handler_id: u8) -> RustIOHandler {
-        RustIOHandler { sender, handler_id }
+    pub fn new(sender: Sender<IoEvent>, handler_id: u8, data_dir: String) -> RustIOHandler {
+        RustIOHandler {
+            sender,
+            handler_id,
+            data_dir,
+        }
     }
 
     // TODO : delete this if not required
@@ -111,7 +109,12 @@ impl InterfaceIO for RustIOHandler {
     }
 
     async fn disconnect_from_peer(&mut self, peer_index: u64) -> Result<(), Error> {
-        todo!()
+        debug!("disconnecting from peer : {:?}", peer_index);
+        let event = IoEvent::new(NetworkEvent::DisconnectFromPeer { peer_index });
+
+        self.sender.send(event).await.unwrap();
+
+        Ok(())
     }
 
     async fn fetch_block_from_peer(
@@ -135,7 +138,7 @@ impl InterfaceIO for RustIOHandler {
         Ok(())
     }
 
-    async fn write_value(&mut self, key: String, value: Vec<u8>) -> Result<(), Error> {
+    async fn write_value(&self, key: String, value: Vec<u8>) -> Result<(), Error> {
         debug!("writing value to disk : {:?}", key);
         let filename = key.as_str();
         let path = Path::new(filename);
@@ -158,16 +161,20 @@ impl InterfaceIO for RustIOHandler {
     }
 
     async fn read_value(&self, key: String) -> Result<Vec<u8>, Error> {
-        let result = File::open(key).await;
+        let result = File::open(&key).await;
         if result.is_err() {
-            todo!()
+            let error = result.err().unwrap();
+            debug!("failed opening file : {:?} : {:?}", key, error);
+            return Err(error);
         }
         let mut file = result.unwrap();
         let mut encoded = Vec::<u8>::new();
 
         let result = file.read_to_end(&mut encoded).await;
         if result.is_err() {
-            todo!()
+            let error = result.err().unwrap();
+            debug!("failed reading file : {:?} : {:?}", key, error);
+            return Err(error);
         }
         Ok(encoded)
     }
@@ -214,7 +221,11 @@ impl InterfaceIO for RustIOHandler {
     }
 
     fn get_block_dir(&self) -> String {
-        BLOCKS_DIR_PATH.to_string()
+        self.data_dir.clone() + "blocks/"
+    }
+
+    fn get_data_dir(&self) -> String {
+        self.data_dir.clone()
     }
 }
 
@@ -227,7 +238,7 @@ mod tests {
     #[tokio::test]
     async fn test_write_value() {
         let (sender, mut _receiver) = tokio::sync::mpsc::channel(10);
-        let mut io_handler = RustIOHandler::new(sender, 0);
+        let io_handler = RustIOHandler::new(sender, 0, "./data/test/".to_string());
 
         let result = io_handler
             .write_value("./data/test/KEY".to_string(), [1, 2, 3, 4].to_vec())
@@ -242,7 +253,7 @@ mod tests {
     #[tokio::test]
     async fn file_exists_success() {
         let (sender, mut _receiver) = tokio::sync::mpsc::channel(10);
-        let mut io_handler = RustIOHandler::new(sender, 0);
+        let io_handler = RustIOHandler::new(sender, 0, "./data/test/".to_string());
         let path = String::from("src/test/test_data/config_handler_tests.json");
 
         let result = io_handler.is_existing_file(path).await;
@@ -252,7 +263,7 @@ mod tests {
     #[tokio::test]
     async fn file_exists_fail() {
         let (sender, mut _receiver) = tokio::sync::mpsc::channel(10);
-        let mut io_handler = RustIOHandler::new(sender, 0);
+        let io_handler = RustIOHandler::new(sender, 0, "./data/test/".to_string());
         let path = String::from("badfilename.json");
 
         let result = io_handler.is_existing_file(path).await;
@@ -38,7 +38,7 @@ mod tests {
             wallet_lock.clone(),
             sender_miner.clone(),
         );
-        test_manager.storage.write_block_to_disk(&mut block).await;
+        test_manager.storage.write_block_to_disk(&block).await;
 
         assert_eq!(block.transactions.len(), 5);
         assert_eq!(block.get_block_type(), BlockType::Full);
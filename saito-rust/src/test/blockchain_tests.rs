@@ -1,10 +1,18 @@
 #[cfg(test)]
 mod tests {
+    use crate::saito::time_keeper::TimeKeeper;
+    use crate::test::test_io_handler::TestIOHandler;
     use crate::test::test_manager::{create_timestamp, TestManager};
     use crate::IoEvent;
     use log::info;
+    use saito_core::common::process_event::ProcessEvent;
+    use saito_core::core::consensus_event_processor::ConsensusEventProcessor;
     use saito_core::core::data::blockchain::Blockchain;
-    use saito_core::core::data::storage::Storage;
+    use saito_core::core::data::configuration::{Configuration, PeerConfig, SYNC_TYPE_LITE};
+    use saito_core::core::data::network::Network;
+    use saito_core::core::data::storage::{
+        is_legacy_block_file, verify_block_file, Storage, BLOCK_FILE_HEADER_SIZE,
+    };
     use saito_core::core::data::wallet::Wallet;
     use std::sync::Arc;
     use tokio::sync::RwLock;
@@ -546,4 +554,566 @@ mod tests {
             }
         }
     }
+
+    /// Restoring the blockchain from the block index, and adding the stored blocks again when the index is missing
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn load_blocks_from_block_index_test() {
+        TestManager::clear_data_folder().await;
+        let wallet_lock1 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock1 = Arc::new(RwLock::new(Blockchain::new(wallet_lock1.clone())));
+        let (sender_miner, _receiver_miner) = tokio::sync::mpsc::channel(1000);
+        let mut test_manager1 = TestManager::new(
+            blockchain_lock1.clone(),
+            wallet_lock1.clone(),
+            sender_miner.clone(),
+        );
+
+        // 10 block chain, replaced by a 15 block chain forking at block 5
+        test_manager1.generate_blockchain(5, [0; 32]).await;
+        let block5_hash = blockchain_lock1.read().await.get_latest_block_hash();
+        let block10_hash = test_manager1.generate_blockchain(5, block5_hash).await;
+        let block15_hash = test_manager1.generate_blockchain(10, block5_hash).await;
+
+        let entries = test_manager1
+            .storage
+            .backend
+            .load_block_index()
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 20);
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|entry| entry.in_longest_chain)
+                .count(),
+            15
+        );
+        assert!(
+            !entries
+                .iter()
+                .find(|entry| entry.block_hash == block10_hash)
+                .unwrap()
+                .in_longest_chain
+        );
+
+        for from_index in [true, false] {
+            if !from_index {
+                tokio::fs::remove_file("data/metadata/block_index")
+                    .await
+                    .unwrap();
+            }
+            let wallet_lock2 = Arc::new(RwLock::new(Wallet::new()));
+            let blockchain_lock2 = Arc::new(RwLock::new(Blockchain::new(wallet_lock2.clone())));
+            let mut test_manager2 = TestManager::new(
+                blockchain_lock2.clone(),
+                wallet_lock2.clone(),
+                sender_miner.clone(),
+            );
+            test_manager2
+                .storage
+                .load_blocks_from_disk(
+                    blockchain_lock2.clone(),
+                    &test_manager2.network,
+                    test_manager2.sender_to_miner.clone(),
+                )
+                .await;
+
+            let blockchain1 = blockchain_lock1.read().await;
+            let blockchain2 = blockchain_lock2.read().await;
+            assert_eq!(blockchain2.get_latest_block_hash(), block15_hash);
+            assert_eq!(blockchain2.get_latest_block_id(), 15);
+            assert!(blockchain2.get_block(&block10_hash).await.is_some());
+            for block_id in 1..=15 {
+                assert_eq!(
+                    blockchain2
+                        .blockring
+                        .get_longest_chain_block_hash_by_block_id(block_id),
+                    blockchain1
+                        .blockring
+                        .get_longest_chain_block_hash_by_block_id(block_id)
+                );
+            }
+            let spendable_slips = |blockchain: &Blockchain| {
+                let mut keys: Vec<_> = blockchain
+                    .utxoset
+                    .iter()
+                    .filter(|(_, spendable)| **spendable)
+                    .map(|(key, _)| *key)
+                    .collect();
+                keys.sort();
+                keys
+            };
+            assert_eq!(spendable_slips(&blockchain2), spendable_slips(&blockchain1));
+        }
+
+        // index is written again while the blocks are added
+        let entries = test_manager1
+            .storage
+            .backend
+            .load_block_index()
+            .await
+            .unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|entry| entry.in_longest_chain)
+                .count(),
+            15
+        );
+    }
+
+    /// Restoring the utxo set from the snapshot taken at the latest genesis period boundary
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn load_blocks_from_utxo_snapshot_test() {
+        TestManager::clear_data_folder().await;
+        let wallet_lock1 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock1 = Arc::new(RwLock::new(Blockchain::new(wallet_lock1.clone())));
+        let (sender_miner, _receiver_miner) = tokio::sync::mpsc::channel(1000);
+        let mut test_manager1 = TestManager::new(
+            blockchain_lock1.clone(),
+            wallet_lock1.clone(),
+            sender_miner.clone(),
+        );
+        let current_timestamp = create_timestamp();
+        test_manager1
+            .add_block(current_timestamp, 10, 0, false, vec![])
+            .await;
+        let mut latest_block_hash = [0; 32];
+        for i in 1..35 {
+            latest_block_hash = test_manager1
+                .add_block(current_timestamp + i * 120000, 0, 1, true, vec![])
+                .await;
+        }
+
+        let snapshot = test_manager1.storage.load_utxo_snapshot().await.unwrap();
+        assert_eq!(snapshot.block_id, 30);
+        {
+            let blockchain1 = blockchain_lock1.read().await;
+            assert_eq!(
+                snapshot.block_hash,
+                blockchain1
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(30)
+            );
+            // blocks before the genesis period are purged
+            assert!(blockchain1.get_block(&snapshot.block_hash).await.is_some());
+            assert_eq!(blockchain1.blocks.len(), 20);
+        }
+
+        for with_snapshot in [true, false] {
+            if !with_snapshot {
+                tokio::fs::remove_file("data/metadata/utxo_snapshot")
+                    .await
+                    .unwrap();
+            }
+            let wallet_lock2 = Arc::new(RwLock::new(Wallet::new()));
+            let blockchain_lock2 = Arc::new(RwLock::new(Blockchain::new(wallet_lock2.clone())));
+            let mut test_manager2 = TestManager::new(
+                blockchain_lock2.clone(),
+                wallet_lock2.clone(),
+                sender_miner.clone(),
+            );
+            test_manager2
+                .storage
+                .load_blocks_from_disk(
+                    blockchain_lock2.clone(),
+                    &test_manager2.network,
+                    test_manager2.sender_to_miner.clone(),
+                )
+                .await;
+
+            let blockchain1 = blockchain_lock1.read().await;
+            let blockchain2 = blockchain_lock2.read().await;
+            assert_eq!(blockchain2.get_latest_block_hash(), latest_block_hash);
+            assert_eq!(blockchain2.blocks.len(), 20);
+            if with_snapshot {
+                assert!(!blockchain1.utxoset.is_empty());
+                // comparing the maps directly, since assert_eq prints the whole utxo sets
+                assert!(blockchain2.utxoset == blockchain1.utxoset);
+                assert_eq!(blockchain2.staking.stakers, blockchain1.staking.stakers);
+            }
+        }
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn load_blocks_with_corrupted_block_test() {
+        TestManager::clear_data_folder().await;
+        let wallet_lock1 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock1 = Arc::new(RwLock::new(Blockchain::new(wallet_lock1.clone())));
+        let (sender_miner, _receiver_miner) = tokio::sync::mpsc::channel(100);
+        let mut test_manager1 = TestManager::new(
+            blockchain_lock1.clone(),
+            wallet_lock1.clone(),
+            sender_miner.clone(),
+        );
+        let block10_hash = test_manager1.generate_blockchain(10, [0; 32]).await;
+        let block9_hash = blockchain_lock1
+            .read()
+            .await
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(9);
+
+        // truncate the latest block
+        let file_name = test_manager1
+            .storage
+            .io_interface
+            .load_block_file_list()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|file_name| file_name.contains(&hex::encode(block10_hash)))
+            .unwrap();
+        let buffer = tokio::fs::read("data/blocks/".to_string() + file_name.as_str())
+            .await
+            .unwrap();
+        tokio::fs::write(
+            "data/blocks/".to_string() + file_name.as_str(),
+            &buffer[0..buffer.len() - 10],
+        )
+        .await
+        .unwrap();
+
+        let wallet_lock2 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock2 = Arc::new(RwLock::new(Blockchain::new(wallet_lock2.clone())));
+        let mut test_manager2 = TestManager::new(
+            blockchain_lock2.clone(),
+            wallet_lock2.clone(),
+            sender_miner.clone(),
+        );
+        test_manager2
+            .storage
+            .load_blocks_from_disk(
+                blockchain_lock2.clone(),
+                &test_manager2.network,
+                test_manager2.sender_to_miner.clone(),
+            )
+            .await;
+
+        {
+            let blockchain2 = blockchain_lock2.read().await;
+            assert_eq!(blockchain2.get_latest_block_id(), 9);
+            assert_eq!(blockchain2.get_latest_block_hash(), block9_hash);
+            assert!(blockchain2.get_block(&block10_hash).await.is_none());
+        }
+        assert!(
+            !test_manager2
+                .storage
+                .file_exists(&("data/blocks/".to_string() + file_name.as_str()))
+                .await
+        );
+        assert!(
+            test_manager2
+                .storage
+                .file_exists(&("data/quarantine/".to_string() + file_name.as_str()))
+                .await
+        );
+        assert_eq!(
+            test_manager2
+                .storage
+                .backend
+                .load_block_index()
+                .await
+                .unwrap()
+                .len(),
+            9
+        );
+
+        // lost block is fetched from the peers connecting later
+        let mut peers = test_manager2.peers.write().await;
+        peers.block_downloads.add_peer_for_lost_blocks(1);
+        assert!(peers.block_downloads.is_downloading(&block10_hash));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn load_blocks_with_missing_indexed_block_test() {
+        TestManager::clear_data_folder().await;
+        let wallet_lock1 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock1 = Arc::new(RwLock::new(Blockchain::new(wallet_lock1.clone())));
+        let (sender_miner, _receiver_miner) = tokio::sync::mpsc::channel(100);
+        let mut test_manager1 = TestManager::new(
+            blockchain_lock1.clone(),
+            wallet_lock1.clone(),
+            sender_miner.clone(),
+        );
+        let block10_hash = test_manager1.generate_blockchain(10, [0; 32]).await;
+        let block9_hash = blockchain_lock1
+            .read()
+            .await
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(9);
+
+        // remove the latest block while it's still in the block index
+        let file_name = test_manager1
+            .storage
+            .io_interface
+            .load_block_file_list()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|file_name| file_name.contains(&hex::encode(block10_hash)))
+            .unwrap();
+        tokio::fs::remove_file("data/blocks/".to_string() + file_name.as_str())
+            .await
+            .unwrap();
+        // file index of the running node still has the removed file
+        assert_eq!(
+            test_manager1
+                .storage
+                .load_block_from_disk(&block10_hash)
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            std::io::ErrorKind::NotFound
+        );
+        assert_eq!(
+            test_manager1
+                .storage
+                .backend
+                .load_block_index()
+                .await
+                .unwrap()
+                .len(),
+            10
+        );
+
+        let wallet_lock2 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock2 = Arc::new(RwLock::new(Blockchain::new(wallet_lock2.clone())));
+        let mut test_manager2 = TestManager::new(
+            blockchain_lock2.clone(),
+            wallet_lock2.clone(),
+            sender_miner.clone(),
+        );
+        test_manager2
+            .storage
+            .load_blocks_from_disk(
+                blockchain_lock2.clone(),
+                &test_manager2.network,
+                test_manager2.sender_to_miner.clone(),
+            )
+            .await;
+
+        {
+            let blockchain2 = blockchain_lock2.read().await;
+            assert_eq!(blockchain2.get_latest_block_id(), 9);
+            assert_eq!(blockchain2.get_latest_block_hash(), block9_hash);
+            assert!(blockchain2.get_block(&block10_hash).await.is_none());
+        }
+        // block index is rebuilt from the remaining blocks
+        assert_eq!(
+            test_manager2
+                .storage
+                .backend
+                .load_block_index()
+                .await
+                .unwrap()
+                .len(),
+            9
+        );
+
+        // missing block is fetched from the peers
+        let mut peers = test_manager2.peers.write().await;
+        peers.block_downloads.add_peer_for_lost_blocks(1);
+        assert!(peers.block_downloads.is_downloading(&block10_hash));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn load_blocks_with_unreadable_block_test() {
+        TestManager::clear_data_folder().await;
+        let wallet_lock1 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock1 = Arc::new(RwLock::new(Blockchain::new(wallet_lock1.clone())));
+        let (sender_miner, _receiver_miner) = tokio::sync::mpsc::channel(100);
+        let mut test_manager1 = TestManager::new(
+            blockchain_lock1.clone(),
+            wallet_lock1.clone(),
+            sender_miner.clone(),
+        );
+        let block10_hash = test_manager1.generate_blockchain(10, [0; 32]).await;
+        let block9_hash = blockchain_lock1
+            .read()
+            .await
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(9);
+
+        // replace the latest block with a link to a directory, which can be opened but not read
+        let file_name = test_manager1
+            .storage
+            .io_interface
+            .load_block_file_list()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|file_name| file_name.contains(&hex::encode(block10_hash)))
+            .unwrap();
+        let file_path = "data/blocks/".to_string() + file_name.as_str();
+        tokio::fs::remove_file(&file_path).await.unwrap();
+        tokio::fs::create_dir_all("data/blocks/unreadable")
+            .await
+            .unwrap();
+        std::os::unix::fs::symlink("unreadable", &file_path).unwrap();
+        assert!(tokio::fs::read(&file_path).await.is_err());
+
+        let wallet_lock2 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock2 = Arc::new(RwLock::new(Blockchain::new(wallet_lock2.clone())));
+        let mut test_manager2 = TestManager::new(
+            blockchain_lock2.clone(),
+            wallet_lock2.clone(),
+            sender_miner.clone(),
+        );
+        test_manager2
+            .storage
+            .load_blocks_from_disk(
+                blockchain_lock2.clone(),
+                &test_manager2.network,
+                test_manager2.sender_to_miner.clone(),
+            )
+            .await;
+
+        {
+            let blockchain2 = blockchain_lock2.read().await;
+            assert_eq!(blockchain2.get_latest_block_id(), 9);
+            assert_eq!(blockchain2.get_latest_block_hash(), block9_hash);
+            assert!(blockchain2.get_block(&block10_hash).await.is_none());
+        }
+        // unreadable block is removed from the stored blocks since it can't be copied to the quarantine
+        assert!(tokio::fs::symlink_metadata(&file_path).await.is_err());
+        assert_eq!(
+            test_manager2
+                .storage
+                .backend
+                .load_block_index()
+                .await
+                .unwrap()
+                .len(),
+            9
+        );
+        tokio::fs::remove_dir("data/blocks/unreadable")
+            .await
+            .unwrap();
+
+        let mut peers = test_manager2.peers.write().await;
+        peers.block_downloads.add_peer_for_lost_blocks(1);
+        assert!(peers.block_downloads.is_downloading(&block10_hash));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn load_blocks_from_legacy_block_files_test() {
+        TestManager::clear_data_folder().await;
+        let wallet_lock1 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock1 = Arc::new(RwLock::new(Blockchain::new(wallet_lock1.clone())));
+        let (sender_miner, _receiver_miner) = tokio::sync::mpsc::channel(100);
+        let mut test_manager1 = TestManager::new(
+            blockchain_lock1.clone(),
+            wallet_lock1.clone(),
+            sender_miner.clone(),
+        );
+        let block10_hash = test_manager1.generate_blockchain(10, [0; 32]).await;
+
+        // block files as written before the header was added, and no block index
+        let file_names = test_manager1
+            .storage
+            .io_interface
+            .load_block_file_list()
+            .await
+            .unwrap();
+        assert_eq!(file_names.len(), 10);
+        for file_name in file_names.iter() {
+            let file_path = "data/blocks/".to_string() + file_name.as_str();
+            let buffer = tokio::fs::read(&file_path).await.unwrap();
+            tokio::fs::write(&file_path, &buffer[BLOCK_FILE_HEADER_SIZE..])
+                .await
+                .unwrap();
+        }
+        tokio::fs::remove_file("data/metadata/block_index")
+            .await
+            .unwrap();
+
+        let wallet_lock2 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock2 = Arc::new(RwLock::new(Blockchain::new(wallet_lock2.clone())));
+        let mut test_manager2 = TestManager::new(
+            blockchain_lock2.clone(),
+            wallet_lock2.clone(),
+            sender_miner.clone(),
+        );
+        test_manager2
+            .storage
+            .load_blocks_from_disk(
+                blockchain_lock2.clone(),
+                &test_manager2.network,
+                test_manager2.sender_to_miner.clone(),
+            )
+            .await;
+
+        {
+            let blockchain2 = blockchain_lock2.read().await;
+            assert_eq!(blockchain2.get_latest_block_id(), 10);
+            assert_eq!(blockchain2.get_latest_block_hash(), block10_hash);
+        }
+        // nothing is quarantined, and the files are written again with the header
+        assert!(tokio::fs::read_dir("data/quarantine").await.is_err());
+        for file_name in file_names.iter() {
+            let file_path = "data/blocks/".to_string() + file_name.as_str();
+            let buffer = tokio::fs::read(&file_path).await.unwrap();
+            assert!(!is_legacy_block_file(&buffer));
+            assert!(verify_block_file(&buffer).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn lite_node_skips_loading_blocks_test() {
+        TestManager::clear_data_folder().await;
+        let wallet_lock1 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock1 = Arc::new(RwLock::new(Blockchain::new(wallet_lock1.clone())));
+        let (sender_miner, _receiver_miner) = tokio::sync::mpsc::channel(100);
+        let mut test_manager1 = TestManager::new(
+            blockchain_lock1.clone(),
+            wallet_lock1.clone(),
+            sender_miner.clone(),
+        );
+        test_manager1.generate_blockchain(3, [0; 32]).await;
+
+        let mut configs = Configuration::new();
+        configs.peers.push(PeerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 12101,
+            protocol: "http".to_string(),
+            synctype: SYNC_TYPE_LITE.to_string(),
+        });
+        let wallet_lock2 = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock2 = Arc::new(RwLock::new(Blockchain::new(wallet_lock2.clone())));
+        let test_manager2 = TestManager::new(
+            blockchain_lock2.clone(),
+            wallet_lock2.clone(),
+            sender_miner.clone(),
+        );
+        let (sender_router, _receiver_router) = tokio::sync::mpsc::channel(10);
+        let mut consensus_event_processor = ConsensusEventProcessor {
+            mempool: test_manager2.mempool_lock.clone(),
+            blockchain: blockchain_lock2.clone(),
+            wallet: wallet_lock2.clone(),
+            sender_to_router: sender_router,
+            sender_to_miner: sender_miner,
+            block_producing_timer: 0,
+            tx_producing_timer: 0,
+            generate_test_tx: false,
+            time_keeper: Box::new(TimeKeeper {}),
+            network: Network::new(Box::new(TestIOHandler::new()), test_manager2.peers.clone()),
+            storage: Storage::new(Box::new(TestIOHandler::new())),
+            block_reconstructions: Default::default(),
+            configs: Arc::new(RwLock::new(configs)),
+        };
+        consensus_event_processor.on_init().await;
+
+        let blockchain2 = blockchain_lock2.read().await;
+        assert_eq!(blockchain2.get_latest_block_id(), 0);
+        assert!(blockchain2.blocks.is_empty());
+    }
 }
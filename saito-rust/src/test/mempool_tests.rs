@@ -1,7 +1,13 @@
 #[cfg(test)]
 mod tests {
+    use crate::saito::time_keeper::TimeKeeper;
+    use crate::test::test_io_handler::TestIOHandler;
     use crate::test::test_manager::{create_timestamp, TestManager};
+    use saito_core::core::consensus_event_processor::ConsensusEventProcessor;
     use saito_core::core::data::blockchain::Blockchain;
+    use saito_core::core::data::configuration::Configuration;
+    use saito_core::core::data::network::Network;
+    use saito_core::core::data::storage::Storage;
 
     use saito_core::core::data::wallet::Wallet;
     use std::sync::Arc;
@@ -45,4 +51,91 @@ mod tests {
         // check chain consistence
         test_manager.check_blockchain().await;
     }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn relayed_transaction_test() {
+        TestManager::clear_data_folder().await;
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (sender_miner, _receiver_miner) = tokio::sync::mpsc::channel(10);
+        let (sender_router, _receiver_router) = tokio::sync::mpsc::channel(10);
+        let mut test_manager = TestManager::new(
+            blockchain_lock.clone(),
+            wallet_lock.clone(),
+            sender_miner.clone(),
+        );
+        test_manager
+            .add_block(create_timestamp(), 3, 0, false, vec![])
+            .await;
+        let mut consensus_event_processor = ConsensusEventProcessor {
+            mempool: test_manager.mempool_lock.clone(),
+            blockchain: blockchain_lock.clone(),
+            wallet: wallet_lock.clone(),
+            sender_to_router: sender_router,
+            sender_to_miner: sender_miner,
+            block_producing_timer: 0,
+            tx_producing_timer: 0,
+            generate_test_tx: false,
+            time_keeper: Box::new(TimeKeeper {}),
+            network: Network::new(Box::new(TestIOHandler::new()), test_manager.peers.clone()),
+            storage: Storage::new(Box::new(TestIOHandler::new())),
+            block_reconstructions: Default::default(),
+            configs: Arc::new(RwLock::new(Configuration::new())),
+        };
+
+        let transaction = test_manager.generate_transaction(1000, 100).await;
+        let transaction_hash = transaction.get_hash_for_signature().unwrap();
+        let inputs: Vec<_> = transaction
+            .get_inputs()
+            .iter()
+            .map(|input| input.get_utxoset_key())
+            .collect();
+
+        // inputs are not known yet, so the transaction is rejected but not remembered
+        {
+            let mut blockchain = blockchain_lock.write().await;
+            for input in inputs.iter() {
+                blockchain.utxoset.remove(input);
+            }
+        }
+        assert!(
+            !consensus_event_processor
+                .process_new_transaction(1, transaction.clone())
+                .await
+        );
+        assert!(!consensus_event_processor
+            .network
+            .is_transaction_seen(&transaction_hash));
+
+        // accepted when relayed again after the inputs are known
+        {
+            let mut blockchain = blockchain_lock.write().await;
+            for input in inputs.iter() {
+                blockchain.utxoset.insert(*input, true);
+            }
+        }
+        assert!(
+            consensus_event_processor
+                .process_new_transaction(2, transaction.clone())
+                .await
+        );
+        assert!(consensus_event_processor
+            .network
+            .is_transaction_seen(&transaction_hash));
+
+        // duplicates are dropped before validating them again
+        test_manager.mempool_lock.write().await.transactions.clear();
+        assert!(
+            !consensus_event_processor
+                .process_new_transaction(3, transaction)
+                .await
+        );
+        assert!(test_manager
+            .mempool_lock
+            .read()
+            .await
+            .transactions
+            .is_empty());
+    }
 }
@@ -2,9 +2,10 @@ mod block_tests;
 mod blockchain_tests;
 mod blockring_tests;
 mod mempool_tests;
+mod network_tests;
 mod staking_tests;
 mod storage_tests;
-mod test_io_handler;
+pub mod test_io_handler;
 pub mod test_manager;
 mod test_setup;
 mod wallet_tests;
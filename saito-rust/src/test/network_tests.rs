@@ -0,0 +1,324 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+
+    use saito_core::core::data::configuration::{Configuration, PeerConfig};
+    use saito_core::core::data::msg::handshake::{
+        MIN_SUPPORTED_PROTOCOL_VERSION, PROTOCOL_VERSION,
+    };
+    use saito_core::core::data::msg::message::Message;
+    use saito_core::core::data::network::Network;
+    use saito_core::core::data::peer_collection::PeerCollection;
+    use saito_core::core::data::wallet::Wallet;
+
+    use crate::test::test_io_handler::TestIOHandler;
+
+    // index of the other node in the peer collections of both nodes
+    const PEER_INDEX: u64 = 1;
+
+    struct TestNode {
+        network: Network,
+        io_handler: TestIOHandler,
+        wallet: Arc<RwLock<Wallet>>,
+        configs: Arc<RwLock<Configuration>>,
+    }
+
+    impl TestNode {
+        fn new() -> TestNode {
+            let io_handler = TestIOHandler::new();
+            TestNode {
+                network: Network::new(
+                    Box::new(io_handler.clone()),
+                    Arc::new(RwLock::new(PeerCollection::new())),
+                ),
+                io_handler,
+                wallet: Arc::new(RwLock::new(Wallet::new())),
+                configs: Arc::new(RwLock::new(Configuration::new())),
+            }
+        }
+        // returns the messages sent to the other node, decrypted if there is a session
+        async fn take_sent_messages(&self, receiver: &TestNode) -> Vec<Message> {
+            let mut messages = vec![];
+            for (_, buffer) in self.io_handler.take_sent_messages() {
+                let buffer = receiver
+                    .network
+                    .decrypt_message(PEER_INDEX, buffer)
+                    .await
+                    .expect("decrypting the message failed");
+                messages.push(Message::deserialize(buffer).expect("decoding the message failed"));
+            }
+            messages
+        }
+    }
+
+    // server accepts the connection from the client and initiates the handshake
+    async fn connect(server: &mut TestNode, client: &mut TestNode) {
+        client
+            .network
+            .handle_new_peer(
+                Some(PeerConfig {
+                    host: "127.0.0.1".to_string(),
+                    port: 12101,
+                    protocol: "http".to_string(),
+                    synctype: "full".to_string(),
+                }),
+                PEER_INDEX,
+                client.wallet.clone(),
+                client.configs.clone(),
+                0,
+            )
+            .await;
+        server
+            .network
+            .handle_new_peer(
+                None,
+                PEER_INDEX,
+                server.wallet.clone(),
+                server.configs.clone(),
+                0,
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn encrypted_session_after_handshake_test() {
+        let mut server = TestNode::new();
+        let mut client = TestNode::new();
+        connect(&mut server, &mut client).await;
+
+        let mut messages = server.take_sent_messages(&client).await;
+        assert_eq!(messages.len(), 1);
+        let challenge = match messages.remove(0) {
+            Message::HandshakeChallenge(challenge) => challenge,
+            message => panic!("unexpected message : {:?}", message),
+        };
+        client
+            .network
+            .handle_handshake_challenge(
+                PEER_INDEX,
+                challenge,
+                client.wallet.clone(),
+                client.configs.clone(),
+            )
+            .await
+            .expect("handling the challenge failed");
+
+        let mut messages = client.take_sent_messages(&server).await;
+        assert_eq!(messages.len(), 1);
+        let response = match messages.remove(0) {
+            Message::HandshakeResponse(response) => response,
+            message => panic!("unexpected message : {:?}", message),
+        };
+        server
+            .network
+            .handle_handshake_response(
+                PEER_INDEX,
+                response,
+                server.wallet.clone(),
+                server.configs.clone(),
+            )
+            .await
+            .expect("handling the response failed");
+
+        // completion is sent in plaintext and the peers are requested over the new session
+        let mut sent = server.io_handler.take_sent_messages();
+        assert_eq!(sent.len(), 2);
+        let completion = match Message::deserialize(sent.remove(0).1).unwrap() {
+            Message::HandshakeCompletion(completion) => completion,
+            message => panic!("unexpected message : {:?}", message),
+        };
+        let encrypted_request = sent.remove(0).1;
+        assert!(Message::deserialize(encrypted_request.clone()).is_err());
+
+        client
+            .network
+            .handle_handshake_completion(PEER_INDEX, completion, client.wallet.clone())
+            .await
+            .expect("handling the completion failed");
+        let buffer = client
+            .network
+            .decrypt_message(PEER_INDEX, encrypted_request)
+            .await
+            .expect("decrypting the message failed");
+        assert!(matches!(
+            Message::deserialize(buffer).unwrap(),
+            Message::GetPeers
+        ));
+
+        // messages are encrypted in both directions
+        let messages = client.take_sent_messages(&server).await;
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0], Message::GetPeers));
+
+        server
+            .network
+            .send_message(PEER_INDEX, Message::Ping(100).serialize())
+            .await
+            .unwrap();
+        client
+            .network
+            .send_message(PEER_INDEX, Message::Pong(100).serialize())
+            .await
+            .unwrap();
+        let messages = server.take_sent_messages(&client).await;
+        assert!(matches!(messages[..], [Message::Ping(100)]));
+        let messages = client.take_sent_messages(&server).await;
+        assert!(matches!(messages[..], [Message::Pong(100)]));
+
+        // plaintext messages are not accepted once the session is set up
+        let result = server
+            .network
+            .decrypt_message(PEER_INDEX, Message::Ping(200).serialize())
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn message_before_handshake_rejected_test() {
+        let mut server = TestNode::new();
+        let mut client = TestNode::new();
+        connect(&mut server, &mut client).await;
+
+        // handshake messages are accepted in plaintext before the session is set up
+        let mut messages = server.take_sent_messages(&client).await;
+        assert!(matches!(messages.remove(0), Message::HandshakeChallenge(_)));
+
+        for message in [
+            Message::BlockHeaderHash([1; 32]),
+            Message::GetPeers,
+            Message::Ping(100),
+        ] {
+            let result = client
+                .network
+                .decrypt_message(PEER_INDEX, message.serialize())
+                .await;
+            assert_eq!(
+                result.err().unwrap().kind(),
+                std::io::ErrorKind::PermissionDenied
+            );
+        }
+        let result = client.network.decrypt_message(PEER_INDEX, vec![]).await;
+        assert_eq!(
+            result.err().unwrap().kind(),
+            std::io::ErrorKind::PermissionDenied
+        );
+    }
+
+    #[tokio::test]
+    async fn tampered_challenge_version_fails_handshake_test() {
+        let mut server = TestNode::new();
+        let mut client = TestNode::new();
+        connect(&mut server, &mut client).await;
+
+        let mut messages = server.take_sent_messages(&client).await;
+        let mut challenge = match messages.remove(0) {
+            Message::HandshakeChallenge(challenge) => challenge,
+            message => panic!("unexpected message : {:?}", message),
+        };
+        // client signs the changed version of the server, which the server doesn't accept
+        challenge.protocol_version = PROTOCOL_VERSION + 1;
+        client
+            .network
+            .handle_handshake_challenge(
+                PEER_INDEX,
+                challenge,
+                client.wallet.clone(),
+                client.configs.clone(),
+            )
+            .await
+            .expect("handling the challenge failed");
+
+        let mut messages = client.take_sent_messages(&server).await;
+        let response = match messages.remove(0) {
+            Message::HandshakeResponse(response) => response,
+            message => panic!("unexpected message : {:?}", message),
+        };
+        let result = server
+            .network
+            .handle_handshake_response(
+                PEER_INDEX,
+                response,
+                server.wallet.clone(),
+                server.configs.clone(),
+            )
+            .await;
+        assert_eq!(
+            result.err().unwrap().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+    }
+
+    #[tokio::test]
+    async fn tampered_response_version_fails_handshake_test() {
+        let mut server = TestNode::new();
+        let mut client = TestNode::new();
+        connect(&mut server, &mut client).await;
+
+        let mut messages = server.take_sent_messages(&client).await;
+        let challenge = match messages.remove(0) {
+            Message::HandshakeChallenge(challenge) => challenge,
+            message => panic!("unexpected message : {:?}", message),
+        };
+        client
+            .network
+            .handle_handshake_challenge(
+                PEER_INDEX,
+                challenge,
+                client.wallet.clone(),
+                client.configs.clone(),
+            )
+            .await
+            .expect("handling the challenge failed");
+
+        let mut messages = client.take_sent_messages(&server).await;
+        let mut response = match messages.remove(0) {
+            Message::HandshakeResponse(response) => response,
+            message => panic!("unexpected message : {:?}", message),
+        };
+        response.protocol_version = PROTOCOL_VERSION + 1;
+        let result = server
+            .network
+            .handle_handshake_response(
+                PEER_INDEX,
+                response,
+                server.wallet.clone(),
+                server.configs.clone(),
+            )
+            .await;
+        assert_eq!(
+            result.err().unwrap().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+        assert!(server.io_handler.take_sent_messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unsupported_protocol_version_rejected_test() {
+        let mut server = TestNode::new();
+        let mut client = TestNode::new();
+        connect(&mut server, &mut client).await;
+
+        let mut messages = server.take_sent_messages(&client).await;
+        let mut challenge = match messages.remove(0) {
+            Message::HandshakeChallenge(challenge) => challenge,
+            message => panic!("unexpected message : {:?}", message),
+        };
+        challenge.protocol_version = MIN_SUPPORTED_PROTOCOL_VERSION - 1;
+        let result = client
+            .network
+            .handle_handshake_challenge(
+                PEER_INDEX,
+                challenge,
+                client.wallet.clone(),
+                client.configs.clone(),
+            )
+            .await;
+        assert_eq!(
+            result.err().unwrap().kind(),
+            std::io::ErrorKind::Unsupported
+        );
+        assert!(client.io_handler.take_sent_messages().is_empty());
+    }
+}
@@ -1,13 +1,24 @@
 #[cfg(test)]
 mod tests {
+    use std::io::{Error, ErrorKind};
     use std::sync::Arc;
 
-    use tokio::sync::RwLock;
+    use async_trait::async_trait;
+    use tokio::sync::{Mutex, RwLock};
 
+    use saito_core::common::defs::SaitoHash;
+
+    use saito_core::core::data::block::{Block, BlockType};
+    use saito_core::core::data::block_index::BlockIndexEntry;
     use saito_core::core::data::blockchain::{Blockchain, MAX_TOKEN_SUPPLY};
+    use saito_core::core::data::lite_chain::LiteChain;
     use saito_core::core::data::storage::Storage;
+    use saito_core::core::data::storage_backend::{
+        FileStorageBackend, StorageBackend, StorageOperation,
+    };
     use saito_core::core::data::wallet::Wallet;
 
+    use crate::test::test_io_handler::TestIOHandler;
     use crate::test::test_manager;
     use crate::test::test_manager::{create_timestamp, TestManager};
 
@@ -72,15 +83,317 @@ mod tests {
 
         let current_timestamp = create_timestamp();
 
-        let mut block = test_manager
+        let block = test_manager
             .generate_block_and_metadata([0; 32], current_timestamp, 0, 1, false, vec![])
             .await;
 
-        let filename = test_manager.storage.write_block_to_disk(&mut block).await;
-        log::trace!("block written to file : {}", filename);
-        let retrieved_block = test_manager.storage.load_block_from_disk(filename).await;
+        test_manager.storage.write_block_to_disk(&block).await;
+        let retrieved_block = test_manager
+            .storage
+            .load_block_from_disk(&block.get_hash())
+            .await;
 
         assert!(retrieved_block.is_ok());
         assert_eq!(block.get_hash(), retrieved_block.unwrap().get_hash());
     }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn read_missing_file_test() {
+        TestManager::clear_data_folder().await;
+        let storage = Storage::new(Box::new(TestIOHandler::new()));
+        let result = storage.read("data/blocks/missing.block").await;
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::NotFound);
+        let result = storage.load_block_from_disk(&[1; 32]).await;
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn read_block_buffer_test() {
+        TestManager::clear_data_folder().await;
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (sender_miner, _receiver_miner) = tokio::sync::mpsc::channel(10);
+        let mut test_manager = TestManager::new(
+            blockchain_lock.clone(),
+            wallet_lock.clone(),
+            sender_miner.clone(),
+        );
+        let block = test_manager
+            .generate_block_and_metadata([0; 32], create_timestamp(), 3, 1, false, vec![])
+            .await;
+        test_manager.storage.write_block_to_disk(&block).await;
+
+        let buffer = test_manager
+            .storage
+            .read_block_buffer(&block.get_hash(), BlockType::Full)
+            .await
+            .unwrap();
+        assert_eq!(buffer, block.serialize_for_net(BlockType::Full));
+        let buffer = test_manager
+            .storage
+            .read_block_buffer(&block.get_hash(), BlockType::Header)
+            .await
+            .unwrap();
+        assert_eq!(buffer, block.serialize_for_net(BlockType::Header));
+
+        assert_eq!(
+            test_manager
+                .storage
+                .read_block_buffer(&block.get_hash(), BlockType::Pruned)
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            test_manager
+                .storage
+                .read_block_buffer(&[1; 32], BlockType::Full)
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn file_storage_backend_test() {
+        TestManager::clear_data_folder().await;
+        let backend = FileStorageBackend::new(Arc::new(TestIOHandler::new()));
+        backend
+            .write_batch(vec![
+                StorageOperation::PutBlock {
+                    block_hash: [1; 32],
+                    block_id: 1,
+                    timestamp: 1000,
+                    buffer: vec![1, 2, 3],
+                },
+                StorageOperation::PutMetadata {
+                    key: "test".to_string(),
+                    value: vec![4, 5],
+                },
+                StorageOperation::PutBlockIndexEntry(BlockIndexEntry {
+                    block_hash: [1; 32],
+                    block_id: 1,
+                    previous_block_hash: [0; 32],
+                    timestamp: 1000,
+                    in_longest_chain: true,
+                }),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(backend.read_block(&[1; 32]).await.unwrap(), vec![1, 2, 3]);
+        assert_eq!(
+            backend.get_block_hashes_by_id(1).await.unwrap(),
+            vec![[1; 32]]
+        );
+        assert_eq!(
+            backend.read_metadata("test").await.unwrap(),
+            Some(vec![4, 5])
+        );
+
+        // blocks written by another instance are found from the block directory
+        let other_backend = FileStorageBackend::new(Arc::new(TestIOHandler::new()));
+        assert_eq!(
+            other_backend.read_block(&[1; 32]).await.unwrap(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            other_backend.get_block_hashes().await.unwrap(),
+            vec![[1; 32]]
+        );
+        // height index of another instance is loaded with the block index
+        let other_backend = FileStorageBackend::new(Arc::new(TestIOHandler::new()));
+        let entries = other_backend.load_block_index().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].block_hash, [1; 32]);
+        assert!(entries[0].in_longest_chain);
+        assert_eq!(
+            other_backend.get_block_hashes_by_id(1).await.unwrap(),
+            vec![[1; 32]]
+        );
+
+        backend
+            .write_batch(vec![
+                StorageOperation::DeleteBlock {
+                    block_hash: [1; 32],
+                    block_id: 1,
+                    timestamp: 1000,
+                },
+                StorageOperation::DeleteMetadata {
+                    key: "test".to_string(),
+                },
+            ])
+            .await
+            .unwrap();
+        assert!(backend.read_block(&[1; 32]).await.is_err());
+        assert!(backend.get_block_hashes_by_id(1).await.unwrap().is_empty());
+        assert!(backend.read_metadata("test").await.unwrap().is_none());
+        assert!(backend.load_block_index().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn lite_chain_storage_test() {
+        TestManager::clear_data_folder().await;
+        let storage = Storage::new(Box::new(TestIOHandler::new()));
+        assert!(storage.load_lite_chain().await.is_none());
+
+        let mut wallet = Wallet::new();
+        let mut lite_chain = LiteChain::new();
+        let mut previous_block_hash = [0; 32];
+        for id in 1..4 {
+            let mut block = Block::new();
+            block.set_id(id);
+            block.set_timestamp(id * 1000);
+            block.set_previous_block_hash(previous_block_hash);
+            previous_block_hash = block.generate_hashes();
+            assert!(lite_chain.add_block(block, &mut wallet));
+        }
+        storage.write_lite_chain(&lite_chain).await;
+
+        // lite chain is read back by another storage
+        let storage = Storage::new(Box::new(TestIOHandler::new()));
+        let loaded_chain = storage.load_lite_chain().await.unwrap();
+        assert_eq!(loaded_chain.get_latest_block_id(), 3);
+        assert_eq!(loaded_chain.get_latest_block_hash(), previous_block_hash);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn file_storage_backend_quarantine_test() {
+        TestManager::clear_data_folder().await;
+        let backend = FileStorageBackend::new(Arc::new(TestIOHandler::new()));
+        backend
+            .write_batch(vec![
+                StorageOperation::PutBlock {
+                    block_hash: [1; 32],
+                    block_id: 1,
+                    timestamp: 1000,
+                    buffer: vec![1, 2, 3],
+                },
+                StorageOperation::PutBlockIndexEntry(BlockIndexEntry {
+                    block_hash: [1; 32],
+                    block_id: 1,
+                    previous_block_hash: [0; 32],
+                    timestamp: 1000,
+                    in_longest_chain: true,
+                }),
+            ])
+            .await
+            .unwrap();
+
+        backend.quarantine_block(&[1; 32]).await.unwrap();
+        assert!(backend.read_block(&[1; 32]).await.is_err());
+        assert!(backend.get_block_hashes().await.unwrap().is_empty());
+        assert!(backend.get_block_hashes_by_id(1).await.unwrap().is_empty());
+        assert!(backend.load_block_index().await.unwrap().is_empty());
+        let quarantined = tokio::fs::read(
+            "data/quarantine/1000-".to_string() + hex::encode([1; 32]).as_str() + ".block",
+        )
+        .await
+        .unwrap();
+        assert_eq!(quarantined, vec![1, 2, 3]);
+
+        assert!(backend.quarantine_block(&[2; 32]).await.is_err());
+    }
+
+    /// Keeps the batches written to the file backend
+    struct RecordingStorageBackend {
+        backend: FileStorageBackend,
+        batches: Mutex<Vec<Vec<StorageOperation>>>,
+    }
+
+    #[async_trait]
+    impl StorageBackend for RecordingStorageBackend {
+        async fn write_batch(&self, operations: Vec<StorageOperation>) -> Result<(), Error> {
+            self.batches.lock().await.push(operations.clone());
+            self.backend.write_batch(operations).await
+        }
+        async fn read_block(&self, block_hash: &SaitoHash) -> Result<Vec<u8>, Error> {
+            self.backend.read_block(block_hash).await
+        }
+        async fn get_block_hashes_by_id(&self, block_id: u64) -> Result<Vec<SaitoHash>, Error> {
+            self.backend.get_block_hashes_by_id(block_id).await
+        }
+        async fn get_block_hashes(&self) -> Result<Vec<SaitoHash>, Error> {
+            self.backend.get_block_hashes().await
+        }
+        async fn load_block_index(&self) -> Result<Vec<BlockIndexEntry>, Error> {
+            self.backend.load_block_index().await
+        }
+        async fn quarantine_block(&self, block_hash: &SaitoHash) -> Result<(), Error> {
+            self.backend.quarantine_block(block_hash).await
+        }
+        async fn read_metadata(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+            self.backend.read_metadata(key).await
+        }
+    }
+
+    fn is_put_block(operation: &StorageOperation, block_hash: &SaitoHash) -> bool {
+        match operation {
+            StorageOperation::PutBlock {
+                block_hash: hash, ..
+            } => hash == block_hash,
+            _ => false,
+        }
+    }
+
+    fn is_longest_chain_index_entry(operation: &StorageOperation, block_hash: &SaitoHash) -> bool {
+        match operation {
+            StorageOperation::PutBlockIndexEntry(entry) => {
+                entry.block_hash == *block_hash && entry.in_longest_chain
+            }
+            _ => false,
+        }
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn added_block_written_with_index_entry_test() {
+        TestManager::clear_data_folder().await;
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (sender_miner, _receiver_miner) = tokio::sync::mpsc::channel(10);
+        let mut test_manager = TestManager::new(
+            blockchain_lock.clone(),
+            wallet_lock.clone(),
+            sender_miner.clone(),
+        );
+        let backend = Arc::new(RecordingStorageBackend {
+            backend: FileStorageBackend::new(Arc::new(TestIOHandler::new())),
+            batches: Default::default(),
+        });
+        test_manager.storage =
+            Storage::new_with_backend(Box::new(TestIOHandler::new()), backend.clone());
+
+        let current_timestamp = create_timestamp();
+        let block1_hash = test_manager
+            .add_block(current_timestamp, 3, 0, false, vec![])
+            .await;
+        let block2_hash = test_manager
+            .add_block(current_timestamp + 120000, 0, 1, false, vec![])
+            .await;
+
+        let batches = backend.batches.lock().await;
+        for block_hash in [block1_hash, block2_hash] {
+            let batch = batches
+                .iter()
+                .find(|batch| {
+                    batch
+                        .iter()
+                        .any(|operation| is_put_block(operation, &block_hash))
+                })
+                .unwrap();
+            assert!(batch
+                .iter()
+                .any(|operation| is_longest_chain_index_entry(operation, &block_hash)));
+        }
+    }
 }
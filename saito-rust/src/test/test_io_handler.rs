@@ -1,6 +1,7 @@
 use std::fs;
 use std::io::Error;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
 use log::{debug, info};
@@ -12,19 +13,35 @@ use saito_core::common::interface_io::InterfaceIO;
 
 use saito_core::core::data::configuration::PeerConfig;
 
+// buffers sent with the indices of the receiving peers
+type SentMessages = Vec<(u64, Vec<u8>)>;
+
 #[derive(Clone, Debug)]
-pub struct TestIOHandler {}
+pub struct TestIOHandler {
+    // messages sent to the peers. shared between the clones so the tests can check what a network sent
+    sent_messages: Arc<Mutex<SentMessages>>,
+}
 
 impl TestIOHandler {
     pub fn new() -> TestIOHandler {
-        TestIOHandler {}
+        TestIOHandler {
+            sent_messages: Default::default(),
+        }
+    }
+    /// Returns the messages sent so far with the indices of the receiving peers, and clears them
+    pub fn take_sent_messages(&self) -> SentMessages {
+        std::mem::take(&mut *self.sent_messages.lock().unwrap())
     }
 }
 
 #[async_trait]
 impl InterfaceIO for TestIOHandler {
     async fn send_message(&self, peer_index: u64, buffer: Vec<u8>) -> Result<(), Error> {
-        // TODO : implement a way to check sent messages
+        debug!("send message to peer : {:?}", peer_index);
+        self.sent_messages
+            .lock()
+            .unwrap()
+            .push((peer_index, buffer));
 
         Ok(())
     }
@@ -46,7 +63,9 @@ impl InterfaceIO for TestIOHandler {
     }
 
     async fn disconnect_from_peer(&mut self, peer_index: u64) -> Result<(), Error> {
-        todo!()
+        debug!("disconnecting from peer : {:?}", peer_index);
+
+        Ok(())
     }
 
     async fn fetch_block_from_peer(
@@ -58,7 +77,7 @@ impl InterfaceIO for TestIOHandler {
         todo!()
     }
 
-    async fn write_value(&mut self, key: String, value: Vec<u8>) -> Result<(), Error> {
+    async fn write_value(&self, key: String, value: Vec<u8>) -> Result<(), Error> {
         debug!("writing value to disk : {:?}", key);
         let filename = key.as_str();
         let path = Path::new(filename);
@@ -81,16 +100,20 @@ impl InterfaceIO for TestIOHandler {
     }
 
     async fn read_value(&self, key: String) -> Result<Vec<u8>, Error> {
-        let mut result = File::open(key).await;
+        let result = File::open(&key).await;
         if result.is_err() {
-            todo!()
+            let error = result.err().unwrap();
+            debug!("failed opening file : {:?} : {:?}", key, error);
+            return Err(error);
         }
         let mut file = result.unwrap();
         let mut encoded = Vec::<u8>::new();
 
         let result = file.read_to_end(&mut encoded).await;
         if result.is_err() {
-            todo!()
+            let error = result.err().unwrap();
+            debug!("failed reading file : {:?} : {:?}", key, error);
+            return Err(error);
         }
         Ok(encoded)
     }
@@ -137,4 +160,8 @@ impl InterfaceIO for TestIOHandler {
     fn get_block_dir(&self) -> String {
         "./data/blocks/".to_string()
     }
+
+    fn get_data_dir(&self) -> String {
+        "./data/".to_string()
+    }
 }
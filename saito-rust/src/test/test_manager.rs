@@ -83,6 +83,9 @@ impl TestManager {
         tokio::fs::create_dir_all("data/blocks").await.unwrap();
         tokio::fs::remove_dir_all("data/wallets").await;
         tokio::fs::create_dir_all("data/wallets").await.unwrap();
+        let _ = tokio::fs::remove_dir_all("data/metadata").await;
+        tokio::fs::create_dir_all("data/metadata").await.unwrap();
+        let _ = tokio::fs::remove_dir_all("data/quarantine").await;
     }
     //
     // add block at end of longest chain
@@ -693,7 +696,7 @@ impl TestManager {
         let mut block2 = block.clone();
 
         let serialized_block = block2.serialize_for_net(BlockType::Full);
-        let mut deserialized_block = Block::deserialize_for_net(&serialized_block);
+        let mut deserialized_block = Block::deserialize_for_net(&serialized_block).unwrap();
 
         block2.generate_metadata();
         deserialized_block.generate_metadata();
@@ -17,9 +17,7 @@ mod tests {
         let publickey1 = wallet.get_publickey().clone();
         let privatekey1 = wallet.get_privatekey().clone();
 
-        let mut storage = Storage {
-            io_interface: Box::new(TestIOHandler::new()),
-        };
+        let mut storage = Storage::new(Box::new(TestIOHandler::new()));
         wallet.save(&mut storage).await;
 
         wallet = Wallet::new();
@@ -16,9 +16,11 @@ use wasm_bindgen::prelude::*;
 use saito_core::common::defs::{Currency, SaitoHash, SaitoPublicKey, SaitoSignature};
 use saito_core::common::process_event::ProcessEvent;
 use saito_core::core::consensus_event_processor::{ConsensusEvent, ConsensusEventProcessor};
+use saito_core::core::data::application_module::ApplicationModules;
 use saito_core::core::data::blockchain::Blockchain;
 use saito_core::core::data::configuration::Configuration;
 use saito_core::core::data::context::Context;
+use saito_core::core::data::lite_chain::LiteChain;
 use saito_core::core::data::mempool::Mempool;
 use saito_core::core::data::miner::Miner;
 use saito_core::core::data::network::Network;
@@ -79,7 +81,10 @@ lazy_static! {
 
 pub fn new() -> SaitoWasm {
     let wallet = Arc::new(RwLock::new(Wallet::new()));
-    let configuration = Arc::new(RwLock::new(Configuration::new()));
+    let mut configuration = Configuration::new();
+    // browsers can't serve the blocks over http, so the peers fetch them over the connection
+    configuration.serve_http_blocks = false;
+    let configuration = Arc::new(RwLock::new(configuration));
 
     let peers = Arc::new(RwLock::new(PeerCollection::new()));
     let context = Context {
@@ -103,6 +108,15 @@ pub fn new() -> SaitoWasm {
             time_keeper: Box::new(WasmTimeKeeper {}),
             wallet,
             network: Network::new(Box::new(WasmIoHandler {}), peers.clone()),
+            storage: Storage::new(Box::new(WasmIoHandler {})),
+            peer_discovery_timer: 0,
+            ping_timer: 0,
+            reconnect_timer: 0,
+            header_sync: None,
+            header_sync_timer: 0,
+            block_download_timer: 0,
+            lite_chain: LiteChain::new(),
+            application_modules: ApplicationModules::new(),
         },
         routing_event_processor: ConsensusEventProcessor {
             mempool: context.mempool.clone(),
@@ -117,6 +131,8 @@ pub fn new() -> SaitoWasm {
             time_keeper: Box::new(WasmTimeKeeper {}),
             network: Network::new(Box::new(WasmIoHandler {}), peers.clone()),
             storage: Storage::new(Box::new(WasmIoHandler {})),
+            block_reconstructions: Default::default(),
+            configs: context.configuration.clone(),
         },
         mining_event_processor: MiningEventProcessor {
             miner: context.miner.clone(),
@@ -32,7 +32,7 @@ impl InterfaceIO for WasmIoHandler {
     //     todo!()
     // }
 
-    async fn write_value(&mut self, key: String, value: Vec<u8>) -> Result<(), Error> {
+    async fn write_value(&self, key: String, value: Vec<u8>) -> Result<(), Error> {
         todo!()
     }
 
@@ -64,6 +64,10 @@ impl InterfaceIO for WasmIoHandler {
         "data/blocks/".to_string()
     }
 
+    fn get_data_dir(&self) -> String {
+        "data/".to_string()
+    }
+
     async fn disconnect_from_peer(&mut self, peer_index: u64) -> Result<(), Error> {
         todo!()
     }